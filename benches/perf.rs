@@ -3,7 +3,8 @@ use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use stoa::db;
 use stoa::diff;
 use stoa::export;
-use stoa::model::{ChatMessage, Conversation, Role};
+use stoa::model::{new_message_id, ChatMessage, Conversation, Role};
+use stoa::theme::ThemeName;
 
 fn make_conversation(msg_count: usize) -> Conversation {
     let mut conv = Conversation::new();
@@ -11,6 +12,8 @@ fn make_conversation(msg_count: usize) -> Conversation {
     for i in 0..msg_count {
         let role = if i % 2 == 0 { Role::User } else { Role::Assistant };
         conv.messages.push(ChatMessage {
+            id: new_message_id(),
+            parent_id: None,
             role,
             content: format!(
                 "This is message number {i} with some content to make it realistic \
@@ -22,6 +25,9 @@ fn make_conversation(msg_count: usize) -> Conversation {
             rating: 0,
             latency_ms: if i % 2 == 1 { Some(250) } else { None },
             images: Vec::new(),
+            canonical: false,
+            created_at: None,
+            completed_at: None,
         });
     }
     conv
@@ -65,6 +71,14 @@ fn bench_db_load(c: &mut Criterion) {
             }
             b.iter(|| db::load_all(&conn));
         });
+        group.bench_with_input(BenchmarkId::new("load_summaries", count), &count, |b, &count| {
+            let conn = db::open_in_memory();
+            for _ in 0..count {
+                let conv = make_conversation(5);
+                db::save_conversation(&conn, &conv).unwrap();
+            }
+            b.iter(|| db::load_summaries(&conn));
+        });
     }
     group.finish();
 }
@@ -161,7 +175,7 @@ fn bench_export(c: &mut Criterion) {
             b.iter(|| export::conversation_to_json(conv));
         });
         group.bench_with_input(BenchmarkId::new("to_html", count), &conv, |b, conv| {
-            b.iter(|| export::conversation_to_html(conv));
+            b.iter(|| export::conversation_to_html(conv, ThemeName::default()));
         });
     }
     group.finish();
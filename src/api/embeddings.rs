@@ -0,0 +1,49 @@
+use crate::config::EmbeddingConfig;
+
+/// Embed `inputs` in one request. Ollama's `/api/embed` and OpenAI-compatible
+/// `/v1/embeddings` endpoints take the same `{model, input}` body; only the
+/// response shape differs.
+pub async fn embed(client: reqwest::Client, config: EmbeddingConfig, inputs: Vec<String>) -> Result<Vec<Vec<f32>>, String> {
+    if inputs.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut request = client
+        .post(&config.api_url)
+        .json(&serde_json::json!({ "model": config.model, "input": inputs }));
+    if !config.api_key.is_empty() {
+        request = request.bearer_auth(&config.api_key);
+    }
+    let resp = request.send().await.map_err(|e| format!("Embedding endpoint not reachable: {e}"))?;
+    let status = resp.status();
+    let json: serde_json::Value = resp.json().await.map_err(|e| format!("Invalid embedding response: {e}"))?;
+    if !status.is_success() {
+        let detail = json["error"]["message"].as_str().or(json["error"].as_str()).unwrap_or("request failed");
+        return Err(format!("Embedding request failed ({status}): {detail}"));
+    }
+    let vectors = parse_embeddings(&json)?;
+    if vectors.len() != inputs.len() {
+        return Err(format!("Embedding endpoint returned {} vectors for {} inputs", vectors.len(), inputs.len()));
+    }
+    Ok(vectors)
+}
+
+/// Read vectors from an Ollama (`embeddings`) or OpenAI (`data[].embedding`) response,
+/// in input order.
+pub fn parse_embeddings(json: &serde_json::Value) -> Result<Vec<Vec<f32>>, String> {
+    let vector = |v: &serde_json::Value| -> Option<Vec<f32>> {
+        v.as_array()?.iter().map(|x| x.as_f64().map(|x| x as f32)).collect()
+    };
+    if let Some(list) = json["embeddings"].as_array() {
+        return list.iter().map(vector).collect::<Option<_>>().ok_or_else(|| "Malformed embedding vector".to_string());
+    }
+    if let Some(data) = json["data"].as_array() {
+        let mut items: Vec<(u64, Vec<f32>)> = data.iter()
+            .enumerate()
+            .map(|(i, item)| Some((item["index"].as_u64().unwrap_or(i as u64), vector(&item["embedding"])?)))
+            .collect::<Option<_>>()
+            .ok_or_else(|| "Malformed embedding vector".to_string())?;
+        items.sort_by_key(|(i, _)| *i);
+        return Ok(items.into_iter().map(|(_, v)| v).collect());
+    }
+    Err("Embedding response has no `embeddings` or `data` field".to_string())
+}
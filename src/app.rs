@@ -1,22 +1,31 @@
 use iced::widget::{column, container, row};
 use iced::{event, keyboard, window, Element, Length, Subscription, Task, Theme};
 use rusqlite::Connection;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::time::Instant;
 
-use crate::config::AppConfig;
-use crate::model::{Conversation, Provider};
+use crate::config::{AppConfig, EncryptionKeySource};
+use crate::import::ImportFormat;
+use crate::model::{Conversation, Provider, ThreadMode};
 use crate::theme::ThemeName;
 use crate::ui;
 
 pub type StreamId = usize;
 
+/// How many conversations keep their messages in memory at once.
+const LOADED_CONVERSATIONS_MAX: usize = 16;
+/// Message hits shown under the sidebar search box.
+const SEARCH_HITS_MAX: usize = 20;
+const BACKUP_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+const SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
 #[derive(Debug, Clone)]
 pub enum View {
     Chat,
     Settings,
     Analytics,
     Diagnostics,
+    Trash,
 }
 
 pub struct ActiveStream {
@@ -32,6 +41,8 @@ pub struct ActiveStream {
 pub struct ChatApp {
     pub conversations: Vec<Conversation>,
     pub active_conversation: usize,
+    /// IDs of conversations with messages in memory, most recently used first
+    pub loaded_conversations: VecDeque<String>,
     pub input_value: String,
     pub config: AppConfig,
     pub view: View,
@@ -40,10 +51,57 @@ pub struct ChatApp {
     // Rename
     pub renaming_conversation: Option<usize>,
     pub rename_value: String,
+    // Message editing
+    pub editing_message: Option<usize>,
+    pub edit_message_value: String,
     // Latency
     pub last_latency_ms: Option<u128>,
     // Database
     pub(crate) db: Connection,
+    /// File behind `db`; `None` when running on a temporary in-memory database
+    pub db_path: Option<std::path::PathBuf>,
+    /// SQLCipher key `db` was opened with
+    pub(crate) db_key: Option<String>,
+    /// Waiting for the passphrase of an encrypted database
+    pub db_locked: bool,
+    pub unlock_passphrase: String,
+    pub unlock_error: Option<String>,
+    /// New passphrase typed in the settings' encryption card
+    pub encryption_passphrase: String,
+    // Workspaces
+    pub workspaces: Vec<String>,
+    pub new_workspace_name: String,
+    /// The credential profile field as typed; applied on submit or save
+    pub credential_profile_input: String,
+    // Trash
+    pub trash: Vec<crate::db::TrashItem>,
+    /// Trash entry the undo toast would restore, and the toast text
+    pub undo: Option<(i64, String)>,
+    /// Conversation waiting for confirmation because it has forks
+    pub pending_delete: Option<usize>,
+    // Backups
+    pub snapshots: Vec<crate::backup::Snapshot>,
+    pub snapshot_preview: Option<crate::backup::SnapshotPreview>,
+    /// A snapshot is being written in the background
+    pub snapshot_in_progress: bool,
+    // Sync
+    /// Outcome of the last sync, shown in settings
+    pub sync_status: Option<String>,
+    /// A sync is running in the background
+    pub sync_in_progress: bool,
+    /// A sync merged changes while replies were streaming; reload on the next one
+    pub sync_reload_pending: bool,
+    // Vault
+    /// Outcome of the last vault export, shown in settings
+    pub vault_status: Option<String>,
+    // Export
+    pub export_dialog: Option<crate::export::ExportDialog>,
+    pub report_dialog: Option<crate::report::ReportDialog>,
+    // Import
+    /// Parsed file waiting for confirmation
+    pub import_preview: Option<crate::import::ImportPreview>,
+    /// Summary shown after an import from a file
+    pub import_report: Option<crate::import::ImportReport>,
     // Shared HTTP client
     pub http_client: reqwest::Client,
     // Multi-model
@@ -61,6 +119,10 @@ pub struct ChatApp {
     // Quick switcher + Command palette
     pub quick_switcher_open: bool,
     pub quick_switcher_query: String,
+    pub quick_switcher_matches: Option<Vec<String>>,
+    pub quick_switcher_semantic: bool,
+    /// Conversations ranked by embedding similarity to the quick switcher query
+    pub semantic_results: Vec<(String, f32)>,
     pub command_palette_open: bool,
     pub command_palette_query: String,
     pub command_palette_selected: usize,
@@ -68,6 +130,10 @@ pub struct ChatApp {
     // Sidebar search
     pub sidebar_search_query: String,
     pub sidebar_search_results: Option<Vec<String>>,
+    pub sidebar_search_hits: Vec<crate::db::SearchHit>,
+    // Message opened from a search hit, and the words to highlight in it
+    pub highlighted_message: Option<String>,
+    pub highlight_terms: Vec<String>,
     // Tag input
     pub tag_input_open: bool,
     pub tag_input_value: String,
@@ -84,10 +150,22 @@ pub struct ChatApp {
     // Web search
     pub web_search_pending: bool,
     pub web_search_context: Option<String>,
+    // Analytics date range (YYYY-MM-DD, blank = open) and search-syntax filter
+    pub analytics_from: String,
+    pub analytics_to: String,
+    pub analytics_query: String,
+    pub analytics: crate::db::AnalyticsSummary,
+    // Semantic search
+    pub semantic_index: Option<crate::semantic::VectorIndex>,
+    pub embedding_in_progress: bool,
+    pub embedding_error: Option<String>,
+    pub related_conversations: Vec<(String, f32)>,
     // Diagnostics
     pub startup_focus_attempts: u32,
     pub startup_focus_successes: u32,
     pub diagnostics_last_run: Option<String>,
+    /// Result of the last `PRAGMA integrity_check`: the problems found, if it ran
+    pub integrity_report: Option<Result<Vec<String>, String>>,
     pub last_shortcut_event: Option<String>,
 }
 
@@ -132,6 +210,11 @@ pub enum Message {
     // Messages
     RetryMessage,
     DeleteMessage(usize),
+    SwitchVersion(usize, i32), // (msg_index, -1/+1)
+    StartEditMessage(usize),
+    EditMessageChanged(String),
+    SubmitMessageEdit,
+    CancelMessageEdit,
     // Multi-model
     ToggleModelPicker,
     SelectModel(String),
@@ -150,11 +233,18 @@ pub enum Message {
     // Comparison + Diff
     ToggleComparisonMode,
     ShowDiff(usize, usize),
+    // Thread isolation
+    ToggleThreadMode,
+    ToggleCanonical(usize),
     DismissDiff,
     // Quick switcher
     ToggleQuickSwitcher,
     QuickSwitcherQueryChanged(String),
     QuickSwitcherSelect(usize),
+    ToggleSemanticSearch,
+    /// The quick switcher query stopped changing; embed it if it is still current
+    SemanticQuerySettled(String),
+    SemanticQueryEmbedded(String, Result<Vec<f32>, String>),
     // Command palette
     ToggleCommandPalette,
     CommandPaletteQueryChanged(String),
@@ -165,6 +255,7 @@ pub enum Message {
     SidebarSearchChanged(String),
     #[allow(dead_code)]
     ClearSidebarSearch,
+    OpenSearchHit(usize),
     // Export
     ExportMarkdown,
     // Forking
@@ -177,8 +268,19 @@ pub enum Message {
     RateMessage(usize, i8), // (msg_index, -1/0/1)
     // Analytics
     ShowAnalytics,
+    AnalyticsFromChanged(String),
+    AnalyticsToChanged(String),
+    AnalyticsQueryChanged(String),
+    AnalyticsLastDays(Option<u64>),
     ShowDiagnostics,
     RunDiagnostics,
+    // Semantic search
+    SetEmbeddingsEnabled(bool),
+    SetEmbeddingsUrl(String),
+    SetEmbeddingsApiKey(String),
+    SetEmbeddingsModel(String),
+    IndexEmbeddings,
+    EmbeddingsIndexed(Vec<(String, String, usize)>, Result<Vec<Vec<f32>>, String>), // (conversation, message, chunks) per message
     // Ollama
     OllamaModelsDiscovered(Vec<String>),
     RefreshOllamaModels,
@@ -195,9 +297,75 @@ pub enum Message {
     // Export
     ExportHtml,
     ExportJson,
+    OpenExportDialog,
+    SetExportScope(crate::export::ExportScope),
+    SetExportFormat(crate::export::ExportFormat),
+    SetExportOptions(crate::export::ExportOptions),
+    ToggleExportChoice(String),
+    SaveExport,
+    /// Status line for the dialog; `None` when the save dialog was cancelled
+    ExportSaved(Option<Result<String, String>>),
+    CloseExportDialog,
+    OpenReport(Option<usize>), // a reply in the turn to report on, or the whole conversation
+    SetReportWhole(bool),
+    SetReportFormat(crate::report::ReportFormat),
+    SaveReport,
+    ReportSaved(Option<Result<String, String>>),
+    CloseReport,
     // Import
+    ImportFile,
     ImportChatGpt,
+    ImportClaude,
+    // Encryption + database backup
+    UnlockPassphraseChanged(String),
+    UnlockDatabase,
+    EncryptionPassphraseChanged(String),
+    SetDatabaseEncryption(EncryptionKeySource),
+    BackupDatabase,
+    BackupDatabaseTo(std::path::PathBuf),
+    // Workspaces
+    SwitchWorkspace(String),
+    NewWorkspaceNameChanged(String),
+    CreateWorkspace,
+    SetCredentialProfile(String),
+    ApplyCredentialProfile,
+    // Trash
+    ShowTrash,
+    CancelDeleteConversation,
+    RestoreFromTrash(i64),
+    DeleteFromTrash(i64),
+    EmptyTrash,
+    UndoDelete,
+    ExpireUndo(i64),
+    SetTrashRetention(String),
+    // Snapshots
+    BackupNow,
+    BackupTick,
+    SnapshotTaken(Result<std::path::PathBuf, String>),
+    SetBackupEnabled(bool),
+    SetBackupDirectory(String),
+    SetBackupInterval(String),
+    SetBackupKeep(String),
+    PreviewSnapshot(std::path::PathBuf),
+    CancelSnapshotPreview,
+    RestoreSnapshot(std::path::PathBuf),
+    // Sync
+    SyncNow,
+    SyncFinished(Result<crate::sync::SyncReport, String>),
+    SetSyncFolder(String),
+    // Vault
+    ExportVault,
+    SetVaultFolder(String),
+    SetVaultIncremental(bool),
+    /// Format (detected when `None`), file name and contents of a picked export
+    ImportLoaded(Result<crate::import::ImportPreview, String>),
+    SetJsonlField(crate::import::JsonlField, String),
+    JsonlMappingSettled(crate::import::JsonlMapping),
+    ConfirmImport,
+    CancelImport,
+    SetImportCopies(bool), // import duplicates as copies instead of skipping them
     ImportComplete(Vec<Conversation>), // imported conversations to save
+    DismissImportReport,
     // Folders
     #[allow(dead_code)]
     SetFolder(Option<String>),
@@ -227,9 +395,11 @@ impl ChatApp {
         let selected_model = config.selected_model.clone()
             .unwrap_or_else(|| config.active_provider_config().model.clone());
 
-        Self {
+        let credential_profile_input = config.credential_profile.clone();
+        let mut app = Self {
             conversations,
             active_conversation: 0,
+            loaded_conversations: VecDeque::new(),
             input_value: String::new(),
             config,
             view: View::Chat,
@@ -237,8 +407,33 @@ impl ChatApp {
             config_saved: false,
             renaming_conversation: None,
             rename_value: String::new(),
+            editing_message: None,
+            edit_message_value: String::new(),
             last_latency_ms: None,
             db,
+            db_path: None,
+            db_key: None,
+            db_locked: false,
+            unlock_passphrase: String::new(),
+            unlock_error: None,
+            encryption_passphrase: String::new(),
+            trash: Vec::new(),
+            undo: None,
+            pending_delete: None,
+            workspaces: vec![crate::workspace::DEFAULT_WORKSPACE.to_string()],
+            new_workspace_name: String::new(),
+            credential_profile_input,
+            snapshots: Vec::new(),
+            snapshot_in_progress: false,
+            snapshot_preview: None,
+            sync_status: None,
+            sync_in_progress: false,
+            sync_reload_pending: false,
+            vault_status: None,
+            export_dialog: None,
+            report_dialog: None,
+            import_preview: None,
+            import_report: None,
             http_client: crate::api::new_shared_client(),
             selected_model,
             model_picker_open: false,
@@ -251,12 +446,18 @@ impl ChatApp {
             diff_active: None,
             quick_switcher_open: false,
             quick_switcher_query: String::new(),
+            quick_switcher_matches: None,
+            quick_switcher_semantic: false,
+            semantic_results: Vec::new(),
             command_palette_open: false,
             command_palette_query: String::new(),
             command_palette_selected: 0,
             shortcut_help_open: false,
             sidebar_search_query: String::new(),
             sidebar_search_results: None,
+            sidebar_search_hits: Vec::new(),
+            highlighted_message: None,
+            highlight_terms: Vec::new(),
             tag_input_open: false,
             tag_input_value: String::new(),
             session_cost: 0.0,
@@ -267,17 +468,59 @@ impl ChatApp {
             attached_images: Vec::new(),
             web_search_pending: false,
             web_search_context: None,
+            analytics_from: String::new(),
+            analytics_to: String::new(),
+            analytics_query: String::new(),
+            analytics: Default::default(),
+            semantic_index: None,
+            embedding_in_progress: false,
+            embedding_error: None,
+            related_conversations: Vec::new(),
             startup_focus_attempts: 0,
             startup_focus_successes: 0,
             diagnostics_last_run: None,
+            integrity_report: None,
             last_shortcut_event: None,
-        }
+        };
+        app.ensure_loaded(0);
+        app
     }
 
     fn active_conv(&self) -> Option<&Conversation> {
         self.conversations.get(self.active_conversation)
     }
 
+    /// Load the messages of conversation `idx` if needed and mark it recently
+    /// used, unloading the least recently used conversations beyond the limit.
+    /// The active conversation and conversations with live streams stay loaded.
+    pub(crate) fn ensure_loaded(&mut self, idx: usize) {
+        let Some(conv) = self.conversations.get_mut(idx) else { return };
+        Self::handle_db_result(&mut self.error_message, crate::db::load_messages(&self.db, conv));
+        let id = conv.id.clone();
+        self.loaded_conversations.retain(|c| *c != id);
+        self.loaded_conversations.push_front(id);
+
+        let active_id = self.active_conv().map(|c| c.id.clone());
+        while self.loaded_conversations.len() > LOADED_CONVERSATIONS_MAX {
+            let evictable = self.loaded_conversations.iter()
+                .rposition(|id| Some(id) != active_id.as_ref() && !self.conv_has_streams(id));
+            let Some(id) = evictable.and_then(|pos| self.loaded_conversations.remove(pos)) else { break };
+            if let Some(ci) = self.conv_index_by_id(&id) {
+                self.conversations[ci].unload();
+            }
+        }
+    }
+
+    pub(crate) fn clear_highlight(&mut self) {
+        self.highlighted_message = None;
+        self.highlight_terms.clear();
+    }
+
+    fn refresh_analytics(&mut self) {
+        let range = crate::model::DateRange::parse(&self.analytics_from, &self.analytics_to);
+        self.analytics = crate::db::analytics(&self.db, &range, &crate::search::SearchQuery::parse(&self.analytics_query));
+    }
+
     pub(crate) fn handle_db_result(error_message: &mut Option<String>, result: Result<(), String>) {
         if let Err(e) = result {
             log::error!("DB error: {e}");
@@ -286,7 +529,17 @@ impl ChatApp {
     }
 
     fn dismiss_top_overlay(&mut self) {
-        if self.shortcut_help_open {
+        if self.pending_delete.is_some() {
+            self.pending_delete = None;
+        } else if self.export_dialog.is_some() {
+            self.export_dialog = None;
+        } else if self.report_dialog.is_some() {
+            self.report_dialog = None;
+        } else if self.import_preview.is_some() {
+            self.import_preview = None;
+        } else if self.import_report.is_some() {
+            self.import_report = None;
+        } else if self.shortcut_help_open {
             self.shortcut_help_open = false;
         } else if self.quick_switcher_open {
             self.quick_switcher_open = false;
@@ -312,11 +565,14 @@ impl ChatApp {
         } else if self.renaming_conversation.is_some() {
             self.renaming_conversation = None;
             self.rename_value.clear();
+        } else if self.editing_message.is_some() {
+            self.editing_message = None;
+            self.edit_message_value.clear();
         }
     }
 
     fn command_palette_commands(&self) -> Vec<crate::commands::CommandEntry> {
-        crate::commands::filtered_commands(&self.command_palette_query, &self.config.keybindings)
+        crate::commands::filtered_commands(&self.command_palette_query, &self.config.keybindings, &self.other_workspaces())
     }
 
     fn command_palette_selection_count(&self) -> usize {
@@ -355,9 +611,14 @@ impl ChatApp {
 
     pub fn new() -> (Self, Task<Message>) {
         let config = AppConfig::load();
-        let db = crate::db::open();
-        let conversations = crate::db::load_all(&db);
-        let app = Self::from_parts(config.clone(), db, conversations);
+        let library = Self::open_library(&config);
+        let conversations = crate::db::load_summaries(&library.db);
+        let mut app = Self::from_parts(config.clone(), library.db, conversations);
+        app.db_path = library.path;
+        app.db_key = library.key;
+        app.db_locked = library.locked;
+        app.error_message = library.error;
+        app.workspaces = crate::workspace::list(&crate::workspace::root());
 
         let ollama_url = config.ollama.api_url.clone();
         let discover_task = Task::perform(
@@ -378,14 +639,21 @@ impl ChatApp {
             |_| Message::RequestStartupFocus,
         );
 
-        (app, Task::batch(vec![discover_task, startup_focus]))
+        let index_task = app.index_embeddings();
+        app.refresh_related();
+        let backup_task = app.run_scheduled_backup();
+        app.purge_expired_trash();
+        let sync_task = app.sync_now();
+        let unlock_focus = if app.db_locked { iced::widget::operation::focus("unlock-input") } else { Task::none() };
+
+        (app, Task::batch(vec![discover_task, startup_focus, index_task, backup_task, sync_task, unlock_focus]))
     }
 
     #[allow(dead_code)]
     pub fn new_for_tests() -> Self {
         let config = AppConfig::default();
         let db = crate::db::open_in_memory();
-        let conversations = crate::db::load_all(&db);
+        let conversations = crate::db::load_summaries(&db);
         Self::from_parts(config, db, conversations)
     }
 
@@ -406,6 +674,12 @@ impl ChatApp {
         self.active_streams.values().filter(|s| s.conversation_id == conv_id).count()
     }
 
+    /// Workspaces the palette can switch to.
+    pub fn other_workspaces(&self) -> Vec<String> {
+        let active = crate::workspace::active();
+        self.workspaces.iter().filter(|name| **name != active).cloned().collect()
+    }
+
     pub fn conv_index_by_id(&self, id: &str) -> Option<usize> {
         self.conversations.iter().position(|c| c.id == id)
     }
@@ -416,6 +690,7 @@ impl ChatApp {
 
     pub fn update(&mut self, message: Message) -> Task<Message> {
         match message {
+            Message::KeyboardPressed(..) if self.db_locked => Task::none(),
             Message::KeyboardPressed(key, physical_key, modifiers) => {
                 if self.command_palette_open {
                     if matches!(key, keyboard::Key::Named(keyboard::key::Named::ArrowUp)) {
@@ -470,17 +745,24 @@ impl ChatApp {
                 Task::none()
             }
             Message::StreamToken(id, token) => self.handle_stream_token(id, token),
-            Message::StreamComplete(id) => self.handle_stream_complete(id),
+            Message::StreamComplete(id) => {
+                let task = self.handle_stream_complete(id);
+                Task::batch([task, self.index_embeddings()])
+            }
             Message::StreamError(id, err) => self.handle_stream_error(id, err),
             Message::StopStreaming => self.handle_stop_streaming(),
             Message::StopStream(id) => self.handle_stop_stream(id),
             Message::SelectConversation(idx) => {
                 if idx < self.conversations.len() {
                     self.active_conversation = idx;
+                    self.ensure_loaded(idx);
                     self.view = View::Chat;
                     self.model_picker_open = false;
                     self.quick_switcher_open = false;
                     self.diff_active = None;
+                    self.editing_message = None;
+                    self.clear_highlight();
+                    self.refresh_related();
                 }
                 Task::none()
             }
@@ -489,23 +771,22 @@ impl ChatApp {
                 Self::handle_db_result(&mut self.error_message,crate::db::save_conversation(&self.db, &conv));
                 self.conversations.push(conv);
                 self.active_conversation = self.conversations.len() - 1;
+                self.ensure_loaded(self.active_conversation);
                 self.view = View::Chat;
                 self.model_picker_open = false;
+                self.editing_message = None;
+                self.clear_highlight();
+                self.related_conversations.clear();
                 Task::none()
             }
-            Message::DeleteConversation(idx) => {
-                if self.conversations.len() <= 1 { return Task::none(); }
-                let conv = &self.conversations[idx];
-                Self::handle_db_result(&mut self.error_message,crate::db::delete_conversation(&self.db, &conv.id));
-                self.conversations.remove(idx);
-                if self.active_conversation >= self.conversations.len() {
-                    self.active_conversation = self.conversations.len() - 1;
-                } else if self.active_conversation > idx {
-                    self.active_conversation -= 1;
-                }
+            Message::DeleteConversation(idx) => self.handle_delete_conversation(idx),
+            Message::ShowSettings => {
+                self.view = View::Settings;
+                self.config_saved = false;
+                self.model_picker_open = false;
+                self.refresh_snapshots();
                 Task::none()
             }
-            Message::ShowSettings => { self.view = View::Settings; self.config_saved = false; self.model_picker_open = false; Task::none() }
             Message::ShowChat => { self.view = View::Chat; Task::none() }
             Message::SetProvider(p) => { self.config.active_provider = p; self.config_saved = false; Task::none() }
             Message::SetApiKey(k) => { self.config.active_provider_config_mut().api_key = k; self.config_saved = false; Task::none() }
@@ -520,7 +801,18 @@ impl ChatApp {
                 self.config_saved = false;
                 Task::none()
             }
-            Message::SaveConfig => { self.config.save(); self.config_saved = true; Task::none() }
+            Message::SaveConfig => {
+                self.apply_credential_profile();
+                self.config.save();
+                self.config_saved = true;
+                // Vectors from another embedding model can't be compared with new ones
+                let model = &self.config.embeddings.model;
+                if self.semantic_index.as_ref().is_some_and(|index| index.model != *model) {
+                    Self::handle_db_result(&mut self.error_message, crate::db::prune_embeddings(&self.db, model));
+                }
+                self.refresh_related();
+                self.index_embeddings()
+            }
             Message::CopyToClipboard(content) => iced::clipboard::write(content),
             Message::StartRename(idx) => {
                 self.rename_value = self.conversations[idx].title.clone();
@@ -546,14 +838,27 @@ impl ChatApp {
                 Task::none()
             }
             Message::RetryMessage => self.handle_retry_message(),
-            Message::DeleteMessage(idx) => {
+            Message::DeleteMessage(idx) => self.handle_delete_message(idx),
+            Message::SwitchVersion(idx, delta) => {
+                if self.is_active_conv_streaming() { return Task::none(); }
                 let Some(conv) = self.conversations.get_mut(self.active_conversation) else { return Task::none() };
-                if idx < conv.messages.len() {
-                    conv.messages.remove(idx);
+                if idx < conv.messages.len() && conv.switch_version(idx, delta) {
+                    self.diff_active = None;
+                    self.review_picker = None;
                     Self::handle_db_result(&mut self.error_message,crate::db::save_conversation(&self.db, conv));
                 }
                 Task::none()
             }
+            Message::StartEditMessage(idx) => {
+                if self.is_active_conv_streaming() { return Task::none(); }
+                let Some(msg) = self.active_conv().and_then(|c| c.messages.get(idx)) else { return Task::none() };
+                self.edit_message_value = msg.content.clone();
+                self.editing_message = Some(idx);
+                iced::widget::operation::focus("edit-message-input")
+            }
+            Message::EditMessageChanged(value) => { self.edit_message_value = value; Task::none() }
+            Message::SubmitMessageEdit => self.handle_submit_message_edit(),
+            Message::CancelMessageEdit => { self.editing_message = None; self.edit_message_value.clear(); Task::none() }
             Message::ToggleModelPicker => { self.model_picker_open = !self.model_picker_open; Task::none() }
             Message::SelectModel(model_id) => {
                 self.selected_model = model_id.clone();
@@ -613,11 +918,30 @@ impl ChatApp {
             // Comparison + Diff
             Message::ToggleComparisonMode => { self.comparison_mode = !self.comparison_mode; self.diff_active = None; Task::none() }
             Message::ShowDiff(a, b) => { self.diff_active = Some((a, b)); Task::none() }
+            // Thread isolation
+            Message::ToggleThreadMode => {
+                let Some(conv) = self.conversations.get_mut(self.active_conversation) else { return Task::none() };
+                conv.thread_mode = match conv.thread_mode {
+                    ThreadMode::Shared => ThreadMode::Isolated,
+                    ThreadMode::Isolated => ThreadMode::Shared,
+                };
+                Self::handle_db_result(&mut self.error_message,crate::db::upsert_conversation(&self.db, conv));
+                Task::none()
+            }
+            Message::ToggleCanonical(idx) => {
+                let Some(conv) = self.conversations.get_mut(self.active_conversation) else { return Task::none() };
+                if conv.messages.get(idx).is_some_and(|m| !m.streaming) {
+                    conv.toggle_canonical(idx);
+                    Self::handle_db_result(&mut self.error_message,crate::db::save_conversation(&self.db, conv));
+                }
+                Task::none()
+            }
             Message::DismissDiff => { self.diff_active = None; Task::none() }
             // Quick Switcher
             Message::ToggleQuickSwitcher => {
                 self.quick_switcher_open = !self.quick_switcher_open;
                 self.quick_switcher_query.clear();
+                self.quick_switcher_matches = None;
                 self.command_palette_open = false;
                 self.shortcut_help_open = false;
                 if self.quick_switcher_open {
@@ -625,16 +949,49 @@ impl ChatApp {
                 }
                 Task::none()
             }
-            Message::QuickSwitcherQueryChanged(q) => { self.quick_switcher_query = q; Task::none() }
+            Message::QuickSwitcherQueryChanged(q) => {
+                // Message content is searched in SQLite, and only for longer queries (3+ chars);
+                // queries with filters always go to SQLite
+                let trimmed = q.trim();
+                let filtered = crate::search::SearchQuery::parse(trimmed).has_filters();
+                self.quick_switcher_matches = (trimmed.len() >= 3 || filtered)
+                    .then(|| crate::db::search_conversations(&self.db, trimmed));
+                self.quick_switcher_query = q;
+                if self.quick_switcher_semantic {
+                    return self.schedule_semantic_query();
+                }
+                Task::none()
+            }
             Message::QuickSwitcherSelect(idx) => {
                 self.quick_switcher_open = false;
                 self.quick_switcher_query.clear();
+                self.quick_switcher_matches = None;
+                self.semantic_results.clear();
                 if idx < self.conversations.len() {
                     self.active_conversation = idx;
+                    self.ensure_loaded(idx);
                     self.view = View::Chat;
+                    self.editing_message = None;
+                    self.refresh_related();
                 }
                 Task::none()
             }
+            Message::ToggleSemanticSearch => {
+                self.quick_switcher_semantic = !self.quick_switcher_semantic;
+                self.semantic_results.clear();
+                if !self.quick_switcher_semantic {
+                    return Task::none();
+                }
+                let query_task = self.embed_semantic_query();
+                Task::batch([query_task, self.index_embeddings()])
+            }
+            Message::SemanticQuerySettled(query) => {
+                if !self.quick_switcher_semantic || query != self.quick_switcher_query.trim() {
+                    return Task::none();
+                }
+                self.embed_semantic_query()
+            }
+            Message::SemanticQueryEmbedded(query, result) => self.handle_semantic_query_embedded(query, result),
             // Command Palette
             Message::ToggleCommandPalette => {
                 self.command_palette_open = !self.command_palette_open;
@@ -694,16 +1051,38 @@ impl ChatApp {
                 let trimmed = query.trim();
                 if trimmed.is_empty() {
                     self.sidebar_search_results = None;
+                    self.sidebar_search_hits.clear();
+                    self.clear_highlight();
                 } else if trimmed.len() >= 2 {
                     self.sidebar_search_results = Some(crate::db::search_conversations(&self.db, trimmed));
+                    self.sidebar_search_hits = crate::db::search_messages(&self.db, trimmed, SEARCH_HITS_MAX);
                 }
                 Task::none()
             }
             Message::ClearSidebarSearch => {
                 self.sidebar_search_query.clear();
                 self.sidebar_search_results = None;
+                self.sidebar_search_hits.clear();
+                self.clear_highlight();
                 Task::none()
             }
+            Message::OpenSearchHit(i) => {
+                let Some(hit) = self.sidebar_search_hits.get(i).cloned() else { return Task::none() };
+                let Some(idx) = self.conv_index_by_id(&hit.conversation_id) else { return Task::none() };
+                let _ = self.update(Message::SelectConversation(idx));
+                let Some(pos) = self.conversations[idx].messages.iter().position(|m| m.id == hit.message_id) else {
+                    return Task::none();
+                };
+                self.highlighted_message = Some(hit.message_id);
+                self.highlight_terms = hit.terms;
+                // The chat scrolls from the bottom, so offset 0 is the newest message
+                let count = self.conversations[idx].messages.len();
+                let y = if count > 1 { 1.0 - pos as f32 / (count - 1) as f32 } else { 0.0 };
+                iced::widget::operation::snap_to(
+                    crate::ui::chat_view::SCROLL_ID,
+                    iced::widget::scrollable::RelativeOffset { x: 0.0, y },
+                )
+            }
             // Export
             Message::ExportMarkdown => {
                 let Some(conv) = self.conversations.get(self.active_conversation) else { return Task::none() };
@@ -717,6 +1096,7 @@ impl ChatApp {
                 Self::handle_db_result(&mut self.error_message,crate::db::save_conversation(&self.db, &forked));
                 self.conversations.push(forked);
                 self.active_conversation = self.conversations.len() - 1;
+                self.ensure_loaded(self.active_conversation);
                 self.view = View::Chat;
                 Task::none()
             }
@@ -734,7 +1114,7 @@ impl ChatApp {
             Message::SaveConvSystemPrompt => {
                 let Some(conv) = self.conversations.get_mut(self.active_conversation) else { return Task::none() };
                 conv.system_prompt = self.conv_system_prompt_value.trim().to_string();
-                Self::handle_db_result(&mut self.error_message,crate::db::save_conversation(&self.db, conv));
+                Self::handle_db_result(&mut self.error_message,crate::db::upsert_conversation(&self.db, conv));
                 self.conv_system_prompt_open = false;
                 self.conv_system_prompt_value.clear();
                 Task::none()
@@ -744,9 +1124,7 @@ impl ChatApp {
                 let Some(conv) = self.conversations.get_mut(self.active_conversation) else { return Task::none() };
                 if let Some(msg) = conv.messages.get_mut(idx) {
                     msg.rating = if msg.rating == rating { 0 } else { rating }; // toggle
-                    let conv_id = conv.id.clone();
-                    let new_rating = msg.rating;
-                    Self::handle_db_result(&mut self.error_message,crate::db::update_rating(&self.db, &conv_id, idx, new_rating));
+                    Self::handle_db_result(&mut self.error_message,crate::db::update_rating(&self.db, &msg.id, msg.rating));
                 }
                 Task::none()
             }
@@ -754,11 +1132,24 @@ impl ChatApp {
             Message::ShowAnalytics => {
                 self.view = View::Analytics;
                 self.model_picker_open = false;
+                self.refresh_analytics();
+                Task::none()
+            }
+            Message::AnalyticsFromChanged(v) => { self.analytics_from = v; self.refresh_analytics(); Task::none() }
+            Message::AnalyticsToChanged(v) => { self.analytics_to = v; self.refresh_analytics(); Task::none() }
+            Message::AnalyticsQueryChanged(v) => { self.analytics_query = v; self.refresh_analytics(); Task::none() }
+            Message::AnalyticsLastDays(days) => {
+                self.analytics_from = days
+                    .map(|d| (chrono::Local::now().date_naive() - chrono::Days::new(d.saturating_sub(1))).format("%Y-%m-%d").to_string())
+                    .unwrap_or_default();
+                self.analytics_to.clear();
+                self.refresh_analytics();
                 Task::none()
             }
             Message::ShowDiagnostics => {
                 self.view = View::Diagnostics;
                 self.model_picker_open = false;
+                self.refresh_snapshots();
                 Task::none()
             }
             Message::RunDiagnostics => {
@@ -767,8 +1158,16 @@ impl ChatApp {
                     .map(|d| d.as_secs())
                     .unwrap_or(0);
                 self.diagnostics_last_run = Some(format!("unix:{ts}"));
+                self.integrity_report = Some(crate::db::integrity_check(&self.db));
                 Task::none()
             }
+            // Semantic search
+            Message::SetEmbeddingsEnabled(v) => { self.config.embeddings.enabled = v; self.config_saved = false; Task::none() }
+            Message::SetEmbeddingsUrl(v) => { self.config.embeddings.api_url = v; self.config_saved = false; Task::none() }
+            Message::SetEmbeddingsApiKey(v) => { self.config.embeddings.api_key = v; self.config_saved = false; Task::none() }
+            Message::SetEmbeddingsModel(v) => { self.config.embeddings.model = v; self.config_saved = false; Task::none() }
+            Message::IndexEmbeddings => self.index_embeddings(),
+            Message::EmbeddingsIndexed(batch, result) => self.handle_embeddings_indexed(batch, result),
             // Ollama
             Message::OllamaModelsDiscovered(models) => {
                 self.config.ollama_models = models;
@@ -875,7 +1274,7 @@ impl ChatApp {
             // Export HTML/JSON
             Message::ExportHtml => {
                 let Some(conv) = self.conversations.get(self.active_conversation) else { return Task::none() };
-                let html = crate::export::conversation_to_html(conv);
+                let html = crate::export::conversation_to_html(conv, self.config.theme);
                 iced::clipboard::write(html)
             }
             Message::ExportJson => {
@@ -883,47 +1282,179 @@ impl ChatApp {
                 let json = crate::export::conversation_to_json(conv);
                 iced::clipboard::write(json)
             }
-            // Import
-            Message::ImportChatGpt => {
+            Message::OpenExportDialog => { self.open_export_dialog(); Task::none() }
+            Message::SetExportScope(scope) => {
+                if let Some(dialog) = &mut self.export_dialog { dialog.scope = scope; dialog.status = None; }
+                Task::none()
+            }
+            Message::SetExportFormat(format) => {
+                if let Some(dialog) = &mut self.export_dialog { dialog.format = format; dialog.status = None; }
+                Task::none()
+            }
+            Message::SetExportOptions(options) => {
+                if let Some(dialog) = &mut self.export_dialog { dialog.options = options; dialog.status = None; }
+                Task::none()
+            }
+            Message::ToggleExportChoice(id) => {
+                if let Some(dialog) = &mut self.export_dialog {
+                    if !dialog.chosen.remove(&id) {
+                        dialog.chosen.insert(id);
+                    }
+                    dialog.scope = crate::export::ExportScope::Chosen;
+                    dialog.status = None;
+                }
+                Task::none()
+            }
+            Message::SaveExport => self.save_export(),
+            Message::ExportSaved(result) => {
+                if let (Some(dialog), Some(result)) = (&mut self.export_dialog, result) {
+                    dialog.status = Some(result.unwrap_or_else(|e| e));
+                }
+                Task::none()
+            }
+            Message::CloseExportDialog => { self.export_dialog = None; Task::none() }
+            Message::OpenReport(turn) => { self.open_report(turn); Task::none() }
+            Message::SetReportWhole(whole) => {
+                if let Some(dialog) = &mut self.report_dialog { dialog.whole = whole; dialog.status = None; }
+                Task::none()
+            }
+            Message::SetReportFormat(format) => {
+                if let Some(dialog) = &mut self.report_dialog { dialog.format = format; dialog.status = None; }
+                Task::none()
+            }
+            Message::SaveReport => self.save_report(),
+            Message::ReportSaved(result) => {
+                if let (Some(dialog), Some(result)) = (&mut self.report_dialog, result) {
+                    dialog.status = Some(result.unwrap_or_else(|e| e));
+                }
+                Task::none()
+            }
+            Message::CloseReport => { self.report_dialog = None; Task::none() }
+            // Encryption + database backup
+            Message::UnlockPassphraseChanged(v) => { self.unlock_passphrase = v; Task::none() }
+            Message::UnlockDatabase => self.unlock_database(),
+            Message::EncryptionPassphraseChanged(v) => { self.encryption_passphrase = v; Task::none() }
+            Message::SetDatabaseEncryption(source) => { self.set_database_encryption(source); Task::none() }
+            Message::BackupDatabase => {
+                let name = format!("stoa-{}.db", chrono::Local::now().format("%Y-%m-%d"));
                 Task::perform(
-                    async {
-                        let handle = rfd::AsyncFileDialog::new()
-                            .add_filter("JSON", &["json"])
-                            .pick_file()
-                            .await;
-                        match handle {
-                            Some(file) => {
-                                let data = file.read().await;
-                                let text = String::from_utf8_lossy(&data).to_string();
-                                let convs = crate::import::import_chatgpt(&text);
-                                if convs.is_empty() { None } else { Some(convs) }
-                            }
-                            None => None,
-                        }
+                    async move {
+                        rfd::AsyncFileDialog::new()
+                            .set_file_name(name)
+                            .add_filter("SQLite database", &["db"])
+                            .save_file()
+                            .await
+                            .map(|file| file.path().to_path_buf())
                     },
-                    |result| match result {
-                        Some(convs) => Message::ImportComplete(convs),
+                    |path| match path {
+                        Some(path) => Message::BackupDatabaseTo(path),
                         None => Message::DismissError,
                     },
                 )
             }
+            Message::BackupDatabaseTo(path) => { self.backup_database(path); Task::none() }
+            // Workspaces
+            Message::SwitchWorkspace(name) => self.switch_workspace(name, None),
+            Message::NewWorkspaceNameChanged(v) => { self.new_workspace_name = v; Task::none() }
+            Message::CreateWorkspace => self.create_workspace(),
+            Message::SetCredentialProfile(v) => { self.credential_profile_input = v; self.config_saved = false; Task::none() }
+            Message::ApplyCredentialProfile => { self.apply_credential_profile(); Task::none() }
+            // Trash
+            Message::ShowTrash => {
+                self.view = View::Trash;
+                self.model_picker_open = false;
+                self.purge_expired_trash();
+                self.refresh_trash();
+                Task::none()
+            }
+            Message::CancelDeleteConversation => { self.pending_delete = None; Task::none() }
+            Message::RestoreFromTrash(id) => { self.restore_from_trash(id); Task::none() }
+            Message::DeleteFromTrash(id) => {
+                Self::handle_db_result(&mut self.error_message, crate::db::delete_from_trash(&self.db, id));
+                self.refresh_trash();
+                Task::none()
+            }
+            Message::EmptyTrash => {
+                Self::handle_db_result(&mut self.error_message, crate::db::purge_trash(&self.db, None).map(|_| ()));
+                self.refresh_trash();
+                Task::none()
+            }
+            Message::UndoDelete => {
+                if let Some((id, _)) = self.undo.take() {
+                    self.restore_from_trash(id);
+                }
+                Task::none()
+            }
+            Message::ExpireUndo(id) => {
+                if self.undo.as_ref().is_some_and(|(current, _)| *current == id) {
+                    self.undo = None;
+                }
+                Task::none()
+            }
+            Message::SetTrashRetention(v) => { self.config.trash_retention_days = v; self.config_saved = false; Task::none() }
+            // Snapshots
+            Message::BackupNow => self.snapshot_in_background(),
+            Message::BackupTick => self.run_scheduled_backup(),
+            Message::SnapshotTaken(result) => { self.snapshot_taken(result); Task::none() }
+            Message::SetBackupEnabled(v) => { self.config.backup.enabled = v; self.config_saved = false; Task::none() }
+            Message::SetBackupDirectory(v) => { self.config.backup.directory = v; self.config_saved = false; self.refresh_snapshots(); Task::none() }
+            Message::SetBackupInterval(v) => { self.config.backup.interval_hours = v; self.config_saved = false; Task::none() }
+            Message::SetBackupKeep(v) => { self.config.backup.keep = v; self.config_saved = false; Task::none() }
+            Message::PreviewSnapshot(path) => { self.preview_snapshot(path); Task::none() }
+            Message::CancelSnapshotPreview => { self.snapshot_preview = None; Task::none() }
+            Message::RestoreSnapshot(path) => { self.restore_snapshot(path); Task::none() }
+            // Sync
+            Message::SyncNow => self.sync_now(),
+            Message::SyncFinished(result) => { self.sync_finished(result); Task::none() }
+            Message::SetSyncFolder(v) => { self.config.sync.folder = v; self.config_saved = false; Task::none() }
+            Message::ExportVault => { self.export_vault(); Task::none() }
+            Message::SetVaultFolder(v) => { self.config.vault.folder = v; self.config_saved = false; Task::none() }
+            Message::SetVaultIncremental(on) => { self.config.vault.incremental = on; self.config_saved = false; Task::none() }
+            // Import
+            Message::ImportFile => self.pick_import_file(None),
+            Message::ImportChatGpt => self.pick_import_file(Some(ImportFormat::ChatGpt)),
+            Message::ImportClaude => self.pick_import_file(Some(ImportFormat::Claude)),
+            Message::ImportLoaded(preview) => { self.preview_import(preview); Task::none() }
+            Message::SetJsonlField(field, value) => self.set_jsonl_field(field, value),
+            Message::JsonlMappingSettled(mapping) => {
+                // A later keystroke has its own timer
+                if mapping == self.config.jsonl_mapping { self.reread_import(); }
+                Task::none()
+            }
+            Message::ConfirmImport => self.confirm_import(),
+            Message::CancelImport => { self.import_preview = None; Task::none() }
+            Message::SetImportCopies(copy) => {
+                if let Some(preview) = &mut self.import_preview { preview.copy_duplicates = copy; }
+                Task::none()
+            }
+            Message::DismissImportReport => { self.import_report = None; Task::none() }
             Message::ImportComplete(convs) => {
+                // Conversations keep their source IDs, so importing a file again adds nothing twice
+                let (duplicates, convs): (Vec<_>, Vec<_>) = convs.into_iter()
+                    .partition(|conv| self.conv_index_by_id(&conv.id).is_some());
                 let count = convs.len();
-                for conv in convs {
+                if let Some(report) = &mut self.import_report {
+                    report.imported = count;
+                    report.already_imported += duplicates.len();
+                }
+                for mut conv in convs {
                     Self::handle_db_result(&mut self.error_message,crate::db::save_conversation(&self.db, &conv));
+                    conv.unload();
                     self.conversations.push(conv);
                 }
                 if count > 0 {
                     self.active_conversation = self.conversations.len() - 1;
+                    self.ensure_loaded(self.active_conversation);
                     self.view = View::Chat;
+                    self.refresh_related();
                 }
-                Task::none()
+                self.index_embeddings()
             }
             // Folders
             Message::SetFolder(folder) => {
                 let Some(conv) = self.conversations.get_mut(self.active_conversation) else { return Task::none() };
                 conv.folder = folder;
-                Self::handle_db_result(&mut self.error_message,crate::db::save_conversation(&self.db, conv));
+                Self::handle_db_result(&mut self.error_message,crate::db::upsert_conversation(&self.db, conv));
                 Task::none()
             }
             Message::DismissError => { self.error_message = None; Task::none() }
@@ -973,6 +1504,9 @@ impl ChatApp {
     }
 
     pub fn view(&self) -> Element<'_, Message> {
+        if self.db_locked {
+            return ui::unlock::view(self);
+        }
         let sidebar = ui::sidebar::view(self);
         let right_panel = ui::right_panel::view(self);
         let bottom_bar = ui::bottom_bar::view(self);
@@ -991,14 +1525,38 @@ impl ChatApp {
             View::Settings => ui::settings::view(self),
             View::Analytics => ui::analytics::view(self),
             View::Diagnostics => ui::diagnostics::view(self),
+            View::Trash => ui::trash::view(self),
         };
 
         let sep_v = || container(iced::widget::Space::new()).width(1).height(Length::Fill).style(sep);
         let main_row = row![sidebar, sep_v(), container(content).width(Length::Fill), sep_v(), right_panel];
         let layout = column![container(main_row).height(Length::Fill), bottom_bar];
-        let base: Element<Message> = container(layout).width(Length::Fill).height(Length::Fill).into();
+        let mut base: Element<Message> = container(layout).width(Length::Fill).height(Length::Fill).into();
+        if let Some((_, label)) = &self.undo {
+            base = iced::widget::stack![base, ui::trash::undo_toast(label)].into();
+        }
 
-        // Overlays: quick switcher, command palette, and shortcut cheat-sheet
+        // Overlays: delete confirmation, import preview and report, quick switcher, command palette, and shortcut cheat-sheet
+        if let Some(idx) = self.pending_delete {
+            let overlay = ui::trash::confirm_delete(self, idx);
+            return iced::widget::stack![base, overlay].into();
+        }
+        if let Some(dialog) = &self.export_dialog {
+            let overlay = ui::export::dialog(self, dialog);
+            return iced::widget::stack![base, overlay].into();
+        }
+        if let Some(dialog) = &self.report_dialog {
+            let overlay = ui::export::report(dialog);
+            return iced::widget::stack![base, overlay].into();
+        }
+        if let Some(preview) = &self.import_preview {
+            let overlay = ui::import::preview(self, preview);
+            return iced::widget::stack![base, overlay].into();
+        }
+        if let Some(report) = &self.import_report {
+            let overlay = ui::import::report(report);
+            return iced::widget::stack![base, overlay].into();
+        }
         if self.shortcut_help_open {
             let overlay = ui::shortcut_help::view(self);
             return iced::widget::stack![base, overlay].into();
@@ -1016,12 +1574,21 @@ impl ChatApp {
     }
 
     pub fn subscription(&self) -> Subscription<Message> {
-        event::listen_with(|event, _status, _window| {
+        let keys = event::listen_with(|event, _status, _window| {
             let iced::Event::Keyboard(keyboard::Event::KeyPressed { key, modifiers, physical_key, .. }) = event else {
                 return None;
             };
             Some(Message::KeyboardPressed(key, physical_key, modifiers))
-        })
+        });
+        let mut subscriptions = vec![keys];
+        if self.config.backup.enabled {
+            // Checks whether a scheduled snapshot is due; the interval itself is in hours
+            subscriptions.push(iced::time::every(BACKUP_CHECK_INTERVAL).map(|_| Message::BackupTick));
+        }
+        if self.config.sync.dir().is_some() {
+            subscriptions.push(iced::time::every(SYNC_INTERVAL).map(|_| Message::SyncNow));
+        }
+        Subscription::batch(subscriptions)
     }
 }
 
@@ -1096,4 +1663,35 @@ mod tests {
             crate::shortcuts::default_binding(crate::shortcuts::ShortcutAction::NewConversation)
         );
     }
+
+    #[test]
+    fn conversations_load_on_demand_and_evict_least_recently_used() {
+        let mut app = ChatApp::new_for_tests();
+        for i in 0..20 {
+            let mut conv = crate::model::Conversation::new();
+            conv.add_user_message(&format!("question {i}"), None);
+            crate::db::save_conversation(&app.db, &conv).unwrap();
+        }
+        // Start over from summaries, as on launch
+        app.conversations = crate::db::load_summaries(&app.db);
+        app.loaded_conversations.clear();
+        app.ensure_loaded(0);
+        assert!(app.conversations[0].is_loaded());
+        assert!(app.conversations[1..].iter().all(|c| !c.is_loaded() && c.messages.is_empty()));
+
+        for i in 0..app.conversations.len() {
+            let _ = app.update(Message::SelectConversation(i));
+        }
+        let loaded = app.conversations.iter().filter(|c| c.is_loaded()).count();
+        assert_eq!(loaded, super::LOADED_CONVERSATIONS_MAX);
+        assert_eq!(app.loaded_conversations.len(), super::LOADED_CONVERSATIONS_MAX);
+        assert!(!app.conversations[0].is_loaded(), "oldest selection is evicted first");
+        assert!(app.conversations.last().unwrap().is_loaded());
+
+        // Unloaded conversations keep their totals and reload intact
+        let total: usize = app.conversations.iter().map(|c| c.stats().message_count).sum();
+        assert_eq!(total, 20);
+        let _ = app.update(Message::SelectConversation(0));
+        assert!(app.conversations[0].is_loaded());
+    }
 }
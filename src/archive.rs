@@ -0,0 +1,77 @@
+use chrono::Utc;
+use serde_json::{json, Value};
+
+use crate::model::Conversation;
+
+/// Marks a file as a Stoa archive.
+pub const ARCHIVE_FORMAT: &str = "stoa-archive";
+/// Bumped when the layout changes in a way older versions can't read.
+pub const ARCHIVE_VERSION: u64 = 1;
+
+/// Whether `value` is the outside of a Stoa archive.
+pub fn is_archive(value: &Value) -> bool {
+    value.get("format").and_then(Value::as_str) == Some(ARCHIVE_FORMAT)
+}
+
+/// An archive of `conversations` with everything needed to restore them:
+/// every branch, ratings, latency, tags, folder, fork lineage and images.
+/// Replies still streaming are left out.
+pub fn write(conversations: &[Conversation]) -> Result<String, String> {
+    let conversations: Vec<Conversation> = conversations.iter()
+        .map(|conv| {
+            let mut conv = conv.clone();
+            conv.messages.retain(|m| !m.streaming);
+            conv.alternates.retain(|m| !m.streaming);
+            conv
+        })
+        .collect();
+    let archive = json!({
+        "format": ARCHIVE_FORMAT,
+        "version": ARCHIVE_VERSION,
+        "exported_at": Utc::now(),
+        "conversations": conversations,
+    });
+    serde_json::to_string_pretty(&archive).map_err(|e| format!("Failed to write archive: {e}"))
+}
+
+/// Chain the messages of a conversation exported before messages had IDs,
+/// as the database migration does; without parent links every reply would
+/// look like a version of every other one.
+fn link_thread(mut conv: Conversation) -> Conversation {
+    if conv.messages.iter().chain(&conv.alternates).all(|m| m.parent_id.is_none()) {
+        let mut parent_id = None;
+        for msg in &mut conv.messages {
+            msg.parent_id = parent_id.replace(msg.id.clone());
+        }
+    }
+    conv
+}
+
+/// Conversations from an archive, or from the JSON the clipboard and file
+/// exports write (one conversation or an array of them). Also returns how
+/// many entries couldn't be read. Archives from a newer version are refused
+/// rather than imported with parts missing.
+pub fn read(data: &str) -> Result<(Vec<Conversation>, usize), String> {
+    let value: Value = serde_json::from_str(data).map_err(|e| format!("Not valid JSON: {e}"))?;
+    let items = if is_archive(&value) {
+        let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+        if version > ARCHIVE_VERSION {
+            return Err(format!("It was written by a newer Stoa (archive version {version}); update Stoa to import it."));
+        }
+        value.get("conversations").cloned().unwrap_or_default()
+    } else {
+        value
+    };
+    let items = match items {
+        Value::Array(items) => items,
+        Value::Null => Vec::new(),
+        single => vec![single],
+    };
+    let total = items.len();
+    let conversations: Vec<Conversation> = items.into_iter()
+        .filter_map(|item| serde_json::from_value(item).ok())
+        .map(link_thread)
+        .collect();
+    let unreadable = total - conversations.len();
+    Ok((conversations, unreadable))
+}
@@ -0,0 +1,100 @@
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+
+use crate::model::Conversation;
+
+const SNAPSHOT_PREFIX: &str = "stoa-";
+const SNAPSHOT_STAMP: &str = "%Y%m%d-%H%M%S";
+
+/// One snapshot file in the backup directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub path: PathBuf,
+    pub created_at: DateTime<Local>,
+    pub size: u64,
+}
+
+/// What a snapshot holds, read before restoring it.
+#[derive(Debug, Clone)]
+pub struct SnapshotPreview {
+    pub snapshot: Snapshot,
+    /// Summaries only; messages are not loaded
+    pub conversations: Vec<Conversation>,
+    pub message_count: usize,
+    /// Findings of `PRAGMA integrity_check`; empty when sound
+    pub problems: Vec<String>,
+}
+
+/// Directory used when the configured one is empty.
+pub fn default_dir() -> PathBuf {
+    crate::workspace::dir().join("backups")
+}
+
+pub fn snapshot_file_name(at: DateTime<Local>) -> String {
+    format!("{SNAPSHOT_PREFIX}{}.db", at.format(SNAPSHOT_STAMP))
+}
+
+fn parse_snapshot_name(name: &str) -> Option<DateTime<Local>> {
+    let stamp = name.strip_prefix(SNAPSHOT_PREFIX)?.strip_suffix(".db")?;
+    let naive = NaiveDateTime::parse_from_str(stamp, SNAPSHOT_STAMP).ok()?;
+    Local.from_local_datetime(&naive).earliest()
+}
+
+/// Snapshots in `dir`, newest first. Other files are ignored.
+pub fn list_snapshots(dir: &Path) -> Vec<Snapshot> {
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    let mut snapshots: Vec<Snapshot> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let created_at = parse_snapshot_name(&entry.file_name().to_string_lossy())?;
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            Some(Snapshot { path: entry.path(), created_at, size })
+        })
+        .collect();
+    snapshots.sort_by_key(|s| std::cmp::Reverse(s.created_at));
+    snapshots
+}
+
+/// Delete all but the newest `keep` snapshots, returning the removed paths.
+pub fn rotate(dir: &Path, keep: usize) -> Result<Vec<PathBuf>, String> {
+    let mut removed = Vec::new();
+    for snapshot in list_snapshots(dir).into_iter().skip(keep.max(1)) {
+        std::fs::remove_file(&snapshot.path)
+            .map_err(|e| format!("Failed to remove old backup {}: {e}", snapshot.path.display()))?;
+        removed.push(snapshot.path);
+    }
+    Ok(removed)
+}
+
+/// Snapshot the open database into `dir` and rotate old snapshots out.
+pub fn take_snapshot(conn: &Connection, dir: &Path, key: Option<&str>, keep: usize, at: DateTime<Local>) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create backup directory {}: {e}", dir.display()))?;
+    let path = dir.join(snapshot_file_name(at));
+    crate::db::backup_to(conn, &path, key)?;
+    rotate(dir, keep)?;
+    Ok(path)
+}
+
+/// Whether a scheduled snapshot is due, given the newest one.
+pub fn is_due(latest: Option<&Snapshot>, interval: chrono::Duration, now: DateTime<Local>) -> bool {
+    latest.is_none_or(|s| now - s.created_at >= interval)
+}
+
+/// Read a snapshot's conversations and check its integrity without changing it.
+pub fn preview(snapshot: &Snapshot, key: Option<&str>) -> Result<SnapshotPreview, String> {
+    // Snapshots keep the encryption the library had when they were taken
+    let key = key.filter(|_| crate::db::is_encrypted(&snapshot.path));
+    let conn = crate::db::open_snapshot(&snapshot.path, key)?;
+    let version = crate::db::schema_version(&conn);
+    if version != crate::db::SCHEMA_VERSION {
+        return Err(format!(
+            "This backup uses schema version {version} (current is {}); it can be restored but not previewed.",
+            crate::db::SCHEMA_VERSION,
+        ));
+    }
+    let problems = crate::db::integrity_check(&conn)?;
+    let conversations = crate::db::load_summaries(&conn);
+    let message_count = conversations.iter().map(|c| c.stats().message_count).sum();
+    Ok(SnapshotPreview { snapshot: snapshot.clone(), conversations, message_count, problems })
+}
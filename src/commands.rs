@@ -4,113 +4,175 @@ use crate::shortcuts::{self, ShortcutAction};
 
 #[derive(Debug, Clone)]
 pub struct CommandEntry {
-    pub label: &'static str,
+    pub label: String,
     pub description: &'static str,
     pub shortcut: String,
     pub message: Message,
 }
 
-pub fn all_commands(bindings: &Keybindings) -> Vec<CommandEntry> {
+/// Every palette command, plus a switch entry for each of `workspaces`.
+pub fn all_commands(bindings: &Keybindings, workspaces: &[String]) -> Vec<CommandEntry> {
     let shortcut = |action: ShortcutAction| bindings.get(action).to_string();
 
-    vec![
+    let mut commands = vec![
         CommandEntry {
-            label: "New Chat",
+            label: "New Chat".to_string(),
             description: "Create a new conversation",
             shortcut: shortcut(ShortcutAction::NewConversation),
             message: Message::NewConversation,
         },
         CommandEntry {
-            label: "Settings",
+            label: "Settings".to_string(),
             description: "Open settings",
             shortcut: shortcut(ShortcutAction::ShowSettings),
             message: Message::ShowSettings,
         },
         CommandEntry {
-            label: "Home",
+            label: "Home".to_string(),
             description: "Go to chat view",
             shortcut: String::new(),
             message: Message::ShowChat,
         },
         CommandEntry {
-            label: "Send to All",
+            label: "Send to All".to_string(),
             description: "Send to all available models",
             shortcut: shortcut(ShortcutAction::SendToAll),
             message: Message::SendToAll,
         },
         CommandEntry {
-            label: "Toggle Comparison",
+            label: "Toggle Comparison".to_string(),
             description: "Switch comparison mode on/off",
             shortcut: String::new(),
             message: Message::ToggleComparisonMode,
         },
         CommandEntry {
-            label: "Export Markdown",
+            label: "Toggle Thread Isolation".to_string(),
+            description: "Let each model see only its own replies",
+            shortcut: String::new(),
+            message: Message::ToggleThreadMode,
+        },
+        CommandEntry {
+            label: "Export Markdown".to_string(),
             description: "Copy conversation as Markdown",
             shortcut: shortcut(ShortcutAction::ExportMarkdown),
             message: Message::ExportMarkdown,
         },
         CommandEntry {
-            label: "Export HTML",
+            label: "Export HTML".to_string(),
             description: "Copy conversation as styled HTML",
             shortcut: String::new(),
             message: Message::ExportHtml,
         },
         CommandEntry {
-            label: "Export JSON",
+            label: "Export JSON".to_string(),
             description: "Copy conversation as JSON",
             shortcut: String::new(),
             message: Message::ExportJson,
         },
         CommandEntry {
-            label: "Import ChatGPT",
+            label: "Import File".to_string(),
+            description: "Import a chat export, detecting its format",
+            shortcut: String::new(),
+            message: Message::ImportFile,
+        },
+        CommandEntry {
+            label: "Import ChatGPT".to_string(),
             description: "Import from ChatGPT export file",
             shortcut: String::new(),
             message: Message::ImportChatGpt,
         },
         CommandEntry {
-            label: "Web Search",
+            label: "Import Claude".to_string(),
+            description: "Import from Claude.ai export file",
+            shortcut: String::new(),
+            message: Message::ImportClaude,
+        },
+        CommandEntry {
+            label: "Back Up Database".to_string(),
+            description: "Save a copy of the database, encrypted like the original",
+            shortcut: String::new(),
+            message: Message::BackupDatabase,
+        },
+        CommandEntry {
+            label: "Export to File".to_string(),
+            description: "Save conversations as Markdown, HTML, JSON or a zip",
+            shortcut: String::new(),
+            message: Message::OpenExportDialog,
+        },
+        CommandEntry {
+            label: "Comparison Report".to_string(),
+            description: "Save this conversation's multi-model turns side by side as Markdown, HTML or CSV",
+            shortcut: String::new(),
+            message: Message::OpenReport(None),
+        },
+        CommandEntry {
+            label: "Export Vault".to_string(),
+            description: "Write the library to the Markdown vault folder",
+            shortcut: String::new(),
+            message: Message::ExportVault,
+        },
+        CommandEntry {
+            label: "Sync Now".to_string(),
+            description: "Exchange changes with the sync folder",
+            shortcut: String::new(),
+            message: Message::SyncNow,
+        },
+        CommandEntry {
+            label: "Web Search".to_string(),
             description: "Search web for current input",
             shortcut: String::new(),
             message: Message::WebSearch,
         },
         CommandEntry {
-            label: "Refresh Ollama",
+            label: "Refresh Ollama".to_string(),
             description: "Re-scan local Ollama models",
             shortcut: String::new(),
             message: Message::RefreshOllamaModels,
         },
         CommandEntry {
-            label: "Analytics",
+            label: "Analytics".to_string(),
             description: "View model stats and ratings",
             shortcut: String::new(),
             message: Message::ShowAnalytics,
         },
         CommandEntry {
-            label: "Diagnostics",
+            label: "Diagnostics".to_string(),
             description: "Inspect keyboard/focus diagnostics",
             shortcut: String::new(),
             message: Message::ShowDiagnostics,
         },
         CommandEntry {
-            label: "Quick Switcher",
+            label: "Trash".to_string(),
+            description: "Restore or permanently delete removed items",
+            shortcut: String::new(),
+            message: Message::ShowTrash,
+        },
+        CommandEntry {
+            label: "Quick Switcher".to_string(),
             description: "Search conversations",
             shortcut: shortcut(ShortcutAction::QuickSwitcher),
             message: Message::ToggleQuickSwitcher,
         },
         CommandEntry {
-            label: "Keyboard Shortcuts",
+            label: "Keyboard Shortcuts".to_string(),
             description: "Show shortcut cheat sheet",
             shortcut: shortcut(ShortcutAction::ToggleShortcutHelp),
             message: Message::ToggleShortcutHelp,
         },
-    ]
+    ];
+    commands.extend(workspaces.iter().map(|name| CommandEntry {
+        label: format!("Workspace: {name}"),
+        description: "Switch to this workspace",
+        shortcut: String::new(),
+        message: Message::SwitchWorkspace(name.clone()),
+    }));
+    commands
 }
 
-pub fn filtered_commands(query: &str, bindings: &Keybindings) -> Vec<CommandEntry> {
+pub fn filtered_commands(query: &str, bindings: &Keybindings, workspaces: &[String]) -> Vec<CommandEntry> {
     let lowered = query.to_lowercase();
     let mut out = Vec::new();
-    for cmd in all_commands(bindings) {
+    for cmd in all_commands(bindings, workspaces) {
         if lowered.is_empty()
             || cmd.label.to_lowercase().contains(&lowered)
             || cmd.description.to_lowercase().contains(&lowered)
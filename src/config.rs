@@ -7,7 +7,7 @@ use crate::theme::ThemeName;
 
 const KEYCHAIN_SERVICE: &str = "stoa";
 
-pub const CONFIG_SCHEMA_VERSION: u32 = 3;
+pub const CONFIG_SCHEMA_VERSION: u32 = 11;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
@@ -36,11 +36,151 @@ pub struct AppConfig {
     pub debug_key_events: bool,
     #[serde(default)]
     pub theme: ThemeName,
+    #[serde(default)]
+    pub embeddings: EmbeddingConfig,
+    #[serde(default)]
+    pub encryption: EncryptionKeySource,
+    #[serde(default)]
+    pub backup: BackupConfig,
+    /// Days deleted conversations and messages stay in the trash
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: String,
+    /// Keychain namespace for API keys; workspaces with the same profile
+    /// share keys, and the empty profile uses the keys from before workspaces
+    #[serde(default)]
+    pub credential_profile: String,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    /// Field mapping last used to import a JSONL file
+    #[serde(default)]
+    pub jsonl_mapping: crate::import::JsonlMapping,
+    #[serde(default)]
+    pub vault: VaultConfig,
 }
 
 fn default_temperature() -> String { "0.7".to_string() }
 fn default_max_tokens() -> String { "4096".to_string() }
 fn default_schema_version() -> u32 { CONFIG_SCHEMA_VERSION }
+fn default_trash_retention_days() -> String { "30".to_string() }
+
+/// Endpoint used to embed messages for semantic search.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmbeddingConfig {
+    /// Off by default: indexing sends every message to the endpoint
+    #[serde(default)]
+    pub enabled: bool,
+    /// Ollama `/api/embed` or an OpenAI-compatible `/v1/embeddings` endpoint
+    pub api_url: String,
+    #[serde(default)]
+    pub api_key: String,
+    pub model: String,
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            api_url: "http://localhost:11434/api/embed".to_string(),
+            api_key: String::new(),
+            model: "nomic-embed-text".to_string(),
+        }
+    }
+}
+
+/// Scheduled snapshots of the database.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    pub enabled: bool,
+    /// Empty for `backup::default_dir()`
+    pub directory: String,
+    pub interval_hours: String,
+    /// Snapshots kept before the oldest are deleted
+    pub keep: String,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            directory: String::new(),
+            interval_hours: "24".to_string(),
+            keep: "7".to_string(),
+        }
+    }
+}
+
+impl BackupConfig {
+    pub fn dir(&self) -> std::path::PathBuf {
+        match self.directory.trim() {
+            "" => crate::backup::default_dir(),
+            dir => std::path::PathBuf::from(dir),
+        }
+    }
+
+    pub fn interval(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.interval_hours.trim().parse::<i64>().ok().filter(|h| *h > 0).unwrap_or(24))
+    }
+
+    pub fn keep_count(&self) -> usize {
+        self.keep.trim().parse::<usize>().ok().filter(|n| *n > 0).unwrap_or(7)
+    }
+}
+
+/// Folder shared with other machines (e.g. through Syncthing or Dropbox)
+/// that changesets are exchanged through.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Empty turns sync off
+    pub folder: String,
+}
+
+impl SyncConfig {
+    pub fn dir(&self) -> Option<std::path::PathBuf> {
+        match self.folder.trim() {
+            "" => None,
+            dir => Some(std::path::PathBuf::from(dir)),
+        }
+    }
+}
+
+/// Folder the library is exported to as Obsidian-style Markdown notes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VaultConfig {
+    /// Empty until a folder is picked
+    pub folder: String,
+    /// Only rewrite notes whose contents changed
+    pub incremental: bool,
+}
+
+impl Default for VaultConfig {
+    fn default() -> Self {
+        Self { folder: String::new(), incremental: true }
+    }
+}
+
+impl VaultConfig {
+    pub fn dir(&self) -> Option<std::path::PathBuf> {
+        match self.folder.trim() {
+            "" => None,
+            dir => Some(std::path::PathBuf::from(dir)),
+        }
+    }
+}
+
+/// Where the SQLCipher key for the database comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EncryptionKeySource {
+    /// Plain SQLite
+    #[default]
+    None,
+    /// A random key stored in the OS keychain
+    Keychain,
+    /// A passphrase asked for at startup and never stored
+    Passphrase,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Keybindings {
@@ -149,17 +289,37 @@ impl Default for AppConfig {
             keybindings: Keybindings::default(),
             debug_key_events: false,
             theme: ThemeName::default(),
+            embeddings: EmbeddingConfig::default(),
+            encryption: EncryptionKeySource::None,
+            backup: BackupConfig::default(),
+            trash_retention_days: default_trash_retention_days(),
+            credential_profile: String::new(),
+            sync: SyncConfig::default(),
+            jsonl_mapping: crate::import::JsonlMapping::default(),
+            vault: VaultConfig::default(),
         }
     }
 }
 
 impl AppConfig {
     fn config_path() -> std::path::PathBuf {
-        let dir = dirs::config_dir()
-            .unwrap_or_else(|| std::path::PathBuf::from("."))
-            .join("stoa");
-        std::fs::create_dir_all(&dir).ok();
-        dir.join("config.json")
+        crate::workspace::dir().join("config.json")
+    }
+
+    /// Keychain entry name for an API key in this config's credential profile.
+    fn credential_key(&self, key: &str) -> String {
+        match self.credential_profile.trim() {
+            "" => key.to_string(),
+            profile => format!("{profile}:{key}"),
+        }
+    }
+
+    /// Keychain entry name for the open workspace's database key.
+    fn database_key_name() -> String {
+        match crate::workspace::active().as_str() {
+            crate::workspace::DEFAULT_WORKSPACE => "database_key".to_string(),
+            workspace => format!("database_key:{workspace}"),
+        }
     }
 
     fn keychain_get(key: &str) -> Option<String> {
@@ -179,16 +339,63 @@ impl AppConfig {
         }
     }
 
+    /// The open workspace's database key kept in the keychain, if any.
+    pub fn database_key() -> Option<String> {
+        Self::keychain_get(&Self::database_key_name())
+    }
+
+    /// Store a database key in the keychain (or delete it when empty) and
+    /// confirm it can be read back, since losing it loses the database.
+    pub fn set_database_key(key: &str) -> Result<(), String> {
+        Self::keychain_set(&Self::database_key_name(), key);
+        match (Self::database_key(), key.is_empty()) {
+            (None, true) => Ok(()),
+            (Some(stored), false) if stored == key => Ok(()),
+            _ => Err("Could not store the database key in the system keychain".to_string()),
+        }
+    }
+
+    /// A new random database key: 244 bits from two v4 UUIDs.
+    pub fn generate_database_key() -> String {
+        format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+    }
+
     fn load_keys_from_keychain(&mut self) {
-        if let Some(k) = Self::keychain_get("openai_api_key") { self.openai.api_key = k; }
-        if let Some(k) = Self::keychain_get("anthropic_api_key") { self.anthropic.api_key = k; }
-        if let Some(k) = Self::keychain_get("openrouter_api_key") { self.openrouter.api_key = k; }
+        if let Some(k) = Self::keychain_get(&self.credential_key("openai_api_key")) { self.openai.api_key = k; }
+        if let Some(k) = Self::keychain_get(&self.credential_key("anthropic_api_key")) { self.anthropic.api_key = k; }
+        if let Some(k) = Self::keychain_get(&self.credential_key("openrouter_api_key")) { self.openrouter.api_key = k; }
+        if let Some(k) = Self::keychain_get(&self.credential_key("embeddings_api_key")) { self.embeddings.api_key = k; }
     }
 
     fn save_keys_to_keychain(&self) {
-        Self::keychain_set("openai_api_key", &self.openai.api_key);
-        Self::keychain_set("anthropic_api_key", &self.anthropic.api_key);
-        Self::keychain_set("openrouter_api_key", &self.openrouter.api_key);
+        Self::keychain_set(&self.credential_key("openai_api_key"), &self.openai.api_key);
+        Self::keychain_set(&self.credential_key("anthropic_api_key"), &self.anthropic.api_key);
+        Self::keychain_set(&self.credential_key("openrouter_api_key"), &self.openrouter.api_key);
+        Self::keychain_set(&self.credential_key("embeddings_api_key"), &self.embeddings.api_key);
+    }
+
+    /// Switch to another credential profile, replacing the API keys in the
+    /// form with that profile's keys.
+    pub fn set_credential_profile(&mut self, profile: String) {
+        self.credential_profile = profile;
+        self.openai.api_key.clear();
+        self.anthropic.api_key.clear();
+        self.openrouter.api_key.clear();
+        self.embeddings.api_key.clear();
+        self.load_keys_from_keychain();
+    }
+
+    /// Settings for a new workspace: a copy of this config with an empty
+    /// system prompt, no encryption, the default backup directory, and sync
+    /// and vault export off, so two libraries never share a folder.
+    pub fn for_new_workspace(&self) -> Self {
+        let mut config = self.clone();
+        config.system_prompt.clear();
+        config.encryption = EncryptionKeySource::None;
+        config.backup.directory.clear();
+        config.sync = SyncConfig::default();
+        config.vault = VaultConfig::default();
+        config
     }
 
     pub fn load() -> Self {
@@ -235,6 +442,7 @@ impl AppConfig {
         copy.openai.api_key.clear();
         copy.anthropic.api_key.clear();
         copy.openrouter.api_key.clear();
+        copy.embeddings.api_key.clear();
         if let Ok(json) = serde_json::to_string_pretty(&copy) {
             std::fs::write(path, json).ok();
         }
@@ -252,11 +460,48 @@ impl AppConfig {
         if self.schema_version < 3 {
             self.schema_version = 3;
         }
+        // v3 -> v4: embeddings settings added; serde default fills them.
+        if self.schema_version < 4 {
+            self.schema_version = 4;
+        }
+        // v4 -> v5: encryption key source added; serde default fills it.
+        if self.schema_version < 5 {
+            self.schema_version = 5;
+        }
+        // v5 -> v6: backup settings added; serde default fills them.
+        if self.schema_version < 6 {
+            self.schema_version = 6;
+        }
+        // v6 -> v7: trash retention added; serde default fills it.
+        if self.schema_version < 7 {
+            self.schema_version = 7;
+        }
+        // v7 -> v8: credential profile added; serde default fills it.
+        if self.schema_version < 8 {
+            self.schema_version = 8;
+        }
+        // v8 -> v9: sync folder added; serde default fills it.
+        if self.schema_version < 9 {
+            self.schema_version = 9;
+        }
+        // v9 -> v10: JSONL import mapping added; serde default fills it.
+        if self.schema_version < 10 {
+            self.schema_version = 10;
+        }
+        // v10 -> v11: vault export settings added; serde default fills them.
+        if self.schema_version < 11 {
+            self.schema_version = 11;
+        }
         if self.schema_version > CONFIG_SCHEMA_VERSION {
             self.schema_version = CONFIG_SCHEMA_VERSION;
         }
     }
 
+    /// How long trash entries are kept before they are purged.
+    pub fn trash_retention(&self) -> chrono::Duration {
+        chrono::Duration::days(self.trash_retention_days.trim().parse::<i64>().ok().filter(|d| *d > 0).unwrap_or(30))
+    }
+
     pub fn active_provider_config(&self) -> &ProviderConfig {
         match self.active_provider {
             Provider::OpenAI => &self.openai,
@@ -0,0 +1,219 @@
+use std::cmp::Reverse;
+use std::sync::LazyLock;
+
+use regex::{Captures, Regex};
+use serde_json::{json, Value};
+
+use crate::model::{group_messages, ChatMessage, Conversation, MessageGroup, Role};
+
+/// Which replies a fine-tuning set trains on, by rating.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RatingFilter {
+    #[default]
+    Any,
+    /// Everything except thumbs down
+    NotDown,
+    /// Only thumbs up
+    Up,
+}
+
+impl RatingFilter {
+    pub const ALL: [RatingFilter; 3] = [RatingFilter::Any, RatingFilter::NotDown, RatingFilter::Up];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RatingFilter::Any => "Any rating",
+            RatingFilter::NotDown => "Not thumbs-down",
+            RatingFilter::Up => "Thumbs-up only",
+        }
+    }
+
+    fn allows(&self, rating: i8) -> bool {
+        match self {
+            RatingFilter::Any => true,
+            RatingFilter::NotDown => rating >= 0,
+            RatingFilter::Up => rating > 0,
+        }
+    }
+}
+
+/// What goes into a training dataset. Conversations are chosen by the
+/// export scope (a tag, a folder, ticked ones); this narrows the replies.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatasetFilter {
+    /// Only replies from models whose name contains this; empty for all
+    pub model: String,
+    /// Fine-tuning only: preference pairs are built from ratings already
+    pub rating: RatingFilter,
+    /// Replace emails, phone numbers, card numbers, IP addresses and API
+    /// keys with placeholders
+    pub scrub_pii: bool,
+}
+
+impl DatasetFilter {
+    fn model_matches(&self, msg: &ChatMessage) -> bool {
+        let wanted = self.model.trim().to_lowercase();
+        wanted.is_empty() || msg.model.as_deref().is_some_and(|m| m.to_lowercase().contains(&wanted))
+    }
+
+    fn allows(&self, msg: &ChatMessage) -> bool {
+        self.model_matches(msg) && self.rating.allows(msg.rating)
+    }
+
+    fn text(&self, content: &str) -> String {
+        if self.scrub_pii { scrub_pii(content) } else { content.to_string() }
+    }
+}
+
+static SECRET: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(?:sk-[A-Za-z0-9_-]{16,}|ghp_[A-Za-z0-9]{20,}|xox[abp]-[A-Za-z0-9-]{10,}|AKIA[0-9A-Z]{16})\b").expect("valid regex")
+});
+static EMAIL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b[A-Z0-9._%+-]+@[A-Z0-9-]+(?:\.[A-Z0-9-]+)*\.[A-Z]{2,}\b").expect("valid regex")
+});
+static CARD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b(?:\d[ -]?){12,18}\d\b").expect("valid regex"));
+static IP: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(?:(?:25[0-5]|2[0-4]\d|1?\d?\d)\.){3}(?:25[0-5]|2[0-4]\d|1?\d?\d)\b").expect("valid regex")
+});
+static PHONE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:\+\d{1,3}[ .-]?)?(?:\(\d{2,4}\)[ .-]?|\b\d{2,4}[ .-]?)\d{3,4}[ .-]?\d{3,4}\b").expect("valid regex")
+});
+
+/// Whether a digit string passes the Luhn check card numbers carry.
+fn luhn(digits: &str) -> bool {
+    let sum: u32 = digits.chars().rev().filter_map(|c| c.to_digit(10)).enumerate()
+        .map(|(i, d)| if i % 2 == 1 { if d * 2 > 9 { d * 2 - 9 } else { d * 2 } } else { d })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Whether the digits at `start..end` run on into more digits, as in an
+/// order number rather than a phone number.
+fn within_longer_number(text: &str, start: usize, end: usize) -> bool {
+    let before: Vec<char> = text[..start].chars().rev().take(2).collect();
+    let after: Vec<char> = text[end..].chars().take(2).collect();
+    let continues = |near: &[char]| match near {
+        [d, ..] if d.is_ascii_digit() => true,
+        [' ' | '-' | '.', d] => d.is_ascii_digit(),
+        _ => false,
+    };
+    continues(&before) || continues(&after)
+}
+
+/// `text` with personal details and credentials replaced by `[EMAIL]`,
+/// `[PHONE]`, `[CARD]`, `[IP]` and `[SECRET]`.
+pub fn scrub_pii(text: &str) -> String {
+    let text = SECRET.replace_all(text, "[SECRET]");
+    let text = EMAIL.replace_all(&text, "[EMAIL]");
+    let text = CARD.replace_all(&text, |caps: &Captures| {
+        let digits: String = caps[0].chars().filter(char::is_ascii_digit).collect();
+        if luhn(&digits) { "[CARD]".to_string() } else { caps[0].to_string() }
+    });
+    let text = IP.replace_all(&text, "[IP]");
+    PHONE.replace_all(&text, |caps: &Captures| {
+        let found = caps.get(0).expect("whole match");
+        if within_longer_number(&text, found.start(), found.end()) { found.as_str().to_string() } else { "[PHONE]".to_string() }
+    }).into_owned()
+}
+
+/// Each prompt on the active path with the replies to it, leaving out
+/// failed and unfinished ones. Prompts nobody answered are dropped.
+fn turns(conv: &Conversation) -> Vec<(&ChatMessage, Vec<&ChatMessage>)> {
+    let mut turns: Vec<(&ChatMessage, Vec<&ChatMessage>)> = Vec::new();
+    for group in group_messages(&conv.messages) {
+        match group {
+            MessageGroup::User(i) => turns.push((&conv.messages[i], Vec::new())),
+            MessageGroup::Assistants(indices) => {
+                if let Some((_, replies)) = turns.last_mut() {
+                    replies.extend(indices.iter().map(|&i| &conv.messages[i]).filter(|m| !m.streaming && !m.is_error()));
+                }
+            }
+        }
+    }
+    turns.retain(|(prompt, replies)| !prompt.streaming && !replies.is_empty());
+    turns
+}
+
+/// The reply a multi-model turn continues with: the canonical one, else the
+/// best-rated one `filter` allows.
+fn pick<'a>(replies: &[&'a ChatMessage], filter: &DatasetFilter) -> &'a ChatMessage {
+    replies.iter().find(|m| m.canonical)
+        .or_else(|| replies.iter().min_by_key(|m| (Reverse(filter.allows(m)), Reverse(m.rating))))
+        .copied()
+        .expect("turns have replies")
+}
+
+fn chat_message(role: Role, content: String) -> Value {
+    let role = match role {
+        Role::User => "user",
+        Role::Assistant => "assistant",
+    };
+    json!({ "role": role, "content": content })
+}
+
+fn system_messages(conv: &Conversation, filter: &DatasetFilter) -> Vec<Value> {
+    if conv.system_prompt.trim().is_empty() {
+        return Vec::new();
+    }
+    vec![json!({ "role": "system", "content": filter.text(&conv.system_prompt) })]
+}
+
+fn jsonl(lines: Vec<Value>) -> String {
+    lines.iter().map(|line| format!("{line}\n")).collect()
+}
+
+/// OpenAI chat fine-tuning JSONL: one example per conversation. Replies the
+/// filter rejects stay in as context with `"weight": 0`, so the model isn't
+/// trained on them; conversations with nothing to train on are left out.
+pub fn fine_tuning(conversations: &[Conversation], filter: &DatasetFilter) -> String {
+    let mut examples = Vec::new();
+    for conv in conversations {
+        let mut messages = system_messages(conv, filter);
+        // Length of `messages` after the last reply trained on
+        let mut trained = None;
+        for (prompt, replies) in turns(conv) {
+            messages.push(chat_message(Role::User, filter.text(&prompt.content)));
+            let reply = pick(&replies, filter);
+            let mut message = chat_message(Role::Assistant, filter.text(&reply.content));
+            if filter.allows(reply) {
+                trained = Some(messages.len() + 1);
+            } else {
+                message["weight"] = json!(0);
+            }
+            messages.push(message);
+        }
+        if let Some(len) = trained {
+            messages.truncate(len);
+            examples.push(json!({ "messages": messages }));
+        }
+    }
+    jsonl(examples)
+}
+
+/// Preference pairs as JSONL lines of `prompt` (the conversation so far),
+/// `chosen` and `rejected`, from turns where one reply got a thumbs up and
+/// another a thumbs down. With a model filter, one of the two replies must
+/// match it.
+pub fn preference_pairs(conversations: &[Conversation], filter: &DatasetFilter) -> String {
+    let mut pairs = Vec::new();
+    for conv in conversations {
+        let mut history = system_messages(conv, filter);
+        for (prompt, replies) in turns(conv) {
+            history.push(chat_message(Role::User, filter.text(&prompt.content)));
+            for up in replies.iter().filter(|m| m.rating > 0) {
+                for down in replies.iter().filter(|m| m.rating < 0) {
+                    if !filter.model_matches(up) && !filter.model_matches(down) {
+                        continue;
+                    }
+                    pairs.push(json!({
+                        "prompt": history,
+                        "chosen": [chat_message(Role::Assistant, filter.text(&up.content))],
+                        "rejected": [chat_message(Role::Assistant, filter.text(&down.content))],
+                    }));
+                }
+            }
+            history.push(chat_message(Role::Assistant, filter.text(&pick(&replies, filter).content)));
+        }
+    }
+    jsonl(pairs)
+}
@@ -1,185 +1,889 @@
-use rusqlite::{Connection, params};
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::Value;
+use rusqlite::{Connection, params, params_from_iter};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-use crate::model::{ChatMessage, Conversation, Role};
+use crate::cost;
+use crate::semantic;
+use crate::search::{self, Filter, FilterTerm, SearchQuery, TextTerm};
+use crate::model::{new_message_id, parents_first, ChatMessage, Conversation, ConversationStats, DateRange, Role, ThreadMode};
 
 /// Open an in-memory database for testing.
 #[allow(dead_code)]
 pub fn open_in_memory() -> Connection {
     let conn = Connection::open_in_memory().expect("failed to open in-memory database");
-    init_schema(&conn);
+    configure(&conn);
+    migrate(&conn).expect("failed to migrate in-memory database");
     conn
 }
 
-fn init_schema(conn: &Connection) {
-    conn.execute_batch(
-        "PRAGMA foreign_keys = ON;
-
-         CREATE TABLE IF NOT EXISTS conversations (
-             id TEXT PRIMARY KEY,
-             title TEXT NOT NULL,
-             updated_at TEXT DEFAULT (datetime('now'))
-         );
-
-         CREATE TABLE IF NOT EXISTS messages (
-             id INTEGER PRIMARY KEY AUTOINCREMENT,
-             conversation_id TEXT NOT NULL,
-             role TEXT NOT NULL CHECK (role IN ('user', 'assistant')),
-             content TEXT NOT NULL,
-             FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
-         );"
-    ).expect("failed to create tables");
-
-    // Migrations (idempotent)
-    conn.execute("ALTER TABLE conversations ADD COLUMN updated_at TEXT", []).ok();
-    conn.execute("ALTER TABLE messages ADD COLUMN model TEXT", []).ok();
-    conn.execute("ALTER TABLE conversations ADD COLUMN tags TEXT DEFAULT ''", []).ok();
-    conn.execute("ALTER TABLE conversations ADD COLUMN pinned INTEGER DEFAULT 0", []).ok();
-    conn.execute("ALTER TABLE messages ADD COLUMN token_count INTEGER", []).ok();
-    conn.execute("ALTER TABLE conversations ADD COLUMN system_prompt TEXT DEFAULT ''", []).ok();
-    conn.execute("ALTER TABLE conversations ADD COLUMN forked_from TEXT", []).ok();
-    conn.execute("ALTER TABLE messages ADD COLUMN rating INTEGER DEFAULT 0", []).ok();
-    conn.execute("ALTER TABLE messages ADD COLUMN latency_ms INTEGER", []).ok();
-    conn.execute("ALTER TABLE conversations ADD COLUMN folder TEXT", []).ok();
-
-    // FTS5 search index
-    conn.execute_batch(
-        "CREATE VIRTUAL TABLE IF NOT EXISTS search_index USING fts5(
-            conversation_id UNINDEXED,
-            title,
-            content
-        );"
-    ).ok();
-}
-
-pub fn open() -> Connection {
-    let dir = dirs::config_dir()
-        .unwrap_or_else(|| std::path::PathBuf::from("."))
-        .join("stoa");
-    std::fs::create_dir_all(&dir).ok();
-    let path = dir.join("chat.db");
-    let conn = Connection::open(&path).expect("failed to open database");
+/// Path of the open workspace's database file, creating its directory if needed.
+pub fn default_path() -> PathBuf {
+    crate::workspace::dir().join("chat.db")
+}
 
+/// Open the open workspace's database with an optional SQLCipher key, upgrading its schema if needed.
+pub fn open(key: Option<&str>) -> Result<Connection, String> {
+    let conn = open_encrypted_at(&default_path(), key)?;
+    migrate_from_json(&conn);
+    Ok(conn)
+}
+
+/// Open the database at `path`, upgrading its schema if needed.
+pub fn open_at(path: &Path) -> Result<Connection, String> {
+    open_encrypted_at(path, None)
+}
+
+/// Open the database at `path` with a SQLCipher key (`None` for plain SQLite),
+/// upgrading its schema if needed.
+pub fn open_encrypted_at(path: &Path, key: Option<&str>) -> Result<Connection, String> {
+    let conn = Connection::open(path)
+        .map_err(|e| format!("Failed to open database {}: {e}", path.display()))?;
+    if let Some(key) = key {
+        // SQLCipher answers PRAGMA key with an "ok" row
+        conn.pragma_update_and_check(None, "key", key, |_| Ok(()))
+            .map_err(|e| format!("Failed to set database key: {e}"))?;
+    }
+    // SQLCipher only checks the key on first read
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+        .map_err(|e| match key {
+            Some(_) => format!("Wrong key for database {}: {e}", path.display()),
+            None if is_encrypted(path) => format!("Database {} is encrypted and needs its key", path.display()),
+            None => format!("Failed to read database {}: {e}", path.display()),
+        })?;
     conn.execute_batch("PRAGMA journal_mode = WAL;").ok();
-    init_schema(&conn);
-    conn.execute("UPDATE conversations SET updated_at = datetime('now') WHERE updated_at IS NULL", []).ok();
+    configure(&conn);
+    migrate(&conn)?;
+    Ok(conn)
+}
 
-    migrate_from_json(&conn);
-    backfill_search_index(&conn);
-    conn
+/// Whether the file at `path` is encrypted. Plain SQLite files start with a
+/// fixed header; SQLCipher files start with a random salt.
+pub fn is_encrypted(path: &Path) -> bool {
+    use std::io::Read;
+    let mut header = [0u8; 16];
+    std::fs::File::open(path).and_then(|mut f| f.read_exact(&mut header)).is_ok()
+        && &header != b"SQLite format 3\0"
 }
 
+/// Write a consistent copy of the open database to `dest`, encrypted with
+/// `key` (`None` for plain SQLite). An existing file at `dest` is replaced.
+pub fn export_to(conn: &Connection, dest: &Path, key: Option<&str>) -> Result<(), String> {
+    if dest.exists() {
+        std::fs::remove_file(dest).map_err(|e| format!("Failed to replace {}: {e}", dest.display()))?;
+    }
+    conn.execute("ATTACH DATABASE ?1 AS export KEY ?2", params![dest.to_string_lossy(), key.unwrap_or("")])
+        .map_err(|e| format!("Failed to create {}: {e}", dest.display()))?;
+    // sqlcipher_export copies schema and data but not user_version
+    let result = conn.query_row("SELECT sqlcipher_export('export')", [], |_| Ok(()))
+        .and_then(|_| conn.execute_batch(&format!("PRAGMA export.user_version = {};", schema_version(conn))))
+        .map_err(|e| format!("Failed to export database: {e}"));
+    conn.execute_batch("DETACH DATABASE export;").ok();
+    if result.is_err() {
+        std::fs::remove_file(dest).ok();
+    }
+    result
+}
+
+/// Copy the open database to `dest` page by page with SQLite's online backup
+/// API. The copy is keyed like the original, since SQLCipher can only back up
+/// between databases sharing a key. An existing file at `dest` is replaced.
+pub fn backup_to(conn: &Connection, dest: &Path, key: Option<&str>) -> Result<(), String> {
+    if dest.exists() {
+        std::fs::remove_file(dest).map_err(|e| format!("Failed to replace {}: {e}", dest.display()))?;
+    }
+    let result = (|| {
+        let mut target = Connection::open(dest)?;
+        if let Some(key) = key {
+            target.pragma_update_and_check(None, "key", key, |_| Ok(()))?;
+        }
+        let backup = rusqlite::backup::Backup::new(conn, &mut target)?;
+        backup.run_to_completion(256, std::time::Duration::ZERO, None)
+    })()
+    .map_err(|e| format!("Failed to back up database to {}: {e}", dest.display()));
+    if result.is_err() {
+        std::fs::remove_file(dest).ok();
+    }
+    result
+}
+
+/// Open a backup read-only, without migrating it.
+pub fn open_snapshot(path: &Path, key: Option<&str>) -> Result<Connection, String> {
+    let conn = Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+    if let Some(key) = key {
+        conn.pragma_update_and_check(None, "key", key, |_| Ok(()))
+            .map_err(|e| format!("Failed to set database key: {e}"))?;
+    }
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+        .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    configure(&conn);
+    Ok(conn)
+}
+
+/// Problems reported by `PRAGMA integrity_check`; empty when the database is sound.
+pub fn integrity_check(conn: &Connection) -> Result<Vec<String>, String> {
+    let rows: Vec<String> = conn.prepare("PRAGMA integrity_check")
+        .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect())
+        .map_err(|e| format!("Integrity check failed to run: {e}"))?;
+    Ok(rows.into_iter().filter(|r| r != "ok").collect())
+}
+
+/// Rewrite the database file at `path` from `old_key` to `new_key`, either of
+/// which may be `None` for plain SQLite. The file must not be open elsewhere.
+pub fn rekey_file(path: &Path, old_key: Option<&str>, new_key: Option<&str>) -> Result<(), String> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".rekey");
+    let tmp = PathBuf::from(tmp);
+    {
+        let conn = open_encrypted_at(path, old_key)?;
+        export_to(&conn, &tmp, new_key)?;
+        conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);").ok();
+    }
+    replace_file(path, &tmp)
+}
+
+/// Move `replacement` over the database file at `path`, which must not be open.
+pub fn replace_file(path: &Path, replacement: &Path) -> Result<(), String> {
+    // Stale WAL pages from the old file must not be replayed into the new one
+    for suffix in ["-wal", "-shm"] {
+        let mut side = path.as_os_str().to_owned();
+        side.push(suffix);
+        std::fs::remove_file(PathBuf::from(side)).ok();
+    }
+    std::fs::rename(replacement, path).map_err(|e| {
+        std::fs::remove_file(replacement).ok();
+        format!("Failed to replace {}: {e}", path.display())
+    })
+}
+
+fn configure(conn: &Connection) {
+    conn.execute_batch("PRAGMA foreign_keys = ON;").ok();
+    // Snapshots and sync run on connections of their own; wait for their
+    // locks rather than failing
+    conn.busy_timeout(std::time::Duration::from_secs(5)).ok();
+    // message_cost(model, role, tokens), for cost filters in search queries
+    conn.create_scalar_function("message_cost", 3, FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC, |ctx| {
+        let model: Option<String> = ctx.get(0)?;
+        let role: String = ctx.get(1)?;
+        let tokens: i64 = ctx.get(2)?;
+        let role = if role == "user" { Role::User } else { Role::Assistant };
+        Ok(cost::message_cost(model.as_deref().unwrap_or(""), &role, tokens as u32))
+    }).ok();
+}
+
+/// A schema change. Migrations run in order, each in its own transaction,
+/// and `PRAGMA user_version` records how many have been applied.
+struct Migration {
+    description: &'static str,
+    apply: fn(&Connection) -> rusqlite::Result<()>,
+}
+
+/// Never edit or reorder an entry once released; append a new one instead.
+/// The early entries use `add_column` because databases from before versioned
+/// migrations report version 0 but may already have any of those columns.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        description: "create conversations and messages",
+        apply: |conn| conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS conversations (
+                 id TEXT PRIMARY KEY,
+                 title TEXT NOT NULL,
+                 updated_at TEXT DEFAULT (datetime('now'))
+             );
+             CREATE TABLE IF NOT EXISTS messages (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 conversation_id TEXT NOT NULL,
+                 role TEXT NOT NULL CHECK (role IN ('user', 'assistant')),
+                 content TEXT NOT NULL,
+                 FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+             );"
+        ),
+    },
+    Migration {
+        description: "add conversation tags, pins, prompts, forks and folders",
+        apply: |conn| {
+            add_column(conn, "conversations", "updated_at", "TEXT")?;
+            add_column(conn, "conversations", "tags", "TEXT DEFAULT ''")?;
+            add_column(conn, "conversations", "pinned", "INTEGER DEFAULT 0")?;
+            add_column(conn, "conversations", "system_prompt", "TEXT DEFAULT ''")?;
+            add_column(conn, "conversations", "forked_from", "TEXT")?;
+            add_column(conn, "conversations", "folder", "TEXT")?;
+            conn.execute("UPDATE conversations SET updated_at = datetime('now') WHERE updated_at IS NULL", [])?;
+            Ok(())
+        },
+    },
+    Migration {
+        description: "add message model, tokens, rating and latency",
+        apply: |conn| {
+            add_column(conn, "messages", "model", "TEXT")?;
+            add_column(conn, "messages", "token_count", "INTEGER")?;
+            add_column(conn, "messages", "rating", "INTEGER DEFAULT 0")?;
+            add_column(conn, "messages", "latency_ms", "INTEGER")
+        },
+    },
+    Migration {
+        description: "add stable message IDs and branches",
+        apply: |conn| {
+            add_column(conn, "messages", "uuid", "TEXT")?;
+            add_column(conn, "messages", "parent_id", "TEXT")?;
+            add_column(conn, "messages", "active", "INTEGER DEFAULT 1")?;
+            add_column(conn, "messages", "canonical", "INTEGER DEFAULT 0")?;
+            backfill_message_ids(conn)?;
+            conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS idx_messages_uuid ON messages(uuid)", [])?;
+            Ok(())
+        },
+    },
+    Migration {
+        description: "add thread mode",
+        apply: |conn| add_column(conn, "conversations", "thread_mode", "TEXT DEFAULT 'shared'"),
+    },
+    Migration {
+        description: "add conversation and message timestamps",
+        apply: |conn| {
+            add_column(conn, "conversations", "created_at", "TEXT")?;
+            add_column(conn, "messages", "created_at", "TEXT")?;
+            add_column(conn, "messages", "completed_at", "TEXT")?;
+            conn.execute("UPDATE conversations SET created_at = COALESCE(updated_at, datetime('now')) WHERE created_at IS NULL", [])?;
+            Ok(())
+        },
+    },
+    Migration {
+        description: "create full-text search index",
+        apply: |conn| {
+            conn.execute_batch(
+                "CREATE VIRTUAL TABLE IF NOT EXISTS search_index USING fts5(
+                    conversation_id UNINDEXED,
+                    title,
+                    content
+                );"
+            )?;
+            backfill_search_index(conn)
+        },
+    },
+    Migration {
+        description: "index messages by conversation",
+        apply: |conn| conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_messages_conversation ON messages(conversation_id, uuid);"
+        ),
+    },
+    Migration {
+        description: "index messages and titles individually for search",
+        apply: |conn| conn.execute_batch(
+            "DROP TABLE IF EXISTS search_index;
+
+             CREATE VIRTUAL TABLE message_index USING fts5(
+                 role, model, content,
+                 content = 'messages', content_rowid = 'id'
+             );
+             CREATE TRIGGER message_index_insert AFTER INSERT ON messages BEGIN
+                 INSERT INTO message_index (rowid, role, model, content)
+                 VALUES (new.id, new.role, new.model, new.content);
+             END;
+             CREATE TRIGGER message_index_delete AFTER DELETE ON messages BEGIN
+                 INSERT INTO message_index (message_index, rowid, role, model, content)
+                 VALUES ('delete', old.id, old.role, old.model, old.content);
+             END;
+             CREATE TRIGGER message_index_update AFTER UPDATE OF role, model, content ON messages BEGIN
+                 INSERT INTO message_index (message_index, rowid, role, model, content)
+                 VALUES ('delete', old.id, old.role, old.model, old.content);
+                 INSERT INTO message_index (rowid, role, model, content)
+                 VALUES (new.id, new.role, new.model, new.content);
+             END;
+             INSERT INTO message_index (message_index) VALUES ('rebuild');
+
+             CREATE VIRTUAL TABLE title_index USING fts5(conversation_id UNINDEXED, title);
+             CREATE TRIGGER title_index_insert AFTER INSERT ON conversations BEGIN
+                 INSERT INTO title_index (conversation_id, title) VALUES (new.id, new.title);
+             END;
+             CREATE TRIGGER title_index_delete AFTER DELETE ON conversations BEGIN
+                 DELETE FROM title_index WHERE conversation_id = old.id;
+             END;
+             CREATE TRIGGER title_index_update AFTER UPDATE OF title ON conversations BEGIN
+                 UPDATE title_index SET title = new.title WHERE conversation_id = old.id;
+             END;
+             INSERT INTO title_index (conversation_id, title) SELECT id, title FROM conversations;"
+        ),
+    },
+    Migration {
+        description: "store message embeddings",
+        apply: |conn| conn.execute_batch(
+            "CREATE TABLE embeddings (
+                 message_id INTEGER NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+                 chunk INTEGER NOT NULL,
+                 model TEXT NOT NULL,
+                 vector BLOB NOT NULL,
+                 PRIMARY KEY (message_id, model, chunk)
+             );
+             CREATE INDEX idx_embeddings_model ON embeddings(model);
+             CREATE TRIGGER embeddings_stale AFTER UPDATE OF content ON messages
+             WHEN old.content IS NOT new.content BEGIN
+                 DELETE FROM embeddings WHERE message_id = new.id;
+             END;"
+        ),
+    },
+    Migration {
+        description: "keep deleted conversations and messages in a trash",
+        apply: |conn| conn.execute_batch(
+            "CREATE TABLE trash (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 kind TEXT NOT NULL CHECK (kind IN ('conversation', 'message')),
+                 conversation_id TEXT NOT NULL,
+                 label TEXT NOT NULL,
+                 payload TEXT NOT NULL,
+                 deleted_at TEXT NOT NULL
+             );
+             CREATE INDEX idx_trash_deleted_at ON trash(deleted_at);"
+        ),
+    },
+    Migration {
+        description: "record change times and deletions for sync",
+        apply: |conn| conn.execute_batch(
+            "CREATE TABLE sync_clock (
+                 entity TEXT NOT NULL CHECK (entity IN ('conversation', 'message')),
+                 id TEXT NOT NULL,
+                 field TEXT NOT NULL,
+                 changed_at TEXT NOT NULL,
+                 PRIMARY KEY (entity, id, field)
+             ) WITHOUT ROWID;
+             CREATE INDEX idx_sync_clock_changed_at ON sync_clock(changed_at);
+             CREATE TABLE sync_deleted (
+                 entity TEXT NOT NULL,
+                 id TEXT NOT NULL,
+                 deleted_at TEXT NOT NULL,
+                 PRIMARY KEY (entity, id)
+             ) WITHOUT ROWID;
+             CREATE TABLE sync_state (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+             CREATE TABLE sync_applied (file TEXT PRIMARY KEY, applied_at TEXT NOT NULL);
+
+             -- Upserts rather than INSERT OR REPLACE: a statement's own conflict policy
+             -- overrides the one in the triggers it fires
+             CREATE TRIGGER conversation_clock_insert AFTER INSERT ON conversations BEGIN
+                 INSERT INTO sync_clock (entity, id, field, changed_at)
+                 SELECT 'conversation', new.id, field, strftime('%Y-%m-%d %H:%M:%f+00:00', 'now') FROM (
+                     SELECT 'title' AS field UNION ALL SELECT 'tags' UNION ALL SELECT 'pinned'
+                     UNION ALL SELECT 'system_prompt' UNION ALL SELECT 'folder' UNION ALL SELECT 'thread_mode'
+                 ) WHERE true
+                 ON CONFLICT (entity, id, field) DO UPDATE SET changed_at = excluded.changed_at;
+                 DELETE FROM sync_deleted WHERE entity = 'conversation' AND id = new.id;
+             END;
+             CREATE TRIGGER conversation_clock_update AFTER UPDATE ON conversations BEGIN
+                 INSERT INTO sync_clock (entity, id, field, changed_at)
+                 SELECT 'conversation', new.id, field, strftime('%Y-%m-%d %H:%M:%f+00:00', 'now') FROM (
+                     SELECT 'title' AS field WHERE old.title IS NOT new.title
+                     UNION ALL SELECT 'tags' WHERE old.tags IS NOT new.tags
+                     UNION ALL SELECT 'pinned' WHERE old.pinned IS NOT new.pinned
+                     UNION ALL SELECT 'system_prompt' WHERE old.system_prompt IS NOT new.system_prompt
+                     UNION ALL SELECT 'folder' WHERE old.folder IS NOT new.folder
+                     UNION ALL SELECT 'thread_mode' WHERE old.thread_mode IS NOT new.thread_mode
+                 ) WHERE true
+                 ON CONFLICT (entity, id, field) DO UPDATE SET changed_at = excluded.changed_at;
+             END;
+             CREATE TRIGGER conversation_clock_delete AFTER DELETE ON conversations BEGIN
+                 DELETE FROM sync_clock WHERE entity = 'conversation' AND id = old.id;
+                 INSERT INTO sync_deleted (entity, id, deleted_at)
+                 VALUES ('conversation', old.id, strftime('%Y-%m-%d %H:%M:%f+00:00', 'now'))
+                 ON CONFLICT (entity, id) DO UPDATE SET deleted_at = excluded.deleted_at;
+             END;
+
+             CREATE TRIGGER message_clock_insert AFTER INSERT ON messages BEGIN
+                 INSERT INTO sync_clock (entity, id, field, changed_at)
+                 SELECT 'message', new.uuid, field, strftime('%Y-%m-%d %H:%M:%f+00:00', 'now') FROM (
+                     SELECT 'content' AS field UNION ALL SELECT 'rating' UNION ALL SELECT 'canonical'
+                 ) WHERE true
+                 ON CONFLICT (entity, id, field) DO UPDATE SET changed_at = excluded.changed_at;
+                 DELETE FROM sync_deleted WHERE entity = 'message' AND id = new.uuid;
+             END;
+             CREATE TRIGGER message_clock_update AFTER UPDATE ON messages BEGIN
+                 INSERT INTO sync_clock (entity, id, field, changed_at)
+                 SELECT 'message', new.uuid, field, strftime('%Y-%m-%d %H:%M:%f+00:00', 'now') FROM (
+                     SELECT 'content' AS field WHERE old.content IS NOT new.content
+                     UNION ALL SELECT 'rating' WHERE old.rating IS NOT new.rating
+                     UNION ALL SELECT 'canonical' WHERE old.canonical IS NOT new.canonical
+                 ) WHERE true
+                 ON CONFLICT (entity, id, field) DO UPDATE SET changed_at = excluded.changed_at;
+             END;
+             -- Messages removed along with their conversation need no tombstone of their own
+             CREATE TRIGGER message_clock_delete AFTER DELETE ON messages BEGIN
+                 DELETE FROM sync_clock WHERE entity = 'message' AND id = old.uuid;
+                 INSERT INTO sync_deleted (entity, id, deleted_at)
+                 SELECT 'message', old.uuid, strftime('%Y-%m-%d %H:%M:%f+00:00', 'now')
+                 WHERE EXISTS (SELECT 1 FROM conversations WHERE id = old.conversation_id)
+                 ON CONFLICT (entity, id) DO UPDATE SET deleted_at = excluded.deleted_at;
+             END;"
+        ),
+    },
+    Migration {
+        description: "store message images",
+        apply: |conn| add_column(conn, "messages", "images", "TEXT"),
+    },
+];
+
+/// The schema version this build writes.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+pub fn schema_version(conn: &Connection) -> u32 {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap_or(0)
+}
+
+/// Bring the schema up to `SCHEMA_VERSION`, backing up an existing database
+/// file first. A failed migration is rolled back, leaving the database at the
+/// last version that applied cleanly.
+fn migrate(conn: &Connection) -> Result<(), String> {
+    let current = schema_version(conn);
+    if current > SCHEMA_VERSION {
+        return Err(format!(
+            "The database uses schema version {current}, but this build of Stoa only supports up to {SCHEMA_VERSION}. Please update Stoa."
+        ));
+    }
+    if current == SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    let backup = backup_before_migration(conn, current)?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = i as u32 + 1;
+        let result = conn.unchecked_transaction().and_then(|tx| {
+            (migration.apply)(&tx)?;
+            tx.pragma_update(None, "user_version", version)?;
+            tx.commit()
+        });
+        if let Err(e) = result {
+            let mut msg = format!(
+                "Database migration {version} ({}) failed: {e}. The database was left at schema version {}.",
+                migration.description, version - 1
+            );
+            if let Some(path) = &backup {
+                msg.push_str(&format!(" A backup from before the upgrade is at {}.", path.display()));
+            }
+            return Err(msg);
+        }
+        log::info!("applied database migration {version}: {}", migration.description);
+    }
+    Ok(())
+}
+
+/// Copy an existing on-disk database aside before upgrading it. Returns the
+/// backup path, or `None` for new and in-memory databases.
+fn backup_before_migration(conn: &Connection, version: u32) -> Result<Option<PathBuf>, String> {
+    let Some(path) = conn.path().filter(|p| !p.is_empty()) else { return Ok(None) };
+    let has_tables: bool = conn
+        .query_row("SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table'", [], |row| row.get(0))
+        .map_err(|e| format!("Failed to read database schema: {e}"))?;
+    if !has_tables {
+        return Ok(None);
+    }
+    let stamp = Utc::now().format("%Y%m%d-%H%M%S");
+    let backup = PathBuf::from(format!("{path}.v{version}-{stamp}.bak"));
+    conn.execute("VACUUM INTO ?1", params![backup.to_string_lossy()])
+        .map_err(|e| format!("Failed to back up the database before upgrading it (nothing was changed): {e}"))?;
+    log::info!("backed up database to {}", backup.display());
+    Ok(Some(backup))
+}
+
+/// Add a column unless it already exists.
+fn add_column(conn: &Connection, table: &str, column: &str, decl: &str) -> rusqlite::Result<()> {
+    let exists: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+        params![table, column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"), [])?;
+    }
+    Ok(())
+}
+
+/// Load every conversation with its messages. Prefer `load_summaries` plus
+/// `load_messages` for large libraries.
 pub fn load_all(conn: &Connection) -> Vec<Conversation> {
+    let mut conversations = load_summaries(conn);
+    for conv in &mut conversations {
+        if let Err(e) = load_messages(conn, conv) {
+            log::error!("{e}");
+        }
+    }
+    conversations
+}
+
+/// Load every conversation's settings and totals, without its messages.
+/// The returned conversations are unloaded; see `load_messages`.
+pub fn load_summaries(conn: &Connection) -> Vec<Conversation> {
     let mut stmt = conn
-        .prepare("SELECT id, title, COALESCE(tags, ''), COALESCE(pinned, 0), COALESCE(system_prompt, ''), forked_from, folder FROM conversations ORDER BY pinned DESC, updated_at DESC, rowid DESC")
+        .prepare("SELECT id, title, COALESCE(tags, ''), COALESCE(pinned, 0), COALESCE(system_prompt, ''), forked_from, folder, COALESCE(thread_mode, 'shared'), created_at FROM conversations ORDER BY pinned DESC, updated_at DESC, rowid DESC")
         .expect("failed to prepare query");
 
-    let conv_rows: Vec<(String, String, String, i32, String, Option<String>, Option<String>)> = stmt
-        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?)))
+    #[allow(clippy::type_complexity)]
+    let conv_rows: Vec<(String, String, String, i32, String, Option<String>, Option<String>, String, Option<DateTime<Utc>>)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?, row.get(8)?)))
         .expect("failed to query conversations")
-        .filter_map(|r| r.ok())
+        .filter_map(|r| r.inspect_err(|e| log::error!("skipped unreadable conversation: {e}")).ok())
         .collect();
 
-    let mut msg_stmt = conn
-        .prepare("SELECT role, content, model, token_count, COALESCE(rating, 0), latency_ms FROM messages WHERE conversation_id = ?1 ORDER BY id")
-        .expect("failed to prepare message query");
-
+    let mut stats = conversation_stats(conn);
     conv_rows
         .into_iter()
-        .map(|(id, title, tags_str, pinned, system_prompt, forked_from, folder)| {
-            let messages: Vec<ChatMessage> = msg_stmt
-                .query_map(params![id], |row| {
-                    let role_str: String = row.get(0)?;
-                    let content: String = row.get(1)?;
-                    let model: Option<String> = row.get(2)?;
-                    let token_count: Option<u32> = row.get(3)?;
-                    let rating: i8 = row.get::<_, i32>(4)? as i8;
-                    let latency_ms: Option<u64> = row.get(5)?;
-                    Ok(ChatMessage {
-                        role: if role_str == "user" { Role::User } else { Role::Assistant },
-                        content,
-                        streaming: false,
-                        model,
-                        token_count,
-                        rating,
-                        latency_ms,
-                        images: Vec::new(),
-                    })
-                })
-                .expect("failed to query messages")
-                .filter_map(|r| r.ok())
-                .collect();
-
+        .map(|(id, title, tags_str, pinned, system_prompt, forked_from, folder, thread_mode, created_at)| {
             let tags = if tags_str.is_empty() {
                 Vec::new()
             } else {
                 tags_str.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
             };
+            let unloaded = Some(stats.remove(&id).unwrap_or_default());
 
-            Conversation { id, title, messages, tags, pinned: pinned != 0, system_prompt, forked_from, folder }
+            Conversation {
+                id, title, messages: Vec::new(), tags, pinned: pinned != 0, system_prompt, forked_from, folder,
+                alternates: Vec::new(),
+                thread_mode: ThreadMode::parse(&thread_mode),
+                created_at: created_at.unwrap_or_else(Utc::now),
+                unloaded,
+            }
         })
         .collect()
 }
 
-pub fn save_conversation(conn: &Connection, conv: &Conversation) -> Result<(), String> {
+/// Message count, cost and last message time for each conversation's active path.
+fn conversation_stats(conn: &Connection) -> HashMap<String, ConversationStats> {
+    // Token estimate matches `cost::estimate_tokens` (bytes / 4, rounded up)
+    let mut stmt = conn
+        .prepare(
+            "SELECT conversation_id, role, COALESCE(model, ''), COUNT(*),
+                    SUM(COALESCE(token_count, (length(CAST(content AS BLOB)) + 3) / 4)),
+                    MAX(COALESCE(completed_at, created_at))
+             FROM messages WHERE COALESCE(active, 1) = 1
+             GROUP BY conversation_id, role, model"
+        )
+        .expect("failed to prepare stats query");
+    let rows = stmt
+        .query_map([], |row| {
+            let role: String = row.get(1)?;
+            Ok((row.get::<_, String>(0)?, role, row.get::<_, String>(2)?, row.get::<_, i64>(3)?, row.get::<_, i64>(4)?, row.get::<_, Option<DateTime<Utc>>>(5)?))
+        })
+        .expect("failed to query stats");
+
+    let mut stats: HashMap<String, ConversationStats> = HashMap::new();
+    for (conv_id, role, model, count, tokens, last) in rows.filter_map(|r| r.inspect_err(|e| log::error!("skipped unreadable message totals: {e}")).ok()) {
+        let role = if role == "user" { Role::User } else { Role::Assistant };
+        let entry = stats.entry(conv_id).or_default();
+        entry.message_count += count as usize;
+        entry.cost += cost::message_cost(&model, &role, tokens as u32);
+        entry.last_message_at = entry.last_message_at.max(last);
+    }
+    stats
+}
+
+/// Columns read by `message_from_row`, in order.
+const MESSAGE_COLUMNS: &str = "role, content, model, token_count, COALESCE(rating, 0), latency_ms, uuid, parent_id, COALESCE(active, 1), COALESCE(canonical, 0), created_at, completed_at, images";
+
+/// A message and whether it is on the active path, from `MESSAGE_COLUMNS`
+/// starting at column `at`.
+fn message_from_row(row: &rusqlite::Row, at: usize) -> rusqlite::Result<(ChatMessage, bool)> {
+    let role_str: String = row.get(at)?;
+    let active: i32 = row.get(at + 8)?;
+    let canonical: i32 = row.get(at + 9)?;
+    let images: Option<String> = row.get(at + 12)?;
+    Ok((ChatMessage {
+        id: row.get(at + 6)?,
+        parent_id: row.get(at + 7)?,
+        role: if role_str == "user" { Role::User } else { Role::Assistant },
+        content: row.get(at + 1)?,
+        streaming: false,
+        model: row.get(at + 2)?,
+        token_count: row.get(at + 3)?,
+        rating: row.get::<_, i32>(at + 4)? as i8,
+        latency_ms: row.get(at + 5)?,
+        images: images.and_then(|json| serde_json::from_str(&json).ok()).unwrap_or_default(),
+        canonical: canonical != 0,
+        created_at: row.get(at + 10)?,
+        completed_at: row.get(at + 11)?,
+    }, active != 0))
+}
+
+/// Load the messages (active path and alternates) of an unloaded conversation.
+pub fn load_messages(conn: &Connection, conv: &mut Conversation) -> Result<(), String> {
+    if conv.is_loaded() {
+        return Ok(());
+    }
+    let mut stmt = conn
+        .prepare_cached(&format!("SELECT {MESSAGE_COLUMNS} FROM messages WHERE conversation_id = ?1 ORDER BY uuid"))
+        .map_err(|e| format!("Failed to load messages: {e}"))?;
+    let rows: Vec<(ChatMessage, bool)> = stmt
+        .query_map(params![conv.id], |row| message_from_row(row, 0))
+        .and_then(|rows| rows.collect())
+        .map_err(|e| format!("Failed to load messages: {e}"))?;
+
+    let mut active = Vec::new();
+    for (msg, is_active) in rows {
+        if is_active { active.push(msg) } else { conv.alternates.push(msg) }
+    }
+    conv.messages = parents_first(active, |m| m);
+    conv.unloaded = None;
+    Ok(())
+}
+
+/// Run `f` inside an immediate transaction, rolling back on error.
+pub(crate) fn in_transaction(conn: &Connection, f: impl FnOnce() -> Result<(), String>) -> Result<(), String> {
     conn.execute_batch("BEGIN IMMEDIATE").map_err(|e| format!("Failed to begin transaction: {e}"))?;
+    match f() {
+        Ok(()) => conn.execute_batch("COMMIT").map_err(|e| format!("Failed to commit: {e}")),
+        Err(e) => {
+            let _ = conn.execute_batch("ROLLBACK");
+            Err(e)
+        }
+    }
+}
 
-    let result = (|| -> Result<(), String> {
-        conn.execute(
-            "INSERT OR REPLACE INTO conversations (id, title, tags, pinned, system_prompt, forked_from, folder, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, datetime('now'))",
-            params![conv.id, conv.title, conv.tags.join(","), conv.pinned as i32, conv.system_prompt, conv.forked_from, conv.folder],
-        ).map_err(|e| format!("Failed to save conversation: {e}"))?;
+/// Insert or update the conversation row only (title, tags, settings), leaving messages untouched.
+pub fn upsert_conversation(conn: &Connection, conv: &Conversation) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO conversations (id, title, tags, pinned, system_prompt, forked_from, folder, thread_mode, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, datetime('now'))
+         ON CONFLICT(id) DO UPDATE SET
+             title = excluded.title, tags = excluded.tags, pinned = excluded.pinned,
+             system_prompt = excluded.system_prompt, forked_from = excluded.forked_from,
+             folder = excluded.folder, thread_mode = excluded.thread_mode, updated_at = excluded.updated_at",
+        params![conv.id, conv.title, conv.tags.join(","), conv.pinned as i32, conv.system_prompt, conv.forked_from, conv.folder, conv.thread_mode.as_str(), conv.created_at],
+    ).map_err(|e| format!("Failed to save conversation: {e}"))?;
+    Ok(())
+}
 
-        conn.execute(
-            "DELETE FROM messages WHERE conversation_id = ?1",
-            params![conv.id],
-        ).map_err(|e| format!("Failed to clear messages: {e}"))?;
+/// Insert a message, or update it in place if its ID is already stored.
+/// Unchanged rows are not rewritten.
+pub fn upsert_message(conn: &Connection, conv_id: &str, msg: &ChatMessage, active: bool) -> Result<(), String> {
+    let role_str = match msg.role {
+        Role::User => "user",
+        Role::Assistant => "assistant",
+    };
+    // Images are stored as a JSON array of base64 strings, NULL when there are none
+    let images = (!msg.images.is_empty()).then(|| serde_json::to_string(&msg.images).unwrap_or_default());
+    conn.prepare_cached(
+        "INSERT INTO messages (conversation_id, role, content, model, token_count, rating, latency_ms, uuid, parent_id, active, canonical, created_at, completed_at, images)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+         ON CONFLICT(uuid) DO UPDATE SET
+             content = excluded.content, model = excluded.model, token_count = excluded.token_count,
+             rating = excluded.rating, latency_ms = excluded.latency_ms, parent_id = excluded.parent_id,
+             active = excluded.active, canonical = excluded.canonical, completed_at = excluded.completed_at,
+             images = excluded.images
+         WHERE content IS NOT excluded.content OR model IS NOT excluded.model
+             OR token_count IS NOT excluded.token_count OR rating IS NOT excluded.rating
+             OR latency_ms IS NOT excluded.latency_ms OR parent_id IS NOT excluded.parent_id
+             OR active IS NOT excluded.active OR canonical IS NOT excluded.canonical
+             OR completed_at IS NOT excluded.completed_at OR images IS NOT excluded.images"
+    )
+    .and_then(|mut stmt| stmt.execute(params![conv_id, role_str, msg.content, msg.model, msg.token_count, msg.rating as i32, msg.latency_ms, msg.id, msg.parent_id, active as i32, msg.canonical as i32, msg.created_at, msg.completed_at, images]))
+    .map_err(|e| format!("Failed to save message: {e}"))?;
+    Ok(())
+}
 
-        let mut stmt = conn
-            .prepare("INSERT INTO messages (conversation_id, role, content, model, token_count, rating, latency_ms) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")
-            .map_err(|e| format!("Failed to prepare insert: {e}"))?;
+/// Persist one message on the active path (e.g. a new prompt or a finished reply)
+/// together with the conversation row, without touching the rest of the thread.
+pub fn save_message(conn: &Connection, conv: &Conversation, index: usize) -> Result<(), String> {
+    let Some(msg) = conv.messages.get(index) else { return Ok(()) };
+    if msg.streaming {
+        return Ok(());
+    }
+    in_transaction(conn, || {
+        upsert_conversation(conn, conv)?;
+        upsert_message(conn, &conv.id, msg, true)
+    })
+}
 
-        for msg in &conv.messages {
+/// Sync a whole conversation: upsert every message and remove rows that are no
+/// longer part of it. Use for structural changes (new, forked, imported, branch switches).
+pub fn save_conversation(conn: &Connection, conv: &Conversation) -> Result<(), String> {
+    // An unloaded conversation has no messages in memory; syncing it would delete them all
+    if !conv.is_loaded() {
+        return upsert_conversation(conn, conv);
+    }
+    in_transaction(conn, || {
+        upsert_conversation(conn, conv)?;
+
+        let active = conv.messages.iter().map(|m| (m, true));
+        let inactive = conv.alternates.iter().map(|m| (m, false));
+        let mut kept = std::collections::HashSet::new();
+        for (msg, is_active) in active.chain(inactive) {
             if msg.streaming {
                 continue;
             }
-            let role_str = match msg.role {
-                Role::User => "user",
-                Role::Assistant => "assistant",
-            };
-            stmt.execute(params![conv.id, role_str, msg.content, msg.model, msg.token_count, msg.rating as i32, msg.latency_ms])
-                .map_err(|e| format!("Failed to insert message: {e}"))?;
+            upsert_message(conn, &conv.id, msg, is_active)?;
+            kept.insert(msg.id.as_str());
         }
-        Ok(())
-    })();
 
-    match result {
-        Ok(()) => {
-            conn.execute_batch("COMMIT").map_err(|e| format!("Failed to commit: {e}"))?;
-            rebuild_search_index(conn, conv);
-            Ok(())
+        let mut stmt = conn.prepare("SELECT uuid FROM messages WHERE conversation_id = ?1")
+            .map_err(|e| format!("Failed to prepare message query: {e}"))?;
+        let stored: Vec<String> = stmt.query_map(params![conv.id], |row| row.get(0))
+            .map_err(|e| format!("Failed to query messages: {e}"))?
+            .filter_map(|r| r.ok())
+            .collect();
+        for id in stored.iter().filter(|id| !kept.contains(id.as_str())) {
+            conn.execute("DELETE FROM messages WHERE uuid = ?1", params![id])
+                .map_err(|e| format!("Failed to remove message: {e}"))?;
         }
-        Err(e) => {
-            let _ = conn.execute_batch("ROLLBACK");
-            Err(e)
+        Ok(())
+    })
+}
+
+pub fn update_rating(conn: &Connection, msg_id: &str, rating: i8) -> Result<(), String> {
+    conn.execute("UPDATE messages SET rating = ?1 WHERE uuid = ?2", params![rating as i32, msg_id])
+        .map_err(|e| format!("Failed to update rating: {e}"))?;
+    Ok(())
+}
+
+/// Delete a single message, re-attaching its replies to its parent
+/// (mirrors `Conversation::remove_message`).
+pub fn delete_message(conn: &Connection, msg_id: &str) -> Result<(), String> {
+    in_transaction(conn, || {
+        conn.execute(
+            "UPDATE messages SET parent_id = (SELECT parent_id FROM messages WHERE uuid = ?1) WHERE parent_id = ?1",
+            params![msg_id],
+        ).map_err(|e| format!("Failed to re-attach replies: {e}"))?;
+        conn.execute("DELETE FROM messages WHERE uuid = ?1", params![msg_id])
+            .map_err(|e| format!("Failed to delete message: {e}"))?;
+        Ok(())
+    })
+}
+
+/// What a trash entry holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrashKind {
+    Conversation,
+    Message,
+}
+
+/// A deleted conversation or message, restorable until it is purged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrashItem {
+    pub id: i64,
+    pub kind: TrashKind,
+    pub conversation_id: String,
+    /// Conversation title, or the start of the message
+    pub label: String,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// A trashed message and the replies that were re-attached to its parent.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TrashedMessage {
+    message: ChatMessage,
+    active: bool,
+    replies: Vec<String>,
+}
+
+/// Move a conversation to the trash. Its rows are deleted like before, so
+/// search, analytics and exports stop seeing it, but a full copy is kept in
+/// `trash` until it is restored or purged. `conv` must have its messages loaded.
+pub fn trash_conversation(conn: &Connection, conv: &Conversation) -> Result<i64, String> {
+    let payload = serde_json::to_string(conv).map_err(|e| format!("Failed to serialize conversation: {e}"))?;
+    let mut trash_id = 0;
+    in_transaction(conn, || {
+        conn.execute(
+            "INSERT INTO trash (kind, conversation_id, label, payload, deleted_at) VALUES ('conversation', ?1, ?2, ?3, ?4)",
+            params![conv.id, conv.title, payload, Utc::now()],
+        ).map_err(|e| format!("Failed to move conversation to trash: {e}"))?;
+        trash_id = conn.last_insert_rowid();
+        delete_conversation(conn, &conv.id)
+    })?;
+    Ok(trash_id)
+}
+
+/// Move a message to the trash, re-attaching its replies to its parent
+/// (mirrors `Conversation::remove_message`).
+pub fn trash_message(conn: &Connection, conv_id: &str, msg: &ChatMessage, active: bool) -> Result<i64, String> {
+    let mut trash_id = 0;
+    in_transaction(conn, || {
+        let replies: Vec<String> = conn.prepare("SELECT uuid FROM messages WHERE parent_id = ?1")
+            .and_then(|mut stmt| stmt.query_map(params![msg.id], |row| row.get(0))?.collect())
+            .map_err(|e| format!("Failed to find replies: {e}"))?;
+        let payload = serde_json::to_string(&TrashedMessage { message: msg.clone(), active, replies })
+            .map_err(|e| format!("Failed to serialize message: {e}"))?;
+        let label: String = msg.content.chars().take(80).collect();
+        conn.execute(
+            "INSERT INTO trash (kind, conversation_id, label, payload, deleted_at) VALUES ('message', ?1, ?2, ?3, ?4)",
+            params![conv_id, label, payload, Utc::now()],
+        ).map_err(|e| format!("Failed to move message to trash: {e}"))?;
+        trash_id = conn.last_insert_rowid();
+        conn.execute(
+            "UPDATE messages SET parent_id = ?2 WHERE parent_id = ?1",
+            params![msg.id, msg.parent_id],
+        ).map_err(|e| format!("Failed to re-attach replies: {e}"))?;
+        conn.execute("DELETE FROM messages WHERE uuid = ?1", params![msg.id])
+            .map_err(|e| format!("Failed to delete message: {e}"))?;
+        Ok(())
+    })?;
+    Ok(trash_id)
+}
+
+/// Trash entries, most recently deleted first.
+pub fn list_trash(conn: &Connection) -> Vec<TrashItem> {
+    conn.prepare("SELECT id, kind, conversation_id, label, deleted_at FROM trash ORDER BY deleted_at DESC, id DESC")
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| {
+                let kind: String = row.get(1)?;
+                Ok(TrashItem {
+                    id: row.get(0)?,
+                    kind: if kind == "message" { TrashKind::Message } else { TrashKind::Conversation },
+                    conversation_id: row.get(2)?,
+                    label: row.get(3)?,
+                    deleted_at: row.get(4)?,
+                })
+            })?
+            .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Put a trash entry back, returning the ID of the conversation it belongs to.
+/// A message can only return to a conversation that still exists.
+pub fn restore_from_trash(conn: &Connection, trash_id: i64) -> Result<String, String> {
+    let (kind, conv_id, payload): (String, String, String) = conn
+        .query_row("SELECT kind, conversation_id, payload FROM trash WHERE id = ?1", params![trash_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .map_err(|e| format!("Trash entry not found: {e}"))?;
+    if kind == "conversation" {
+        let conv: Conversation = serde_json::from_str(&payload).map_err(|e| format!("Trash entry is unreadable: {e}"))?;
+        save_conversation(conn, &conv)?;
+    } else {
+        let trashed: TrashedMessage = serde_json::from_str(&payload).map_err(|e| format!("Trash entry is unreadable: {e}"))?;
+        let exists: bool = conn
+            .query_row("SELECT COUNT(*) > 0 FROM conversations WHERE id = ?1", params![conv_id], |row| row.get(0))
+            .map_err(|e| format!("Failed to find conversation: {e}"))?;
+        if !exists {
+            return Err("This message's conversation was deleted; restore the conversation first.".to_string());
         }
+        in_transaction(conn, || {
+            upsert_message(conn, &conv_id, &trashed.message, trashed.active)?;
+            for reply in &trashed.replies {
+                conn.execute("UPDATE messages SET parent_id = ?1 WHERE uuid = ?2", params![trashed.message.id, reply])
+                    .map_err(|e| format!("Failed to re-attach replies: {e}"))?;
+            }
+            Ok(())
+        })?;
     }
+    conn.execute("DELETE FROM trash WHERE id = ?1", params![trash_id])
+        .map_err(|e| format!("Failed to update trash: {e}"))?;
+    Ok(conv_id)
 }
 
-pub fn update_rating(conn: &Connection, conv_id: &str, msg_index: usize, rating: i8) -> Result<(), String> {
-    let mut stmt = conn.prepare(
-        "SELECT id FROM messages WHERE conversation_id = ?1 ORDER BY id LIMIT 1 OFFSET ?2"
-    ).map_err(|e| format!("Failed to prepare rating query: {e}"))?;
-    let msg_id = stmt.query_row(params![conv_id, msg_index], |row| row.get::<_, i64>(0))
-        .map_err(|e| format!("Failed to find message for rating: {e}"))?;
-    conn.execute("UPDATE messages SET rating = ?1 WHERE id = ?2", params![rating as i32, msg_id])
-        .map_err(|e| format!("Failed to update rating: {e}"))?;
+/// Permanently delete one trash entry.
+pub fn delete_from_trash(conn: &Connection, trash_id: i64) -> Result<(), String> {
+    conn.execute("DELETE FROM trash WHERE id = ?1", params![trash_id])
+        .map_err(|e| format!("Failed to delete from trash: {e}"))?;
     Ok(())
 }
 
+/// Permanently delete trash entries deleted before `cutoff` (all of them for
+/// `None`), returning how many were removed.
+pub fn purge_trash(conn: &Connection, cutoff: Option<DateTime<Utc>>) -> Result<usize, String> {
+    conn.execute("DELETE FROM trash WHERE ?1 IS NULL OR deleted_at < ?1", params![cutoff])
+        .map_err(|e| format!("Failed to empty trash: {e}"))
+}
+
 pub fn delete_conversation(conn: &Connection, id: &str) -> Result<(), String> {
     conn.execute("DELETE FROM conversations WHERE id = ?1", params![id])
         .map_err(|e| format!("Failed to delete conversation: {e}"))?;
-    conn.execute("DELETE FROM search_index WHERE conversation_id = ?1", params![id]).ok();
     Ok(())
 }
 
@@ -207,88 +911,595 @@ pub fn set_tags(conn: &Connection, id: &str, tags: &[String]) -> Result<(), Stri
     Ok(())
 }
 
-fn rebuild_search_index(conn: &Connection, conv: &Conversation) {
-    conn.execute("DELETE FROM search_index WHERE conversation_id = ?1", params![conv.id]).ok();
-    let content: String = conv.messages.iter()
-        .filter(|m| !m.streaming)
-        .map(|m| m.content.as_str())
-        .collect::<Vec<_>>()
-        .join(" ");
-    conn.execute(
-        "INSERT INTO search_index (conversation_id, title, content) VALUES (?1, ?2, ?3)",
-        params![conv.id, conv.title, content],
-    ).ok();
+/// SQL parameters collected while compiling a search query.
+#[derive(Default)]
+struct Binder {
+    params: Vec<Value>,
 }
 
-pub fn search_conversations(conn: &Connection, query: &str) -> Vec<String> {
-    // Try FTS5 first, fall back to LIKE for non-FTS queries
-    let fts_query = query.split_whitespace()
-        .map(|w| format!("\"{}\"", w.replace('"', "")))
-        .collect::<Vec<_>>()
-        .join(" ");
-
-    if !fts_query.is_empty() {
-        let mut stmt = conn.prepare(
-            "SELECT DISTINCT s.conversation_id FROM search_index s
-             JOIN conversations c ON c.id = s.conversation_id
-             WHERE search_index MATCH ?1
-             ORDER BY c.pinned DESC, c.updated_at DESC"
-        ).ok();
-
-        if let Some(ref mut stmt) = stmt {
-            let results: Vec<String> = stmt.query_map(params![fts_query], |row| row.get(0))
-                .ok()
-                .map(|rows| rows.filter_map(|r| r.ok()).collect())
-                .unwrap_or_default();
-            if !results.is_empty() {
-                return results;
+impl Binder {
+    fn bind(&mut self, value: impl Into<Value>) -> String {
+        self.params.push(value.into());
+        format!("?{}", self.params.len())
+    }
+}
+
+/// How free text is matched: the FTS5 indexes, or a substring scan used as a
+/// fallback for fragments the tokenizer can't match (e.g. the middle of a word).
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TextMatch {
+    Fts,
+    Like,
+}
+
+/// Where free text is searched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TextField {
+    /// `m.content`, through `message_index`
+    Message,
+    /// `c.title`, through `title_index`
+    Title,
+}
+
+fn fts_term(term: &TextTerm) -> String {
+    let text = term.text.replace('"', "");
+    if term.phrase { format!("\"{text}\"") } else { format!("\"{text}\"*") }
+}
+
+fn like_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+fn like_pattern(text: &str) -> String {
+    format!("%{}%", like_escape(text))
+}
+
+/// A condition that holds when `field` matches all (or any) of `terms`.
+fn text_condition(field: TextField, terms: &[&TextTerm], all: bool, mode: TextMatch, b: &mut Binder) -> String {
+    match mode {
+        TextMatch::Fts => {
+            let expr = terms.iter().map(|t| fts_term(t)).collect::<Vec<_>>().join(if all { " " } else { " OR " });
+            let p = b.bind(expr);
+            match field {
+                TextField::Message => format!("m.id IN (SELECT rowid FROM message_index WHERE message_index MATCH {p})"),
+                TextField::Title => format!("c.id IN (SELECT conversation_id FROM title_index WHERE title_index MATCH {p})"),
             }
         }
+        TextMatch::Like => {
+            let column = match field { TextField::Message => "m.content", TextField::Title => "c.title" };
+            let conds: Vec<String> = terms.iter()
+                .map(|t| format!("{column} LIKE {} ESCAPE '\\'", b.bind(like_pattern(&t.text))))
+                .collect();
+            format!("({})", conds.join(if all { " AND " } else { " OR " }))
+        }
     }
+}
+
+/// A condition on message `m` for a message-level filter.
+fn message_filter_condition(filter: &Filter, b: &mut Binder) -> String {
+    let day_start = |day: Option<NaiveDate>| DateRange { from: day, to: None }.utc_bounds().0;
+    let instant = |t: Option<DateTime<Utc>>| t.map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string());
+    match filter {
+        Filter::Model(model) => format!("COALESCE(m.model, '') LIKE {} ESCAPE '\\'", b.bind(like_pattern(model))),
+        Filter::Rating(r) if *r > 0 => "COALESCE(m.rating, 0) > 0".to_string(),
+        Filter::Rating(_) => "COALESCE(m.rating, 0) < 0".to_string(),
+        Filter::Role(Role::User) => "m.role = 'user'".to_string(),
+        Filter::Role(Role::Assistant) => "m.role = 'assistant'".to_string(),
+        Filter::Before(day) => match instant(day_start(Some(*day))) {
+            Some(t) => format!("m.created_at < {}", b.bind(t)),
+            None => "1".to_string(),
+        },
+        Filter::After(day) => match instant(day_start(Some(*day))) {
+            Some(t) => format!("m.created_at >= {}", b.bind(t)),
+            None => "1".to_string(),
+        },
+        _ => "1".to_string(),
+    }
+}
 
-    // Fallback to LIKE
-    let pattern = format!("%{query}%");
-    let mut stmt = conn.prepare(
-        "SELECT DISTINCT c.id FROM conversations c
-         LEFT JOIN messages m ON m.conversation_id = c.id
-         WHERE c.title LIKE ?1 OR m.content LIKE ?1
-         ORDER BY c.pinned DESC, c.updated_at DESC"
-    ).expect("search query failed");
+/// A condition on conversation `c` for a conversation-level filter.
+fn conversation_filter_condition(filter: &Filter, b: &mut Binder) -> String {
+    match filter {
+        Filter::Tag(tag) => format!(
+            "(',' || LOWER(COALESCE(c.tags, '')) || ',') LIKE {} ESCAPE '\\'",
+            b.bind(like_pattern(&format!(",{tag},")))
+        ),
+        Filter::Folder(folder) => {
+            let (exact, nested) = (b.bind(folder.clone()), b.bind(format!("{}/%", like_escape(folder))));
+            format!("(LOWER(COALESCE(c.folder, '')) = {exact} OR LOWER(COALESCE(c.folder, '')) LIKE {nested} ESCAPE '\\')")
+        }
+        // Token estimate matches `cost::estimate_tokens` (bytes / 4, rounded up)
+        Filter::Cost(cmp, usd) => format!(
+            "(SELECT COALESCE(SUM(message_cost(cm.model, cm.role,
+                 COALESCE(cm.token_count, (length(CAST(cm.content AS BLOB)) + 3) / 4))), 0)
+              FROM messages cm WHERE cm.conversation_id = c.id AND COALESCE(cm.active, 1) = 1) {} {}",
+            cmp.sql(), b.bind(*usd)
+        ),
+        Filter::Pinned => "COALESCE(c.pinned, 0) = 1".to_string(),
+        Filter::Fork => "c.forked_from IS NOT NULL".to_string(),
+        _ => "1".to_string(),
+    }
+}
 
-    stmt.query_map(params![pattern], |row| row.get(0))
-        .expect("search failed")
-        .filter_map(|r| r.ok())
+fn negate(cond: String, negated: bool) -> String {
+    if negated { format!("NOT ({cond})") } else { cond }
+}
+
+fn and_all(parts: Vec<String>) -> String {
+    if parts.is_empty() { "1".to_string() } else { parts.join(" AND ") }
+}
+
+/// Conditions on conversation `c` from the conversation-level filters only.
+fn conversation_filters(query: &SearchQuery, b: &mut Binder) -> Vec<String> {
+    query.filters.iter()
+        .filter(|f| !f.filter.on_message())
+        .map(|f| negate(conversation_filter_condition(&f.filter, b), f.negated))
         .collect()
 }
 
-fn backfill_search_index(conn: &Connection) {
-    let count: i64 = conn
-        .query_row("SELECT COUNT(*) FROM search_index", [], |row| row.get(0))
-        .unwrap_or(0);
-    if count > 0 { return; }
+/// A condition on conversation `c`. Text matches the title or any active
+/// message; message-level filters require a message satisfying all of them
+/// (with the text), and a negated one excludes conversations with any such message.
+fn conversation_condition(query: &SearchQuery, message_filters: bool, mode: TextMatch, b: &mut Binder) -> String {
+    let mut parts = conversation_filters(query, b);
+    let active = "m.conversation_id = c.id AND COALESCE(m.active, 1) = 1";
+    let (positive, negated) = (query.text_terms(false), query.text_terms(true));
+    let on_message: Vec<&FilterTerm> = query.filters.iter().filter(|f| message_filters && f.filter.on_message()).collect();
+    let required: Vec<&Filter> = on_message.iter().filter(|f| !f.negated).map(|f| &f.filter).collect();
 
-    let conv_count: i64 = conn
-        .query_row("SELECT COUNT(*) FROM conversations", [], |row| row.get(0))
-        .unwrap_or(0);
-    if conv_count == 0 { return; }
+    if !positive.is_empty() || !required.is_empty() {
+        let mut conds = vec![active.to_string()];
+        conds.extend(required.iter().map(|f| message_filter_condition(f, b)));
+        if !positive.is_empty() {
+            conds.push(text_condition(TextField::Message, &positive, true, mode, b));
+        }
+        let exists = format!("EXISTS (SELECT 1 FROM messages m WHERE {})", conds.join(" AND "));
+        if required.is_empty() {
+            parts.push(format!("({} OR {exists})", text_condition(TextField::Title, &positive, true, mode, b)));
+        } else {
+            parts.push(exists);
+        }
+    }
+    for f in on_message.iter().filter(|f| f.negated) {
+        parts.push(format!("NOT EXISTS (SELECT 1 FROM messages m WHERE {active} AND {})", message_filter_condition(&f.filter, b)));
+    }
+    if !negated.is_empty() {
+        parts.push(format!("NOT {}", text_condition(TextField::Title, &negated, false, mode, b)));
+        parts.push(format!(
+            "NOT EXISTS (SELECT 1 FROM messages m WHERE {active} AND {})",
+            text_condition(TextField::Message, &negated, false, mode, b)
+        ));
+    }
+    and_all(parts)
+}
+
+/// A condition on message `m`: active, passing the message-level filters and
+/// excluding negated text, in a conversation passing `conversation`.
+fn message_condition(query: &SearchQuery, conversation: Option<String>, b: &mut Binder) -> String {
+    let mut parts = vec!["COALESCE(m.active, 1) = 1".to_string()];
+    for f in query.filters.iter().filter(|f| f.filter.on_message()) {
+        parts.push(negate(message_filter_condition(&f.filter, b), f.negated));
+    }
+    let negated = query.text_terms(true);
+    if !negated.is_empty() {
+        parts.push(format!("NOT {}", text_condition(TextField::Message, &negated, false, TextMatch::Fts, b)));
+    }
+    if let Some(cond) = conversation.filter(|c| c != "1") {
+        parts.push(format!("m.conversation_id IN (SELECT c.id FROM conversations c WHERE {cond})"));
+    }
+    and_all(parts)
+}
+
+/// A message matching a search, with its matched terms marked by
+/// `search::MATCH_START` / `search::MATCH_END`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub conversation_id: String,
+    pub message_id: String,
+    pub role: Role,
+    pub model: Option<String>,
+    /// Excerpt around the best match
+    pub snippet: String,
+    /// Distinct words that matched anywhere in the message
+    pub terms: Vec<String>,
+}
+
+/// Search the active path of every conversation, best match first. Takes the
+/// `search::SearchQuery` syntax; only queries with some text produce hits.
+pub fn search_messages(conn: &Connection, query: &str, limit: usize) -> Vec<SearchHit> {
+    let query = SearchQuery::parse(query);
+    let positive = query.text_terms(false);
+    if positive.is_empty() {
+        return Vec::new();
+    }
+    let mut b = Binder::default();
+    let expr = b.bind(positive.iter().map(|t| fts_term(t)).collect::<Vec<_>>().join(" "));
+    let conversation = and_all(conversation_filters(&query, &mut b));
+    let filter = message_condition(&query, Some(conversation), &mut b);
+    let limit = b.bind(limit as i64);
+    let sql = format!(
+        "SELECT m.conversation_id, m.uuid, m.role, m.model,
+                snippet(message_index, 2, char(2), char(3), '…', 12),
+                highlight(message_index, 2, char(2), char(3))
+         FROM message_index
+         JOIN messages m ON m.id = message_index.rowid
+         WHERE message_index MATCH {expr} AND {filter}
+         ORDER BY bm25(message_index, 0.5, 1.0, 4.0)
+         LIMIT {limit}"
+    );
+    let mut stmt = match conn.prepare(&sql) {
+        Ok(stmt) => stmt,
+        Err(e) => {
+            log::warn!("message search failed: {e}");
+            return Vec::new();
+        }
+    };
+    stmt.query_map(params_from_iter(b.params), |row| {
+        let role: String = row.get(2)?;
+        let highlighted: String = row.get(5)?;
+        Ok(SearchHit {
+            conversation_id: row.get(0)?,
+            message_id: row.get(1)?,
+            role: if role == "user" { Role::User } else { Role::Assistant },
+            model: row.get(3)?,
+            snippet: row.get(4)?,
+            terms: search::marked_terms(&highlighted),
+        })
+    })
+    .map(|rows| rows.filter_map(|r| r.ok()).collect())
+    .unwrap_or_default()
+}
+
+/// IDs of conversations matching a `search::SearchQuery`, pinned first, then
+/// most recently updated.
+pub fn search_conversations(conn: &Connection, query: &str) -> Vec<String> {
+    let query = SearchQuery::parse(query);
+    let run = |mode: TextMatch| -> rusqlite::Result<Vec<String>> {
+        let mut b = Binder::default();
+        let cond = conversation_condition(&query, true, mode, &mut b);
+        let mut stmt = conn.prepare(&format!(
+            "SELECT c.id FROM conversations c WHERE {cond} ORDER BY c.pinned DESC, c.updated_at DESC"
+        ))?;
+        stmt.query_map(params_from_iter(b.params), |row| row.get(0))?.collect()
+    };
+
+    // Try FTS5 first, fall back to substring matching for text it can't find
+    let results = run(TextMatch::Fts).unwrap_or_else(|e| {
+        log::warn!("conversation search failed: {e}");
+        Vec::new()
+    });
+    if !results.is_empty() || query.text.is_empty() {
+        return results;
+    }
+    run(TextMatch::Like).unwrap_or_default()
+}
+
+/// Active messages not yet embedded with `model`, oldest first, as
+/// `(conversation_id, message_id, content)`.
+pub fn pending_embeddings(conn: &Connection, model: &str, limit: usize) -> Vec<(String, String, String)> {
+    conn.prepare_cached(
+        "SELECT m.conversation_id, m.uuid, m.content FROM messages m
+         WHERE COALESCE(m.active, 1) = 1 AND trim(m.content) != ''
+           AND NOT EXISTS (SELECT 1 FROM embeddings e WHERE e.message_id = m.id AND e.model = ?1)
+         ORDER BY m.id LIMIT ?2"
+    )
+    .and_then(|mut stmt| {
+        stmt.query_map(params![model, limit as i64], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Store the chunk vectors of one message, replacing any it had for `model`.
+pub fn save_embeddings(conn: &Connection, model: &str, message_id: &str, vectors: &[Vec<f32>]) -> Result<(), String> {
+    in_transaction(conn, || {
+        let row_id: i64 = conn
+            .query_row("SELECT id FROM messages WHERE uuid = ?1", params![message_id], |row| row.get(0))
+            .map_err(|e| format!("Failed to find message for embedding: {e}"))?;
+        conn.execute("DELETE FROM embeddings WHERE message_id = ?1 AND model = ?2", params![row_id, model])
+            .map_err(|e| format!("Failed to replace embeddings: {e}"))?;
+        for (chunk, vector) in vectors.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO embeddings (message_id, chunk, model, vector) VALUES (?1, ?2, ?3, ?4)",
+                params![row_id, chunk as i64, model, semantic::to_blob(vector)],
+            ).map_err(|e| format!("Failed to save embedding: {e}"))?;
+        }
+        Ok(())
+    })
+}
+
+/// Every stored chunk vector for `model` on an active message.
+pub fn load_embeddings(conn: &Connection, model: &str) -> Vec<semantic::Entry> {
+    conn.prepare(
+        "SELECT m.conversation_id, m.uuid, e.vector FROM embeddings e
+         JOIN messages m ON m.id = e.message_id
+         WHERE e.model = ?1 AND COALESCE(m.active, 1) = 1
+         ORDER BY e.message_id, e.chunk"
+    )
+    .and_then(|mut stmt| {
+        stmt.query_map(params![model], |row| {
+            let blob: Vec<u8> = row.get(2)?;
+            Ok(semantic::Entry { conversation_id: row.get(0)?, message_id: row.get(1)?, vector: semantic::from_blob(&blob) })
+        })?
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Remove vectors made by any model other than `model`.
+pub fn prune_embeddings(conn: &Connection, model: &str) -> Result<(), String> {
+    conn.execute("DELETE FROM embeddings WHERE model != ?1", params![model])
+        .map_err(|e| format!("Failed to prune embeddings: {e}"))?;
+    Ok(())
+}
+
+/// What a sync clock or tombstone belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncEntity {
+    Conversation,
+    Message,
+}
+
+impl SyncEntity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SyncEntity::Conversation => "conversation",
+            SyncEntity::Message => "message",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        if s == "message" { SyncEntity::Message } else { SyncEntity::Conversation }
+    }
+}
+
+/// When each tracked field of a conversation or message last changed. Fields
+/// are stamped by triggers; rows from before sync have no stamps.
+pub fn sync_clocks(conn: &Connection, entity: SyncEntity, id: &str) -> HashMap<String, DateTime<Utc>> {
+    conn.prepare_cached("SELECT field, changed_at FROM sync_clock WHERE entity = ?1 AND id = ?2")
+        .and_then(|mut stmt| stmt.query_map(params![entity.as_str(), id], |row| Ok((row.get(0)?, row.get(1)?)))?.collect())
+        .unwrap_or_default()
+}
+
+/// Overwrite the change times of an entity's fields, e.g. with the times
+/// carried by a merged change instead of the time it was applied.
+pub fn set_sync_clocks(conn: &Connection, entity: SyncEntity, id: &str, clocks: &HashMap<String, DateTime<Utc>>) -> Result<(), String> {
+    let mut stmt = conn.prepare_cached("INSERT OR REPLACE INTO sync_clock (entity, id, field, changed_at) VALUES (?1, ?2, ?3, ?4)")
+        .map_err(|e| format!("Failed to record change time: {e}"))?;
+    for (field, at) in clocks {
+        stmt.execute(params![entity.as_str(), id, field, at])
+            .map_err(|e| format!("Failed to record change time: {e}"))?;
+    }
+    Ok(())
+}
+
+/// IDs of conversations with a field changed after `since` (all of them for `None`).
+pub fn conversations_changed_since(conn: &Connection, since: Option<DateTime<Utc>>) -> Vec<String> {
+    conn.prepare(
+        "SELECT id FROM conversations WHERE ?1 IS NULL
+             OR id IN (SELECT id FROM sync_clock WHERE entity = 'conversation' AND changed_at > ?1)"
+    )
+    .and_then(|mut stmt| stmt.query_map(params![since], |row| row.get(0))?.collect())
+    .unwrap_or_default()
+}
+
+/// `(conversation_id, message)` for messages with a field changed after
+/// `since` (all of them for `None`), oldest first.
+pub fn messages_changed_since(conn: &Connection, since: Option<DateTime<Utc>>) -> Vec<(String, ChatMessage)> {
+    conn.prepare(&format!(
+        "SELECT conversation_id, {MESSAGE_COLUMNS} FROM messages WHERE ?1 IS NULL
+             OR uuid IN (SELECT id FROM sync_clock WHERE entity = 'message' AND changed_at > ?1)
+         ORDER BY uuid"
+    ))
+    .and_then(|mut stmt| stmt.query_map(params![since], |row| Ok((row.get(0)?, message_from_row(row, 1)?.0)))?.collect())
+    .unwrap_or_default()
+}
+
+/// A stored message by ID: its conversation, the message, and whether it is active.
+pub fn load_message(conn: &Connection, id: &str) -> Option<(String, ChatMessage, bool)> {
+    conn.query_row(
+        &format!("SELECT conversation_id, {MESSAGE_COLUMNS} FROM messages WHERE uuid = ?1"),
+        params![id],
+        |row| {
+            let (msg, active) = message_from_row(row, 1)?;
+            Ok((row.get(0)?, msg, active))
+        },
+    ).ok()
+}
+
+/// Whether `parent` (the thread root for `None`) has a reply on the active path.
+pub fn has_active_reply(conn: &Connection, conv_id: &str, parent: Option<&str>) -> bool {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM messages WHERE conversation_id = ?1 AND parent_id IS ?2 AND COALESCE(active, 1) = 1)",
+        params![conv_id, parent],
+        |row| row.get(0),
+    ).unwrap_or(false)
+}
+
+/// Conversations and messages deleted after `since` (all for `None`).
+pub fn deletions_since(conn: &Connection, since: Option<DateTime<Utc>>) -> Vec<(SyncEntity, String, DateTime<Utc>)> {
+    conn.prepare("SELECT entity, id, deleted_at FROM sync_deleted WHERE ?1 IS NULL OR deleted_at > ?1 ORDER BY deleted_at")
+        .and_then(|mut stmt| {
+            stmt.query_map(params![since], |row| {
+                let entity: String = row.get(0)?;
+                Ok((SyncEntity::parse(&entity), row.get(1)?, row.get(2)?))
+            })?
+            .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// When an entity was deleted here, if it was.
+pub fn deleted_at(conn: &Connection, entity: SyncEntity, id: &str) -> Option<DateTime<Utc>> {
+    conn.query_row(
+        "SELECT deleted_at FROM sync_deleted WHERE entity = ?1 AND id = ?2",
+        params![entity.as_str(), id],
+        |row| row.get(0),
+    ).ok()
+}
+
+pub fn set_deleted_at(conn: &Connection, entity: SyncEntity, id: &str, at: DateTime<Utc>) -> Result<(), String> {
+    conn.execute(
+        "INSERT OR REPLACE INTO sync_deleted (entity, id, deleted_at) VALUES (?1, ?2, ?3)",
+        params![entity.as_str(), id, at],
+    ).map_err(|e| format!("Failed to record deletion: {e}"))?;
+    Ok(())
+}
+
+pub fn sync_state(conn: &Connection, key: &str) -> Option<String> {
+    conn.query_row("SELECT value FROM sync_state WHERE key = ?1", params![key], |row| row.get(0)).ok()
+}
+
+pub fn set_sync_state(conn: &Connection, key: &str, value: &str) -> Result<(), String> {
+    conn.execute("INSERT OR REPLACE INTO sync_state (key, value) VALUES (?1, ?2)", params![key, value])
+        .map_err(|e| format!("Failed to save sync state: {e}"))?;
+    Ok(())
+}
+
+/// Whether the changeset file `file` was already merged (or written) here.
+pub fn sync_applied(conn: &Connection, file: &str) -> bool {
+    conn.query_row("SELECT EXISTS (SELECT 1 FROM sync_applied WHERE file = ?1)", params![file], |row| row.get(0))
+        .unwrap_or(false)
+}
+
+pub fn mark_sync_applied(conn: &Connection, file: &str) -> Result<(), String> {
+    conn.execute("INSERT OR IGNORE INTO sync_applied (file, applied_at) VALUES (?1, ?2)", params![file, Utc::now()])
+        .map_err(|e| format!("Failed to record merged changeset: {e}"))?;
+    Ok(())
+}
+
+/// Usage totals for one model's replies.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelUsage {
+    pub model: String,
+    pub messages: u32,
+    pub total_tokens: u32,
+    pub total_cost: f64,
+    pub thumbs_up: u32,
+    pub thumbs_down: u32,
+    pub total_latency_ms: u64,
+    pub latency_count: u32,
+}
+
+/// Library-wide totals for the analytics page.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AnalyticsSummary {
+    pub conversations: u32,
+    pub messages: u32,
+    pub total_cost: f64,
+    /// Most active model first
+    pub models: Vec<ModelUsage>,
+}
+
+/// Aggregate usage over the active path of every conversation, counting only
+/// messages created within `range` that pass the message-level filters of
+/// `query`, in conversations matching its text and conversation-level filters.
+/// A conversation counts if it has such a message, or (without message
+/// filters) was created in the range.
+pub fn analytics(conn: &Connection, range: &DateRange, query: &SearchQuery) -> AnalyticsSummary {
+    let (from, to) = range.utc_bounds();
+    let bound = |t: Option<DateTime<Utc>>| t.map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string());
+    let mut b = Binder::default();
+    let (from, to) = (b.bind(bound(from)), b.bind(bound(to)));
+    let in_range = |col: &str| format!("({from} IS NULL OR {col} >= {from}) AND ({to} IS NULL OR {col} < {to})");
+    let conversation = conversation_condition(query, false, TextMatch::Fts, &mut b);
+    let msg_filter = format!("{} AND {}", message_condition(query, Some(conversation.clone()), &mut b), in_range("m.created_at"));
+    let created_in_range = if query.filters.iter().any(|f| f.filter.on_message()) { "0".to_string() } else { in_range("c.created_at") };
+    let params = b.params;
+
+    let conversations: u32 = conn.query_row(
+        &format!(
+            "SELECT COUNT(*) FROM conversations c WHERE ({created_in_range} AND {conversation}) OR EXISTS (
+                 SELECT 1 FROM messages m WHERE m.conversation_id = c.id AND {msg_filter})"
+        ),
+        params_from_iter(&params),
+        |row| row.get(0),
+    ).unwrap_or(0);
+    let messages: u32 = conn.query_row(
+        &format!("SELECT COUNT(*) FROM messages m WHERE {msg_filter}"),
+        params_from_iter(&params),
+        |row| row.get(0),
+    ).unwrap_or(0);
+
+    let mut models: Vec<ModelUsage> = conn.prepare(&format!(
+        "SELECT m.model, COUNT(*),
+                SUM(COALESCE(m.token_count, (length(CAST(m.content AS BLOB)) + 3) / 4)),
+                SUM(m.rating > 0), SUM(m.rating < 0),
+                COALESCE(SUM(m.latency_ms), 0), COUNT(m.latency_ms)
+         FROM messages m
+         WHERE m.role = 'assistant' AND m.model IS NOT NULL AND {msg_filter}
+         GROUP BY m.model"
+    ))
+    .and_then(|mut stmt| {
+        stmt.query_map(params_from_iter(&params), |row| {
+            let model: String = row.get(0)?;
+            let total_tokens: u32 = row.get(2)?;
+            Ok(ModelUsage {
+                total_cost: cost::message_cost(&model, &Role::Assistant, total_tokens),
+                model,
+                messages: row.get(1)?,
+                total_tokens,
+                thumbs_up: row.get(3)?,
+                thumbs_down: row.get(4)?,
+                total_latency_ms: row.get(5)?,
+                latency_count: row.get(6)?,
+            })
+        })?.collect()
+    })
+    .unwrap_or_default();
+    models.sort_by(|a, b| b.messages.cmp(&a.messages).then_with(|| a.model.cmp(&b.model)));
+
+    AnalyticsSummary {
+        conversations,
+        messages,
+        total_cost: models.iter().map(|m| m.total_cost).sum(),
+        models,
+    }
+}
+
+/// Give messages saved before stable IDs a UUID, chaining each conversation's
+/// messages by parent in their original order.
+fn backfill_message_ids(conn: &Connection) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare("SELECT id, conversation_id FROM messages WHERE uuid IS NULL ORDER BY conversation_id, id")?;
+    let rows: Vec<(i64, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    if rows.is_empty() { return Ok(()); }
+
+    let mut parent: Option<(String, String)> = None; // (conversation_id, uuid)
+    for (row_id, conv_id) in &rows {
+        let uuid = new_message_id();
+        let parent_id = parent.as_ref().filter(|(c, _)| c == conv_id).map(|(_, id)| id.clone());
+        conn.execute("UPDATE messages SET uuid = ?1, parent_id = ?2 WHERE id = ?3", params![uuid, parent_id, row_id])?;
+        parent = Some((conv_id.clone(), uuid));
+    }
+    log::info!("assigned stable IDs to {} messages", rows.len());
+    Ok(())
+}
+
+fn backfill_search_index(conn: &Connection) -> rusqlite::Result<()> {
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM search_index", [], |row| row.get(0))?;
+    if count > 0 { return Ok(()); }
+
+    let conv_count: i64 = conn.query_row("SELECT COUNT(*) FROM conversations", [], |row| row.get(0))?;
+    if conv_count == 0 { return Ok(()); }
 
     // Bulk populate from existing data
     conn.execute_batch(
         "INSERT INTO search_index (conversation_id, title, content)
          SELECT c.id, c.title, COALESCE(GROUP_CONCAT(m.content, ' '), '')
          FROM conversations c
-         LEFT JOIN messages m ON m.conversation_id = c.id
+         LEFT JOIN messages m ON m.conversation_id = c.id AND COALESCE(m.active, 1) = 1
          GROUP BY c.id"
-    ).ok();
+    )?;
 
     log::info!("backfilled FTS5 search index for {conv_count} conversations");
+    Ok(())
 }
 
 fn migrate_from_json(conn: &Connection) {
-    let json_dir = dirs::config_dir()
-        .unwrap_or_else(|| std::path::PathBuf::from("."))
-        .join("stoa")
-        .join("conversations");
+    let json_dir = crate::workspace::dir().join("conversations");
 
     if !json_dir.exists() {
         return;
@@ -1,6 +1,133 @@
-use crate::model::{Conversation, Role};
+use std::collections::HashSet;
+use std::io::Write;
 
-fn escape_html(s: &str) -> String {
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use iced::Color;
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+use serde_json::json;
+
+use crate::dataset::DatasetFilter;
+use crate::highlight::Token;
+use crate::model::{group_messages, ChatMessage, Conversation, MessageGroup, Role};
+use crate::theme::ThemeName;
+
+/// Folder images are written to inside a bundle.
+const BUNDLE_IMAGES_DIR: &str = "images";
+
+/// Which conversations a file export covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportScope {
+    /// The open conversation
+    Conversation,
+    /// Conversations ticked in the export dialog
+    Chosen,
+    Folder(String),
+    Tag(String),
+    /// The sidebar's current search results
+    SearchResults,
+    /// Every conversation in the library
+    Library,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+    /// A zip with Markdown, HTML and JSON files per conversation, and images
+    Bundle,
+    /// Everything Stoa needs to import the conversations again
+    Archive,
+    /// OpenAI chat fine-tuning JSONL
+    FineTune,
+    /// Chosen/rejected reply pairs for preference tuning (DPO), as JSONL
+    Preferences,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 7] = [
+        ExportFormat::Markdown, ExportFormat::Html, ExportFormat::Json, ExportFormat::Bundle,
+        ExportFormat::Archive, ExportFormat::FineTune, ExportFormat::Preferences,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Html => "HTML",
+            ExportFormat::Json => "JSON",
+            ExportFormat::Bundle => "Zip bundle",
+            ExportFormat::Archive => "Stoa archive",
+            ExportFormat::FineTune => "Fine-tuning JSONL",
+            ExportFormat::Preferences => "Preference pairs",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Json => "json",
+            ExportFormat::Bundle => "zip",
+            ExportFormat::Archive => "stoa",
+            ExportFormat::FineTune | ExportFormat::Preferences => "jsonl",
+        }
+    }
+
+    /// Training datasets, which take a `DatasetFilter` instead of the
+    /// Include options.
+    pub fn is_dataset(&self) -> bool {
+        matches!(self, ExportFormat::FineTune | ExportFormat::Preferences)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportOptions {
+    /// Tags, times, models, tokens and latency; JSON without it keeps only
+    /// titles, roles and text
+    pub metadata: bool,
+    /// Replies that failed with an error
+    pub errored: bool,
+    pub images: bool,
+    /// Palette HTML is styled with
+    pub theme: ThemeName,
+    /// Which replies training datasets use
+    pub dataset: DatasetFilter,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self { metadata: true, errored: false, images: true, theme: ThemeName::default(), dataset: DatasetFilter::default() }
+    }
+}
+
+/// The export dialog's choices.
+#[derive(Debug, Clone)]
+pub struct ExportDialog {
+    pub scope: ExportScope,
+    pub format: ExportFormat,
+    pub options: ExportOptions,
+    /// IDs ticked for `ExportScope::Chosen`
+    pub chosen: HashSet<String>,
+    /// Where the last export went, or why it failed
+    pub status: Option<String>,
+}
+
+/// How a message's images appear in Markdown and HTML.
+#[derive(Clone, Copy)]
+enum Images<'a> {
+    Omit,
+    /// As data URLs
+    Inline,
+    /// As links to files in this folder
+    Linked(&'a str),
+}
+
+fn format_timestamp(t: &DateTime<Utc>) -> String {
+    t.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+pub(crate) fn escape_html(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
@@ -8,83 +135,408 @@ fn escape_html(s: &str) -> String {
         .replace('\'', "&#x27;")
 }
 
-pub fn conversation_to_markdown(conv: &Conversation) -> String {
+pub(crate) fn image_extension(data: &[u8]) -> &'static str {
+    match data {
+        [0x89, b'P', b'N', b'G', ..] => "png",
+        [0xFF, 0xD8, 0xFF, ..] => "jpg",
+        [b'G', b'I', b'F', b'8', ..] => "gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "webp",
+        _ => "bin",
+    }
+}
+
+fn image_mime(data: &[u8]) -> &'static str {
+    match image_extension(data) {
+        "png" => "image/png",
+        "jpg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+/// A message's images as (file name, bytes); the names are unique across
+/// the library. Images that aren't valid base64 are left out.
+pub(crate) fn message_images(msg: &ChatMessage) -> Vec<(String, Vec<u8>)> {
+    msg.images.iter().enumerate()
+        .filter_map(|(i, image)| {
+            let data = base64::engine::general_purpose::STANDARD.decode(image).ok()?;
+            Some((format!("{}-{i}.{}", msg.id, image_extension(&data)), data))
+        })
+        .collect()
+}
+
+/// Image sources for a message: data URLs or paths under `Linked`'s folder.
+fn image_sources(msg: &ChatMessage, images: Images) -> Vec<String> {
+    match images {
+        Images::Omit => Vec::new(),
+        Images::Inline => message_images(msg).into_iter()
+            .map(|(_, data)| format!("data:{};base64,{}", image_mime(&data), base64::engine::general_purpose::STANDARD.encode(&data)))
+            .collect(),
+        Images::Linked(dir) => message_images(msg).into_iter().map(|(name, _)| format!("{dir}/{name}")).collect(),
+    }
+}
+
+/// A copy of `conv` with only what `options` asks for.
+fn prepared(conv: &Conversation, options: &ExportOptions) -> Conversation {
+    let keep = |m: &&ChatMessage| !m.streaming && (options.errored || !m.is_error());
+    let strip = |m: &ChatMessage| {
+        let mut m = m.clone();
+        if !options.images {
+            m.images.clear();
+        }
+        m
+    };
+    let mut copy = conv.clone();
+    copy.messages = conv.messages.iter().filter(keep).map(strip).collect();
+    copy.alternates = conv.alternates.iter().filter(keep).map(strip).collect();
+    copy
+}
+
+fn speaker(msg: &ChatMessage, metadata: bool) -> String {
+    match (&msg.role, &msg.model) {
+        (Role::User, _) => "You".to_string(),
+        (Role::Assistant, Some(m)) if metadata => format!("Assistant ({m})"),
+        (Role::Assistant, _) => "Assistant".to_string(),
+    }
+}
+
+/// Bold speaker line above a message in Markdown, with its time.
+pub(crate) fn markdown_label(msg: &ChatMessage) -> String {
+    let label = format!("**{}**", speaker(msg, true));
+    match &msg.created_at {
+        Some(t) => format!("{label} · _{}_", format_timestamp(t)),
+        None => label,
+    }
+}
+
+fn markdown_with(conv: &Conversation, metadata: bool, images: Images) -> String {
     let mut md = format!("# {}\n\n", conv.title);
-    if !conv.tags.is_empty() {
-        md.push_str(&format!("**Tags:** {}\n\n", conv.tags.join(", ")));
+    if metadata {
+        if !conv.tags.is_empty() {
+            md.push_str(&format!("**Tags:** {}\n\n", conv.tags.join(", ")));
+        }
+        md.push_str(&format!("**Created:** {}\n\n", format_timestamp(&conv.created_at)));
     }
     for msg in &conv.messages {
         if msg.streaming { continue; }
-        let label = match msg.role {
-            Role::User => "**You**".to_string(),
-            Role::Assistant => match &msg.model {
-                Some(m) => format!("**Assistant ({m})**"),
-                None => "**Assistant**".to_string(),
-            },
-        };
-        md.push_str(&format!("{label}\n\n{}\n\n---\n\n", msg.content));
+        let label = if metadata { markdown_label(msg) } else { format!("**{}**", speaker(msg, false)) };
+        md.push_str(&format!("{label}\n\n{}\n\n", msg.content));
+        for src in image_sources(msg, images) {
+            md.push_str(&format!("![image]({src})\n\n"));
+        }
+        md.push_str("---\n\n");
     }
     md
 }
 
+pub fn conversation_to_markdown(conv: &Conversation) -> String {
+    markdown_with(conv, true, Images::Omit)
+}
+
+fn json_value(conv: &Conversation, metadata: bool) -> serde_json::Value {
+    if metadata {
+        return serde_json::to_value(conv).unwrap_or_default();
+    }
+    let messages: Vec<serde_json::Value> = conv.messages.iter()
+        .map(|m| {
+            let mut msg = json!({ "role": m.role, "content": m.content });
+            if !m.images.is_empty() {
+                msg["images"] = json!(m.images);
+            }
+            msg
+        })
+        .collect();
+    json!({ "title": conv.title, "messages": messages })
+}
+
 pub fn conversation_to_json(conv: &Conversation) -> String {
     serde_json::to_string_pretty(conv).unwrap_or_else(|_| "{}".to_string())
 }
 
-pub fn conversation_to_html(conv: &Conversation) -> String {
-    let title = escape_html(&conv.title);
-    let mut html = format!(r#"<!DOCTYPE html>
+fn css_color(c: Color) -> String {
+    let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", channel(c.r), channel(c.g), channel(c.b))
+}
+
+pub(crate) fn html_document(title: &str, body: &str, theme: ThemeName) -> String {
+    let title = escape_html(title);
+    let t = theme.colors();
+    let [main_bg, card_bg, user_bg, code_bg, head, body_text, sec, muted, accent, border, subtle, divider, string, number] = [
+        t.main_bg, t.card_bg, t.user_bg, t.code_bg, t.text_head, t.text_body, t.text_sec, t.text_muted,
+        t.accent, t.border_default, t.border_subtle, t.divider, t.diff_a_text, t.diff_b_text,
+    ].map(css_color);
+    format!(r#"<!DOCTYPE html>
 <html lang="en">
 <head>
 <meta charset="UTF-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
 <title>{title}</title>
 <style>
-body {{ font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', sans-serif; max-width: 800px; margin: 0 auto; padding: 20px; background: #111922; color: #e8e0d0; }}
-h1 {{ color: #c9a84c; }}
-.tags {{ color: #8a909a; font-size: 14px; margin-bottom: 20px; }}
-.message {{ margin-bottom: 24px; padding: 16px; border-radius: 8px; }}
-.user {{ background: #1e2836; text-align: right; }}
-.assistant {{ background: #161e2a; }}
-.role {{ font-size: 12px; color: #8a909a; margin-bottom: 8px; }}
-.content {{ white-space: pre-wrap; line-height: 1.6; }}
-.meta {{ font-size: 11px; color: #505a66; margin-top: 8px; }}
-hr {{ border: none; border-top: 1px solid #1e2834; margin: 16px 0; }}
+body {{ font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', sans-serif; max-width: 960px; margin: 0 auto; padding: 20px; background: {main_bg}; color: {body_text}; }}
+h1 {{ color: {head}; }}
+.tags {{ color: {muted}; font-size: 14px; margin-bottom: 20px; }}
+.message {{ margin-bottom: 24px; padding: 16px; border-radius: 8px; background: {card_bg}; border: 1px solid {subtle}; min-width: 0; }}
+.user {{ background: {user_bg}; }}
+.role {{ font-size: 12px; color: {sec}; margin-bottom: 8px; }}
+.content {{ line-height: 1.6; overflow-wrap: anywhere; }}
+.user .content {{ white-space: pre-wrap; }}
+.content > :first-child {{ margin-top: 0; }}
+.content > :last-child {{ margin-bottom: 0; }}
+.content h1, .content h2, .content h3, .content h4 {{ color: {head}; }}
+.content a {{ color: {accent}; }}
+.content blockquote {{ margin: 0; padding-left: 12px; border-left: 3px solid {accent}; color: {sec}; }}
+.content table {{ border-collapse: collapse; }}
+.content th, .content td {{ border: 1px solid {border}; padding: 4px 10px; }}
+.content code {{ font-family: ui-monospace, 'SF Mono', Menlo, monospace; font-size: 13px; background: {code_bg}; padding: 1px 4px; border-radius: 4px; }}
+.content pre {{ background: {code_bg}; padding: 12px; border-radius: 6px; overflow-x: auto; }}
+.content pre code {{ padding: 0; }}
+.hl-kw {{ color: {accent}; font-weight: 600; }}
+.hl-str {{ color: {string}; }}
+.hl-num {{ color: {number}; }}
+.hl-com {{ color: {muted}; font-style: italic; }}
+.message img {{ max-width: 100%; border-radius: 6px; margin-top: 8px; }}
+.turn {{ display: grid; gap: 12px; margin-bottom: 24px; }}
+.turn .message {{ margin-bottom: 0; }}
+.agreement {{ grid-column: 1 / -1; font-size: 12px; color: {accent}; }}
+.meta {{ font-size: 11px; color: {muted}; margin-top: 8px; }}
+hr {{ border: none; border-top: 1px solid {divider}; margin: 16px 0; }}
 </style>
 </head>
 <body>
-<h1>{title}</h1>
-"#);
+{body}</body>
+</html>
+"#)
+}
 
-    if !conv.tags.is_empty() {
-        let tags_escaped: Vec<String> = conv.tags.iter().map(|t| escape_html(t)).collect();
-        html.push_str(&format!("<p class=\"tags\">Tags: {}</p>\n", tags_escaped.join(", ")));
+/// Links and images a reply may point to; anything with another scheme
+/// (`javascript:` and friends) is dropped.
+fn safe_url(url: &str, image: bool) -> bool {
+    let lower = url.trim().to_ascii_lowercase();
+    match lower.split_once(':') {
+        Some((scheme, _)) if !scheme.contains(['/', '?', '#']) => {
+            matches!(scheme, "http" | "https" | "mailto") || (image && lower.starts_with("data:image/"))
+        }
+        _ => true,
     }
+}
 
-    for msg in &conv.messages {
-        if msg.streaming { continue; }
-        let (class, label) = match msg.role {
-            Role::User => ("user", "You".to_string()),
-            Role::Assistant => ("assistant", match &msg.model {
-                Some(m) => format!("Assistant ({})", escape_html(m)),
-                None => "Assistant".to_string(),
-            }),
+/// A code block with its tokens wrapped in `hl-*` classes.
+fn highlighted_code(code: &str, lang: &str) -> String {
+    let mut html = match lang {
+        "" => "<pre><code>".to_string(),
+        lang => format!("<pre><code class=\"language-{}\">", escape_html(lang)),
+    };
+    for (token, piece) in crate::highlight::tokens(code, lang) {
+        let class = match token {
+            Token::Plain => {
+                html.push_str(&escape_html(piece));
+                continue;
+            }
+            Token::Keyword => "hl-kw",
+            Token::String => "hl-str",
+            Token::Comment => "hl-com",
+            Token::Number => "hl-num",
         };
-        let escaped = escape_html(&msg.content);
-        let mut meta_parts = Vec::new();
+        html.push_str(&format!("<span class=\"{class}\">{}</span>", escape_html(piece)));
+    }
+    html.push_str("</code></pre>\n");
+    html
+}
+
+/// A reply's Markdown as HTML. Raw HTML in the reply shows as text, so a
+/// model can't slip scripts or styles into the page.
+pub(crate) fn markdown_html(content: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS | Options::ENABLE_FOOTNOTES;
+    let mut events = Vec::new();
+    // (language, source) of the code block being read
+    let mut code: Option<(String, String)> = None;
+    for event in Parser::new_ext(content, options) {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let lang = match kind {
+                    CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or("").to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                code = Some((lang, String::new()));
+            }
+            Event::Text(t) if code.is_some() => {
+                if let Some((_, source)) = &mut code {
+                    source.push_str(&t);
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                if let Some((lang, source)) = code.take() {
+                    events.push(Event::Html(highlighted_code(&source, &lang).into()));
+                }
+            }
+            Event::Html(raw) | Event::InlineHtml(raw) => events.push(Event::Text(raw)),
+            Event::Start(Tag::Link { link_type, dest_url, title, id }) if !safe_url(&dest_url, false) => {
+                events.push(Event::Start(Tag::Link { link_type, dest_url: "#".into(), title, id }));
+            }
+            Event::Start(Tag::Image { link_type, dest_url, title, id }) if !safe_url(&dest_url, true) => {
+                events.push(Event::Start(Tag::Image { link_type, dest_url: "".into(), title, id }));
+            }
+            other => events.push(other),
+        }
+    }
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events.into_iter());
+    html
+}
+
+fn html_message(msg: &ChatMessage, metadata: bool, images: Images) -> String {
+    let class = match msg.role {
+        Role::User => "user",
+        Role::Assistant => "assistant",
+    };
+    let label = escape_html(&speaker(msg, metadata));
+    // Replies are Markdown; what the user typed shows as written
+    let content = match msg.role {
+        Role::User => escape_html(&msg.content),
+        Role::Assistant => markdown_html(&msg.content),
+    };
+    let pictures: String = image_sources(msg, images).iter()
+        .map(|src| format!("<img src=\"{}\" alt=\"image\">", escape_html(src)))
+        .collect();
+    let mut meta_parts = Vec::new();
+    if metadata {
+        if let Some(t) = &msg.created_at {
+            meta_parts.push(format_timestamp(t));
+        }
         if let Some(tokens) = msg.token_count {
             meta_parts.push(format!("{tokens} tokens"));
         }
         if let Some(lat) = msg.latency_ms {
             meta_parts.push(format!("{lat} ms"));
         }
-        let meta = if meta_parts.is_empty() { String::new() } else {
-            format!("<div class=\"meta\">{}</div>", meta_parts.join(" · "))
-        };
-        html.push_str(&format!(
-            "<div class=\"message {class}\"><div class=\"role\">{label}</div><div class=\"content\">{escaped}</div>{meta}</div>\n"
-        ));
+    }
+    let meta = if meta_parts.is_empty() { String::new() } else {
+        format!("<div class=\"meta\">{}</div>", meta_parts.join(" · "))
+    };
+    format!("<div class=\"message {class}\"><div class=\"role\">{label}</div><div class=\"content\">{content}</div>{pictures}{meta}</div>\n")
+}
+
+/// Replies from several models to one prompt, side by side, with how much
+/// each pair agrees.
+fn html_comparison(replies: &[&ChatMessage], metadata: bool, images: Images) -> String {
+    let mut html = format!("<div class=\"turn\" style=\"grid-template-columns: repeat({}, minmax(0, 1fr))\">\n", replies.len());
+    for msg in replies {
+        html.push_str(&html_message(msg, metadata, images));
+    }
+    let name = |i: usize| match &replies[i].model {
+        Some(model) if metadata => model.clone(),
+        _ => format!("Reply {}", i + 1),
+    };
+    let mut pairs = Vec::new();
+    for a in 0..replies.len() {
+        for b in a + 1..replies.len() {
+            let agreement = crate::diff::agreement_percentage(&replies[a].content, &replies[b].content);
+            pairs.push(format!("{} vs {}: {agreement:.0}% agreement", escape_html(&name(a)), escape_html(&name(b))));
+        }
+    }
+    html.push_str(&format!("<div class=\"agreement\">{}</div>\n</div>\n", pairs.join(" · ")));
+    html
+}
+
+fn html_body(conv: &Conversation, metadata: bool, images: Images) -> String {
+    let mut html = format!("<h1>{}</h1>\n", escape_html(&conv.title));
+    if metadata {
+        if !conv.tags.is_empty() {
+            let tags_escaped: Vec<String> = conv.tags.iter().map(|t| escape_html(t)).collect();
+            html.push_str(&format!("<p class=\"tags\">Tags: {}</p>\n", tags_escaped.join(", ")));
+        }
+        html.push_str(&format!("<p class=\"tags\">Created {}</p>\n", format_timestamp(&conv.created_at)));
     }
 
-    html.push_str("</body>\n</html>\n");
+    for group in group_messages(&conv.messages) {
+        let indices = match group {
+            MessageGroup::User(i) => vec![i],
+            MessageGroup::Assistants(indices) => indices,
+        };
+        let shown: Vec<&ChatMessage> = indices.iter().map(|&i| &conv.messages[i]).filter(|m| !m.streaming).collect();
+        match shown.as_slice() {
+            [] => {}
+            [only] => html.push_str(&html_message(only, metadata, images)),
+            replies => html.push_str(&html_comparison(replies, metadata, images)),
+        }
+    }
     html
 }
+
+/// A conversation as a standalone HTML page in `theme`'s colours.
+pub fn conversation_to_html(conv: &Conversation, theme: ThemeName) -> String {
+    html_document(&conv.title, &html_body(conv, true, Images::Inline), theme)
+}
+
+fn document_title(conversations: &[Conversation]) -> &str {
+    match conversations {
+        [only] => &only.title,
+        _ => "Stoa export",
+    }
+}
+
+/// Render `conversations` as one file. Markdown and HTML put them one after
+/// another; JSON writes one conversation as an object and several as an array.
+/// Archives ignore `options` and keep everything; datasets only use its
+/// `DatasetFilter`.
+pub fn render(conversations: &[Conversation], format: ExportFormat, options: &ExportOptions) -> Result<Vec<u8>, String> {
+    match format {
+        ExportFormat::Archive => return crate::archive::write(conversations).map(String::into_bytes),
+        ExportFormat::FineTune => return Ok(crate::dataset::fine_tuning(conversations, &options.dataset).into_bytes()),
+        ExportFormat::Preferences => return Ok(crate::dataset::preference_pairs(conversations, &options.dataset).into_bytes()),
+        _ => {}
+    }
+    let convs: Vec<Conversation> = conversations.iter().map(|c| prepared(c, options)).collect();
+    let images = if options.images { Images::Inline } else { Images::Omit };
+    let out = match format {
+        ExportFormat::Markdown => convs.iter().map(|c| markdown_with(c, options.metadata, images)).collect::<Vec<_>>().join("\n"),
+        ExportFormat::Html => {
+            let body: String = convs.iter().map(|c| html_body(c, options.metadata, images)).collect::<Vec<_>>().join("<hr>\n");
+            html_document(document_title(&convs), &body, options.theme)
+        }
+        ExportFormat::Json => {
+            let value = match convs.as_slice() {
+                [only] => json_value(only, options.metadata),
+                many => serde_json::Value::Array(many.iter().map(|c| json_value(c, options.metadata)).collect()),
+            };
+            serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?
+        }
+        ExportFormat::Bundle => return bundle(&convs, options),
+        ExportFormat::Archive | ExportFormat::FineTune | ExportFormat::Preferences => unreachable!(),
+    };
+    Ok(out.into_bytes())
+}
+
+/// A zip with `markdown/`, `html/` and `json/` files per conversation, named
+/// like vault notes, and the images they link to under `images/`.
+fn bundle(conversations: &[Conversation], options: &ExportOptions) -> Result<Vec<u8>, String> {
+    let zip_err = |e: zip::result::ZipError| format!("Failed to write zip: {e}");
+    let io_err = |e: std::io::Error| format!("Failed to write zip: {e}");
+    let names = crate::vault::note_names(conversations);
+    let images = if options.images { Images::Linked("../images") } else { Images::Omit };
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let file_options = zip::write::SimpleFileOptions::default();
+
+    for conv in conversations {
+        let name = &names[&conv.id];
+        let html = html_document(&conv.title, &html_body(conv, options.metadata, images), options.theme);
+        let json = serde_json::to_string_pretty(&json_value(conv, options.metadata)).map_err(|e| e.to_string())?;
+        let files = [
+            (format!("markdown/{name}.md"), markdown_with(conv, options.metadata, images).into_bytes()),
+            (format!("html/{name}.html"), html.into_bytes()),
+            (format!("json/{name}.json"), json.into_bytes()),
+        ];
+        for (path, data) in files {
+            zip.start_file(path, file_options).map_err(zip_err)?;
+            zip.write_all(&data).map_err(io_err)?;
+        }
+        if options.images {
+            for (file, data) in conv.messages.iter().flat_map(message_images) {
+                zip.start_file(format!("{BUNDLE_IMAGES_DIR}/{file}"), file_options).map_err(zip_err)?;
+                zip.write_all(&data).map_err(io_err)?;
+            }
+        }
+    }
+    Ok(zip.finish().map_err(zip_err)?.into_inner())
+}
@@ -0,0 +1,287 @@
+use std::path::PathBuf;
+
+use iced::Task;
+
+use chrono::Local;
+
+use crate::app::{ChatApp, Message};
+use crate::backup;
+use crate::config::{AppConfig, EncryptionKeySource};
+use crate::model::Conversation;
+
+/// Shortest passphrase accepted for encrypting the database.
+const PASSPHRASE_MIN_CHARS: usize = 8;
+
+/// The open workspace's database, as found at startup or after a switch.
+pub(crate) struct Library {
+    pub db: rusqlite::Connection,
+    /// `None` when running on a temporary database
+    pub path: Option<PathBuf>,
+    pub key: Option<String>,
+    /// Waiting for a passphrase; `db` is a placeholder until then
+    pub locked: bool,
+    pub error: Option<String>,
+}
+
+impl ChatApp {
+    /// Open the open workspace's database. Encrypted databases open with the
+    /// keychain key, or wait for a passphrase.
+    pub(crate) fn open_library(config: &AppConfig) -> Library {
+        let path = crate::db::default_path();
+        let encrypted = crate::db::is_encrypted(&path);
+        let key = match config.encryption {
+            EncryptionKeySource::Passphrase => None,
+            _ if encrypted => AppConfig::database_key(),
+            _ => None,
+        };
+        let locked = encrypted && key.is_none();
+        if locked {
+            return Library { db: crate::db::open_in_memory(), path: Some(path), key, locked, error: None };
+        }
+        match crate::db::open(key.as_deref()) {
+            Ok(db) => Library { db, path: Some(path), key, locked, error: None },
+            Err(e) => {
+                log::error!("{e}");
+                let error = format!("{e} Stoa is running with a temporary database; changes in this session will not be saved.");
+                Library { db: crate::db::open_in_memory(), path: None, key, locked, error: Some(error) }
+            }
+        }
+    }
+
+    /// Open the encrypted database with the passphrase typed on the unlock screen.
+    pub(crate) fn unlock_database(&mut self) -> Task<Message> {
+        let Some(path) = self.db_path.clone() else { return Task::none() };
+        let passphrase = std::mem::take(&mut self.unlock_passphrase);
+        match crate::db::open(Some(&passphrase)) {
+            Ok(db) => {
+                log::info!("unlocked database {}", path.display());
+                self.db = db;
+                self.db_key = Some(passphrase);
+                self.db_locked = false;
+                self.unlock_error = None;
+                self.reload_conversations();
+                let backup_task = self.run_scheduled_backup();
+                self.purge_expired_trash();
+                let sync_task = self.sync_now();
+                Task::batch([backup_task, sync_task, self.index_embeddings()])
+            }
+            Err(e) => {
+                log::warn!("{e}");
+                self.unlock_error = Some("Wrong passphrase".to_string());
+                Task::none()
+            }
+        }
+    }
+
+    /// Replace the conversation list with what the (re)opened database holds.
+    pub(crate) fn reload_conversations(&mut self) {
+        self.conversations = crate::db::load_summaries(&self.db);
+        if self.conversations.is_empty() {
+            let conv = Conversation::new();
+            Self::handle_db_result(&mut self.error_message, crate::db::save_conversation(&self.db, &conv));
+            self.conversations.push(conv);
+        }
+        self.loaded_conversations.clear();
+        self.active_conversation = 0;
+        self.ensure_loaded(0);
+        self.semantic_index = None;
+        self.refresh_related();
+    }
+
+    /// Encrypt, re-key or decrypt the database file in place. The connection is
+    /// closed while the file is rewritten and reopened with whichever key the
+    /// file ends up with.
+    pub(crate) fn set_database_encryption(&mut self, source: EncryptionKeySource) {
+        let Some(path) = self.db_path.clone() else {
+            self.error_message = Some("The database is temporary and can't be encrypted.".to_string());
+            return;
+        };
+        // A fresh keychain key would overwrite the one the file is encrypted with
+        if source == self.config.encryption && source != EncryptionKeySource::Passphrase {
+            return;
+        }
+        let new_key = match source {
+            EncryptionKeySource::None => None,
+            EncryptionKeySource::Keychain => {
+                let key = AppConfig::generate_database_key();
+                // The key must be safely stored before anything is encrypted with it
+                if let Err(e) = AppConfig::set_database_key(&key) {
+                    self.error_message = Some(e);
+                    return;
+                }
+                Some(key)
+            }
+            EncryptionKeySource::Passphrase => {
+                if self.encryption_passphrase.chars().count() < PASSPHRASE_MIN_CHARS {
+                    self.error_message = Some(format!("Use a passphrase of at least {PASSPHRASE_MIN_CHARS} characters."));
+                    return;
+                }
+                Some(std::mem::take(&mut self.encryption_passphrase))
+            }
+        };
+
+        let db = std::mem::replace(&mut self.db, crate::db::open_in_memory());
+        drop(db);
+        let result = crate::db::rekey_file(&path, self.db_key.as_deref(), new_key.as_deref());
+        if result.is_ok() {
+            let previous = self.config.encryption;
+            self.db_key = new_key;
+            self.config.encryption = source;
+            // Persist only this setting, leaving other unsaved edits in the form
+            let mut saved = AppConfig::load();
+            saved.encryption = source;
+            saved.save();
+            if previous == EncryptionKeySource::Keychain && source != EncryptionKeySource::Keychain {
+                AppConfig::set_database_key("").ok();
+            }
+            log::info!("database encryption set to {source:?}");
+        } else if source == EncryptionKeySource::Keychain && self.config.encryption != EncryptionKeySource::Keychain {
+            AppConfig::set_database_key("").ok();
+        }
+        Self::handle_db_result(&mut self.error_message, result);
+
+        match crate::db::open_encrypted_at(&path, self.db_key.as_deref()) {
+            Ok(db) => self.db = db,
+            Err(e) => {
+                log::error!("{e}");
+                self.error_message = Some(format!("{e} Stoa is running with a temporary database; restart to try again."));
+                self.db_path = None;
+            }
+        }
+    }
+
+    /// Write a copy of the database to `dest`, encrypted with the same key as the original.
+    pub(crate) fn backup_database(&mut self, dest: PathBuf) {
+        let result = crate::db::backup_to(&self.db, &dest, self.db_key.as_deref());
+        if result.is_ok() {
+            log::info!("backed up database to {}", dest.display());
+        }
+        Self::handle_db_result(&mut self.error_message, result);
+    }
+
+    /// Take a snapshot into the backup directory, rotating old ones out, and
+    /// wait for it; a restore needs its safety snapshot before going on.
+    pub(crate) fn snapshot_now(&mut self) {
+        if self.db_path.is_none() {
+            return;
+        }
+        let backup = &self.config.backup;
+        let result = backup::take_snapshot(&self.db, &backup.dir(), self.db_key.as_deref(), backup.keep_count(), Local::now());
+        match result {
+            Ok(path) => log::info!("snapshot saved to {}", path.display()),
+            Err(e) => {
+                log::error!("{e}");
+                self.error_message = Some(e);
+            }
+        }
+        self.refresh_snapshots();
+    }
+
+    /// Take a snapshot off the UI thread, through a connection of its own;
+    /// the outcome arrives as `SnapshotTaken`.
+    pub(crate) fn snapshot_in_background(&mut self) -> Task<Message> {
+        let Some(path) = self.db_path.clone() else { return Task::none() };
+        if self.snapshot_in_progress || self.db_locked {
+            return Task::none();
+        }
+        self.snapshot_in_progress = true;
+        let key = self.db_key.clone();
+        let (dir, keep) = (self.config.backup.dir(), self.config.backup.keep_count());
+        Task::perform(
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    let conn = crate::db::open_encrypted_at(&path, key.as_deref())?;
+                    backup::take_snapshot(&conn, &dir, key.as_deref(), keep, Local::now())
+                })
+                .await
+                .unwrap_or_else(|e| Err(format!("Snapshot failed: {e}")))
+            },
+            Message::SnapshotTaken,
+        )
+    }
+
+    pub(crate) fn snapshot_taken(&mut self, result: Result<PathBuf, String>) {
+        self.snapshot_in_progress = false;
+        match result {
+            Ok(path) => log::info!("snapshot saved to {}", path.display()),
+            Err(e) => {
+                log::error!("{e}");
+                self.error_message = Some(e);
+            }
+        }
+        self.refresh_snapshots();
+    }
+
+    /// Take a scheduled snapshot if the newest one is older than the interval.
+    pub(crate) fn run_scheduled_backup(&mut self) -> Task<Message> {
+        let backup = &self.config.backup;
+        if !backup.enabled || self.db_locked {
+            return Task::none();
+        }
+        let snapshots = backup::list_snapshots(&backup.dir());
+        if !backup::is_due(snapshots.first(), backup.interval(), Local::now()) {
+            return Task::none();
+        }
+        self.snapshot_in_background()
+    }
+
+    pub(crate) fn refresh_snapshots(&mut self) {
+        self.snapshots = backup::list_snapshots(&self.config.backup.dir());
+    }
+
+    pub(crate) fn preview_snapshot(&mut self, path: PathBuf) {
+        let Some(snapshot) = self.snapshots.iter().find(|s| s.path == path) else { return };
+        match backup::preview(snapshot, self.db_key.as_deref()) {
+            Ok(preview) => self.snapshot_preview = Some(preview),
+            Err(e) => self.error_message = Some(e),
+        }
+    }
+
+    /// Replace the library with a snapshot. The current library is snapshotted
+    /// first, so a restore can itself be undone.
+    pub(crate) fn restore_snapshot(&mut self, snapshot: PathBuf) {
+        self.snapshot_preview = None;
+        let Some(path) = self.db_path.clone() else {
+            self.error_message = Some("The database is temporary; restart Stoa to restore a backup.".to_string());
+            return;
+        };
+        // Copy first: taking the safety snapshot may rotate this one out
+        let mut staged = path.as_os_str().to_owned();
+        staged.push(".restore");
+        let staged = PathBuf::from(staged);
+        if let Err(e) = std::fs::copy(&snapshot, &staged) {
+            self.error_message = Some(format!("Failed to read backup {}: {e}", snapshot.display()));
+            return;
+        }
+        // Snapshots keep the encryption the library had when they were taken
+        let snapshot_key = self.db_key.clone().filter(|_| crate::db::is_encrypted(&staged));
+        if let Err(e) = crate::db::open_snapshot(&staged, snapshot_key.as_deref()) {
+            std::fs::remove_file(&staged).ok();
+            self.error_message = Some(e);
+            return;
+        }
+        self.snapshot_now();
+
+        let db = std::mem::replace(&mut self.db, crate::db::open_in_memory());
+        drop(db);
+        let mut result = crate::db::replace_file(&path, &staged);
+        if result.is_ok() && snapshot_key != self.db_key {
+            result = crate::db::rekey_file(&path, snapshot_key.as_deref(), self.db_key.as_deref());
+        }
+        Self::handle_db_result(&mut self.error_message, result);
+
+        match crate::db::open_encrypted_at(&path, self.db_key.as_deref()) {
+            Ok(db) => {
+                log::info!("restored library from {}", snapshot.display());
+                self.db = db;
+                self.reload_conversations();
+            }
+            Err(e) => {
+                log::error!("{e}");
+                self.error_message = Some(format!("{e} Stoa is running with a temporary database; restart to try again."));
+                self.db_path = None;
+            }
+        }
+        self.refresh_snapshots();
+    }
+}
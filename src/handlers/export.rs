@@ -0,0 +1,157 @@
+use chrono::Local;
+use iced::Task;
+
+use crate::app::{ChatApp, Message};
+use crate::export::{ExportDialog, ExportFormat, ExportOptions, ExportScope};
+use crate::model::Conversation;
+use crate::report::{ReportDialog, ReportFormat};
+use crate::vault::VaultReport;
+
+fn describe_vault(report: &VaultReport) -> String {
+    let mut parts = vec![format!("{} note(s) written", report.written)];
+    if report.unchanged > 0 {
+        parts.push(format!("{} unchanged", report.unchanged));
+    }
+    if report.assets > 0 {
+        parts.push(format!("{} image(s) saved", report.assets));
+    }
+    if report.removed > 0 {
+        parts.push(format!("{} old file(s) removed", report.removed));
+    }
+    format!("Exported at {}: {}.", Local::now().format("%H:%M"), parts.join(", "))
+}
+
+impl ChatApp {
+    /// Write the library to the vault folder as Markdown notes.
+    pub(crate) fn export_vault(&mut self) {
+        let Some(dir) = self.config.vault.dir() else {
+            self.error_message = Some("Set a vault folder in Settings first.".to_string());
+            return;
+        };
+        if self.db_locked {
+            return;
+        }
+        match crate::vault::export(&self.db, &dir, self.config.vault.incremental) {
+            Ok(report) => {
+                log::info!("vault export to {}: {report:?}", dir.display());
+                self.vault_status = Some(describe_vault(&report));
+            }
+            Err(e) => {
+                log::error!("{e}");
+                self.vault_status = Some(e);
+            }
+        }
+    }
+
+    pub(crate) fn open_export_dialog(&mut self) {
+        self.export_dialog = Some(ExportDialog {
+            scope: ExportScope::Conversation,
+            format: ExportFormat::Markdown,
+            options: ExportOptions { theme: self.config.theme, ..ExportOptions::default() },
+            chosen: self.conversations.get(self.active_conversation).map(|c| c.id.clone()).into_iter().collect(),
+            status: None,
+        });
+    }
+
+    /// IDs of the conversations the export dialog's scope covers, in sidebar order.
+    pub fn export_ids(&self) -> Vec<String> {
+        let Some(dialog) = &self.export_dialog else { return Vec::new() };
+        let matches = |conv: &Conversation| match &dialog.scope {
+            ExportScope::Conversation => self.conversations.get(self.active_conversation).is_some_and(|c| c.id == conv.id),
+            ExportScope::Chosen => dialog.chosen.contains(&conv.id),
+            ExportScope::Folder(folder) => conv.folder.as_deref()
+                .is_some_and(|f| f == folder || f.strip_prefix(folder.as_str()).is_some_and(|rest| rest.starts_with('/'))),
+            ExportScope::Tag(tag) => conv.tags.contains(tag),
+            ExportScope::SearchResults => self.sidebar_search_results.as_ref().is_some_and(|ids| ids.contains(&conv.id)),
+            ExportScope::Library => true,
+        };
+        self.conversations.iter().filter(|c| matches(c)).map(|c| c.id.clone()).collect()
+    }
+
+    /// Render the export dialog's selection and ask where to save it.
+    pub(crate) fn save_export(&mut self) -> Task<Message> {
+        let Some(dialog) = &self.export_dialog else { return Task::none() };
+        let (format, options) = (dialog.format, dialog.options.clone());
+        let mut conversations = Vec::new();
+        for id in self.export_ids() {
+            let Some(idx) = self.conv_index_by_id(&id) else { continue };
+            // Loaded into copies rather than through `ensure_loaded`, so the
+            // export doesn't fill `self.conversations` and push the ones in
+            // use out of the recently-used list
+            let mut conv = self.conversations[idx].clone();
+            Self::handle_db_result(&mut self.error_message, crate::db::load_messages(&self.db, &mut conv));
+            conversations.push(conv);
+        }
+        let dialog = self.export_dialog.as_mut().expect("checked above");
+        if conversations.is_empty() {
+            dialog.status = Some("Nothing to export.".to_string());
+            return Task::none();
+        }
+        let data = match crate::export::render(&conversations, format, &options) {
+            Ok(data) if data.is_empty() => {
+                dialog.status = Some("Nothing in these conversations matches the filters.".to_string());
+                return Task::none();
+            }
+            Ok(data) => data,
+            Err(e) => {
+                dialog.status = Some(e);
+                return Task::none();
+            }
+        };
+        let file_name = match conversations.as_slice() {
+            [only] => format!("{}.{}", crate::vault::note_names(&conversations)[&only.id], format.extension()),
+            _ => format!("stoa-export-{}.{}", Local::now().format("%Y-%m-%d"), format.extension()),
+        };
+        let count = conversations.len();
+        Task::perform(
+            async move {
+                let file = rfd::AsyncFileDialog::new()
+                    .set_file_name(&file_name)
+                    .add_filter(format.label(), &[format.extension()])
+                    .save_file()
+                    .await?;
+                Some(match file.write(&data).await {
+                    Ok(()) => Ok(format!("Saved {count} conversation(s) to {}.", file.path().display())),
+                    Err(e) => Err(format!("Failed to write {}: {e}", file.path().display())),
+                })
+            },
+            Message::ExportSaved,
+        )
+    }
+
+    /// Open the comparison report dialog for the turn holding the reply at
+    /// `turn`, or for the whole conversation.
+    pub(crate) fn open_report(&mut self, turn: Option<usize>) {
+        self.report_dialog = Some(ReportDialog { turn, whole: turn.is_none(), format: ReportFormat::Markdown, status: None });
+    }
+
+    /// Render the comparison report and ask where to save it.
+    pub(crate) fn save_report(&mut self) -> Task<Message> {
+        let Some(dialog) = &mut self.report_dialog else { return Task::none() };
+        let Some(conv) = self.conversations.get(self.active_conversation) else { return Task::none() };
+        let turns = crate::report::turns(conv, if dialog.whole { None } else { dialog.turn });
+        if turns.is_empty() {
+            dialog.status = Some("No turn here was answered by more than one model.".to_string());
+            return Task::none();
+        }
+        let format = dialog.format;
+        let data = crate::report::render(&conv.title, &turns, format, self.config.theme);
+        let name = &crate::vault::note_names(std::slice::from_ref(conv))[&conv.id];
+        let file_name = format!("{name} comparison.{}", format.extension());
+        let count = turns.len();
+        Task::perform(
+            async move {
+                let file = rfd::AsyncFileDialog::new()
+                    .set_file_name(&file_name)
+                    .add_filter(format.label(), &[format.extension()])
+                    .save_file()
+                    .await?;
+                Some(match file.write(data.as_bytes()).await {
+                    Ok(()) => Ok(format!("Saved a report on {count} turn(s) to {}.", file.path().display())),
+                    Err(e) => Err(format!("Failed to write {}: {e}", file.path().display())),
+                })
+            },
+            Message::ReportSaved,
+        )
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashSet;
+
+use iced::Task;
+
+use crate::app::{ChatApp, Message};
+use crate::config::AppConfig;
+use crate::import::{self, ImportFormat, ImportPreview, JsonlField};
+
+/// How long typing in a JSONL field must pause before the previewed file
+/// is read again with the new mapping.
+const JSONL_MAPPING_DELAY: std::time::Duration = std::time::Duration::from_millis(400);
+
+impl ChatApp {
+    /// Pick an export file, in `format` or detected from its contents, and
+    /// read it off the UI thread; the preview arrives as `ImportLoaded`.
+    pub(crate) fn pick_import_file(&self, format: Option<ImportFormat>) -> Task<Message> {
+        let mapping = self.config.jsonl_mapping.clone();
+        Task::perform(
+            async move {
+                let file = rfd::AsyncFileDialog::new()
+                    .add_filter("Chat export", &["json", "jsonl", "stoa", "md"])
+                    .pick_file()
+                    .await?;
+                let (source, data) = (file.file_name(), file.read().await);
+                let data = String::from_utf8_lossy(&data).to_string();
+                let preview = tokio::task::spawn_blocking(move || import::preview(format, source, data, &mapping))
+                    .await
+                    .unwrap_or_else(|e| Err(format!("Import failed: {e}")));
+                Some(preview)
+            },
+            |picked| match picked {
+                Some(preview) => Message::ImportLoaded(preview),
+                None => Message::DismissError,
+            },
+        )
+    }
+
+    /// Show what importing a read file would add.
+    pub(crate) fn preview_import(&mut self, preview: Result<ImportPreview, String>) {
+        match preview {
+            Ok(mut preview) => {
+                preview.duplicates = import::find_duplicates(&preview.conversations, &self.conversations);
+                self.import_preview = Some(preview);
+            }
+            Err(e) => self.error_message = Some(e),
+        }
+    }
+
+    /// Change a JSONL field; the previewed file is read again with it once
+    /// typing pauses, see `JsonlMappingSettled`.
+    pub(crate) fn set_jsonl_field(&mut self, field: JsonlField, value: String) -> Task<Message> {
+        self.config.jsonl_mapping.set(field, value);
+        if self.import_preview.is_none() {
+            return Task::none();
+        }
+        let mapping = self.config.jsonl_mapping.clone();
+        Task::perform(
+            async { tokio::time::sleep(JSONL_MAPPING_DELAY).await },
+            move |_| Message::JsonlMappingSettled(mapping.clone()),
+        )
+    }
+
+    /// Read the previewed file again if the mapping has changed since.
+    pub(crate) fn reread_import(&mut self) {
+        let Some(preview) = &mut self.import_preview else { return };
+        if preview.format != ImportFormat::Jsonl || preview.mapping == self.config.jsonl_mapping {
+            return;
+        }
+        let (conversations, report) = import::parse(preview.format, &preview.data, &self.config.jsonl_mapping);
+        preview.mapping = self.config.jsonl_mapping.clone();
+        preview.duplicates = import::find_duplicates(&conversations, &self.conversations);
+        preview.conversations = conversations;
+        preview.report = report;
+    }
+
+    /// Save the previewed conversations the library doesn't have yet, or
+    /// all of them with duplicates as copies.
+    pub(crate) fn confirm_import(&mut self) -> Task<Message> {
+        // Don't import with a mapping the preview hasn't caught up with
+        self.reread_import();
+        let Some(preview) = self.import_preview.take() else { return Task::none() };
+        if preview.format == ImportFormat::Jsonl {
+            // Persist only the mapping, leaving other unsaved edits in the settings form
+            let mut saved = AppConfig::load();
+            saved.jsonl_mapping = self.config.jsonl_mapping.clone();
+            saved.save();
+        }
+        let mut report = preview.report;
+        let mut convs = preview.conversations;
+        if preview.copy_duplicates {
+            import::remap_ids(&mut convs, &preview.duplicates);
+        } else {
+            report.already_imported = preview.duplicates.len();
+            convs.retain(|c| !preview.duplicates.contains(&c.id));
+        }
+        // Message IDs are unique across the library; a conversation reusing
+        // one would overwrite another's message, so it gets fresh IDs
+        let clashing: HashSet<String> = convs.iter()
+            .filter(|c| c.messages.iter().chain(&c.alternates).any(|m| crate::db::load_message(&self.db, &m.id).is_some()))
+            .map(|c| c.id.clone())
+            .collect();
+        import::remap_ids(&mut convs, &clashing);
+        self.import_report = Some(report);
+        self.update(Message::ImportComplete(convs))
+    }
+}
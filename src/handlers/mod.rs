@@ -1,2 +1,9 @@
 mod streaming;
+mod database;
+mod semantic;
+mod trash;
+mod workspace;
+mod sync;
+mod import;
+mod export;
 mod send;
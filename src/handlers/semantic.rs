@@ -0,0 +1,144 @@
+use iced::Task;
+
+use crate::app::{ChatApp, Message};
+use crate::semantic::{self, VectorIndex};
+
+/// Messages embedded per request while indexing.
+const EMBEDDING_BATCH: usize = 16;
+/// Entries in the "related conversations" list.
+const RELATED_MAX: usize = 5;
+/// Semantic matches shown in the quick switcher.
+const SEMANTIC_RESULTS_MAX: usize = 12;
+/// How long typing must pause before the query is embedded, so each
+/// keystroke doesn't cost an API call.
+const SEMANTIC_QUERY_DELAY: std::time::Duration = std::time::Duration::from_millis(400);
+
+impl ChatApp {
+    /// The in-memory vector index for the configured model, loaded from SQLite
+    /// on first use or after the model changes.
+    fn vector_index(&mut self) -> &mut VectorIndex {
+        let model = &self.config.embeddings.model;
+        if self.semantic_index.as_ref().is_none_or(|index| index.model != *model) {
+            let entries = crate::db::load_embeddings(&self.db, model);
+            self.semantic_index = Some(VectorIndex::new(model, entries));
+        }
+        self.semantic_index.get_or_insert_default()
+    }
+
+    /// Embed the next batch of unindexed messages, if semantic search is on
+    /// and no batch is already in flight. Each finished batch starts the next.
+    pub(crate) fn index_embeddings(&mut self) -> Task<Message> {
+        let config = self.config.embeddings.clone();
+        if !config.enabled || self.embedding_in_progress {
+            return Task::none();
+        }
+        let pending = crate::db::pending_embeddings(&self.db, &config.model, EMBEDDING_BATCH);
+        if pending.is_empty() {
+            return Task::none();
+        }
+        let mut batch = Vec::new();
+        let mut inputs = Vec::new();
+        for (conv_id, msg_id, content) in pending {
+            let chunks = semantic::chunks(&content);
+            batch.push((conv_id, msg_id, chunks.len()));
+            inputs.extend(chunks);
+        }
+        self.embedding_in_progress = true;
+        Task::perform(
+            crate::api::embeddings::embed(self.http_client.clone(), config, inputs),
+            move |result| Message::EmbeddingsIndexed(batch.clone(), result),
+        )
+    }
+
+    pub(crate) fn handle_embeddings_indexed(
+        &mut self,
+        batch: Vec<(String, String, usize)>,
+        result: Result<Vec<Vec<f32>>, String>,
+    ) -> Task<Message> {
+        self.embedding_in_progress = false;
+        let vectors = match result {
+            Ok(vectors) => vectors,
+            Err(e) => {
+                log::warn!("embedding failed: {e}");
+                self.embedding_error = Some(e);
+                return Task::none();
+            }
+        };
+        self.embedding_error = None;
+        let model = self.config.embeddings.model.clone();
+        let mut vectors = vectors.into_iter();
+        for (conv_id, msg_id, chunk_count) in batch {
+            let mut chunk_vectors: Vec<Vec<f32>> = vectors.by_ref().take(chunk_count).collect();
+            chunk_vectors.iter_mut().for_each(|v| semantic::normalize(v));
+            // The message may have been deleted while the request was in flight
+            if let Err(e) = crate::db::save_embeddings(&self.db, &model, &msg_id, &chunk_vectors) {
+                log::warn!("{e}");
+                continue;
+            }
+            self.vector_index().insert(&conv_id, &msg_id, chunk_vectors);
+        }
+        self.refresh_related();
+        self.index_embeddings()
+    }
+
+    /// The quick switcher query if it is worth embedding, clearing stale
+    /// results otherwise.
+    fn semantic_query(&mut self) -> Option<String> {
+        let query = self.quick_switcher_query.trim().to_string();
+        if !self.config.embeddings.enabled || query.chars().count() < 3 {
+            self.semantic_results.clear();
+            return None;
+        }
+        Some(query)
+    }
+
+    /// Embed the quick switcher query once typing pauses; see `SemanticQuerySettled`.
+    pub(crate) fn schedule_semantic_query(&mut self) -> Task<Message> {
+        let Some(query) = self.semantic_query() else { return Task::none() };
+        Task::perform(
+            async { tokio::time::sleep(SEMANTIC_QUERY_DELAY).await },
+            move |_| Message::SemanticQuerySettled(query.clone()),
+        )
+    }
+
+    /// Embed the quick switcher query; results arrive as `SemanticQueryEmbedded`.
+    pub(crate) fn embed_semantic_query(&mut self) -> Task<Message> {
+        let Some(query) = self.semantic_query() else { return Task::none() };
+        Task::perform(
+            crate::api::embeddings::embed(self.http_client.clone(), self.config.embeddings.clone(), vec![query.clone()]),
+            move |result| Message::SemanticQueryEmbedded(
+                query.clone(),
+                result.and_then(|v| v.into_iter().next().ok_or_else(|| "Empty embedding response".to_string())),
+            ),
+        )
+    }
+
+    pub(crate) fn handle_semantic_query_embedded(&mut self, query: String, result: Result<Vec<f32>, String>) -> Task<Message> {
+        // Drop answers to queries the user has already typed past
+        if !self.quick_switcher_semantic || query != self.quick_switcher_query.trim() {
+            return Task::none();
+        }
+        match result {
+            Ok(mut vector) => {
+                semantic::normalize(&mut vector);
+                self.semantic_results = self.vector_index().search(&vector, SEMANTIC_RESULTS_MAX);
+                self.embedding_error = None;
+            }
+            Err(e) => {
+                log::warn!("query embedding failed: {e}");
+                self.embedding_error = Some(e);
+            }
+        }
+        Task::none()
+    }
+
+    /// Recompute the right panel's "related conversations" for the active conversation.
+    pub(crate) fn refresh_related(&mut self) {
+        self.related_conversations.clear();
+        if !self.config.embeddings.enabled {
+            return;
+        }
+        let Some(active_id) = self.conversations.get(self.active_conversation).map(|c| c.id.clone()) else { return };
+        self.related_conversations = self.vector_index().related(&active_id, RELATED_MAX);
+    }
+}
@@ -29,7 +29,7 @@ impl ChatApp {
         if let Some(msg) = conv.messages.last_mut() {
             msg.token_count = Some(crate::cost::estimate_tokens(&text));
         }
-        Self::handle_db_result(&mut self.error_message, crate::db::save_conversation(&self.db, conv));
+        Self::handle_db_result(&mut self.error_message, crate::db::save_message(&self.db, conv, conv.messages.len() - 1));
         self.start_stream(&model_id)
     }
 
@@ -43,7 +43,7 @@ impl ChatApp {
         let Some(conv) = self.conversations.get_mut(self.active_conversation) else { return Task::none() };
         conv.add_user_message(&text, None);
         if let Some(msg) = conv.messages.last_mut() { msg.token_count = Some(crate::cost::estimate_tokens(&text)); }
-        Self::handle_db_result(&mut self.error_message, crate::db::save_conversation(&self.db, conv));
+        Self::handle_db_result(&mut self.error_message, crate::db::save_message(&self.db, conv, conv.messages.len() - 1));
         self.start_multi_stream(&model_ids)
     }
 
@@ -58,7 +58,7 @@ impl ChatApp {
         let Some(conv) = self.conversations.get_mut(self.active_conversation) else { return Task::none() };
         conv.add_user_message(&text, None);
         if let Some(msg) = conv.messages.last_mut() { msg.token_count = Some(crate::cost::estimate_tokens(&text)); }
-        Self::handle_db_result(&mut self.error_message, crate::db::save_conversation(&self.db, conv));
+        Self::handle_db_result(&mut self.error_message, crate::db::save_message(&self.db, conv, conv.messages.len() - 1));
         self.start_multi_stream(&all_ids)
     }
 
@@ -69,13 +69,35 @@ impl ChatApp {
             .filter(|m| m.role == Role::Assistant)
             .and_then(|m| m.model.clone())
             .unwrap_or_else(|| self.selected_model.clone());
-        if let Some(last) = conv.messages.last() { if last.role == Role::Assistant { conv.messages.pop(); } }
+        // Keep the previous answer as an inactive version rather than discarding it
+        if let Some(last) = conv.messages.last() { if last.role == Role::Assistant { conv.branch_from(conv.messages.len() - 1); } }
         if conv.messages.is_empty() { return Task::none(); }
         Self::handle_db_result(&mut self.error_message, crate::db::save_conversation(&self.db, conv));
         self.last_latency_ms = None;
         self.start_stream(&retry_model)
     }
 
+    /// Replace an earlier user message with an edited version and resend it to
+    /// the model(s) that answered it. The old turn stays as an inactive version.
+    pub(crate) fn handle_submit_message_edit(&mut self) -> Task<Message> {
+        let Some(idx) = self.editing_message.take() else { return Task::none() };
+        let text = std::mem::take(&mut self.edit_message_value);
+        if text.trim().is_empty() || self.is_active_conv_streaming() { return Task::none(); }
+        let Some(conv) = self.conversations.get_mut(self.active_conversation) else { return Task::none() };
+        let Some(original) = conv.messages.get(idx).filter(|m| m.role == Role::User).cloned() else { return Task::none() };
+        let mut model_ids = conv.turn_models(idx);
+        if model_ids.is_empty() {
+            model_ids.push(original.model.clone().unwrap_or_else(|| self.selected_model.clone()));
+        }
+        self.error_message = None;
+        self.last_latency_ms = None;
+        conv.branch_from(idx);
+        conv.add_user_message_with_images(&text, original.model, original.images);
+        if let Some(msg) = conv.messages.last_mut() { msg.token_count = Some(crate::cost::estimate_tokens(&text)); }
+        Self::handle_db_result(&mut self.error_message, crate::db::save_conversation(&self.db, conv));
+        self.start_multi_stream(&model_ids)
+    }
+
     pub(crate) fn handle_review_with(&mut self, model_id: String) -> Task<Message> {
         let review_idx = self.review_picker.take();
         if self.is_active_conv_streaming() { return Task::none(); }
@@ -86,7 +108,7 @@ impl ChatApp {
         let prompt = format!("[Review request]\nPlease review the following response and provide feedback, corrections, or improvements:\n\n{}", review_content);
         let Some(conv) = self.conversations.get_mut(self.active_conversation) else { return Task::none() };
         conv.add_user_message(&prompt, Some(model_id.clone()));
-        Self::handle_db_result(&mut self.error_message, crate::db::save_conversation(&self.db, conv));
+        Self::handle_db_result(&mut self.error_message, crate::db::save_message(&self.db, conv, conv.messages.len() - 1));
         self.last_latency_ms = None;
         self.start_stream(&model_id)
     }
@@ -94,6 +116,7 @@ impl ChatApp {
     pub(crate) fn handle_analyze_with(&mut self, model_id: String) -> Task<Message> {
         let source_idx = match self.analyze_source_conversation.take() { Some(idx) => idx, None => return Task::none() };
         if self.is_active_conv_streaming() || source_idx >= self.conversations.len() { return Task::none(); }
+        self.ensure_loaded(source_idx);
         let source = &self.conversations[source_idx];
         let mut formatted = format!("[Analyze conversation] Analyzing: \"{}\"\n\n", source.title);
         for msg in &source.messages {
@@ -109,7 +132,7 @@ impl ChatApp {
         formatted.push_str("Please analyze this conversation. Summarize the key points, identify any errors or areas for improvement, and provide your assessment.");
         let Some(conv) = self.conversations.get_mut(self.active_conversation) else { return Task::none() };
         conv.add_user_message(&formatted, Some(model_id.clone()));
-        Self::handle_db_result(&mut self.error_message, crate::db::save_conversation(&self.db, conv));
+        Self::handle_db_result(&mut self.error_message, crate::db::save_message(&self.db, conv, conv.messages.len() - 1));
         self.last_latency_ms = None;
         self.start_stream(&model_id)
     }
@@ -67,7 +67,7 @@ impl ChatApp {
         let Some(conv) = self.conversations.get_mut(self.active_conversation) else { return Task::none() };
         let conv_id = conv.id.clone();
         let msg_index = conv.push_streaming_assistant(Some(model_id.to_string()));
-        let messages = conv.messages.clone();
+        let messages = conv.history_for(model_id);
         let provider_config = self.config.provider_config_for_model(model_id);
         let system_prompt = if !conv.system_prompt.is_empty() {
             Some(conv.system_prompt.clone())
@@ -147,7 +147,7 @@ impl ChatApp {
                     && conv.title.chars().count() <= 30
                     && conv.forked_from.is_none();
 
-                Self::handle_db_result(&mut self.error_message, crate::db::save_conversation(&self.db, conv));
+                Self::handle_db_result(&mut self.error_message, crate::db::save_message(&self.db, conv, stream.message_index));
 
                 if should_auto_title {
                     let conv_id = conv.id.clone();
@@ -167,11 +167,11 @@ impl ChatApp {
 
     pub(crate) fn handle_stream_error(&mut self, id: StreamId, err: String) -> Task<Message> {
         if let Some(stream) = self.active_streams.remove(&id) {
-            let error_content = format!("[Error: {err}]");
+            let error_content = format!("{}{err}]", crate::model::ERROR_PREFIX);
             if let Some(ci) = self.conv_index_by_id(&stream.conversation_id) {
                 let conv = &mut self.conversations[ci];
                 conv.finalize_at(stream.message_index, &error_content);
-                Self::handle_db_result(&mut self.error_message, crate::db::save_conversation(&self.db, conv));
+                Self::handle_db_result(&mut self.error_message, crate::db::save_message(&self.db, conv, stream.message_index));
             }
         }
         self.error_message = Some(err);
@@ -198,8 +198,8 @@ impl ChatApp {
             for stream in to_finalize {
                 let content = if stream.current_response.is_empty() { "[stopped]".to_string() } else { stream.current_response };
                 conv.finalize_at(stream.message_index, &content);
+                Self::handle_db_result(&mut self.error_message, crate::db::save_message(&self.db, conv, stream.message_index));
             }
-            Self::handle_db_result(&mut self.error_message, crate::db::save_conversation(&self.db, conv));
         }
         Task::none()
     }
@@ -211,7 +211,7 @@ impl ChatApp {
             if let Some(ci) = self.conv_index_by_id(&stream.conversation_id) {
                 let conv = &mut self.conversations[ci];
                 conv.finalize_at(stream.message_index, &content);
-                Self::handle_db_result(&mut self.error_message, crate::db::save_conversation(&self.db, conv));
+                Self::handle_db_result(&mut self.error_message, crate::db::save_message(&self.db, conv, stream.message_index));
             }
         }
         Task::none()
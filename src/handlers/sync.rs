@@ -0,0 +1,90 @@
+use chrono::Local;
+use iced::Task;
+
+use crate::app::{ChatApp, Message};
+use crate::sync::SyncReport;
+
+fn describe(report: &SyncReport) -> String {
+    let mut parts = vec![format!("{} changeset(s) merged", report.merged_files)];
+    if report.conversations + report.messages > 0 {
+        parts.push(format!("{} conversation(s) and {} message(s) updated", report.conversations, report.messages));
+    }
+    if report.deleted > 0 {
+        parts.push(format!("{} moved to trash", report.deleted));
+    }
+    if report.conflicts > 0 {
+        parts.push(format!("{} conflicting edit(s) kept as forks", report.conflicts));
+    }
+    if report.unreadable_files > 0 {
+        parts.push(format!("{} changeset(s) not readable yet", report.unreadable_files));
+    }
+    if report.written.is_some() {
+        parts.push("local changes written".to_string());
+    }
+    format!("Synced at {}: {}.", Local::now().format("%H:%M"), parts.join(", "))
+}
+
+impl ChatApp {
+    /// Exchange changesets with the sync folder off the UI thread, through a
+    /// connection of its own; the outcome arrives as `SyncFinished`. Skipped
+    /// while replies are streaming, since merging reloads the conversation
+    /// list; the next tick catches up.
+    pub(crate) fn sync_now(&mut self) -> Task<Message> {
+        let Some(dir) = self.config.sync.dir() else { return Task::none() };
+        let Some(path) = self.db_path.clone() else { return Task::none() };
+        if self.db_locked || self.sync_in_progress || self.is_streaming() {
+            return Task::none();
+        }
+        if std::mem::take(&mut self.sync_reload_pending) {
+            self.reload_keeping_selection();
+        }
+        self.sync_in_progress = true;
+        let key = self.db_key.clone();
+        Task::perform(
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    let conn = crate::db::open_encrypted_at(&path, key.as_deref())?;
+                    crate::sync::run(&conn, &dir)
+                })
+                .await
+                .unwrap_or_else(|e| Err(format!("Sync failed: {e}")))
+            },
+            Message::SyncFinished,
+        )
+    }
+
+    /// Report a finished sync and pick up what it merged. If replies started
+    /// streaming meanwhile, the reload waits for the next sync.
+    pub(crate) fn sync_finished(&mut self, result: Result<SyncReport, String>) {
+        self.sync_in_progress = false;
+        match result {
+            Ok(report) => {
+                log::info!("sync: {report:?}");
+                if report.changed() {
+                    if self.is_streaming() {
+                        self.sync_reload_pending = true;
+                    } else {
+                        self.reload_keeping_selection();
+                    }
+                }
+                self.sync_status = Some(describe(&report));
+            }
+            Err(e) => {
+                log::error!("{e}");
+                self.sync_status = Some(e);
+            }
+        }
+    }
+
+    /// Reload the conversation list after the database changed underneath,
+    /// staying on the open conversation if it still exists.
+    fn reload_keeping_selection(&mut self) {
+        let active_id = self.conversations.get(self.active_conversation).map(|c| c.id.clone());
+        self.reload_conversations();
+        if let Some(idx) = active_id.and_then(|id| self.conv_index_by_id(&id)) {
+            self.active_conversation = idx;
+            self.ensure_loaded(idx);
+            self.refresh_related();
+        }
+    }
+}
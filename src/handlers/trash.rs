@@ -0,0 +1,120 @@
+use iced::Task;
+
+use crate::app::{ChatApp, Message};
+
+/// How long the undo toast stays up after a delete.
+const UNDO_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(8);
+
+impl ChatApp {
+    /// Conversations forked from the one at `idx`.
+    pub fn forks_of(&self, idx: usize) -> Vec<usize> {
+        let Some(id) = self.conversations.get(idx).map(|c| &c.id) else { return Vec::new() };
+        self.conversations.iter()
+            .enumerate()
+            .filter(|(_, c)| c.forked_from.as_ref() == Some(id))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    pub(crate) fn handle_delete_conversation(&mut self, idx: usize) -> Task<Message> {
+        if self.conversations.len() <= 1 || idx >= self.conversations.len() {
+            return Task::none();
+        }
+        // Forks keep their content but lose their parent, so ask first
+        if !self.forks_of(idx).is_empty() && self.pending_delete != Some(idx) {
+            self.pending_delete = Some(idx);
+            return Task::none();
+        }
+        self.pending_delete = None;
+
+        let mut conv = self.conversations[idx].clone();
+        if let Err(e) = crate::db::load_messages(&self.db, &mut conv) {
+            self.error_message = Some(e);
+            return Task::none();
+        }
+        let trash_id = match crate::db::trash_conversation(&self.db, &conv) {
+            Ok(id) => id,
+            Err(e) => {
+                self.error_message = Some(e);
+                return Task::none();
+            }
+        };
+        self.conversations.remove(idx);
+        self.loaded_conversations.retain(|id| *id != conv.id);
+        if self.active_conversation >= self.conversations.len() {
+            self.active_conversation = self.conversations.len() - 1;
+        } else if self.active_conversation > idx {
+            self.active_conversation -= 1;
+        }
+        self.ensure_loaded(self.active_conversation);
+        self.refresh_related();
+        self.show_undo(trash_id, format!("Moved \"{}\" to Trash", conv.title))
+    }
+
+    pub(crate) fn handle_delete_message(&mut self, idx: usize) -> Task<Message> {
+        let Some(conv) = self.conversations.get_mut(self.active_conversation) else { return Task::none() };
+        let Some(msg) = conv.messages.get(idx).cloned() else { return Task::none() };
+        match crate::db::trash_message(&self.db, &conv.id, &msg, true) {
+            Ok(trash_id) => {
+                conv.remove_message(idx);
+                self.show_undo(trash_id, "Message moved to Trash".to_string())
+            }
+            Err(e) => {
+                self.error_message = Some(e);
+                Task::none()
+            }
+        }
+    }
+
+    fn show_undo(&mut self, trash_id: i64, label: String) -> Task<Message> {
+        self.undo = Some((trash_id, label));
+        Task::perform(
+            async { tokio::time::sleep(UNDO_TIMEOUT).await },
+            move |_| Message::ExpireUndo(trash_id),
+        )
+    }
+
+    /// Put a trash entry back where it came from and select its conversation.
+    pub(crate) fn restore_from_trash(&mut self, trash_id: i64) {
+        if self.undo.as_ref().is_some_and(|(id, _)| *id == trash_id) {
+            self.undo = None;
+        }
+        let conv_id = match crate::db::restore_from_trash(&self.db, trash_id) {
+            Ok(conv_id) => conv_id,
+            Err(e) => {
+                self.error_message = Some(e);
+                return;
+            }
+        };
+        self.trash.retain(|t| t.id != trash_id);
+        let idx = match self.conv_index_by_id(&conv_id) {
+            // A restored message: reload the conversation's tree
+            Some(idx) => {
+                self.conversations[idx].unload();
+                idx
+            }
+            None => {
+                let Some(conv) = crate::db::load_summaries(&self.db).into_iter().find(|c| c.id == conv_id) else { return };
+                self.conversations.push(conv);
+                self.conversations.len() - 1
+            }
+        };
+        self.active_conversation = idx;
+        self.ensure_loaded(idx);
+        self.refresh_related();
+    }
+
+    pub(crate) fn refresh_trash(&mut self) {
+        self.trash = crate::db::list_trash(&self.db);
+    }
+
+    /// Permanently delete trash entries older than the retention period.
+    pub(crate) fn purge_expired_trash(&mut self) {
+        let cutoff = chrono::Utc::now() - self.config.trash_retention();
+        match crate::db::purge_trash(&self.db, Some(cutoff)) {
+            Ok(0) => {}
+            Ok(n) => log::info!("purged {n} trash entries older than {cutoff}"),
+            Err(e) => log::warn!("{e}"),
+        }
+    }
+}
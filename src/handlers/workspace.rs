@@ -0,0 +1,104 @@
+use iced::Task;
+
+use crate::app::{ChatApp, Message, View};
+use crate::config::AppConfig;
+use crate::workspace;
+
+impl ChatApp {
+    /// Close the current workspace and open `name`, with its own config and
+    /// database. A new workspace is seeded with `seed`; an existing one loads
+    /// its saved config. Unsaved settings edits are discarded.
+    pub(crate) fn switch_workspace(&mut self, name: String, seed: Option<AppConfig>) -> Task<Message> {
+        if seed.is_none() && name == workspace::active() {
+            return Task::none();
+        }
+        if self.is_streaming() {
+            self.error_message = Some("Wait for responses to finish before switching workspaces.".to_string());
+            return Task::none();
+        }
+        let root = workspace::root();
+        if !workspace::list(&root).contains(&name) {
+            self.error_message = Some(format!("Workspace \"{name}\" no longer exists."));
+            self.workspaces = workspace::list(&root);
+            return Task::none();
+        }
+        workspace::set_active(&name);
+        workspace::remember(&root, &name);
+        log::info!("switched to workspace {name}");
+
+        let config = match seed {
+            Some(config) => {
+                config.save();
+                config
+            }
+            None => AppConfig::load(),
+        };
+        crate::theme::set_theme(config.theme);
+        self.selected_model = config.selected_model.clone()
+            .unwrap_or_else(|| config.active_provider_config().model.clone());
+        self.selected_models.clear();
+        self.credential_profile_input = config.credential_profile.clone();
+        self.config = config;
+        self.config_saved = false;
+
+        let library = Self::open_library(&self.config);
+        self.db = library.db;
+        self.db_path = library.path;
+        self.db_key = library.key;
+        self.db_locked = library.locked;
+        self.error_message = library.error;
+        self.unlock_passphrase.clear();
+        self.unlock_error = None;
+
+        self.view = View::Chat;
+        self.trash.clear();
+        self.undo = None;
+        self.pending_delete = None;
+        self.snapshot_preview = None;
+        self.integrity_report = None;
+        self.sync_status = None;
+        self.vault_status = None;
+        self.sidebar_search_query.clear();
+        self.sidebar_search_results = None;
+        self.sidebar_search_hits.clear();
+        self.clear_highlight();
+        self.workspaces = workspace::list(&root);
+        self.reload_conversations();
+        if self.db_locked {
+            return Task::none();
+        }
+        self.refresh_snapshots();
+        let backup_task = self.run_scheduled_backup();
+        self.purge_expired_trash();
+        let sync_task = self.sync_now();
+        Task::batch([backup_task, sync_task, self.index_embeddings()])
+    }
+
+    /// Switch to the credential profile typed in settings, if it changed.
+    /// Done once rather than per keystroke: switching reloads every API key
+    /// from the keychain, which may prompt for each one.
+    pub(crate) fn apply_credential_profile(&mut self) {
+        let profile = self.credential_profile_input.trim().to_string();
+        if profile != self.config.credential_profile {
+            self.config.set_credential_profile(profile);
+        }
+        self.credential_profile_input = self.config.credential_profile.clone();
+    }
+
+    /// Create a workspace named in the settings form and switch to it. It
+    /// starts with a copy of the current settings.
+    pub(crate) fn create_workspace(&mut self) -> Task<Message> {
+        let root = workspace::root();
+        match workspace::create(&root, &self.new_workspace_name) {
+            Ok(name) => {
+                self.new_workspace_name.clear();
+                let seed = self.config.for_new_workspace();
+                self.switch_workspace(name, Some(seed))
+            }
+            Err(e) => {
+                self.error_message = Some(e);
+                Task::none()
+            }
+        }
+    }
+}
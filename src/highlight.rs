@@ -0,0 +1,158 @@
+/// What a piece of source code is, for colouring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Plain,
+    Keyword,
+    String,
+    Comment,
+    Number,
+}
+
+/// Just enough of a language's lexical rules to colour it.
+struct Syntax {
+    keywords: &'static [&'static str],
+    line_comment: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+}
+
+const C_LIKE_COMMENTS: &[&str] = &["//"];
+
+const RUST: Syntax = Syntax {
+    keywords: &["as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while"],
+    line_comment: C_LIKE_COMMENTS,
+    block_comment: Some(("/*", "*/")),
+    // No single quote: it starts lifetimes far more often than chars
+    quotes: &['"'],
+};
+
+const PYTHON: Syntax = Syntax {
+    keywords: &["and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else", "except", "False", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return", "True", "try", "while", "with", "yield"],
+    line_comment: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+const JAVASCRIPT: Syntax = Syntax {
+    keywords: &["async", "await", "break", "case", "catch", "class", "const", "continue", "default", "delete", "do", "else", "export", "extends", "false", "finally", "for", "from", "function", "if", "import", "in", "instanceof", "interface", "let", "new", "null", "of", "return", "static", "super", "switch", "this", "throw", "true", "try", "type", "typeof", "undefined", "var", "void", "while", "yield"],
+    line_comment: C_LIKE_COMMENTS,
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+};
+
+const GO: Syntax = Syntax {
+    keywords: &["break", "case", "chan", "const", "continue", "default", "defer", "else", "false", "for", "func", "go", "if", "import", "interface", "map", "nil", "package", "range", "return", "select", "struct", "switch", "true", "type", "var"],
+    line_comment: C_LIKE_COMMENTS,
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+};
+
+/// C, C++, C#, Java, Kotlin and Swift share enough to colour them alike.
+const C_FAMILY: Syntax = Syntax {
+    keywords: &["auto", "bool", "break", "case", "catch", "char", "class", "const", "continue", "default", "delete", "do", "double", "else", "enum", "extends", "false", "final", "float", "for", "fun", "func", "if", "implements", "import", "int", "let", "long", "namespace", "new", "null", "nullptr", "override", "package", "private", "protected", "public", "return", "short", "static", "struct", "switch", "template", "this", "throw", "true", "try", "typedef", "unsigned", "using", "val", "var", "virtual", "void", "while"],
+    line_comment: C_LIKE_COMMENTS,
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+};
+
+const SHELL: Syntax = Syntax {
+    keywords: &["case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if", "in", "local", "return", "then", "until", "while"],
+    line_comment: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+const SQL: Syntax = Syntax {
+    keywords: &["and", "as", "by", "create", "delete", "desc", "distinct", "drop", "from", "group", "having", "index", "insert", "into", "join", "left", "limit", "not", "null", "on", "or", "order", "primary", "key", "select", "set", "table", "union", "update", "values", "where", "with"],
+    line_comment: &["--"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['\''],
+};
+
+/// JSON, YAML and TOML: strings, numbers, comments and literals.
+const DATA: Syntax = Syntax {
+    keywords: &["true", "false", "null"],
+    line_comment: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+fn syntax(lang: &str) -> Option<&'static Syntax> {
+    Some(match lang.to_ascii_lowercase().as_str() {
+        "rust" | "rs" => &RUST,
+        "python" | "py" => &PYTHON,
+        "javascript" | "js" | "jsx" | "typescript" | "ts" | "tsx" => &JAVASCRIPT,
+        "go" | "golang" => &GO,
+        "c" | "h" | "cpp" | "c++" | "hpp" | "cs" | "csharp" | "java" | "kotlin" | "kt" | "swift" => &C_FAMILY,
+        "sh" | "bash" | "shell" | "zsh" | "console" => &SHELL,
+        "sql" | "sqlite" | "postgresql" => &SQL,
+        "json" | "yaml" | "yml" | "toml" => &DATA,
+        _ => return None,
+    })
+}
+
+/// Split `code` into coloured pieces. Languages without rules come back as
+/// one plain piece.
+pub fn tokens<'a>(code: &'a str, lang: &str) -> Vec<(Token, &'a str)> {
+    let Some(syntax) = syntax(lang) else { return vec![(Token::Plain, code)] };
+    // SQL keywords are written in either case
+    let ignore_case = std::ptr::eq(syntax, &SQL);
+    let mut out: Vec<(Token, &'a str)> = Vec::new();
+    let mut push = |token: Token, piece: &'a str| match out.last_mut() {
+        // Merge plain runs so the output stays small
+        Some((Token::Plain, last)) if token == Token::Plain => {
+            let start = last.as_ptr() as usize - code.as_ptr() as usize;
+            *last = &code[start..start + last.len() + piece.len()];
+        }
+        _ => out.push((token, piece)),
+    };
+
+    let mut i = 0;
+    while i < code.len() {
+        let rest = &code[i..];
+        let c = rest.chars().next().expect("i is on a char boundary");
+
+        let len = if syntax.line_comment.iter().any(|m| rest.starts_with(m)) {
+            push(Token::Comment, &rest[..rest.find('\n').unwrap_or(rest.len())]);
+            rest.find('\n').unwrap_or(rest.len())
+        } else if let Some((open, close)) = syntax.block_comment.filter(|(open, _)| rest.starts_with(open)) {
+            let len = rest[open.len()..].find(close).map_or(rest.len(), |end| open.len() + end + close.len());
+            push(Token::Comment, &rest[..len]);
+            len
+        } else if syntax.quotes.contains(&c) {
+            let mut escaped = false;
+            let mut len = rest.len();
+            for (j, ch) in rest.char_indices().skip(1) {
+                if escaped {
+                    escaped = false;
+                } else if ch == '\\' {
+                    escaped = true;
+                } else if ch == c {
+                    len = j + 1;
+                    break;
+                } else if ch == '\n' && c != '`' {
+                    // Unterminated: stop at the line's end
+                    len = j;
+                    break;
+                }
+            }
+            push(Token::String, &rest[..len]);
+            len
+        } else if c.is_ascii_digit() {
+            let len = rest.find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '.' || ch == '_')).unwrap_or(rest.len());
+            push(Token::Number, &rest[..len]);
+            len
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest.find(|ch: char| !(ch.is_alphanumeric() || ch == '_')).unwrap_or(rest.len());
+            let word = &rest[..len];
+            let keyword = syntax.keywords.iter().any(|k| if ignore_case { k.eq_ignore_ascii_case(word) } else { *k == word });
+            push(if keyword { Token::Keyword } else { Token::Plain }, word);
+            len
+        } else {
+            push(Token::Plain, &rest[..c.len_utf8()]);
+            c.len_utf8()
+        };
+        i += len.max(1);
+    }
+    out
+}
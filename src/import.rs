@@ -1,87 +1,1094 @@
-use crate::model::{ChatMessage, Conversation, Role};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::model::{new_message_id, ChatMessage, Conversation, Role};
+
+/// Export formats Stoa can read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    ChatGpt,
+    Claude,
+    OpenWebUi,
+    LibreChat,
+    /// One message per line, read with a `JsonlMapping`
+    Jsonl,
+    /// Stoa's own archive, or its JSON export
+    Stoa,
+    /// A transcript such as Stoa's Markdown export or a vault note
+    Markdown,
+}
+
+impl ImportFormat {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ImportFormat::ChatGpt => "ChatGPT",
+            ImportFormat::Claude => "Claude.ai",
+            ImportFormat::OpenWebUi => "Open WebUI",
+            ImportFormat::LibreChat => "LibreChat",
+            ImportFormat::Jsonl => "JSONL",
+            ImportFormat::Stoa => "Stoa archive",
+            ImportFormat::Markdown => "Markdown",
+        }
+    }
+
+    /// Tag added next to "imported"
+    fn tag(&self) -> Option<&'static str> {
+        match self {
+            ImportFormat::ChatGpt => Some("chatgpt"),
+            ImportFormat::Claude => Some("claude"),
+            ImportFormat::OpenWebUi => Some("open-webui"),
+            ImportFormat::LibreChat => Some("librechat"),
+            ImportFormat::Jsonl | ImportFormat::Stoa | ImportFormat::Markdown => None,
+        }
+    }
+}
+
+/// Which fields of a JSONL line hold what. Nested fields are written with
+/// dots (`message.content`); an empty field is not read.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JsonlMapping {
+    /// Lines with the same value form one conversation
+    pub conversation: String,
+    pub role: String,
+    pub content: String,
+    pub timestamp: String,
+    pub model: String,
+    pub title: String,
+    /// Comma-separated role values that mean the user; `system` becomes the
+    /// system prompt and anything else the assistant
+    pub user_roles: String,
+}
+
+impl Default for JsonlMapping {
+    fn default() -> Self {
+        Self {
+            conversation: "conversation_id".to_string(),
+            role: "role".to_string(),
+            content: "content".to_string(),
+            timestamp: "timestamp".to_string(),
+            model: "model".to_string(),
+            title: "title".to_string(),
+            user_roles: "user,human,true".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonlField {
+    Conversation,
+    Role,
+    Content,
+    Timestamp,
+    Model,
+    Title,
+    UserRoles,
+}
+
+impl JsonlField {
+    pub const ALL: [JsonlField; 7] = [
+        JsonlField::Conversation,
+        JsonlField::Role,
+        JsonlField::Content,
+        JsonlField::Timestamp,
+        JsonlField::Model,
+        JsonlField::Title,
+        JsonlField::UserRoles,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            JsonlField::Conversation => "Conversation",
+            JsonlField::Role => "Role",
+            JsonlField::Content => "Content",
+            JsonlField::Timestamp => "Timestamp",
+            JsonlField::Model => "Model",
+            JsonlField::Title => "Title",
+            JsonlField::UserRoles => "User roles",
+        }
+    }
+}
+
+impl JsonlMapping {
+    pub fn get(&self, field: JsonlField) -> &str {
+        match field {
+            JsonlField::Conversation => &self.conversation,
+            JsonlField::Role => &self.role,
+            JsonlField::Content => &self.content,
+            JsonlField::Timestamp => &self.timestamp,
+            JsonlField::Model => &self.model,
+            JsonlField::Title => &self.title,
+            JsonlField::UserRoles => &self.user_roles,
+        }
+    }
+
+    pub fn set(&mut self, field: JsonlField, value: String) {
+        match field {
+            JsonlField::Conversation => self.conversation = value,
+            JsonlField::Role => self.role = value,
+            JsonlField::Content => self.content = value,
+            JsonlField::Timestamp => self.timestamp = value,
+            JsonlField::Model => self.model = value,
+            JsonlField::Title => self.title = value,
+            JsonlField::UserRoles => self.user_roles = value,
+        }
+    }
+}
+
+/// What an import left out, and what became of the conversations it found.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    /// Conversations saved to the library
+    pub imported: usize,
+    /// Conversations the library already had
+    pub already_imported: usize,
+    /// Content that couldn't be imported, counted by kind
+    pub skipped: BTreeMap<String, usize>,
+}
+
+impl ImportReport {
+    fn skip(&mut self, kind: &str) {
+        *self.skipped.entry(kind.to_string()).or_default() += 1;
+    }
+}
+
+/// A parsed file waiting for the user to confirm the import.
+#[derive(Debug, Clone)]
+pub struct ImportPreview {
+    /// File name, for the dialog
+    pub source: String,
+    pub format: ImportFormat,
+    /// The file's contents, kept to re-read JSONL with another mapping
+    pub data: String,
+    /// The mapping `conversations` were read with, for JSONL
+    pub mapping: JsonlMapping,
+    pub conversations: Vec<Conversation>,
+    pub report: ImportReport,
+    /// IDs of conversations the library already has
+    pub duplicates: HashSet<String>,
+    /// Import duplicates as copies with new IDs instead of skipping them
+    pub copy_duplicates: bool,
+}
+
+impl ImportPreview {
+    /// The conversations confirming would save.
+    pub fn new_conversations(&self) -> impl Iterator<Item = &Conversation> {
+        self.conversations.iter().filter(|c| self.copy_duplicates || !self.duplicates.contains(&c.id))
+    }
+
+    pub fn message_count(&self) -> usize {
+        self.conversations.iter().map(|c| c.messages.len() + c.alternates.len()).sum()
+    }
+
+    /// Earliest and latest time in the file.
+    pub fn date_range(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let times = self.conversations.iter().flat_map(|c| {
+            std::iter::once(c.created_at)
+                .chain(c.messages.iter().chain(&c.alternates).filter_map(|m| m.completed_at.or(m.created_at)))
+        });
+        times.fold(None, |range, t| match range {
+            None => Some((t, t)),
+            Some((first, last)) => Some((first.min(t), last.max(t))),
+        })
+    }
+}
+
+/// Recognize an export from its contents.
+pub fn detect(data: &str) -> Option<ImportFormat> {
+    let Ok(value) = serde_json::from_str::<Value>(data) else {
+        let mut lines = data.lines().filter(|line| !line.trim().is_empty()).peekable();
+        lines.peek()?;
+        if lines.all(|line| serde_json::from_str::<Value>(line).is_ok_and(|v| v.is_object())) {
+            return Some(ImportFormat::Jsonl);
+        }
+        return is_markdown_transcript(data).then_some(ImportFormat::Markdown);
+    };
+    let first = match &value {
+        Value::Array(items) => items.first()?,
+        other => other,
+    };
+    if crate::archive::is_archive(&value) {
+        return Some(ImportFormat::Stoa);
+    }
+    if first.get("mapping").is_some() {
+        Some(ImportFormat::ChatGpt)
+    } else if first.get("chat_messages").is_some() {
+        Some(ImportFormat::Claude)
+    } else if first.get("chat").is_some_and(|chat| chat.get("history").is_some() || chat.get("messages").is_some()) {
+        Some(ImportFormat::OpenWebUi)
+    } else if first.get("conversationId").is_some() && first.get("messages").is_some() {
+        Some(ImportFormat::LibreChat)
+    } else if is_stoa_conversation(first) {
+        Some(ImportFormat::Stoa)
+    } else if value.is_object() {
+        // A JSONL file with a single line
+        Some(ImportFormat::Jsonl)
+    } else {
+        None
+    }
+}
+
+/// Read a picked file for the import preview, in `format` or detected from
+/// its contents. The error says why there is nothing to preview.
+pub fn preview(format: Option<ImportFormat>, source: String, data: String, mapping: &JsonlMapping) -> Result<ImportPreview, String> {
+    let Some(format) = format.or_else(|| detect(&data)) else {
+        return Err(format!("{source} isn't an export Stoa recognizes."));
+    };
+    let (conversations, report) = if format == ImportFormat::Stoa {
+        // Say why an archive can't be read instead of finding nothing in it
+        let mut report = ImportReport::default();
+        let conversations = stoa_conversations(&data, &mut report).map_err(|e| format!("Can't import {source}: {e}"))?;
+        (conversations, report)
+    } else {
+        parse(format, &data, mapping)
+    };
+    // A JSONL file may only need another mapping
+    if conversations.is_empty() && format != ImportFormat::Jsonl {
+        return Err(format!("No conversations found in {source}."));
+    }
+    Ok(ImportPreview {
+        source,
+        format,
+        data,
+        mapping: mapping.clone(),
+        conversations,
+        report,
+        duplicates: HashSet::new(),
+        copy_duplicates: false,
+    })
+}
+
+/// Read an export in `format`. `mapping` is only used for JSONL.
+pub fn parse(format: ImportFormat, data: &str, mapping: &JsonlMapping) -> (Vec<Conversation>, ImportReport) {
+    let mut report = ImportReport::default();
+    if format == ImportFormat::Jsonl {
+        let conversations = jsonl_conversations(data, mapping, &mut report);
+        return (conversations, report);
+    }
+    if format == ImportFormat::Stoa {
+        let conversations = stoa_conversations(data, &mut report).unwrap_or_default();
+        return (conversations, report);
+    }
+    if format == ImportFormat::Markdown {
+        return (markdown_conversation(data).into_iter().collect(), report);
+    }
+    let items = match serde_json::from_str::<Value>(data) {
+        Ok(Value::Array(items)) => items,
+        Ok(single) => vec![single],
+        Err(_) => return (Vec::new(), report),
+    };
+    let parse_one = match format {
+        ImportFormat::ChatGpt => chatgpt_conversation,
+        ImportFormat::Claude => claude_conversation,
+        ImportFormat::OpenWebUi => open_webui_conversation,
+        ImportFormat::LibreChat => librechat_conversation,
+        ImportFormat::Jsonl | ImportFormat::Stoa | ImportFormat::Markdown => unreachable!(),
+    };
+    let conversations = items.iter().filter_map(|item| parse_one(item, &mut report)).collect();
+    (conversations, report)
+}
+
+/// Conversations from a Stoa archive or conversation file, counting the
+/// ones that can't be read in the report.
+fn stoa_conversations(data: &str, report: &mut ImportReport) -> Result<Vec<Conversation>, String> {
+    let (conversations, unreadable) = crate::archive::read(data)?;
+    for _ in 0..unreadable {
+        report.skip("unreadable conversation");
+    }
+    Ok(conversations)
+}
 
 /// Import conversations from ChatGPT's export format (conversations.json).
 pub fn import_chatgpt(data: &str) -> Vec<Conversation> {
-    let parsed: Result<Vec<serde_json::Value>, _> = serde_json::from_str(data);
-    let items = match parsed {
-        Ok(v) => v,
-        Err(_) => return Vec::new(),
+    parse(ImportFormat::ChatGpt, data, &JsonlMapping::default()).0
+}
+
+/// Import conversations from a Claude.ai data export (conversations.json).
+pub fn import_claude(data: &str) -> Vec<Conversation> {
+    parse(ImportFormat::Claude, data, &JsonlMapping::default()).0
+}
+
+/// IDs of `incoming` conversations the library already has: the same ID
+/// (most exports keep theirs), or the same title and start time.
+pub fn find_duplicates(incoming: &[Conversation], library: &[Conversation]) -> HashSet<String> {
+    let ids: HashSet<&str> = library.iter().map(|c| c.id.as_str()).collect();
+    let starts: HashSet<(&str, i64)> = library.iter().map(|c| (c.title.as_str(), c.created_at.timestamp())).collect();
+    incoming.iter()
+        .filter(|c| ids.contains(c.id.as_str()) || starts.contains(&(c.title.as_str(), c.created_at.timestamp())))
+        .map(|c| c.id.clone())
+        .collect()
+}
+
+/// Give the `ids` conversations and all their messages fresh IDs, so they
+/// can sit next to the ones they collide with. Forks among `conversations`
+/// follow their source to its new ID.
+pub fn remap_ids(conversations: &mut [Conversation], ids: &HashSet<String>) {
+    let mut renamed = HashMap::new();
+    for conv in conversations.iter_mut().filter(|c| ids.contains(&c.id)) {
+        let new_id = Conversation::new().id;
+        renamed.insert(std::mem::replace(&mut conv.id, new_id.clone()), new_id);
+        // IDs order versions by age, so the new ones are handed out in the old order
+        let mut old_ids: Vec<String> = conv.messages.iter().chain(&conv.alternates).map(|m| m.id.clone()).collect();
+        old_ids.sort();
+        let message_ids: HashMap<String, String> = old_ids.into_iter().map(|id| (id, new_message_id())).collect();
+        for msg in conv.messages.iter_mut().chain(conv.alternates.iter_mut()) {
+            msg.id = message_ids[&msg.id].clone();
+            if let Some(parent) = msg.parent_id.as_ref().and_then(|p| message_ids.get(p)) {
+                msg.parent_id = Some(parent.clone());
+            }
+        }
+    }
+    for conv in conversations.iter_mut() {
+        if let Some(source) = conv.forked_from.as_ref().and_then(|id| renamed.get(id)) {
+            conv.forked_from = Some(source.clone());
+        }
+    }
+}
+
+// ── Shared helpers ──
+
+/// A time as epoch seconds, milliseconds, microseconds or nanoseconds, or
+/// as RFC 3339 or `YYYY-MM-DD HH:MM:SS` text (taken as UTC).
+fn timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    let number = match value {
+        Value::Number(n) => n.as_f64()?,
+        Value::String(s) => {
+            if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+                return Some(t.with_timezone(&Utc));
+            }
+            for format in ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"] {
+                if let Ok(t) = NaiveDateTime::parse_from_str(s, format) {
+                    return Some(t.and_utc());
+                }
+            }
+            s.trim().parse::<f64>().ok()?
+        }
+        _ => return None,
+    };
+    let millis = match number {
+        n if n > 1e17 => n / 1e6,
+        n if n > 1e14 => n / 1e3,
+        n if n > 1e11 => n,
+        n => n * 1e3,
     };
+    DateTime::from_timestamp_millis(millis as i64)
+}
 
-    let mut conversations = Vec::new();
-    for item in &items {
-        let title = item["title"].as_str().unwrap_or("Imported Chat").to_string();
-        let mapping = match item.get("mapping") {
-            Some(m) => m,
-            None => continue,
-        };
+/// Text of a list of content blocks (`[{"type": "text", "text": ...}]`).
+fn text_blocks(value: &Value) -> String {
+    value.as_array()
+        .map(|blocks| {
+            blocks.iter()
+                .filter(|b| b["type"] == "text")
+                .filter_map(|b| b["text"].as_str())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_default()
+}
+
+fn imported_message(role: Role, content: String, model: Option<String>, created_at: Option<DateTime<Utc>>, completed_at: Option<DateTime<Utc>>) -> ChatMessage {
+    ChatMessage {
+        id: String::new(),
+        parent_id: None,
+        completed_at: if role == Role::Assistant { completed_at.or(created_at) } else { None },
+        role,
+        content,
+        streaming: false,
+        model,
+        token_count: None,
+        rating: 0,
+        latency_ms: None,
+        images: Vec::new(),
+        canonical: false,
+        created_at,
+    }
+}
 
-        let mut messages = Vec::new();
+/// A message of an export that stores threads as trees.
+struct TreeNode {
+    id: String,
+    parent: Option<String>,
+    /// `None` for nodes that aren't imported (roots, system and tool messages)
+    message: Option<ChatMessage>,
+}
 
-        // ChatGPT export uses a mapping of node_id -> message node
-        // We need to traverse the tree to get messages in order
-        let mut nodes: Vec<(&str, &serde_json::Value)> = mapping.as_object()
-            .map(|obj| obj.iter().map(|(k, v)| (k.as_str(), v)).collect())
-            .unwrap_or_default();
+/// Chain nodes in list order, for exports without parent links.
+fn link_in_order(nodes: &mut [TreeNode]) {
+    for i in 1..nodes.len() {
+        nodes[i].parent = Some(nodes[i - 1].id.clone());
+    }
+}
+
+/// Lay out an export's messages with fresh IDs. The branch ending at
+/// `current` becomes the active path and every other branch becomes
+/// versions; without `current` the thread ends at the most recent message.
+fn thread_from_tree(mut nodes: Vec<TreeNode>, current: Option<&str>) -> (Vec<ChatMessage>, Vec<ChatMessage>) {
+    let index: HashMap<String, usize> = nodes.iter().enumerate().map(|(i, n)| (n.id.clone(), i)).collect();
+    let parent_of = |i: usize| nodes[i].parent.as_ref().and_then(|p| index.get(p)).copied();
+    // Nearest imported ancestor; the step limit guards against cycles
+    let kept_parent = |i: usize| {
+        let mut next = parent_of(i);
+        for _ in 0..nodes.len() {
+            let p = next?;
+            if nodes[p].message.is_some() {
+                return Some(p);
+            }
+            next = parent_of(p);
+        }
+        None
+    };
+    let created = |i: usize| nodes[i].message.as_ref().and_then(|m| m.created_at);
+
+    let kept: Vec<usize> = (0..nodes.len()).filter(|&i| nodes[i].message.is_some()).collect();
+    let parents: HashMap<usize, Option<usize>> = kept.iter().map(|&i| (i, kept_parent(i))).collect();
+    let mut children: HashMap<Option<usize>, Vec<usize>> = HashMap::new();
+    for &i in &kept {
+        children.entry(parents[&i]).or_default().push(i);
+    }
+    for siblings in children.values_mut() {
+        siblings.sort_by_key(|&i| (created(i), i));
+    }
 
-        // Sort by create_time to get chronological order
-        nodes.sort_by(|a, b| {
-            let time_a = a.1["message"]["create_time"].as_f64().unwrap_or(0.0);
-            let time_b = b.1["message"]["create_time"].as_f64().unwrap_or(0.0);
-            time_a.partial_cmp(&time_b).unwrap_or(std::cmp::Ordering::Equal)
+    // Parents before children, so IDs sort the way the thread reads
+    let mut order = Vec::new();
+    let mut stack: Vec<usize> = children.get(&None).cloned().unwrap_or_default();
+    stack.reverse();
+    while let Some(i) = stack.pop() {
+        order.push(i);
+        if let Some(replies) = children.get(&Some(i)) {
+            stack.extend(replies.iter().rev());
+        }
+    }
+
+    let leaf = current
+        .and_then(|id| index.get(id))
+        .and_then(|&i| if nodes[i].message.is_some() { Some(i) } else { kept_parent(i) })
+        .or_else(|| {
+            order.iter().copied()
+                .filter(|i| !children.contains_key(&Some(*i)))
+                .max_by_key(|&i| (created(i), i))
         });
+    let mut active = HashSet::new();
+    let mut next = leaf;
+    while let Some(i) = next {
+        if !active.insert(i) {
+            break;
+        }
+        next = parents.get(&i).copied().flatten();
+    }
 
-        for (_node_id, node) in &nodes {
-            let msg = match node.get("message") {
-                Some(m) if !m.is_null() => m,
-                _ => continue,
-            };
+    let ids: HashMap<usize, String> = order.iter().map(|&i| (i, new_message_id())).collect();
+    let (mut messages, mut alternates) = (Vec::new(), Vec::new());
+    for &i in &order {
+        let Some(mut msg) = nodes[i].message.take() else { continue };
+        msg.id = ids[&i].clone();
+        msg.parent_id = parents[&i].map(|p| ids[&p].clone());
+        if active.contains(&i) { messages.push(msg) } else { alternates.push(msg) }
+    }
+    (messages, alternates)
+}
 
-            let role_str = msg["author"]["role"].as_str().unwrap_or("");
-            let role = match role_str {
-                "user" => Role::User,
-                "assistant" => Role::Assistant,
-                _ => continue,
-            };
+/// Assemble an imported conversation, keeping the source's ID when it is a
+/// UUID so importing the same file again can be recognized.
+fn conversation(
+    format: ImportFormat,
+    id: Option<&str>,
+    title: Option<&str>,
+    created_at: Option<DateTime<Utc>>,
+    (messages, alternates): (Vec<ChatMessage>, Vec<ChatMessage>),
+) -> Option<Conversation> {
+    let first = messages.first()?;
+    let mut conv = Conversation::new();
+    if let Some(id) = id.filter(|id| uuid::Uuid::parse_str(id).is_ok()) {
+        conv.id = id.to_string();
+    }
+    conv.title = title.map(str::trim).filter(|t| !t.is_empty()).unwrap_or("Imported Chat").to_string();
+    if let Some(created_at) = created_at.or(first.created_at) {
+        conv.created_at = created_at;
+    }
+    conv.messages = messages;
+    conv.alternates = alternates;
+    conv.tags = std::iter::once("imported").chain(format.tag()).map(String::from).collect();
+    Some(conv)
+}
 
-            // Content can be in parts array or content.text
-            let content = if let Some(parts) = msg["content"]["parts"].as_array() {
-                parts.iter()
-                    .filter_map(|p| p.as_str())
-                    .collect::<Vec<_>>()
-                    .join("\n")
-            } else if let Some(text) = msg["content"]["text"].as_str() {
-                text.to_string()
-            } else {
-                continue;
+// ── Stoa ──
+
+/// A conversation as Stoa's JSON export writes it.
+fn is_stoa_conversation(value: &Value) -> bool {
+    value.get("id").is_some()
+        && value.get("title").is_some()
+        && value.get("messages").and_then(Value::as_array).is_some_and(|messages| {
+            messages.iter().all(|m| matches!(m.get("role").and_then(Value::as_str), Some("User" | "Assistant")))
+        })
+}
+
+// ── ChatGPT ──
+
+/// Text of a ChatGPT message. Images live in separate files in the export,
+/// so they are kept as references.
+fn chatgpt_content(content: &Value, report: &mut ImportReport) -> Option<String> {
+    match content["content_type"].as_str().unwrap_or("text") {
+        "text" | "multimodal_text" => {
+            let mut parts = Vec::new();
+            for part in content["parts"].as_array().into_iter().flatten() {
+                if let Some(text) = part.as_str() {
+                    parts.push(text.to_string());
+                    continue;
+                }
+                match part["content_type"].as_str() {
+                    Some("image_asset_pointer") => {
+                        let asset = part["asset_pointer"].as_str().unwrap_or_default();
+                        let asset = asset.split("://").last().unwrap_or(asset);
+                        parts.push(format!("[Image: {asset}]"));
+                        report.skip("image");
+                    }
+                    Some(kind) => report.skip(kind),
+                    None => {}
+                }
+            }
+            Some(parts.join("\n"))
+        }
+        "code" => {
+            let language = content["language"].as_str().filter(|l| *l != "unknown").unwrap_or_default();
+            content["text"].as_str().map(|code| format!("```{language}\n{code}\n```"))
+        }
+        kind => match content["text"].as_str() {
+            Some(text) => Some(text.to_string()),
+            None => {
+                report.skip(kind);
+                None
+            }
+        },
+    }
+}
+
+/// Custom instructions as a system prompt.
+fn chatgpt_custom_instructions(content: &Value) -> String {
+    ["user_profile", "user_instructions"].iter()
+        .filter_map(|field| content[*field].as_str().map(str::trim))
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// A ChatGPT message node, or `None` for content that doesn't become a
+/// message. Custom instructions and visible system messages are collected
+/// into `system_prompt`.
+fn chatgpt_message(msg: &Value, system_prompt: &mut Vec<String>, report: &mut ImportReport) -> Option<ChatMessage> {
+    if msg.is_null() {
+        return None;
+    }
+    if msg["content"]["content_type"] == "user_editable_context" {
+        let instructions = chatgpt_custom_instructions(&msg["content"]);
+        if !instructions.is_empty() {
+            system_prompt.push(instructions);
+        }
+        return None;
+    }
+    let role = match msg["author"]["role"].as_str().unwrap_or("") {
+        "user" => Role::User,
+        "assistant" => Role::Assistant,
+        "system" => {
+            let hidden = msg["metadata"]["is_visually_hidden_from_conversation"].as_bool().unwrap_or(false);
+            match chatgpt_content(&msg["content"], report) {
+                Some(text) if !hidden && !text.trim().is_empty() => system_prompt.push(text),
+                _ => {}
+            }
+            return None;
+        }
+        "tool" => {
+            report.skip("tool output");
+            return None;
+        }
+        _ => return None,
+    };
+    let content = chatgpt_content(&msg["content"], report)?;
+    if content.trim().is_empty() {
+        return None;
+    }
+    let model = msg["metadata"]["model_slug"].as_str().map(|s| s.to_string());
+    Some(imported_message(role, content, model, timestamp(&msg["create_time"]), timestamp(&msg["update_time"])))
+}
+
+/// One conversation of a ChatGPT export. The thread shown in ChatGPT (from
+/// `current_node` back to the root) becomes the active path; edited and
+/// regenerated branches become versions.
+fn chatgpt_conversation(item: &Value, report: &mut ImportReport) -> Option<Conversation> {
+    let mapping = item["mapping"].as_object()?;
+    let mut system_prompt = Vec::new();
+    let mut nodes: Vec<TreeNode> = mapping.iter()
+        .map(|(id, node)| TreeNode {
+            id: id.clone(),
+            parent: node["parent"].as_str().map(String::from),
+            message: chatgpt_message(&node["message"], &mut system_prompt, report),
+        })
+        .collect();
+    if !mapping.values().any(|node| node.get("parent").is_some()) {
+        // Exports without tree links: one thread in time order
+        nodes.sort_by_key(|n| n.message.as_ref().and_then(|m| m.created_at));
+        link_in_order(&mut nodes);
+    }
+    let thread = thread_from_tree(nodes, item["current_node"].as_str());
+    let id = item["conversation_id"].as_str().or(item["id"].as_str());
+    let mut conv = conversation(ImportFormat::ChatGpt, id, item["title"].as_str(), timestamp(&item["create_time"]), thread)?;
+    conv.system_prompt = system_prompt.join("\n\n");
+    Some(conv)
+}
+
+// ── Claude.ai ──
+
+/// Text of a Claude.ai message: `text`, or the text blocks of `content` in
+/// newer exports, followed by the extracted text of its attachments.
+fn claude_message_text(msg: &Value) -> String {
+    let mut text = msg["text"].as_str().unwrap_or_default().to_string();
+    if text.trim().is_empty() {
+        text = text_blocks(&msg["content"]);
+    }
+    let mut parts = vec![text.trim().to_string()];
+    for attachment in msg["attachments"].as_array().into_iter().flatten() {
+        let name = attachment["file_name"].as_str().unwrap_or("attachment");
+        match attachment["extracted_content"].as_str().map(str::trim) {
+            Some(content) if !content.is_empty() => parts.push(format!("**Attachment: {name}**\n\n```\n{content}\n```")),
+            _ => parts.push(format!("**Attachment: {name}**")),
+        }
+    }
+    // Images and other files come without their contents
+    for file in msg["files"].as_array().into_iter().flatten() {
+        if let Some(name) = file["file_name"].as_str() {
+            parts.push(format!("**File: {name}**"));
+        }
+    }
+    parts.retain(|p| !p.is_empty());
+    parts.join("\n\n")
+}
+
+fn claude_conversation(item: &Value, _report: &mut ImportReport) -> Option<Conversation> {
+    let chat_messages = item["chat_messages"].as_array()?;
+    let mut nodes: Vec<TreeNode> = chat_messages.iter().enumerate()
+        .map(|(i, msg)| {
+            let role = match msg["sender"].as_str().unwrap_or("") {
+                "human" => Some(Role::User),
+                "assistant" => Some(Role::Assistant),
+                _ => None,
             };
+            let content = claude_message_text(msg);
+            TreeNode {
+                id: msg["uuid"].as_str().map(String::from).unwrap_or_else(|| format!("#{i}")),
+                parent: msg["parent_message_uuid"].as_str().map(String::from),
+                message: role.filter(|_| !content.is_empty()).map(|role| {
+                    imported_message(role, content, None, timestamp(&msg["created_at"]), timestamp(&msg["updated_at"]))
+                }),
+            }
+        })
+        .collect();
+    if !chat_messages.iter().any(|msg| msg["parent_message_uuid"].is_string()) {
+        link_in_order(&mut nodes);
+    }
+    let thread = thread_from_tree(nodes, None);
+    conversation(ImportFormat::Claude, item["uuid"].as_str(), item["name"].as_str(), timestamp(&item["created_at"]), thread)
+}
+
+// ── Open WebUI ──
 
-            if content.trim().is_empty() {
-                continue;
+fn open_webui_message(msg: &Value, system_prompt: &mut Vec<String>, report: &mut ImportReport) -> Option<ChatMessage> {
+    let content = match &msg["content"] {
+        Value::String(text) => text.clone(),
+        blocks => text_blocks(blocks),
+    };
+    let role = match msg["role"].as_str().unwrap_or("") {
+        "user" => Role::User,
+        "assistant" => Role::Assistant,
+        "system" => {
+            if !content.trim().is_empty() {
+                system_prompt.push(content);
+            }
+            return None;
+        }
+        _ => return None,
+    };
+    let mut images = Vec::new();
+    for file in msg["files"].as_array().into_iter().flatten() {
+        match file["url"].as_str().and_then(|url| url.strip_prefix("data:image/")) {
+            Some(data_url) if file["type"] == "image" => {
+                images.extend(data_url.split_once(";base64,").map(|(_, data)| data.to_string()));
             }
+            _ => report.skip("file"),
+        }
+    }
+    if content.trim().is_empty() && images.is_empty() {
+        return None;
+    }
+    let model = msg["model"].as_str().map(String::from);
+    let mut message = imported_message(role, content, model, timestamp(&msg["timestamp"]), None);
+    message.images = images;
+    Some(message)
+}
 
-            let model = msg["metadata"]["model_slug"].as_str().map(|s| s.to_string());
-
-            messages.push(ChatMessage {
-                role,
-                content,
-                streaming: false,
-                model,
-                token_count: None,
-                rating: 0,
-                latency_ms: None,
-                images: Vec::new(),
+fn open_webui_conversation(item: &Value, report: &mut ImportReport) -> Option<Conversation> {
+    let chat = &item["chat"];
+    let mut system_prompt = Vec::new();
+    let mut nodes = Vec::new();
+    if let Some(messages) = chat["history"]["messages"].as_object() {
+        for (id, msg) in messages {
+            nodes.push(TreeNode {
+                id: id.clone(),
+                parent: msg["parentId"].as_str().map(String::from),
+                message: open_webui_message(msg, &mut system_prompt, report),
+            });
+        }
+    } else {
+        for (i, msg) in chat["messages"].as_array()?.iter().enumerate() {
+            nodes.push(TreeNode {
+                id: format!("#{i}"),
+                parent: None,
+                message: open_webui_message(msg, &mut system_prompt, report),
             });
         }
+        link_in_order(&mut nodes);
+    }
+    let thread = thread_from_tree(nodes, chat["history"]["currentId"].as_str());
+    let title = item["title"].as_str().or(chat["title"].as_str());
+    let mut conv = conversation(ImportFormat::OpenWebUi, item["id"].as_str(), title, timestamp(&item["created_at"]), thread)?;
+    if let Some(prompt) = chat["system"].as_str().filter(|p| !p.trim().is_empty()) {
+        system_prompt.insert(0, prompt.to_string());
+    }
+    conv.system_prompt = system_prompt.join("\n\n");
+    Some(conv)
+}
+
+// ── LibreChat ──
 
-        if !messages.is_empty() {
-            let mut conv = Conversation::new();
-            conv.title = title;
-            conv.messages = messages;
-            conv.tags = vec!["imported".to_string()];
-            conversations.push(conv);
+/// Messages with their parent IDs, from a flat list or one nested under
+/// `children`.
+fn librechat_messages<'a>(messages: &'a Value, parent: Option<&'a str>, out: &mut Vec<(&'a Value, Option<&'a str>)>) {
+    for msg in messages.as_array().into_iter().flatten() {
+        out.push((msg, msg["parentMessageId"].as_str().or(parent)));
+        librechat_messages(&msg["children"], msg["messageId"].as_str(), out);
+    }
+}
+
+fn librechat_conversation(item: &Value, report: &mut ImportReport) -> Option<Conversation> {
+    let mut messages = Vec::new();
+    librechat_messages(&item["messages"], None, &mut messages);
+    let linked = messages.iter().any(|(_, parent)| parent.is_some());
+    let mut nodes: Vec<TreeNode> = messages.iter().enumerate()
+        .map(|(i, (msg, parent))| {
+            let mut text = msg["text"].as_str().unwrap_or_default().to_string();
+            if text.trim().is_empty() {
+                text = text_blocks(&msg["content"]);
+            }
+            let message = if msg["error"].as_bool().unwrap_or(false) {
+                report.skip("errored message");
+                None
+            } else if text.trim().is_empty() {
+                None
+            } else {
+                let role = if msg["isCreatedByUser"].as_bool().unwrap_or(false) { Role::User } else { Role::Assistant };
+                let model = msg["model"].as_str().map(String::from);
+                Some(imported_message(role, text, model, timestamp(&msg["createdAt"]), timestamp(&msg["updatedAt"])))
+            };
+            TreeNode {
+                id: msg["messageId"].as_str().map(String::from).unwrap_or_else(|| format!("#{i}")),
+                parent: parent.map(String::from),
+                message,
+            }
+        })
+        .collect();
+    if !linked {
+        link_in_order(&mut nodes);
+    }
+    let thread = thread_from_tree(nodes, None);
+    conversation(ImportFormat::LibreChat, item["conversationId"].as_str(), item["title"].as_str(), timestamp(&item["createdAt"]), thread)
+}
+
+// ── JSONL ──
+
+/// Value at a dotted path; numeric segments index arrays.
+fn field<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.trim().is_empty() {
+        return None;
+    }
+    path.trim().split('.').try_fold(value, |v, key| match key.parse::<usize>() {
+        Ok(i) if v.is_array() => v.get(i),
+        _ => v.get(key),
+    })
+}
+
+fn field_text(value: &Value, path: &str) -> Option<String> {
+    match field(value, path)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        blocks @ Value::Array(_) => Some(text_blocks(blocks)),
+        _ => None,
+    }
+}
+
+/// Conversations from a JSONL file, one message per line, grouped by the
+/// mapping's conversation field in order of first appearance.
+fn jsonl_conversations(data: &str, mapping: &JsonlMapping, report: &mut ImportReport) -> Vec<Conversation> {
+    struct Group {
+        key: String,
+        title: Option<String>,
+        system_prompt: Vec<String>,
+        nodes: Vec<TreeNode>,
+    }
+
+    let user_roles: Vec<String> = mapping.user_roles.split(',').map(|r| r.trim().to_lowercase()).filter(|r| !r.is_empty()).collect();
+    let mut groups: Vec<Group> = Vec::new();
+    let mut group_index: HashMap<String, usize> = HashMap::new();
+    for line in data.lines().filter(|line| !line.trim().is_empty()) {
+        let Ok(value) = serde_json::from_str::<Value>(line) else {
+            report.skip("unreadable line");
+            continue;
+        };
+        let key = field_text(&value, &mapping.conversation).unwrap_or_default();
+        let pos = *group_index.entry(key.clone()).or_insert_with(|| {
+            groups.push(Group { key, title: None, system_prompt: Vec::new(), nodes: Vec::new() });
+            groups.len() - 1
+        });
+        let group = &mut groups[pos];
+        if group.title.is_none() {
+            group.title = field_text(&value, &mapping.title).filter(|t| !t.trim().is_empty());
         }
+        let Some(content) = field_text(&value, &mapping.content).filter(|c| !c.trim().is_empty()) else {
+            report.skip("line without content");
+            continue;
+        };
+        let role = field_text(&value, &mapping.role).unwrap_or_default().to_lowercase();
+        let role = if user_roles.contains(&role) {
+            Role::User
+        } else if role == "system" {
+            group.system_prompt.push(content);
+            continue;
+        } else {
+            Role::Assistant
+        };
+        let created_at = field(&value, &mapping.timestamp).and_then(timestamp);
+        let model = field_text(&value, &mapping.model).filter(|m| !m.is_empty());
+        group.nodes.push(TreeNode {
+            id: group.nodes.len().to_string(),
+            parent: None,
+            message: Some(imported_message(role, content, model, created_at, None)),
+        });
+    }
+
+    groups.into_iter()
+        .filter_map(|mut group| {
+            link_in_order(&mut group.nodes);
+            let thread = thread_from_tree(group.nodes, None);
+            let mut conv = conversation(ImportFormat::Jsonl, Some(&group.key), group.title.as_deref(), None, thread)?;
+            conv.system_prompt = group.system_prompt.join("\n\n");
+            Some(conv)
+        })
+        .collect()
+}
+
+// ── Markdown ──
+
+/// How a transcript marks who is speaking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LabelStyle {
+    /// `**You**`, `**Assistant (model)** · _time_`, as Stoa exports write
+    Bold,
+    /// `## User`
+    Heading,
+    /// `User: text`
+    Prefix,
+}
+
+/// A speaker line: its style, the role and model it names, its time, and
+/// for prefix labels the text after the colon.
+struct SpeakerLabel<'a> {
+    style: LabelStyle,
+    role: Role,
+    model: Option<String>,
+    created_at: Option<DateTime<Utc>>,
+    rest: &'a str,
+}
+
+/// A speaker name, optionally with the model in parentheses.
+fn speaker_role(name: &str) -> Option<(Role, Option<String>)> {
+    let name = name.trim();
+    let (name, model) = match name.strip_suffix(')').and_then(|n| n.split_once(" (")) {
+        Some((name, model)) => (name.trim(), Some(model.trim().to_string()).filter(|m| !m.is_empty())),
+        None => (name, None),
+    };
+    match name.to_lowercase().as_str() {
+        "you" | "user" | "human" => Some((Role::User, None)),
+        "assistant" | "ai" => Some((Role::Assistant, model)),
+        _ => None,
     }
+}
 
-    conversations
+/// A time as the Markdown export writes it (`YYYY-MM-DD HH:MM UTC`), or in
+/// any form `timestamp` reads.
+fn markdown_time(text: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M UTC")
+        .map(|t| t.and_utc())
+        .ok()
+        .or_else(|| timestamp(&Value::String(text.to_string())))
+}
+
+fn speaker_label(line: &str) -> Option<SpeakerLabel<'_>> {
+    let line = line.trim_end();
+    if let Some(bold) = line.strip_prefix("**") {
+        let (name, after) = bold.split_once("**")?;
+        let (role, model) = speaker_role(name.trim_end_matches(':'))?;
+        let after = after.trim_start();
+        let (created_at, rest) = if let Some(time) = after.strip_prefix('·') {
+            (Some(markdown_time(time.trim().trim_matches('_'))?), "")
+        } else if let Some(rest) = after.strip_prefix(':') {
+            (None, rest.trim_start())
+        } else if name.ends_with(':') || after.is_empty() {
+            (None, after)
+        } else {
+            return None;
+        };
+        return Some(SpeakerLabel { style: LabelStyle::Bold, role, model, created_at, rest });
+    }
+    if let Some(heading) = line.strip_prefix("## ").or_else(|| line.strip_prefix("### ")) {
+        let (role, model) = speaker_role(heading.trim_end_matches(':'))?;
+        return Some(SpeakerLabel { style: LabelStyle::Heading, role, model, created_at: None, rest: "" });
+    }
+    let (name, rest) = line.split_once(':')?;
+    let (role, model) = speaker_role(name)?;
+    Some(SpeakerLabel { style: LabelStyle::Prefix, role, model, created_at: None, rest: rest.trim_start() })
+}
+
+/// Whether `data` looks like a chat transcript in Markdown: someone asks
+/// and an assistant answers.
+fn is_markdown_transcript(data: &str) -> bool {
+    let roles: Vec<Role> = data.lines().filter_map(speaker_label).map(|label| label.role).collect();
+    roles.contains(&Role::User) && roles.contains(&Role::Assistant)
+}
+
+/// A YAML scalar as the vault export writes it: bare or double-quoted.
+fn yaml_value(text: &str) -> String {
+    let text = text.trim();
+    let Some(quoted) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) else {
+        return text.trim_matches('\'').to_string();
+    };
+    let mut out = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => out.push('\n'),
+                Some(escaped) => out.push(escaped),
+                None => {}
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// The keys of YAML front matter, with lists (block or `[a, b]`) as their
+/// items. Only the flat layout notes use is read.
+fn front_matter(lines: &[&str]) -> HashMap<String, Vec<String>> {
+    let mut fields: HashMap<String, Vec<String>> = HashMap::new();
+    let mut key = None;
+    for line in lines {
+        if let (Some(item), Some(key)) = (line.trim_start().strip_prefix("- "), &key) {
+            fields.entry(String::clone(key)).or_default().push(yaml_value(item));
+            continue;
+        }
+        let Some((name, value)) = line.split_once(':') else { continue };
+        let name = name.trim().to_string();
+        let value = value.trim();
+        let values = match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            Some(items) => items.split(',').map(yaml_value).filter(|v| !v.is_empty()).collect(),
+            None if value.is_empty() => Vec::new(),
+            None => vec![yaml_value(value)],
+        };
+        fields.insert(name.clone(), values);
+        key = Some(name);
+    }
+    fields
+}
+
+/// A transcript in Markdown: Stoa's export and vault notes, or the common
+/// `User:` prefix and `## User` heading layouts. The first label sets the
+/// style, so a reply quoting another style isn't split; labels inside code
+/// blocks are never read. Front matter and the export's `**Tags:**` and
+/// `**Created:**` lines restore tags, folder and start time.
+fn markdown_conversation(data: &str) -> Option<Conversation> {
+    let mut lines: Vec<&str> = data.lines().collect();
+    let mut meta = HashMap::new();
+    if lines.first().is_some_and(|l| l.trim_end() == "---")
+        && let Some(end) = lines.iter().skip(1).position(|l| l.trim_end() == "---")
+    {
+        meta = front_matter(&lines[1..=end]);
+        lines.drain(..=end + 1);
+    }
+    let first = |key: &str| meta.get(key).and_then(|v| v.first()).filter(|v| !v.is_empty()).cloned();
+    let mut title = first("title");
+    let mut tags = meta.get("tags").cloned().unwrap_or_default();
+    let mut created_at = first("created").and_then(|t| markdown_time(&t));
+    let mut system_prompt = Vec::new();
+    let mut in_callout = false;
+
+    let mut style = None;
+    let mut in_code = false;
+    let mut nodes: Vec<TreeNode> = Vec::new();
+    let mut body: Vec<&str> = Vec::new();
+    let finish = |nodes: &mut Vec<TreeNode>, body: &mut Vec<&str>| {
+        let Some(message) = nodes.last_mut().and_then(|n| n.message.as_mut()) else { return };
+        // The separator after each message isn't part of it
+        while body.last().is_some_and(|l| l.trim().is_empty() || l.trim_end() == "---") {
+            body.pop();
+        }
+        let start = body.iter().position(|l| !l.trim().is_empty()).unwrap_or(body.len());
+        message.content = body[start..].join("\n");
+        body.clear();
+    };
+    for line in lines {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+        }
+        let label = (!in_code).then(|| speaker_label(line)).flatten()
+            .filter(|label| style.is_none_or(|style| label.style == style));
+        if let Some(label) = label {
+            finish(&mut nodes, &mut body);
+            style = Some(label.style);
+            let message = imported_message(label.role, String::new(), label.model, label.created_at, None);
+            nodes.push(TreeNode { id: nodes.len().to_string(), parent: None, message: Some(message) });
+            body.push(label.rest);
+        } else if !nodes.is_empty() {
+            body.push(line);
+        } else if let Some(heading) = line.strip_prefix("# ") {
+            title.get_or_insert_with(|| heading.trim().to_string());
+        } else if let Some(list) = line.strip_prefix("**Tags:**") {
+            tags.extend(list.split(',').map(str::trim).filter(|t| !t.is_empty()).map(String::from));
+        } else if let Some(time) = line.strip_prefix("**Created:**") {
+            created_at = created_at.or_else(|| markdown_time(time));
+        } else if line.starts_with("> [!note] System prompt") {
+            in_callout = true;
+        } else if let Some(quoted) = line.strip_prefix('>').filter(|_| in_callout) {
+            system_prompt.push(quoted.strip_prefix(' ').unwrap_or(quoted));
+        } else {
+            in_callout = false;
+        }
+    }
+    finish(&mut nodes, &mut body);
+    nodes.retain(|n| n.message.as_ref().is_some_and(|m| !m.content.is_empty()));
+
+    link_in_order(&mut nodes);
+    let thread = thread_from_tree(nodes, None);
+    let mut conv = conversation(ImportFormat::Markdown, first("id").as_deref(), title.as_deref(), created_at, thread)?;
+    for tag in tags {
+        if !conv.tags.contains(&tag) {
+            conv.tags.push(tag);
+        }
+    }
+    conv.folder = first("folder");
+    conv.system_prompt = system_prompt.join("\n");
+    Some(conv)
 }
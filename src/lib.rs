@@ -1,15 +1,25 @@
 pub mod api;
 pub mod app;
+pub mod archive;
+pub mod backup;
 mod handlers;
 pub mod commands;
 pub mod config;
 pub mod cost;
+pub mod dataset;
 pub mod db;
 pub mod diff;
 pub mod export;
+pub mod highlight;
 pub mod import;
 pub mod model;
+pub mod report;
+pub mod search;
+pub mod semantic;
 pub mod shortcuts;
+pub mod sync;
 pub mod theme;
 pub mod ui;
+pub mod vault;
 pub mod web_search;
+pub mod workspace;
@@ -4,6 +4,10 @@ use stoa::app::ChatApp;
 fn main() -> iced::Result {
     env_logger::init();
     info!("starting...");
+    if let Err(e) = stoa::workspace::init(std::env::args().skip(1)) {
+        eprintln!("{e}");
+        std::process::exit(2);
+    }
 
     let result = iced::application(ChatApp::new, ChatApp::update, ChatApp::view)
         .title("Stoa")
@@ -1,3 +1,6 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Local, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -7,8 +10,117 @@ pub enum Role {
     Assistant,
 }
 
+/// Which replies each model sees when a conversation has multi-model turns.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ThreadMode {
+    /// Every model sees every reply, as if it had written them all
+    #[default]
+    Shared,
+    /// Each model sees only its own replies, or the turn's canonical reply
+    Isolated,
+}
+
+impl ThreadMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ThreadMode::Shared => "Shared",
+            ThreadMode::Isolated => "Isolated",
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThreadMode::Shared => "shared",
+            ThreadMode::Isolated => "isolated",
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "isolated" => ThreadMode::Isolated,
+            _ => ThreadMode::Shared,
+        }
+    }
+}
+
+/// Sidebar section a conversation falls into, by its last activity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DateGroup {
+    Today,
+    Yesterday,
+    Last7Days,
+    Older,
+}
+
+impl DateGroup {
+    /// Bucket a (local) calendar date relative to `today`.
+    pub fn of(date: NaiveDate, today: NaiveDate) -> Self {
+        match (today - date).num_days() {
+            ..=0 => DateGroup::Today,
+            1 => DateGroup::Yesterday,
+            2..=7 => DateGroup::Last7Days,
+            _ => DateGroup::Older,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DateGroup::Today => "Today",
+            DateGroup::Yesterday => "Yesterday",
+            DateGroup::Last7Days => "Last 7 days",
+            DateGroup::Older => "Older",
+        }
+    }
+}
+
+/// An inclusive range of local calendar days, open-ended on either side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl DateRange {
+    /// Parse `YYYY-MM-DD` bounds; blank or invalid input leaves that side open.
+    pub fn parse(from: &str, to: &str) -> Self {
+        let day = |s: &str| NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d").ok();
+        Self { from: day(from), to: day(to) }
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.from.is_none() && self.to.is_none()
+    }
+
+    /// The range as UTC instants: inclusive start, exclusive end.
+    pub fn utc_bounds(&self) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        let midnight = |day: NaiveDate| day.and_time(chrono::NaiveTime::MIN)
+            .and_local_timezone(Local)
+            .earliest()
+            .map(|t| t.with_timezone(&Utc));
+        (self.from.and_then(midnight), self.to.and_then(|d| d.succ_opt()).and_then(midnight))
+    }
+
+    /// Whether `t` falls inside the range. Items without a timestamp only
+    /// match an unbounded range.
+    pub fn contains(&self, t: Option<DateTime<Utc>>) -> bool {
+        let Some(t) = t else { return self.is_unbounded() };
+        let day = t.with_timezone(&Local).date_naive();
+        self.from.is_none_or(|from| day >= from) && self.to.is_none_or(|to| day <= to)
+    }
+}
+
+/// Generate a message ID. UUIDv7 is time-ordered, so sibling versions sort by creation.
+pub fn new_message_id() -> String {
+    Uuid::now_v7().to_string()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
+    #[serde(default = "new_message_id")]
+    pub id: String,
+    /// The message this one replies to; `None` for the first message of a thread
+    #[serde(default)]
+    pub parent_id: Option<String>,
     pub role: Role,
     pub content: String,
     #[serde(default)]
@@ -26,6 +138,82 @@ pub struct ChatMessage {
     /// Base64-encoded images attached to this message
     #[serde(default)]
     pub images: Vec<String>,
+    /// The reply other models see for this turn in isolated threads
+    #[serde(default)]
+    pub canonical: bool,
+    /// When the message was sent, or when the reply started streaming
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    /// When the reply finished streaming (assistant messages only)
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// Prefix of the content saved for a reply that failed.
+pub const ERROR_PREFIX: &str = "[Error: ";
+
+impl ChatMessage {
+    /// Whether this is a reply that failed instead of an answer.
+    pub fn is_error(&self) -> bool {
+        self.role == Role::Assistant && self.content.starts_with(ERROR_PREFIX)
+    }
+}
+
+/// A user message, or the run of assistant replies that answer it (several
+/// when a turn was sent to more than one model).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageGroup {
+    User(usize),
+    Assistants(Vec<usize>), // indices of consecutive assistant messages
+}
+
+pub fn group_messages(messages: &[ChatMessage]) -> Vec<MessageGroup> {
+    let mut groups = Vec::new();
+    let mut i = 0;
+    while i < messages.len() {
+        if messages[i].role == Role::User {
+            groups.push(MessageGroup::User(i));
+            i += 1;
+        } else {
+            let start = i;
+            while i < messages.len() && messages[i].role == Role::Assistant {
+                i += 1;
+            }
+            groups.push(MessageGroup::Assistants((start..i).collect()));
+        }
+    }
+    groups
+}
+
+/// `items` ordered so each message comes after its parent, for messages
+/// from another machine: IDs sort by creation time, but one written where
+/// the clock runs behind can sort before the message it answers. ID order
+/// still decides between siblings, and messages caught in a parent cycle
+/// keep their place at the end.
+pub fn parents_first<T>(items: Vec<T>, message: impl Fn(&T) -> &ChatMessage) -> Vec<T> {
+    let ids: HashSet<&str> = items.iter().map(|item| message(item).id.as_str()).collect();
+    let mut children: HashMap<Option<&str>, Vec<usize>> = HashMap::new();
+    for (i, item) in items.iter().enumerate() {
+        let parent = message(item).parent_id.as_deref().filter(|p| ids.contains(p));
+        children.entry(parent).or_default().push(i);
+    }
+    for siblings in children.values_mut() {
+        siblings.sort_by(|&a, &b| message(&items[a]).id.cmp(&message(&items[b]).id));
+    }
+    let mut order = Vec::with_capacity(items.len());
+    let mut stack: Vec<usize> = children.get(&None).cloned().unwrap_or_default();
+    stack.reverse();
+    while let Some(i) = stack.pop() {
+        order.push(i);
+        if let Some(replies) = children.get(&Some(message(&items[i]).id.as_str())) {
+            stack.extend(replies.iter().rev());
+        }
+    }
+    let placed: HashSet<usize> = order.iter().copied().collect();
+    order.extend((0..items.len()).filter(|i| !placed.contains(i)));
+
+    let mut slots: Vec<Option<T>> = items.into_iter().map(Some).collect();
+    order.into_iter().filter_map(|i| slots[i].take()).collect()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +231,26 @@ pub struct Conversation {
     pub forked_from: Option<String>,
     #[serde(default)]
     pub folder: Option<String>,
+    /// Messages on inactive branches (older versions from retries and edits).
+    /// `messages` is always the active path from the root.
+    #[serde(default)]
+    pub alternates: Vec<ChatMessage>,
+    #[serde(default)]
+    pub thread_mode: ThreadMode,
+    #[serde(default = "Utc::now")]
+    pub created_at: DateTime<Utc>,
+    /// Set while only the conversation's summary is in memory; `messages`
+    /// and `alternates` stay empty until it is loaded.
+    #[serde(skip)]
+    pub unloaded: Option<ConversationStats>,
+}
+
+/// Totals for a conversation's active path, available without its messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConversationStats {
+    pub message_count: usize,
+    pub cost: f64,
+    pub last_message_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -72,15 +280,25 @@ impl Conversation {
             system_prompt: String::new(),
             forked_from: None,
             folder: None,
+            alternates: Vec::new(),
+            thread_mode: ThreadMode::Shared,
+            created_at: Utc::now(),
+            unloaded: None,
         }
     }
 
     pub fn fork(&self, up_to_index: usize) -> Self {
-        let messages: Vec<ChatMessage> = self.messages.iter()
+        let mut messages: Vec<ChatMessage> = self.messages.iter()
             .take(up_to_index + 1)
             .filter(|m| !m.streaming)
             .cloned()
             .collect();
+        // The fork is a new thread, so its messages get fresh IDs
+        let mut parent_id = None;
+        for msg in &mut messages {
+            msg.id = new_message_id();
+            msg.parent_id = parent_id.replace(msg.id.clone());
+        }
         Self {
             id: Uuid::new_v4().to_string(),
             title: format!("Fork of {}", self.title),
@@ -90,11 +308,56 @@ impl Conversation {
             system_prompt: self.system_prompt.clone(),
             forked_from: Some(self.id.clone()),
             folder: self.folder.clone(),
+            alternates: Vec::new(),
+            thread_mode: self.thread_mode,
+            created_at: Utc::now(),
+            unloaded: None,
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.unloaded.is_none()
+    }
+
+    /// Message count, cost and last message time, from the stored summary
+    /// when the messages are not loaded.
+    pub fn stats(&self) -> ConversationStats {
+        if let Some(stats) = self.unloaded {
+            return stats;
         }
+        ConversationStats {
+            message_count: self.messages.iter().filter(|m| !m.streaming).count(),
+            cost: crate::cost::conversation_cost(&self.messages),
+            last_message_at: self.messages.iter().filter_map(|m| m.completed_at.or(m.created_at)).max(),
+        }
+    }
+
+    /// Drop the messages from memory, keeping only the summary.
+    pub fn unload(&mut self) {
+        if !self.is_loaded() { return; }
+        self.unloaded = Some(self.stats());
+        self.messages = Vec::new();
+        self.alternates = Vec::new();
+    }
+
+    /// The most recent time anything happened in this conversation.
+    pub fn last_activity(&self) -> DateTime<Utc> {
+        let last_message_at = match self.unloaded {
+            Some(stats) => stats.last_message_at,
+            None => self.messages.iter().filter_map(|m| m.completed_at.or(m.created_at)).max(),
+        };
+        last_message_at.map_or(self.created_at, |t| t.max(self.created_at))
+    }
+
+    fn last_message_id(&self) -> Option<String> {
+        self.messages.last().map(|m| m.id.clone())
     }
 
     pub fn add_user_message(&mut self, content: &str, target_model: Option<String>) {
+        let parent_id = self.last_message_id();
         self.messages.push(ChatMessage {
+            id: new_message_id(),
+            parent_id,
             role: Role::User,
             content: content.to_string(),
             streaming: false,
@@ -103,6 +366,9 @@ impl Conversation {
             rating: 0,
             latency_ms: None,
             images: Vec::new(),
+            canonical: false,
+            created_at: Some(Utc::now()),
+            completed_at: None,
         });
         if self.title == "New Chat" && !content.trim().is_empty() {
             self.title = content.chars().take(30).collect();
@@ -110,7 +376,10 @@ impl Conversation {
     }
 
     pub fn add_user_message_with_images(&mut self, content: &str, target_model: Option<String>, images: Vec<String>) {
+        let parent_id = self.last_message_id();
         self.messages.push(ChatMessage {
+            id: new_message_id(),
+            parent_id,
             role: Role::User,
             content: content.to_string(),
             streaming: false,
@@ -119,6 +388,9 @@ impl Conversation {
             rating: 0,
             latency_ms: None,
             images,
+            canonical: false,
+            created_at: Some(Utc::now()),
+            completed_at: None,
         });
         if self.title == "New Chat" && !content.trim().is_empty() {
             self.title = content.chars().take(30).collect();
@@ -127,7 +399,10 @@ impl Conversation {
 
     pub fn push_streaming_assistant(&mut self, model: Option<String>) -> usize {
         let idx = self.messages.len();
+        let parent_id = self.last_message_id();
         self.messages.push(ChatMessage {
+            id: new_message_id(),
+            parent_id,
             role: Role::Assistant,
             content: String::new(),
             streaming: true,
@@ -136,6 +411,9 @@ impl Conversation {
             rating: 0,
             latency_ms: None,
             images: Vec::new(),
+            canonical: false,
+            created_at: Some(Utc::now()),
+            completed_at: None,
         });
         idx
     }
@@ -161,6 +439,124 @@ impl Conversation {
             if msg.role == Role::Assistant {
                 msg.content = content.to_string();
                 msg.streaming = false;
+                msg.completed_at = Some(Utc::now());
+            }
+        }
+    }
+
+    /// Move the message at `index` and everything after it onto an inactive
+    /// branch, so a new version can be appended in its place.
+    pub fn branch_from(&mut self, index: usize) {
+        if index >= self.messages.len() { return; }
+        let tail = self.messages.split_off(index);
+        self.alternates.extend(tail.into_iter().filter(|m| !m.streaming));
+    }
+
+    /// All versions of the message at `index` (itself included), oldest first.
+    pub fn siblings(&self, index: usize) -> Vec<&ChatMessage> {
+        let Some(msg) = self.messages.get(index) else { return Vec::new() };
+        let mut siblings: Vec<&ChatMessage> = std::iter::once(msg)
+            .chain(self.alternates.iter().filter(|m| m.parent_id == msg.parent_id && m.role == msg.role))
+            .collect();
+        siblings.sort_by(|a, b| a.id.cmp(&b.id));
+        siblings
+    }
+
+    /// (1-based position, total) of the message at `index` among its versions.
+    pub fn version_info(&self, index: usize) -> (usize, usize) {
+        let Some(msg) = self.messages.get(index) else { return (0, 0) };
+        let siblings = self.siblings(index);
+        let pos = siblings.iter().position(|m| m.id == msg.id).unwrap_or(0);
+        (pos + 1, siblings.len())
+    }
+
+    /// Switch the message at `index` to a neighbouring version, restoring the
+    /// newest branch below it. Returns false if there is no version that way.
+    pub fn switch_version(&mut self, index: usize, delta: i32) -> bool {
+        let siblings = self.siblings(index);
+        let Some(pos) = siblings.iter().position(|m| m.id == self.messages[index].id) else { return false };
+        let target = pos as i64 + delta as i64;
+        if target < 0 || target as usize >= siblings.len() { return false; }
+        let target_id = siblings[target as usize].id.clone();
+
+        self.branch_from(index);
+        let mut next_id = Some(target_id);
+        while let Some(id) = next_id.take() {
+            let Some(pos) = self.alternates.iter().position(|m| m.id == id) else { break };
+            let msg = self.alternates.remove(pos);
+            next_id = self.alternates.iter()
+                .filter(|m| m.parent_id.as_deref() == Some(msg.id.as_str()))
+                .map(|m| m.id.clone())
+                .max();
+            self.messages.push(msg);
+        }
+        true
+    }
+
+    /// Models that answered the user message at `index`, in reply order.
+    pub fn turn_models(&self, index: usize) -> Vec<String> {
+        let mut models = Vec::new();
+        for msg in self.messages.iter().skip(index + 1).take_while(|m| m.role == Role::Assistant) {
+            if let Some(model) = &msg.model && !models.contains(model) {
+                models.push(model.clone());
+            }
+        }
+        models
+    }
+
+    /// Indices of the assistant replies in the same turn as the message at `index`.
+    pub fn turn_of(&self, index: usize) -> std::ops::Range<usize> {
+        let is_reply = |i: usize| self.messages.get(i).is_some_and(|m| m.role == Role::Assistant);
+        if !is_reply(index) { return index..index; }
+        let mut start = index;
+        while start > 0 && is_reply(start - 1) { start -= 1; }
+        let mut end = index + 1;
+        while is_reply(end) { end += 1; }
+        start..end
+    }
+
+    /// Mark the reply at `index` as its turn's canonical answer (or unmark it).
+    pub fn toggle_canonical(&mut self, index: usize) {
+        let Some(msg) = self.messages.get(index) else { return };
+        let marked = !msg.canonical;
+        for i in self.turn_of(index) {
+            self.messages[i].canonical = marked && i == index;
+        }
+    }
+
+    /// The history `model_id` should receive, according to the thread mode.
+    /// In isolated threads each turn keeps only that model's own reply,
+    /// falling back to the canonical reply when the model did not answer.
+    pub fn history_for(&self, model_id: &str) -> Vec<ChatMessage> {
+        if self.thread_mode == ThreadMode::Shared {
+            return self.messages.clone();
+        }
+        let mut history = Vec::new();
+        let mut i = 0;
+        while i < self.messages.len() {
+            if self.messages[i].role == Role::User {
+                history.push(self.messages[i].clone());
+                i += 1;
+                continue;
+            }
+            let turn = self.turn_of(i);
+            let replies = &self.messages[turn.clone()];
+            let own = replies.iter().rev().find(|m| m.model.as_deref() == Some(model_id));
+            if let Some(reply) = own.or_else(|| replies.iter().find(|m| m.canonical)) {
+                history.push(reply.clone());
+            }
+            i = turn.end;
+        }
+        history
+    }
+
+    /// Remove the message at `index`, re-attaching its replies to its parent.
+    pub fn remove_message(&mut self, index: usize) {
+        if index >= self.messages.len() { return; }
+        let removed = self.messages.remove(index);
+        for msg in self.messages.iter_mut().chain(self.alternates.iter_mut()) {
+            if msg.parent_id.as_deref() == Some(removed.id.as_str()) {
+                msg.parent_id = removed.parent_id.clone();
             }
         }
     }
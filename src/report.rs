@@ -0,0 +1,242 @@
+use crate::cost;
+use crate::export::{escape_html, html_document, markdown_html};
+use crate::model::{group_messages, ChatMessage, Conversation, MessageGroup, Role};
+use crate::theme::ThemeName;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+    /// One row per answer, for spreadsheets
+    Csv,
+}
+
+impl ReportFormat {
+    pub const ALL: [ReportFormat; 3] = [ReportFormat::Markdown, ReportFormat::Html, ReportFormat::Csv];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReportFormat::Markdown => "Markdown",
+            ReportFormat::Html => "HTML",
+            ReportFormat::Csv => "CSV",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Markdown => "md",
+            ReportFormat::Html => "html",
+            ReportFormat::Csv => "csv",
+        }
+    }
+}
+
+/// The comparison report dialog's choices.
+#[derive(Debug, Clone)]
+pub struct ReportDialog {
+    /// A reply in the turn the dialog was opened from
+    pub turn: Option<usize>,
+    /// Cover every multi-model turn instead of `turn`
+    pub whole: bool,
+    pub format: ReportFormat,
+    /// Where the last report went, or why it failed
+    pub status: Option<String>,
+}
+
+/// A prompt and the answers being compared.
+#[derive(Debug, Clone)]
+pub struct Turn<'a> {
+    pub prompt: Option<&'a ChatMessage>,
+    pub replies: Vec<&'a ChatMessage>,
+}
+
+/// The turn holding the reply at `index`, or with `None` every turn of the
+/// conversation that more than one model answered. Failed replies aren't
+/// answers, so they are left out.
+pub fn turns(conv: &Conversation, index: Option<usize>) -> Vec<Turn<'_>> {
+    let turn = |range: &[usize]| {
+        let prompt = range.first()
+            .and_then(|&start| start.checked_sub(1))
+            .map(|i| &conv.messages[i])
+            .filter(|m| m.role == Role::User);
+        let replies = range.iter().map(|&i| &conv.messages[i]).filter(|m| !m.streaming && !m.is_error()).collect();
+        Turn { prompt, replies }
+    };
+    let turns: Vec<Turn> = match index {
+        Some(index) => vec![turn(&conv.turn_of(index).collect::<Vec<_>>())],
+        None => group_messages(&conv.messages).into_iter()
+            .filter_map(|group| match group {
+                MessageGroup::Assistants(indices) => Some(turn(&indices)),
+                MessageGroup::User(_) => None,
+            })
+            .filter(|t| t.replies.len() > 1)
+            .collect(),
+    };
+    turns.into_iter().filter(|t| !t.replies.is_empty()).collect()
+}
+
+fn model_name(msg: &ChatMessage, position: usize) -> String {
+    msg.model.clone().unwrap_or_else(|| format!("Reply {}", position + 1))
+}
+
+/// Tokens a reply used, estimated from its length when the API didn't say.
+fn tokens(msg: &ChatMessage) -> u32 {
+    msg.token_count.unwrap_or_else(|| cost::estimate_tokens(&msg.content))
+}
+
+fn reply_cost(msg: &ChatMessage) -> f64 {
+    cost::message_cost(msg.model.as_deref().unwrap_or(""), &msg.role, tokens(msg))
+}
+
+fn rating_mark(rating: i8) -> &'static str {
+    match rating {
+        r if r > 0 => "\u{1F44D}",
+        r if r < 0 => "\u{1F44E}",
+        _ => "",
+    }
+}
+
+fn latency(msg: &ChatMessage) -> String {
+    msg.latency_ms.map(|ms| format!("{ms} ms")).unwrap_or_default()
+}
+
+/// How much each pair of replies agrees, in percent; 100 on the diagonal.
+pub fn agreement_matrix(replies: &[&ChatMessage]) -> Vec<Vec<f32>> {
+    replies.iter().enumerate()
+        .map(|(i, a)| replies.iter().enumerate()
+            .map(|(j, b)| if i == j { 100.0 } else { crate::diff::agreement_percentage(&a.content, &b.content) })
+            .collect())
+        .collect()
+}
+
+fn table_cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+fn markdown(title: &str, turns: &[Turn]) -> String {
+    let mut md = format!("# Comparison: {title}\n\n");
+    for (n, turn) in turns.iter().enumerate() {
+        md.push_str(&format!("## Turn {}\n\n", n + 1));
+        if let Some(prompt) = turn.prompt {
+            for line in prompt.content.lines() {
+                md.push_str(&format!("> {line}\n"));
+            }
+            md.push('\n');
+        }
+        let names: Vec<String> = turn.replies.iter().enumerate().map(|(i, m)| table_cell(&model_name(m, i))).collect();
+
+        md.push_str("| Model | Tokens | Latency | Cost | Rating |\n|---|---:|---:|---:|:---:|\n");
+        for (msg, name) in turn.replies.iter().zip(&names) {
+            md.push_str(&format!("| {name} | {} | {} | ${:.4} | {} |\n", tokens(msg), latency(msg), reply_cost(msg), rating_mark(msg.rating)));
+        }
+
+        if turn.replies.len() > 1 {
+            md.push_str(&format!("\n**Agreement**\n\n| | {} |\n|---|{}\n", names.join(" | "), "---:|".repeat(names.len())));
+            for (row, name) in agreement_matrix(&turn.replies).iter().zip(&names) {
+                let cells: Vec<String> = row.iter().map(|pct| format!("{pct:.0}%")).collect();
+                md.push_str(&format!("| {name} | {} |\n", cells.join(" | ")));
+            }
+        }
+
+        for (i, msg) in turn.replies.iter().enumerate() {
+            md.push_str(&format!("\n### {}\n\n{}\n", model_name(msg, i), msg.content));
+        }
+        md.push('\n');
+    }
+    md
+}
+
+fn html(title: &str, turns: &[Turn], theme: ThemeName) -> String {
+    let mut body = format!("<h1>Comparison: {}</h1>\n", escape_html(title));
+    for (n, turn) in turns.iter().enumerate() {
+        body.push_str(&format!("<h2>Turn {}</h2>\n", n + 1));
+        if let Some(prompt) = turn.prompt {
+            body.push_str(&format!(
+                "<div class=\"message user\"><div class=\"role\">Prompt</div><div class=\"content\">{}</div></div>\n",
+                escape_html(&prompt.content)
+            ));
+        }
+        let names: Vec<String> = turn.replies.iter().enumerate().map(|(i, m)| escape_html(&model_name(m, i))).collect();
+
+        body.push_str("<div class=\"content\"><table>\n<thead><tr><th>Model</th><th>Tokens</th><th>Latency</th><th>Cost</th><th>Rating</th></tr></thead><tbody>\n");
+        for (msg, name) in turn.replies.iter().zip(&names) {
+            body.push_str(&format!(
+                "<tr><td>{name}</td><td>{}</td><td>{}</td><td>${:.4}</td><td>{}</td></tr>\n",
+                tokens(msg), latency(msg), reply_cost(msg), rating_mark(msg.rating)
+            ));
+        }
+        body.push_str("</tbody></table>\n");
+
+        if turn.replies.len() > 1 {
+            let header: String = names.iter().map(|name| format!("<th>{name}</th>")).collect();
+            body.push_str(&format!("<h3>Agreement</h3>\n<table>\n<thead><tr><th></th>{header}</tr></thead><tbody>\n"));
+            for (row, name) in agreement_matrix(&turn.replies).iter().zip(&names) {
+                let cells: String = row.iter().map(|pct| format!("<td>{pct:.0}%</td>")).collect();
+                body.push_str(&format!("<tr><th>{name}</th>{cells}</tr>\n"));
+            }
+            body.push_str("</tbody></table>\n");
+        }
+        body.push_str("</div>\n");
+
+        body.push_str(&format!("<div class=\"turn\" style=\"grid-template-columns: repeat({}, minmax(0, 1fr))\">\n", turn.replies.len().min(3)));
+        for (msg, name) in turn.replies.iter().zip(&names) {
+            body.push_str(&format!(
+                "<div class=\"message assistant\"><div class=\"role\">{name}</div><div class=\"content\">{}</div></div>\n",
+                markdown_html(&msg.content)
+            ));
+        }
+        body.push_str("</div>\n");
+    }
+    html_document(&format!("Comparison: {title}"), &body, theme)
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn csv(turns: &[Turn]) -> String {
+    let mut out = String::from("turn,prompt,model,tokens,latency_ms,cost_usd,rating,mean_agreement,agreement,answer\n");
+    for (n, turn) in turns.iter().enumerate() {
+        let names: Vec<String> = turn.replies.iter().enumerate().map(|(i, m)| model_name(m, i)).collect();
+        let matrix = agreement_matrix(&turn.replies);
+        let prompt = turn.prompt.map(|p| p.content.as_str()).unwrap_or("");
+        for (i, msg) in turn.replies.iter().enumerate() {
+            let others: Vec<(usize, f32)> = matrix[i].iter().copied().enumerate().filter(|(j, _)| *j != i).collect();
+            let mean = match others.len() {
+                0 => String::new(),
+                count => format!("{:.1}", others.iter().map(|(_, pct)| pct).sum::<f32>() / count as f32),
+            };
+            let pairs: Vec<String> = others.iter().map(|(j, pct)| format!("{}: {pct:.0}%", names[*j])).collect();
+            let fields = [
+                (n + 1).to_string(),
+                csv_field(prompt),
+                csv_field(&names[i]),
+                tokens(msg).to_string(),
+                msg.latency_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+                format!("{:.6}", reply_cost(msg)),
+                msg.rating.to_string(),
+                mean,
+                csv_field(&pairs.join("; ")),
+                csv_field(&msg.content),
+            ];
+            out.push_str(&fields.join(","));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// A comparison report of `turns` from the conversation titled `title`:
+/// the prompt, each model's answer with its tokens, latency, cost and
+/// rating, and how much each pair of answers agrees.
+pub fn render(title: &str, turns: &[Turn], format: ReportFormat, theme: ThemeName) -> String {
+    match format {
+        ReportFormat::Markdown => markdown(title, turns),
+        ReportFormat::Html => html(title, turns, theme),
+        ReportFormat::Csv => csv(turns),
+    }
+}
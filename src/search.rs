@@ -0,0 +1,243 @@
+use std::ops::Range;
+
+use chrono::NaiveDate;
+
+use crate::model::Role;
+
+/// Marks the start of a matched term in search snippets.
+pub const MATCH_START: char = '\u{2}';
+/// Marks the end of a matched term in search snippets.
+pub const MATCH_END: char = '\u{3}';
+
+/// Split a marked snippet into `(text, is_match)` segments.
+pub fn split_marked(snippet: &str) -> Vec<(String, bool)> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut in_match = false;
+    for c in snippet.chars() {
+        if c == MATCH_START || c == MATCH_END {
+            if !current.is_empty() {
+                segments.push((std::mem::take(&mut current), in_match));
+            }
+            in_match = c == MATCH_START;
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        segments.push((current, in_match));
+    }
+    segments
+}
+
+/// The distinct matched terms in a marked string, in order of appearance.
+pub fn marked_terms(marked: &str) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for (segment, is_match) in split_marked(marked) {
+        if is_match && !terms.iter().any(|t| t.eq_ignore_ascii_case(&segment)) {
+            terms.push(segment);
+        }
+    }
+    terms
+}
+
+/// Byte ranges of `text` that match any of `terms` (ASCII case-insensitive),
+/// sorted and non-overlapping.
+pub fn match_ranges(text: &str, terms: &[String]) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if !text.is_char_boundary(i) {
+            i += 1;
+            continue;
+        }
+        let longest = terms.iter()
+            .filter(|t| !t.is_empty())
+            .filter(|t| {
+                let end = i + t.len();
+                end <= bytes.len() && text.is_char_boundary(end) && bytes[i..end].eq_ignore_ascii_case(t.as_bytes())
+            })
+            .map(|t| t.len())
+            .max();
+        match longest {
+            Some(len) => {
+                ranges.push(i..i + len);
+                i += len;
+            }
+            None => i += 1,
+        }
+    }
+    ranges
+}
+
+/// A search query: free text plus `key:value` filters.
+///
+/// Words match by prefix and `"quoted phrases"` match exactly. A leading `-`
+/// negates any term. Filters are `model:`, `tag:`, `folder:`, `rating:up|down`,
+/// `role:user|assistant`, `before:`/`after:` (`YYYY-MM-DD`), `cost>`/`cost<`
+/// (USD, also `>=`/`<=`), `is:pinned` and `is:fork`. Anything that isn't a
+/// valid filter is searched as text.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchQuery {
+    pub text: Vec<TextTerm>,
+    pub filters: Vec<FilterTerm>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextTerm {
+    pub text: String,
+    /// Quoted: match the exact phrase rather than a word prefix
+    pub phrase: bool,
+    pub negated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterTerm {
+    pub filter: Filter,
+    pub negated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// Model ID contains the value
+    Model(String),
+    Tag(String),
+    /// The folder or any of its subfolders
+    Folder(String),
+    /// 1 for thumbs up, -1 for thumbs down
+    Rating(i8),
+    Role(Role),
+    /// Created before the start of this local day
+    Before(NaiveDate),
+    /// Created on or after this local day
+    After(NaiveDate),
+    /// Total conversation cost in USD
+    Cost(Comparison, f64),
+    Pinned,
+    Fork,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    pub fn sql(self) -> &'static str {
+        match self {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+impl Filter {
+    /// Whether the filter tests individual messages rather than whole conversations.
+    pub fn on_message(&self) -> bool {
+        matches!(self, Filter::Model(_) | Filter::Rating(_) | Filter::Role(_) | Filter::Before(_) | Filter::After(_))
+    }
+
+    /// Parse one unquoted `key:value` or `cost>value` token.
+    fn parse(token: &str) -> Option<Self> {
+        if let Some(rest) = token.strip_prefix("cost") {
+            let (cmp, value) = [(">=", Comparison::GreaterOrEqual), ("<=", Comparison::LessOrEqual), (">", Comparison::Greater), ("<", Comparison::Less)]
+                .into_iter()
+                .find_map(|(op, cmp)| rest.strip_prefix(op).map(|v| (cmp, v)))?;
+            let value: f64 = value.trim_start_matches('$').parse().ok()?;
+            return value.is_finite().then_some(Filter::Cost(cmp, value));
+        }
+        let (key, value) = token.split_once(':')?;
+        if value.is_empty() {
+            return None;
+        }
+        let date = || NaiveDate::parse_from_str(value, "%Y-%m-%d").ok();
+        match (key.to_lowercase().as_str(), value.to_lowercase().as_str()) {
+            ("model", v) => Some(Filter::Model(v.to_string())),
+            ("tag", v) => Some(Filter::Tag(v.trim_start_matches('#').to_string())),
+            ("folder", v) => Some(Filter::Folder(v.trim_matches('/').to_string())),
+            ("rating", "up" | "good") => Some(Filter::Rating(1)),
+            ("rating", "down" | "bad") => Some(Filter::Rating(-1)),
+            ("role", "user" | "you") => Some(Filter::Role(Role::User)),
+            ("role", "assistant") => Some(Filter::Role(Role::Assistant)),
+            ("before", _) => date().map(Filter::Before),
+            ("after", _) => date().map(Filter::After),
+            ("is", "pinned") => Some(Filter::Pinned),
+            ("is", "fork" | "forked") => Some(Filter::Fork),
+            _ => None,
+        }
+    }
+}
+
+impl SearchQuery {
+    pub fn parse(input: &str) -> Self {
+        let mut query = Self::default();
+        for (raw, quoted, negated) in tokenize(input) {
+            if !quoted && let Some(filter) = Filter::parse(&raw) {
+                query.filters.push(FilterTerm { filter, negated });
+            } else if !raw.trim().is_empty() {
+                query.text.push(TextTerm { text: raw, phrase: quoted, negated });
+            }
+        }
+        query
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.filters.is_empty()
+    }
+
+    pub fn has_filters(&self) -> bool {
+        !self.filters.is_empty()
+    }
+
+    /// The text terms (positive or negated) that a match must satisfy.
+    pub fn text_terms(&self, negated: bool) -> Vec<&TextTerm> {
+        self.text.iter().filter(|t| t.negated == negated).collect()
+    }
+}
+
+/// Split a query into `(text, quoted, negated)` tokens. A token quoted as a
+/// whole is a phrase; quotes inside a token (`tag:"two words"`) only group.
+fn tokenize(input: &str) -> Vec<(String, bool, bool)> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut negated = false;
+        if c == '-' {
+            chars.next();
+            match chars.peek() {
+                Some(next) if !next.is_whitespace() => negated = true,
+                _ => continue,
+            }
+        }
+        let quoted = chars.peek() == Some(&'"');
+        let mut token = String::new();
+        let mut in_quotes = false;
+        while let Some(&c) = chars.peek() {
+            if c == '"' {
+                in_quotes = !in_quotes;
+                chars.next();
+                if quoted && !in_quotes {
+                    break;
+                }
+                continue;
+            }
+            if c.is_whitespace() && !in_quotes {
+                break;
+            }
+            token.push(c);
+            chars.next();
+        }
+        tokens.push((token, quoted, negated));
+    }
+    tokens
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+/// Longest chunk of message text embedded as one vector.
+pub const CHUNK_CHARS: usize = 1500;
+
+/// Split text into chunks of at most `CHUNK_CHARS` characters, breaking at
+/// paragraph, line or word boundaries where possible.
+pub fn chunks(text: &str) -> Vec<String> {
+    let text = text.trim();
+    let mut out = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        if rest.chars().count() <= CHUNK_CHARS {
+            out.push(rest.to_string());
+            break;
+        }
+        let limit = rest.char_indices().nth(CHUNK_CHARS).map_or(rest.len(), |(i, _)| i);
+        let window = &rest[..limit];
+        let cut = ["\n\n", "\n", ". ", " "].iter()
+            .find_map(|sep| window.rfind(sep).filter(|&i| i > limit / 2).map(|i| i + sep.len()))
+            .unwrap_or(limit);
+        out.push(rest[..cut].trim().to_string());
+        rest = rest[cut..].trim_start();
+    }
+    out
+}
+
+/// Scale a vector to unit length, so cosine similarity is a dot product.
+pub fn normalize(v: &mut [f32]) {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Encode a vector as little-endian `f32`s for a SQLite BLOB.
+pub fn to_blob(v: &[f32]) -> Vec<u8> {
+    v.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+pub fn from_blob(bytes: &[u8]) -> Vec<f32> {
+    bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+}
+
+/// One embedded chunk of a message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub conversation_id: String,
+    pub message_id: String,
+    /// Unit length
+    pub vector: Vec<f32>,
+}
+
+/// All embedded chunks for one embedding model, searched by brute force.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VectorIndex {
+    pub model: String,
+    pub entries: Vec<Entry>,
+}
+
+impl VectorIndex {
+    pub fn new(model: &str, entries: Vec<Entry>) -> Self {
+        Self { model: model.to_string(), entries }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Replace the chunks of `message_id` with freshly embedded ones.
+    pub fn insert(&mut self, conversation_id: &str, message_id: &str, vectors: Vec<Vec<f32>>) {
+        self.entries.retain(|e| e.message_id != message_id);
+        self.entries.extend(vectors.into_iter().map(|vector| Entry {
+            conversation_id: conversation_id.to_string(),
+            message_id: message_id.to_string(),
+            vector,
+        }));
+    }
+
+    /// Conversations ranked by their best-matching chunk, most similar first.
+    pub fn search(&self, query: &[f32], limit: usize) -> Vec<(String, f32)> {
+        self.rank(query, limit, |_| true)
+    }
+
+    /// Conversations most similar to `conversation_id`, comparing the mean of
+    /// its chunks against every other conversation's chunks.
+    pub fn related(&self, conversation_id: &str, limit: usize) -> Vec<(String, f32)> {
+        let own: Vec<&Entry> = self.entries.iter().filter(|e| e.conversation_id == conversation_id).collect();
+        let Some(first) = own.first() else { return Vec::new() };
+        let mut centroid = vec![0.0; first.vector.len()];
+        for entry in &own {
+            centroid.iter_mut().zip(&entry.vector).for_each(|(c, x)| *c += x);
+        }
+        normalize(&mut centroid);
+        self.rank(&centroid, limit, |e| e.conversation_id != conversation_id)
+    }
+
+    fn rank(&self, query: &[f32], limit: usize, keep: impl Fn(&Entry) -> bool) -> Vec<(String, f32)> {
+        let mut best: HashMap<&str, f32> = HashMap::new();
+        for entry in self.entries.iter().filter(|e| e.vector.len() == query.len() && keep(e)) {
+            let score = dot(query, &entry.vector);
+            let slot = best.entry(entry.conversation_id.as_str()).or_insert(f32::MIN);
+            *slot = slot.max(score);
+        }
+        let mut ranked: Vec<(String, f32)> = best.into_iter().map(|(id, score)| (id.to_string(), score)).collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked.truncate(limit);
+        ranked
+    }
+}
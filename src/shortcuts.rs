@@ -287,10 +287,7 @@ pub fn append_debug_key_log(line: &str) {
 }
 
 pub fn key_log_path() -> Option<std::path::PathBuf> {
-    let mut path = dirs::config_dir()?;
-    path.push("stoa");
-    path.push("key-events.log");
-    Some(path)
+    Some(crate::workspace::root().join("key-events.log"))
 }
 
 #[cfg(test)]
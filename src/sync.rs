@@ -0,0 +1,426 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::db::{self, SyncEntity};
+use crate::model::{parents_first, ChatMessage, Conversation};
+
+/// Bumped when a changeset could mislead an older reader.
+pub const CHANGESET_VERSION: u32 = 1;
+
+/// Conversation fields merged one by one, last writer wins.
+const CONVERSATION_FIELDS: [&str; 6] = ["title", "tags", "pinned", "system_prompt", "folder", "thread_mode"];
+/// Message fields merged one by one. A reply's model, tokens, latency and
+/// completion time travel with its `content`.
+const MESSAGE_FIELDS: [&str; 3] = ["content", "rating", "canonical"];
+
+/// When each field of a conversation or message last changed.
+pub type Clocks = HashMap<String, DateTime<Utc>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationChange {
+    /// Settings only; messages travel as `MessageChange`s
+    pub conversation: Conversation,
+    pub clocks: Clocks,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageChange {
+    pub conversation_id: String,
+    pub message: ChatMessage,
+    pub clocks: Clocks,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Deletion {
+    pub entity: SyncEntity,
+    pub id: String,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// Everything one library changed since it last wrote a changeset. Entities
+/// are keyed by their stable IDs, so any library can merge any changeset.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Changeset {
+    pub version: u32,
+    /// Library that wrote it
+    pub device: String,
+    pub created_at: DateTime<Utc>,
+    /// When the writer last merged the folder. Its edits were made without
+    /// seeing changes that reached the folder later.
+    #[serde(default)]
+    pub based_on: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub conversations: Vec<ConversationChange>,
+    #[serde(default)]
+    pub messages: Vec<MessageChange>,
+    #[serde(default)]
+    pub deletions: Vec<Deletion>,
+}
+
+impl Changeset {
+    pub fn is_empty(&self) -> bool {
+        self.conversations.is_empty() && self.messages.is_empty() && self.deletions.is_empty()
+    }
+}
+
+/// What a sync did.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncReport {
+    pub merged_files: usize,
+    /// Changesets that couldn't be read yet (e.g. still being copied) or
+    /// were written by a newer Stoa; they are retried on the next sync
+    pub unreadable_files: usize,
+    pub written: Option<PathBuf>,
+    pub conversations: usize,
+    pub messages: usize,
+    pub deleted: usize,
+    /// Local edits that lost to a newer one and were kept as forks
+    pub conflicts: usize,
+    /// Messages whose conversation no longer exists here
+    pub skipped: usize,
+}
+
+impl SyncReport {
+    /// Whether merging changed the library.
+    pub fn changed(&self) -> bool {
+        self.conversations + self.messages + self.deleted + self.conflicts > 0
+    }
+}
+
+/// This library's ID in changesets, created on first use.
+pub fn device_id(conn: &Connection) -> Result<String, String> {
+    if let Some(id) = db::sync_state(conn, "device_id") {
+        return Ok(id);
+    }
+    let id = uuid::Uuid::new_v4().to_string();
+    db::set_sync_state(conn, "device_id", &id)?;
+    Ok(id)
+}
+
+fn message_fallback(msg: &ChatMessage) -> DateTime<Utc> {
+    msg.completed_at.or(msg.created_at).unwrap_or(DateTime::UNIX_EPOCH)
+}
+
+/// `clocks` with every field in `fields`; rows from before sync count as
+/// changed at `fallback`.
+fn complete(mut clocks: Clocks, fields: &[&str], fallback: DateTime<Utc>) -> Clocks {
+    for field in fields {
+        clocks.entry(field.to_string()).or_insert(fallback);
+    }
+    clocks
+}
+
+fn latest(clocks: &Clocks) -> DateTime<Utc> {
+    clocks.values().copied().max().unwrap_or(DateTime::UNIX_EPOCH)
+}
+
+/// The conversations, messages and deletions changed after `since`
+/// (everything for `None`), by a library that last merged at `based_on`.
+pub fn collect(conn: &Connection, device: &str, since: Option<DateTime<Utc>>, based_on: Option<DateTime<Utc>>) -> Changeset {
+    let changed: HashSet<String> = db::conversations_changed_since(conn, since).into_iter().collect();
+    let conversations = db::load_summaries(conn)
+        .into_iter()
+        .filter(|conv| changed.contains(&conv.id))
+        .map(|conversation| {
+            let clocks = complete(db::sync_clocks(conn, SyncEntity::Conversation, &conversation.id), &CONVERSATION_FIELDS, conversation.created_at);
+            ConversationChange { conversation, clocks }
+        })
+        .collect();
+    let messages = db::messages_changed_since(conn, since)
+        .into_iter()
+        .map(|(conversation_id, message)| {
+            let clocks = complete(db::sync_clocks(conn, SyncEntity::Message, &message.id), &MESSAGE_FIELDS, message_fallback(&message));
+            MessageChange { conversation_id, message, clocks }
+        })
+        .collect();
+    let deletions = db::deletions_since(conn, since)
+        .into_iter()
+        .map(|(entity, id, deleted_at)| Deletion { entity, id, deleted_at })
+        .collect();
+    Changeset {
+        version: CHANGESET_VERSION,
+        device: device.to_string(),
+        created_at: Utc::now(),
+        based_on,
+        conversations,
+        messages,
+        deletions,
+    }
+}
+
+fn set_conversation_field(conv: &mut Conversation, from: &Conversation, field: &str) {
+    match field {
+        "title" => conv.title = from.title.clone(),
+        "tags" => conv.tags = from.tags.clone(),
+        "pinned" => conv.pinned = from.pinned,
+        "system_prompt" => conv.system_prompt = from.system_prompt.clone(),
+        "folder" => conv.folder = from.folder.clone(),
+        "thread_mode" => conv.thread_mode = from.thread_mode,
+        _ => {}
+    }
+}
+
+fn set_message_field(msg: &mut ChatMessage, from: &ChatMessage, field: &str) {
+    match field {
+        "content" => {
+            msg.content = from.content.clone();
+            msg.model = from.model.clone();
+            msg.token_count = from.token_count;
+            msg.latency_ms = from.latency_ms;
+            msg.completed_at = from.completed_at;
+        }
+        "rating" => msg.rating = from.rating,
+        "canonical" => msg.canonical = from.canonical,
+        _ => {}
+    }
+}
+
+/// Merge one changeset into the library. Each field takes the most recent
+/// write. A message edited here that the writer hadn't seen when it made a
+/// newer edit is kept in a fork of its conversation; replies written on both
+/// machines at once become versions of each other where the threads diverge.
+pub fn merge(conn: &Connection, changeset: &Changeset, report: &mut SyncReport) -> Result<(), String> {
+    let mut local: HashMap<String, Conversation> = db::load_summaries(conn).into_iter().map(|c| (c.id.clone(), c)).collect();
+    let mut conflicts = Vec::new();
+    db::in_transaction(conn, || {
+        for change in &changeset.conversations {
+            merge_conversation(conn, change, &mut local, report)?;
+        }
+        for change in parents_first(changeset.messages.iter().collect(), |c| &c.message) {
+            merge_message(conn, change, &local, changeset.based_on, &mut conflicts, report)?;
+        }
+        Ok(())
+    })?;
+    for deletion in &changeset.deletions {
+        apply_deletion(conn, deletion, &local, report)?;
+    }
+    for (conv_id, lost) in conflicts {
+        fork_conflict(conn, &conv_id, &lost)?;
+        report.conflicts += 1;
+    }
+    Ok(())
+}
+
+fn merge_conversation(conn: &Connection, change: &ConversationChange, local: &mut HashMap<String, Conversation>, report: &mut SyncReport) -> Result<(), String> {
+    let incoming = &change.conversation;
+    let theirs = complete(change.clocks.clone(), &CONVERSATION_FIELDS, incoming.created_at);
+    match local.get_mut(&incoming.id) {
+        None => {
+            // Deleted here after the other side last changed it
+            if db::deleted_at(conn, SyncEntity::Conversation, &incoming.id).is_some_and(|at| latest(&theirs) <= at) {
+                return Ok(());
+            }
+            let mut conv = incoming.clone();
+            conv.messages.clear();
+            conv.alternates.clear();
+            db::upsert_conversation(conn, &conv)?;
+            db::set_sync_clocks(conn, SyncEntity::Conversation, &conv.id, &theirs)?;
+            local.insert(conv.id.clone(), conv);
+            report.conversations += 1;
+        }
+        Some(conv) => {
+            let mut ours = complete(db::sync_clocks(conn, SyncEntity::Conversation, &conv.id), &CONVERSATION_FIELDS, conv.created_at);
+            let mut changed = false;
+            for field in CONVERSATION_FIELDS {
+                if theirs[field] > ours[field] {
+                    set_conversation_field(conv, incoming, field);
+                    ours.insert(field.to_string(), theirs[field]);
+                    changed = true;
+                }
+            }
+            if changed {
+                db::upsert_conversation(conn, conv)?;
+                db::set_sync_clocks(conn, SyncEntity::Conversation, &conv.id, &ours)?;
+                report.conversations += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn merge_message(
+    conn: &Connection,
+    change: &MessageChange,
+    local: &HashMap<String, Conversation>,
+    based_on: Option<DateTime<Utc>>,
+    conflicts: &mut Vec<(String, ChatMessage)>,
+    report: &mut SyncReport,
+) -> Result<(), String> {
+    let incoming = &change.message;
+    let theirs = complete(change.clocks.clone(), &MESSAGE_FIELDS, message_fallback(incoming));
+    let Some((conv_id, mut msg, active)) = db::load_message(conn, &incoming.id) else {
+        if !local.contains_key(&change.conversation_id) {
+            report.skipped += 1;
+            return Ok(());
+        }
+        if db::deleted_at(conn, SyncEntity::Message, &incoming.id).is_some_and(|at| latest(&theirs) <= at) {
+            return Ok(());
+        }
+        // Joins the active path unless this side already continued the thread differently
+        let parent_active = match &incoming.parent_id {
+            None => true,
+            Some(parent) => db::load_message(conn, parent).is_some_and(|(_, _, active)| active),
+        };
+        let active = parent_active && !db::has_active_reply(conn, &change.conversation_id, incoming.parent_id.as_deref());
+        let mut msg = incoming.clone();
+        msg.streaming = false;
+        db::upsert_message(conn, &change.conversation_id, &msg, active)?;
+        db::set_sync_clocks(conn, SyncEntity::Message, &msg.id, &theirs)?;
+        report.messages += 1;
+        return Ok(());
+    };
+
+    let mut ours = complete(db::sync_clocks(conn, SyncEntity::Message, &msg.id), &MESSAGE_FIELDS, message_fallback(&msg));
+    let mut changed = false;
+    for field in MESSAGE_FIELDS {
+        if theirs[field] <= ours[field] {
+            continue;
+        }
+        let unseen = based_on.is_none_or(|at| ours[field] > at);
+        if field == "content" && unseen && msg.content != incoming.content {
+            conflicts.push((conv_id.clone(), msg.clone()));
+        }
+        set_message_field(&mut msg, incoming, field);
+        ours.insert(field.to_string(), theirs[field]);
+        changed = true;
+    }
+    if changed {
+        db::upsert_message(conn, &conv_id, &msg, active)?;
+        db::set_sync_clocks(conn, SyncEntity::Message, &msg.id, &ours)?;
+        report.messages += 1;
+    }
+    Ok(())
+}
+
+/// Move a conversation or message deleted on the other side to the trash,
+/// unless it was changed here after that.
+fn apply_deletion(conn: &Connection, deletion: &Deletion, local: &HashMap<String, Conversation>, report: &mut SyncReport) -> Result<(), String> {
+    match deletion.entity {
+        SyncEntity::Conversation => {
+            let Some(conv) = local.get(&deletion.id) else { return Ok(()) };
+            let ours = complete(db::sync_clocks(conn, SyncEntity::Conversation, &conv.id), &CONVERSATION_FIELDS, conv.created_at);
+            if latest(&ours) > deletion.deleted_at {
+                return Ok(());
+            }
+            let mut conv = conv.clone();
+            conv.unload();
+            db::load_messages(conn, &mut conv)?;
+            db::trash_conversation(conn, &conv)?;
+        }
+        SyncEntity::Message => {
+            let Some((conv_id, msg, active)) = db::load_message(conn, &deletion.id) else { return Ok(()) };
+            let ours = complete(db::sync_clocks(conn, SyncEntity::Message, &msg.id), &MESSAGE_FIELDS, message_fallback(&msg));
+            if latest(&ours) > deletion.deleted_at {
+                return Ok(());
+            }
+            db::trash_message(conn, &conv_id, &msg, active)?;
+        }
+    }
+    db::set_deleted_at(conn, deletion.entity, &deletion.id, deletion.deleted_at)?;
+    report.deleted += 1;
+    Ok(())
+}
+
+/// Keep a local edit that lost to a newer one: fork the conversation at the
+/// message, with the lost text in the fork.
+fn fork_conflict(conn: &Connection, conv_id: &str, lost: &ChatMessage) -> Result<(), String> {
+    let Some(mut conv) = db::load_summaries(conn).into_iter().find(|c| c.id == conv_id) else { return Ok(()) };
+    db::load_messages(conn, &mut conv)?;
+    let mut path = Vec::new();
+    {
+        let by_id: HashMap<&str, &ChatMessage> = conv.messages.iter().chain(&conv.alternates).map(|m| (m.id.as_str(), m)).collect();
+        let mut next = lost.parent_id.as_deref();
+        while let Some(msg) = next.and_then(|id| by_id.get(id)) {
+            path.push((*msg).clone());
+            next = msg.parent_id.as_deref();
+        }
+    }
+    path.reverse();
+    path.push(lost.clone());
+
+    let title = conv.title.clone();
+    conv.messages = path;
+    conv.alternates.clear();
+    let mut fork = conv.fork(conv.messages.len() - 1);
+    fork.title = format!("{title} (sync conflict)");
+    db::save_conversation(conn, &fork)
+}
+
+fn changeset_file_name(changeset: &Changeset) -> String {
+    let device: String = changeset.device.chars().take(8).collect();
+    format!("{}-{device}.json", changeset.created_at.format("%Y%m%dT%H%M%S%3fZ"))
+}
+
+/// Write a changeset into `dir`, returning its path. The file appears
+/// complete or not at all, so peers never merge half of it.
+pub fn write_changeset(dir: &Path, changeset: &Changeset) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create sync folder {}: {e}", dir.display()))?;
+    let path = dir.join(changeset_file_name(changeset));
+    let json = serde_json::to_string(changeset).map_err(|e| format!("Failed to serialize changeset: {e}"))?;
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    std::fs::write(&partial, json).map_err(|e| format!("Failed to write {}: {e}", partial.display()))?;
+    std::fs::rename(&partial, &path).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+    Ok(path)
+}
+
+pub fn read_changeset(path: &Path) -> Result<Changeset, String> {
+    let data = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    let changeset: Changeset = serde_json::from_str(&data).map_err(|e| format!("Failed to parse {}: {e}", path.display()))?;
+    if changeset.version > CHANGESET_VERSION {
+        return Err(format!("{} was written by a newer version of Stoa", path.display()));
+    }
+    Ok(changeset)
+}
+
+fn parse_time(value: Option<String>) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(&value?).ok().map(|t| t.with_timezone(&Utc))
+}
+
+/// Merge every changeset in `dir` not seen before, oldest first, then write
+/// one with this library's changes since it last wrote to `dir`.
+pub fn run(conn: &Connection, dir: &Path) -> Result<SyncReport, String> {
+    let device = device_id(conn)?;
+    let last_sync = parse_time(db::sync_state(conn, "last_sync"));
+    let started = Utc::now();
+    let mut report = SyncReport::default();
+
+    let mut pending: Vec<(String, PathBuf)> = std::fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read sync folder {}: {e}", dir.display()))?
+        .flatten()
+        .map(|entry| (entry.file_name().to_string_lossy().into_owned(), entry.path()))
+        .filter(|(name, _)| name.ends_with(".json") && !db::sync_applied(conn, name))
+        .collect();
+    pending.sort();
+    for (name, path) in pending {
+        let changeset = match read_changeset(&path) {
+            Ok(changeset) => changeset,
+            Err(e) => {
+                log::warn!("{e}");
+                report.unreadable_files += 1;
+                continue;
+            }
+        };
+        merge(conn, &changeset, &mut report)?;
+        db::mark_sync_applied(conn, &name)?;
+        report.merged_files += 1;
+    }
+
+    let export_key = format!("last_export:{}", dir.display());
+    let since = parse_time(db::sync_state(conn, &export_key));
+    let exported_at = Utc::now();
+    let changeset = collect(conn, &device, since, last_sync);
+    if !changeset.is_empty() {
+        let path = write_changeset(dir, &changeset)?;
+        db::mark_sync_applied(conn, &changeset_file_name(&changeset))?;
+        report.written = Some(path);
+    }
+    db::set_sync_state(conn, &export_key, &exported_at.to_rfc3339())?;
+    db::set_sync_state(conn, "last_sync", &started.to_rfc3339())?;
+    Ok(report)
+}
@@ -1,9 +1,8 @@
-use iced::widget::{column, container, row, scrollable, text, Column};
+use iced::widget::{button, column, container, row, scrollable, text, text_input, Column};
 use iced::{Alignment, Element, Length, Border, Theme};
-use std::collections::HashMap;
 
 use crate::app::{ChatApp, Message};
-use crate::model::Role;
+use crate::model::DateRange;
 use crate::theme::*;
 use crate::ui::input_bar::{short_model_name, provider_icon};
 
@@ -15,6 +14,40 @@ fn card_style(_: &Theme) -> container::Style {
     }
 }
 
+fn range_chip_style(active: bool) -> impl Fn(&Theme, button::Status) -> button::Style {
+    move |_: &Theme, status: button::Status| {
+        let bg = match (active, status) {
+            (true, _) => CHIP_ACTIVE_BG(),
+            (false, button::Status::Hovered) => BG_HOVER(),
+            _ => iced::Color::TRANSPARENT,
+        };
+        button::Style {
+            background: Some(iced::Background::Color(bg)),
+            text_color: if active { ACCENT() } else { TEXT_SEC() },
+            border: Border { radius: 14.0.into(), width: 1.0, color: if active { ACCENT() } else { BORDER_DEFAULT() } },
+            ..Default::default()
+        }
+    }
+}
+
+fn date_input_style(_: &Theme, status: text_input::Status) -> text_input::Style {
+    text_input::Style {
+        background: iced::Background::Color(INPUT_BG()),
+        border: Border {
+            radius: 8.0.into(),
+            width: 1.0,
+            color: match status {
+                text_input::Status::Focused { .. } => ACCENT(),
+                _ => BORDER_DEFAULT(),
+            },
+        },
+        icon: TEXT_MUTED(),
+        placeholder: TEXT_MUTED(),
+        value: TEXT_HEAD(),
+        selection: SELECTION(),
+    }
+}
+
 fn stat_row<'a>(label: &'a str, value: String) -> Element<'a, Message> {
     row![
         text(label).size(FONT_CAPTION).color(TEXT_SEC()),
@@ -23,16 +56,6 @@ fn stat_row<'a>(label: &'a str, value: String) -> Element<'a, Message> {
     ].align_y(Alignment::Center).into()
 }
 
-struct ModelStats {
-    messages: u32,
-    total_tokens: u32,
-    total_cost: f64,
-    thumbs_up: u32,
-    thumbs_down: u32,
-    total_latency_ms: u64,
-    latency_count: u32,
-}
-
 pub fn view(app: &ChatApp) -> Element<'_, Message> {
     let header = container(
         row![text("Analytics").size(FONT_H1).color(TEXT_HEAD())].align_y(Alignment::Center)
@@ -41,59 +64,59 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
         ..Default::default()
     });
 
-    // Aggregate stats across all conversations
-    let mut model_stats: HashMap<String, ModelStats> = HashMap::new();
-    let mut total_conversations = 0;
-    let mut total_messages = 0u32;
-    let mut total_cost = 0.0f64;
-
-    for conv in &app.conversations {
-        total_conversations += 1;
-        for msg in &conv.messages {
-            if msg.streaming { continue; }
-            total_messages += 1;
-
-            if msg.role == Role::Assistant {
-                if let Some(model_id) = &msg.model {
-                    let stats = model_stats.entry(model_id.clone()).or_insert(ModelStats {
-                        messages: 0, total_tokens: 0, total_cost: 0.0,
-                        thumbs_up: 0, thumbs_down: 0, total_latency_ms: 0, latency_count: 0,
-                    });
-                    stats.messages += 1;
-                    let tokens = msg.token_count.unwrap_or_else(|| crate::cost::estimate_tokens(&msg.content));
-                    stats.total_tokens += tokens;
-                    let cost = crate::cost::message_cost(model_id, &msg.role, tokens);
-                    stats.total_cost += cost;
-                    total_cost += cost;
-                    if msg.rating > 0 { stats.thumbs_up += 1; }
-                    if msg.rating < 0 { stats.thumbs_down += 1; }
-                    if let Some(lat) = msg.latency_ms {
-                        stats.total_latency_ms += lat;
-                        stats.latency_count += 1;
-                    }
-                }
-            }
-        }
+    // Totals come from SQLite and are refreshed when the page, range or filter changes
+    let summary = &app.analytics;
+
+    // Date range filter: quick presets fill the From/To fields
+    let range = DateRange::parse(&app.analytics_from, &app.analytics_to);
+    let last_days = |days: u64| {
+        let from = (chrono::Local::now().date_naive() - chrono::Days::new(days - 1)).format("%Y-%m-%d").to_string();
+        app.analytics_from == from && app.analytics_to.is_empty()
+    };
+    let mut presets = row![].spacing(6).align_y(Alignment::Center);
+    for (label, days) in [("All time", None), ("Today", Some(1)), ("7 days", Some(7)), ("30 days", Some(30)), ("90 days", Some(90))] {
+        let active = match days {
+            None => range.is_unbounded(),
+            Some(d) => last_days(d),
+        };
+        presets = presets.push(
+            button(text(label).size(FONT_CAPTION)).padding([3, 10])
+                .on_press(Message::AnalyticsLastDays(days))
+                .style(range_chip_style(active))
+        );
     }
+    let range_bar = column![presets, row![
+        text("Range").size(FONT_CAPTION).color(TEXT_SEC()),
+        text_input("From YYYY-MM-DD", &app.analytics_from)
+            .on_input(Message::AnalyticsFromChanged)
+            .size(FONT_CAPTION).padding([4, 8]).width(120).style(date_input_style),
+        text("\u{2013}").size(FONT_CAPTION).color(TEXT_MUTED()),
+        text_input("To YYYY-MM-DD", &app.analytics_to)
+            .on_input(Message::AnalyticsToChanged)
+            .size(FONT_CAPTION).padding([4, 8]).width(120).style(date_input_style),
+    ].spacing(6).align_y(Alignment::Center), row![
+        text("Filter").size(FONT_CAPTION).color(TEXT_SEC()),
+        text_input("model:claude tag:paper rating:up ...", &app.analytics_query)
+            .on_input(Message::AnalyticsQueryChanged)
+            .size(FONT_CAPTION).padding([4, 8]).width(Length::Fill).style(date_input_style),
+    ].spacing(6).align_y(Alignment::Center)].spacing(8);
 
     // Overview card
     let overview = container(column![
         text("Overview").size(FONT_SMALL).color(TEXT_HEAD()),
         iced::widget::Space::new().height(8),
-        stat_row("Conversations", total_conversations.to_string()),
-        stat_row("Messages", total_messages.to_string()),
-        stat_row("Total Est. Cost", format!("${:.4}", total_cost)),
+        stat_row("Conversations", summary.conversations.to_string()),
+        stat_row("Messages", summary.messages.to_string()),
+        stat_row("Total Est. Cost", format!("${:.4}", summary.total_cost)),
         stat_row("Session Cost", format!("${:.4}", app.session_cost)),
-        stat_row("Models Used", model_stats.len().to_string()),
+        stat_row("Models Used", summary.models.len().to_string()),
         stat_row("Ollama Models", app.config.ollama_models.len().to_string()),
     ].spacing(6)).padding(16).width(Length::Fill).style(card_style);
 
     // Per-model cards
     let mut model_cards = Column::new().spacing(12);
-    let mut sorted_models: Vec<_> = model_stats.iter().collect();
-    sorted_models.sort_by(|a, b| b.1.messages.cmp(&a.1.messages));
-
-    for (model_id, stats) in &sorted_models {
+    for stats in &summary.models {
+        let model_id = &stats.model;
         let icon = provider_icon(model_id);
         let name = short_model_name(model_id);
         let avg_latency = if stats.latency_count > 0 {
@@ -126,7 +149,7 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
         model_cards = model_cards.push(card);
     }
 
-    if sorted_models.is_empty() {
+    if summary.models.is_empty() {
         model_cards = model_cards.push(
             text("No model data yet. Start chatting to see analytics.").size(FONT_SMALL).color(TEXT_MUTED())
         );
@@ -138,6 +161,7 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
     ].spacing(12);
 
     let body = column![
+        range_bar,
         overview,
         iced::widget::Space::new().height(16),
         content,
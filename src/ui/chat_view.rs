@@ -3,11 +3,14 @@ use iced::{Alignment, Element, Length, Color, Border, Theme};
 
 use crate::app::{ChatApp, Message};
 use crate::config::AppConfig;
-use crate::model::{ChatMessage, Role};
+use crate::model::{group_messages, ChatMessage, MessageGroup, Role, ThreadMode};
 use crate::theme::*;
 use crate::ui::input_bar::{short_model_name, provider_icon};
 use crate::ui::markdown;
 
+/// Widget ID of the message scrollable, for jumping to search hits.
+pub const SCROLL_ID: &str = "chat-scroll";
+
 fn user_bubble(_: &Theme) -> container::Style {
     container::Style {
         background: Some(iced::Background::Color(USER_BG())),
@@ -24,6 +27,13 @@ fn special_user_bubble(_: &Theme) -> container::Style {
     }
 }
 
+fn highlighted_message_style(_: &Theme) -> container::Style {
+    container::Style {
+        border: Border { radius: 8.0.into(), width: 1.0, color: ACCENT() },
+        ..Default::default()
+    }
+}
+
 fn action_btn_style(_: &Theme, status: button::Status) -> button::Style {
     button::Style {
         background: Some(iced::Background::Color(Color::TRANSPARENT)),
@@ -83,6 +93,17 @@ fn error_style(_: &Theme) -> container::Style {
     }
 }
 
+fn edit_input_style(_: &Theme, status: iced::widget::text_input::Status) -> iced::widget::text_input::Style {
+    iced::widget::text_input::Style {
+        background: iced::Background::Color(INPUT_BG()),
+        border: Border { radius: 12.0.into(), width: 1.0, color: match status {
+            iced::widget::text_input::Status::Focused { .. } => ACCENT(),
+            _ => BORDER_DEFAULT(),
+        }},
+        icon: TEXT_MUTED(), placeholder: TEXT_MUTED(), value: TEXT_HEAD(), selection: SELECTION(),
+    }
+}
+
 fn is_special_user_message(content: &str) -> bool {
     content.starts_with("[Review request]") || content.starts_with("[Analyze conversation]")
 }
@@ -93,28 +114,20 @@ fn special_label(content: &str) -> &str {
     else { "You" }
 }
 
-/// Group messages into display groups for comparison mode.
-enum DisplayGroup {
-    User(usize),
-    Assistants(Vec<usize>), // indices of consecutive assistant messages
-}
-
-fn group_messages(messages: &[ChatMessage]) -> Vec<DisplayGroup> {
-    let mut groups = Vec::new();
-    let mut i = 0;
-    while i < messages.len() {
-        if messages[i].role == Role::User {
-            groups.push(DisplayGroup::User(i));
-            i += 1;
-        } else {
-            let start = i;
-            while i < messages.len() && messages[i].role == Role::Assistant {
-                i += 1;
-            }
-            groups.push(DisplayGroup::Assistants((start..i).collect()));
-        }
-    }
-    groups
+/// "< 2/3 >" navigation between versions of a message, if it has any.
+fn version_nav<'a>(app: &ChatApp, i: usize, is_streaming: bool) -> Option<Row<'a, Message>> {
+    let conv = &app.conversations[app.active_conversation];
+    let (pos, total) = conv.version_info(i);
+    if total < 2 { return None; }
+    let prev = button(text("<").size(FONT_SMALL)).padding([2, 5]).style(action_btn_style)
+        .on_press_maybe((!is_streaming && pos > 1).then_some(Message::SwitchVersion(i, -1)));
+    let next = button(text(">").size(FONT_SMALL)).padding([2, 5]).style(action_btn_style)
+        .on_press_maybe((!is_streaming && pos < total).then_some(Message::SwitchVersion(i, 1)));
+    Some(row![
+        prev,
+        text(format!("{pos}/{total}")).size(FONT_CAPTION).color(TEXT_MUTED()),
+        next,
+    ].spacing(2).align_y(Alignment::Center))
 }
 
 fn render_assistant_message<'a>(
@@ -148,10 +161,11 @@ fn render_assistant_message<'a>(
             col = col.push(button(text("Stop").size(FONT_CAPTION)).padding([3, 8]).on_press(Message::StopStream(stream_id)).style(stop_stream_style));
         }
     } else {
-        // Lazy-render finalized markdown: only re-parses when content or theme changes
+        // Lazy-render finalized markdown: only re-parses when content, theme or highlight changes
         let theme = app.config.theme;
-        col = col.push(iced::widget::lazy((msg.content.clone(), theme), |(content, _): &(String, ThemeName)| {
-            markdown::render_markdown(content)
+        let terms = if app.highlighted_message.as_ref() == Some(&msg.id) { app.highlight_terms.clone() } else { Vec::new() };
+        col = col.push(iced::widget::lazy((msg.content.clone(), theme, terms), |(content, _, terms): &(String, ThemeName, Vec<String>)| {
+            markdown::render_markdown_highlighted(content, terms)
         }));
     }
 
@@ -160,7 +174,11 @@ fn render_assistant_message<'a>(
         let up_color = if msg.rating > 0 { SUCCESS() } else { TEXT_MUTED() };
         let down_color = if msg.rating < 0 { DANGER() } else { TEXT_MUTED() };
 
-        let mut actions = row![
+        let mut actions = Row::new().spacing(4);
+        if let Some(nav) = version_nav(app, i, is_streaming) {
+            actions = actions.push(nav);
+        }
+        actions = actions.extend([
             button(text("\u{25B2}").size(FONT_SMALL).color(up_color)).padding([2, 5])
                 .style(action_btn_style).on_press(Message::RateMessage(i, 1)),
             button(text("\u{25BC}").size(FONT_SMALL).color(down_color)).padding([2, 5])
@@ -169,8 +187,14 @@ fn render_assistant_message<'a>(
                 .on_press(Message::CopyToClipboard(msg.content.clone())),
             button(text("\u{2442}").size(FONT_SMALL)).padding([2, 6]).style(action_btn_style)
                 .on_press(Message::ForkConversation(i)),
-        ].spacing(4);
+        ].map(Element::from));
 
+        let conv = &app.conversations[app.active_conversation];
+        if conv.thread_mode == ThreadMode::Isolated && conv.turn_of(i).len() >= 2 {
+            let star_color = if msg.canonical { ACCENT() } else { TEXT_MUTED() };
+            actions = actions.push(button(text("\u{2605}").size(FONT_SMALL).color(star_color)).padding([2, 6])
+                .style(action_btn_style).on_press(Message::ToggleCanonical(i)));
+        }
         if last_assistant_idx == Some(i) && !is_streaming {
             actions = actions.push(button(text("\u{21BB}").size(FONT_SMALL)).padding([2, 6]).style(action_btn_style).on_press(Message::RetryMessage));
         }
@@ -297,10 +321,22 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
         ..Default::default()
     });
 
+    let isolated = conv.thread_mode == ThreadMode::Isolated;
+    let thread_mode_btn = button(
+        text(format!("Threads: {}", conv.thread_mode.label())).size(FONT_SMALL)
+    ).on_press(Message::ToggleThreadMode).padding([4, 10]).style(move |_: &Theme, status: button::Status| button::Style {
+        background: Some(iced::Background::Color(match status { button::Status::Hovered => BG_HOVER(), _ => BG_ACTIVE() })),
+        text_color: if isolated { ACCENT() } else { TEXT_SEC() },
+        border: Border { radius: 10.0.into(), width: 1.0, color: if isolated { ACCENT_DIM() } else { BORDER_DEFAULT() } },
+        ..Default::default()
+    });
+
     let chat_header = container(
         row![
             text("Home").size(FONT_H1).color(TEXT_HEAD()),
             iced::widget::Space::new().width(Length::Fill),
+            thread_mode_btn,
+            iced::widget::Space::new().width(8),
             sys_prompt_btn,
             iced::widget::Space::new().width(8),
             compare_btn,
@@ -358,7 +394,7 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
 
     for group in &groups {
         match group {
-            DisplayGroup::User(i) => {
+            MessageGroup::User(i) => {
                 let msg = &conv.messages[*i];
                 let mut col = Column::new().spacing(4);
                 let is_special = is_special_user_message(&msg.content);
@@ -367,14 +403,48 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
                 col = col.push(container(text(label).size(FONT_SMALL).color(label_color)).width(Length::Fill).align_x(Alignment::End));
 
                 let bubble_style: fn(&Theme) -> container::Style = if is_special { special_user_bubble } else { user_bubble };
-                col = col.push(
-                    container(container(text(msg.content.clone()).size(FONT_BODY).color(TEXT_HEAD())).padding([12, 16]).max_width(600).style(bubble_style))
-                        .width(Length::Fill).align_x(Alignment::End)
-                );
-                col = col.push(container(button(text("\u{00D7}").size(FONT_SMALL)).padding([2, 6]).style(action_btn_style).on_press(Message::DeleteMessage(*i))).width(Length::Fill).align_x(Alignment::End));
-                messages_col = messages_col.push(container(col).width(Length::Fill));
+                if app.editing_message == Some(*i) {
+                    let edit_input = iced::widget::text_input("Edit message...", &app.edit_message_value)
+                        .on_input(Message::EditMessageChanged)
+                        .on_submit(Message::SubmitMessageEdit)
+                        .id("edit-message-input")
+                        .size(FONT_BODY).padding([10, 14])
+                        .style(edit_input_style);
+                    let models = conv.turn_models(*i);
+                    let resend_label = match models.len() {
+                        0 | 1 => "Resend".to_string(),
+                        n => format!("Resend to {n} models"),
+                    };
+                    col = col.push(container(edit_input).width(Length::Fill).max_width(600).align_x(Alignment::End));
+                    col = col.push(container(row![
+                        button(text("Cancel").size(FONT_SMALL)).padding([4, 10]).style(action_btn_style).on_press(Message::CancelMessageEdit),
+                        button(text(resend_label).size(FONT_SMALL)).padding([4, 10]).style(diff_btn_style).on_press(Message::SubmitMessageEdit),
+                    ].spacing(6)).width(Length::Fill).align_x(Alignment::End));
+                } else {
+                    let terms: &[String] = if app.highlighted_message.as_ref() == Some(&msg.id) { &app.highlight_terms } else { &[] };
+                    let body = rich_text(markdown::highlighted_spans(&msg.content, terms, |piece| span(piece).color(TEXT_HEAD())))
+                        .size(FONT_BODY);
+                    col = col.push(
+                        container(container(body).padding([12, 16]).max_width(600).style(bubble_style))
+                            .width(Length::Fill).align_x(Alignment::End)
+                    );
+                    let mut user_actions = Row::new().spacing(4).align_y(Alignment::Center);
+                    if let Some(nav) = version_nav(app, *i, is_streaming) {
+                        user_actions = user_actions.push(nav);
+                    }
+                    if !is_streaming {
+                        user_actions = user_actions.push(button(text("\u{270E}").size(FONT_SMALL)).padding([2, 6]).style(action_btn_style).on_press(Message::StartEditMessage(*i)));
+                    }
+                    user_actions = user_actions.push(button(text("\u{00D7}").size(FONT_SMALL)).padding([2, 6]).style(action_btn_style).on_press(Message::DeleteMessage(*i)));
+                    col = col.push(container(user_actions).width(Length::Fill).align_x(Alignment::End));
+                }
+                let mut item = container(col).width(Length::Fill);
+                if app.highlighted_message.as_ref() == Some(&msg.id) {
+                    item = item.padding([8, 10]).style(highlighted_message_style);
+                }
+                messages_col = messages_col.push(item);
             }
-            DisplayGroup::Assistants(indices) => {
+            MessageGroup::Assistants(indices) => {
                 let use_comparison = app.comparison_mode && indices.len() >= 2;
 
                 if use_comparison {
@@ -389,8 +459,13 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
                     }
                     messages_col = messages_col.push(container(cols_row).width(Length::Fill));
 
+                    let report_btn = button(text("Report").size(FONT_CAPTION))
+                        .padding([4, 10]).style(diff_btn_style).on_press(Message::OpenReport(Some(indices[0])));
+
                     // Diff button for exactly 2 messages
-                    if indices.len() == 2 {
+                    if indices.len() != 2 {
+                        messages_col = messages_col.push(row![report_btn]);
+                    } else {
                         let is_diff_active = app.diff_active == Some((indices[0], indices[1]));
                         let diff_label = if is_diff_active { "Hide Diff" } else { "Show Diff" };
                         let model_a = conv.messages[indices[0]].model.as_deref().map(short_model_name).unwrap_or("A");
@@ -401,7 +476,8 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
                             row![
                                 button(text(format!("{diff_label} ({model_a} vs {model_b})")).size(FONT_CAPTION))
                                     .padding([4, 10]).style(diff_btn_style).on_press(btn_msg),
-                            ]
+                                report_btn,
+                            ].spacing(6)
                         );
                     }
 
@@ -416,7 +492,11 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
                         let msg = &conv.messages[i];
                         let show_model = !prev_was_assistant || msg.model.is_some();
                         let col = render_assistant_message(app, msg, i, is_streaming, last_assistant_idx, show_model, false);
-                        messages_col = messages_col.push(container(col).width(Length::Fill));
+                        let mut item = container(col).width(Length::Fill);
+                        if app.highlighted_message.as_ref() == Some(&msg.id) {
+                            item = item.padding([8, 10]).style(highlighted_message_style);
+                        }
+                        messages_col = messages_col.push(item);
                         prev_was_assistant = true;
                     }
                 }
@@ -444,7 +524,7 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
         chat_header,
         container(iced::widget::Space::new()).width(Length::Fill).height(1)
             .style(|_: &Theme| container::Style { background: Some(iced::Background::Color(DIVIDER())), ..Default::default() }),
-        container(scrollable(messages_col).height(Length::Fill).anchor_bottom())
+        container(scrollable(messages_col).id(SCROLL_ID).height(Length::Fill).anchor_bottom())
             .width(Length::Fill).height(Length::Fill)
             .style(|_: &Theme| container::Style { background: Some(iced::Background::Color(MAIN_BG())), ..Default::default() }),
     ].into()
@@ -56,7 +56,7 @@ fn cmd_style(active: bool) -> impl Fn(&Theme, button::Status) -> button::Style {
 }
 
 pub fn filtered_commands(app: &ChatApp) -> Vec<commands::CommandEntry> {
-    commands::filtered_commands(&app.command_palette_query, &app.config.keybindings)
+    commands::filtered_commands(&app.command_palette_query, &app.config.keybindings, &app.other_workspaces())
 }
 
 fn shortcuts_hint() -> Element<'static, Message> {
@@ -86,7 +86,7 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
     for (index, cmd) in commands.iter().enumerate() {
         let is_selected = index == app.command_palette_selected.min(commands.len().saturating_sub(1));
         let mut cmd_row = row![
-            text(cmd.label).size(FONT_SMALL).color(TEXT_HEAD()),
+            text(cmd.label.clone()).size(FONT_SMALL).color(TEXT_HEAD()),
             iced::widget::Space::new().width(8),
             text(cmd.description).size(FONT_MICRO).color(TEXT_MUTED()),
             iced::widget::Space::new().width(Length::Fill),
@@ -36,6 +36,30 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
         stat_row("Platform", if cfg!(target_os = "macos") { "macOS".to_string() } else { "non-macOS".to_string() }),
     ].spacing(6)).padding(16).width(Length::Fill).style(card_style);
 
+    let integrity = match &app.integrity_report {
+        None => "not checked (Run Diagnostics)".to_string(),
+        Some(Ok(problems)) if problems.is_empty() => "ok".to_string(),
+        Some(Ok(problems)) => format!("{} problems", problems.len()),
+        Some(Err(e)) => e.clone(),
+    };
+    let latest_snapshot = app.snapshots.first()
+        .map(|s| s.created_at.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "<none>".to_string());
+    let mut database_col = column![
+        text("Database").size(FONT_SMALL).color(TEXT_HEAD()),
+        iced::widget::Space::new().height(8),
+        stat_row("Schema version", format!("{} (latest {})", crate::db::schema_version(&app.db), crate::db::SCHEMA_VERSION)),
+        stat_row("Encryption", format!("{:?}", app.config.encryption)),
+        stat_row("Integrity", integrity),
+        stat_row("Latest snapshot", latest_snapshot),
+    ].spacing(6);
+    if let Some(Ok(problems)) = &app.integrity_report {
+        for problem in problems.iter().take(10) {
+            database_col = database_col.push(text(problem.as_str()).size(FONT_CAPTION).color(DANGER()).font(iced::Font::MONOSPACE));
+        }
+    }
+    let database = container(database_col).padding(16).width(Length::Fill).style(card_style);
+
     let key_log_path = crate::shortcuts::key_log_path()
         .map(|p| p.display().to_string())
         .unwrap_or_else(|| "<unavailable>".to_string());
@@ -87,7 +111,7 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
         .color(TEXT_MUTED()),
     ].spacing(8)).padding(16).width(Length::Fill).style(card_style);
 
-    let body = column![run_card, focus, keyboard, bindings]
+    let body = column![run_card, focus, database, keyboard, bindings]
         .spacing(12)
         .padding([20, 32])
         .max_width(760);
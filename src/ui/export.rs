@@ -0,0 +1,249 @@
+use std::collections::BTreeSet;
+
+use iced::widget::{button, column, container, row, scrollable, text, text_input};
+use iced::{Alignment, Border, Element, Length, Theme};
+
+use crate::app::{ChatApp, Message};
+use crate::dataset::{DatasetFilter, RatingFilter};
+use crate::export::{ExportDialog, ExportFormat, ExportOptions, ExportScope};
+use crate::report::{ReportDialog, ReportFormat};
+use crate::theme::*;
+
+fn modal_style(_: &Theme) -> container::Style {
+    container::Style {
+        background: Some(iced::Background::Color(CARD_BG())),
+        border: Border { radius: 12.0.into(), width: 1.0, color: BORDER_DEFAULT() },
+        ..Default::default()
+    }
+}
+
+fn overlay_style(_: &Theme) -> container::Style {
+    container::Style {
+        background: Some(iced::Background::Color(OVERLAY_BG())),
+        ..Default::default()
+    }
+}
+
+fn chip_style(active: bool) -> impl Fn(&Theme, button::Status) -> button::Style {
+    move |_: &Theme, status: button::Status| {
+        let bg = match (active, status) {
+            (true, _) => CHIP_ACTIVE_BG(),
+            (false, button::Status::Hovered) => BG_HOVER(),
+            _ => iced::Color::TRANSPARENT,
+        };
+        button::Style {
+            background: Some(iced::Background::Color(bg)),
+            text_color: if active { ACCENT() } else { TEXT_SEC() },
+            border: Border { radius: 14.0.into(), width: 1.0, color: if active { ACCENT() } else { BORDER_DEFAULT() } },
+            ..Default::default()
+        }
+    }
+}
+
+fn action_style(_: &Theme, status: button::Status) -> button::Style {
+    button::Style {
+        background: Some(iced::Background::Color(match status {
+            button::Status::Hovered => BG_HOVER(),
+            _ => BG_ACTIVE(),
+        })),
+        text_color: TEXT_HEAD(),
+        border: Border { radius: 8.0.into(), width: 1.0, color: BORDER_DEFAULT() },
+        ..Default::default()
+    }
+}
+
+fn field_style(_: &Theme, status: text_input::Status) -> text_input::Style {
+    text_input::Style {
+        background: iced::Background::Color(INPUT_BG()),
+        border: Border {
+            radius: 8.0.into(),
+            width: 1.0,
+            color: if matches!(status, text_input::Status::Focused { .. }) { ACCENT() } else { BORDER_DEFAULT() },
+        },
+        icon: TEXT_MUTED(),
+        placeholder: TEXT_MUTED(),
+        value: TEXT_HEAD(),
+        selection: SELECTION(),
+    }
+}
+
+fn chip<'a>(label: impl text::IntoFragment<'a>, active: bool, on_press: Option<Message>) -> Element<'a, Message> {
+    button(text(label).size(FONT_SMALL))
+        .on_press_maybe(on_press)
+        .padding([4, 10])
+        .style(chip_style(active))
+        .into()
+}
+
+fn section<'a>(label: &'a str, chips: Vec<Element<'a, Message>>) -> Element<'a, Message> {
+    column![
+        text(label).size(FONT_CAPTION).color(TEXT_MUTED()),
+        iced::widget::Row::with_children(chips).spacing(6).wrap(),
+    ]
+    .spacing(6)
+    .into()
+}
+
+/// Model, rating and PII filters for training datasets.
+fn dataset_section<'a>(format: ExportFormat, options: &'a ExportOptions) -> Element<'a, Message> {
+    let with = |dataset: DatasetFilter| Message::SetExportOptions(ExportOptions { dataset, ..options.clone() });
+    let filter = &options.dataset;
+    let mut col = column![
+        text("Dataset").size(FONT_CAPTION).color(TEXT_MUTED()),
+        text_input("Model name contains\u{2026} (all models)", &filter.model)
+            .on_input(move |model| with(DatasetFilter { model, ..filter.clone() }))
+            .padding([6, 10])
+            .size(FONT_SMALL)
+            .style(field_style),
+    ]
+    .spacing(6);
+    if format == ExportFormat::FineTune {
+        col = col.push(iced::widget::Row::with_children(RatingFilter::ALL.iter()
+            .map(|r| chip(r.label(), filter.rating == *r, Some(with(DatasetFilter { rating: *r, ..filter.clone() }))))
+            .collect::<Vec<_>>()).spacing(6).wrap());
+    } else {
+        col = col.push(
+            text("Pairs come from multi-model turns with one reply rated up and another down.")
+                .size(FONT_CAPTION).color(TEXT_MUTED())
+        );
+    }
+    col.push(chip("Scrub PII", filter.scrub_pii, Some(with(DatasetFilter { scrub_pii: !filter.scrub_pii, ..filter.clone() }))))
+        .into()
+}
+
+/// Choose what to export, in which format and with what, then save it to a file.
+pub fn dialog<'a>(app: &'a ChatApp, dialog: &'a ExportDialog) -> Element<'a, Message> {
+    let scope = &dialog.scope;
+    let mut col = column![text("Export").size(FONT_BODY).color(TEXT_HEAD())].spacing(14);
+
+    let searching = app.sidebar_search_results.is_some();
+    col = col.push(section("Conversations", vec![
+        chip("This conversation", *scope == ExportScope::Conversation, Some(Message::SetExportScope(ExportScope::Conversation))),
+        chip("Choose\u{2026}", *scope == ExportScope::Chosen, Some(Message::SetExportScope(ExportScope::Chosen))),
+        chip("Search results", *scope == ExportScope::SearchResults, searching.then_some(Message::SetExportScope(ExportScope::SearchResults))),
+        chip("Whole library", *scope == ExportScope::Library, Some(Message::SetExportScope(ExportScope::Library))),
+    ]));
+
+    let folders: BTreeSet<&str> = app.conversations.iter().filter_map(|c| c.folder.as_deref()).collect();
+    if !folders.is_empty() {
+        col = col.push(section("Folder", folders.into_iter()
+            .map(|f| chip(f, *scope == ExportScope::Folder(f.to_string()), Some(Message::SetExportScope(ExportScope::Folder(f.to_string())))))
+            .collect()));
+    }
+    let tags: BTreeSet<&str> = app.conversations.iter().flat_map(|c| c.tags.iter().map(String::as_str)).collect();
+    if !tags.is_empty() {
+        col = col.push(section("Tag", tags.into_iter()
+            .map(|t| chip(t, *scope == ExportScope::Tag(t.to_string()), Some(Message::SetExportScope(ExportScope::Tag(t.to_string())))))
+            .collect()));
+    }
+
+    if *scope == ExportScope::Chosen {
+        let mut list = column![].spacing(2);
+        for conv in &app.conversations {
+            let ticked = dialog.chosen.contains(&conv.id);
+            let check = if ticked { "\u{2611}" } else { "\u{2610}" };
+            list = list.push(
+                button(text(format!("{check} {}", conv.title)).size(FONT_SMALL))
+                    .on_press(Message::ToggleExportChoice(conv.id.clone()))
+                    .width(Length::Fill)
+                    .padding([4, 10])
+                    .style(chip_style(ticked))
+            );
+        }
+        col = col.push(scrollable(list).height(180));
+    }
+
+    col = col.push(section("Format", ExportFormat::ALL.iter()
+        .map(|f| chip(f.label(), dialog.format == *f, Some(Message::SetExportFormat(*f))))
+        .collect()));
+
+    let options = &dialog.options;
+    if dialog.format == ExportFormat::Archive {
+        col = col.push(
+            text("Archives keep every branch, rating, tag, folder, fork link and image, and can be imported again.")
+                .size(FONT_CAPTION).color(TEXT_MUTED())
+        );
+    } else if dialog.format.is_dataset() {
+        col = col.push(dataset_section(dialog.format, options));
+    } else {
+        col = col.push(section("Include", vec![
+            chip("Metadata", options.metadata, Some(Message::SetExportOptions(ExportOptions { metadata: !options.metadata, ..options.clone() }))),
+            chip("Errored replies", options.errored, Some(Message::SetExportOptions(ExportOptions { errored: !options.errored, ..options.clone() }))),
+            chip("Images", options.images, Some(Message::SetExportOptions(ExportOptions { images: !options.images, ..options.clone() }))),
+        ]));
+    }
+
+    if let Some(status) = &dialog.status {
+        col = col.push(text(status.as_str()).size(FONT_CAPTION).color(TEXT_SEC()));
+    }
+
+    let count = app.export_ids().len();
+    col = col.push(
+        row![
+            text(format!("{count} conversation(s)")).size(FONT_CAPTION).color(TEXT_MUTED()),
+            iced::widget::Space::new().width(Length::Fill),
+            button(text("Close").size(FONT_CAPTION))
+                .on_press(Message::CloseExportDialog)
+                .padding([8, 14])
+                .style(action_style),
+            button(text("Save\u{2026}").size(FONT_CAPTION))
+                .on_press_maybe((count > 0).then_some(Message::SaveExport))
+                .padding([8, 14])
+                .style(action_style),
+        ]
+        .spacing(8)
+        .align_y(Alignment::Center)
+    );
+
+    container(container(scrollable(col)).width(520).padding(18).style(modal_style))
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .align_x(Alignment::Center)
+        .align_y(iced::alignment::Vertical::Center)
+        .style(overlay_style)
+        .into()
+}
+
+/// Save a comparison report of one multi-model turn or the whole conversation.
+pub fn report(dialog: &ReportDialog) -> Element<'_, Message> {
+    let mut col = column![text("Comparison report").size(FONT_BODY).color(TEXT_HEAD())].spacing(14);
+    if dialog.turn.is_some() {
+        col = col.push(section("Covers", vec![
+            chip("This turn", !dialog.whole, Some(Message::SetReportWhole(false))),
+            chip("Whole conversation", dialog.whole, Some(Message::SetReportWhole(true))),
+        ]));
+    }
+    col = col.push(section("Format", ReportFormat::ALL.iter()
+        .map(|f| chip(f.label(), dialog.format == *f, Some(Message::SetReportFormat(*f))))
+        .collect()));
+    col = col.push(
+        text("The prompt, each model's answer with tokens, latency, cost and rating, and how much each pair agrees.")
+            .size(FONT_CAPTION).color(TEXT_MUTED())
+    );
+    if let Some(status) = &dialog.status {
+        col = col.push(text(status.as_str()).size(FONT_CAPTION).color(TEXT_SEC()));
+    }
+    col = col.push(
+        row![
+            iced::widget::Space::new().width(Length::Fill),
+            button(text("Close").size(FONT_CAPTION))
+                .on_press(Message::CloseReport)
+                .padding([8, 14])
+                .style(action_style),
+            button(text("Save\u{2026}").size(FONT_CAPTION))
+                .on_press(Message::SaveReport)
+                .padding([8, 14])
+                .style(action_style),
+        ]
+        .spacing(8)
+        .align_y(Alignment::Center)
+    );
+
+    container(container(col).width(440).padding(18).style(modal_style))
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .align_x(Alignment::Center)
+        .align_y(iced::alignment::Vertical::Center)
+        .style(overlay_style)
+        .into()
+}
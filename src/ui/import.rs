@@ -0,0 +1,215 @@
+use chrono::Local;
+use iced::widget::{button, column, container, row, scrollable, text, text_input};
+use iced::{Alignment, Border, Element, Length, Theme};
+
+use crate::app::{ChatApp, Message};
+use crate::import::{ImportFormat, ImportPreview, ImportReport, JsonlField};
+use crate::theme::*;
+
+/// Sample conversations shown in the preview.
+const PREVIEW_SAMPLES: usize = 3;
+
+fn modal_style(_: &Theme) -> container::Style {
+    container::Style {
+        background: Some(iced::Background::Color(CARD_BG())),
+        border: Border { radius: 12.0.into(), width: 1.0, color: BORDER_DEFAULT() },
+        ..Default::default()
+    }
+}
+
+fn overlay_style(_: &Theme) -> container::Style {
+    container::Style {
+        background: Some(iced::Background::Color(OVERLAY_BG())),
+        ..Default::default()
+    }
+}
+
+fn field_style(_: &Theme, status: text_input::Status) -> text_input::Style {
+    text_input::Style {
+        background: iced::Background::Color(INPUT_BG()),
+        border: Border {
+            radius: 8.0.into(),
+            width: 1.0,
+            color: if matches!(status, text_input::Status::Focused { .. }) { ACCENT() } else { BORDER_DEFAULT() },
+        },
+        icon: TEXT_MUTED(),
+        placeholder: TEXT_MUTED(),
+        value: TEXT_HEAD(),
+        selection: SELECTION(),
+    }
+}
+
+fn action_style(_: &Theme, status: button::Status) -> button::Style {
+    button::Style {
+        background: Some(iced::Background::Color(match status {
+            button::Status::Hovered => BG_HOVER(),
+            _ => BG_ACTIVE(),
+        })),
+        text_color: TEXT_HEAD(),
+        border: Border { radius: 8.0.into(), width: 1.0, color: BORDER_DEFAULT() },
+        ..Default::default()
+    }
+}
+
+fn chip_style(active: bool) -> impl Fn(&Theme, button::Status) -> button::Style {
+    move |_: &Theme, status: button::Status| {
+        let bg = match (active, status) {
+            (true, _) => CHIP_ACTIVE_BG(),
+            (false, button::Status::Hovered) => BG_HOVER(),
+            _ => iced::Color::TRANSPARENT,
+        };
+        button::Style {
+            background: Some(iced::Background::Color(bg)),
+            text_color: if active { ACCENT() } else { TEXT_SEC() },
+            border: Border { radius: 14.0.into(), width: 1.0, color: if active { ACCENT() } else { BORDER_DEFAULT() } },
+            ..Default::default()
+        }
+    }
+}
+
+fn chip(label: &str, active: bool, on_press: Message) -> Element<'_, Message> {
+    button(text(label).size(FONT_SMALL))
+        .on_press(on_press)
+        .padding([4, 10])
+        .style(chip_style(active))
+        .into()
+}
+
+fn modal<'a>(body: impl Into<Element<'a, Message>>, width: u32) -> Element<'a, Message> {
+    container(container(body).width(width).padding(18).style(modal_style))
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .align_x(Alignment::Center)
+        .align_y(iced::alignment::Vertical::Center)
+        .style(overlay_style)
+        .into()
+}
+
+fn skipped_list(report: &ImportReport) -> Element<'_, Message> {
+    let mut col = column![text("Left out:").size(FONT_SMALL).color(TEXT_SEC())].spacing(4);
+    for (kind, count) in &report.skipped {
+        col = col.push(text(format!("\u{2022} {count} \u{00d7} {kind}")).size(FONT_SMALL).color(TEXT_HEAD()));
+    }
+    if report.skipped.contains_key("image") {
+        col = col.push(
+            text("Images stay in the export's files; messages keep an [Image: …] reference.")
+                .size(FONT_CAPTION).color(TEXT_MUTED())
+        );
+    }
+    col.into()
+}
+
+/// What importing a file would add, before anything is saved.
+pub fn preview<'a>(app: &'a ChatApp, preview: &'a ImportPreview) -> Element<'a, Message> {
+    let mut col = column![
+        text(format!("Import {}", preview.source)).size(FONT_BODY).color(TEXT_HEAD()),
+        text(format!("{} export", preview.format.label())).size(FONT_CAPTION).color(TEXT_MUTED()),
+    ]
+    .spacing(8);
+
+    if preview.format == ImportFormat::Jsonl {
+        let mut fields = column![
+            text("Field mapping (nested fields as a.b)").size(FONT_SMALL).color(TEXT_SEC()),
+        ].spacing(6);
+        for field in JsonlField::ALL {
+            fields = fields.push(
+                row![
+                    text(field.label()).size(FONT_SMALL).color(TEXT_MUTED()).width(110),
+                    text_input("not read", app.config.jsonl_mapping.get(field))
+                        .on_input(move |v| Message::SetJsonlField(field, v))
+                        .padding([6, 10])
+                        .size(FONT_SMALL)
+                        .style(field_style),
+                ].spacing(8).align_y(Alignment::Center)
+            );
+        }
+        col = col.push(fields);
+    }
+
+    col = col.push(
+        text(format!("{} conversation(s), {} message(s)", preview.conversations.len(), preview.message_count()))
+            .size(FONT_SMALL).color(TEXT_HEAD())
+    );
+    if let Some((first, last)) = preview.date_range() {
+        let day = |t: chrono::DateTime<chrono::Utc>| t.with_timezone(&Local).format("%Y-%m-%d").to_string();
+        col = col.push(text(format!("From {} to {}", day(first), day(last))).size(FONT_SMALL).color(TEXT_SEC()));
+    }
+    if !preview.duplicates.is_empty() {
+        let outcome = if preview.copy_duplicates { "will be imported as copies" } else { "will be skipped" };
+        col = col.push(
+            text(format!("{} already in the library and {outcome}.", preview.duplicates.len()))
+                .size(FONT_SMALL).color(TEXT_SEC())
+        );
+        col = col.push(
+            row![
+                chip("Skip them", !preview.copy_duplicates, Message::SetImportCopies(false)),
+                chip("Import as copies", preview.copy_duplicates, Message::SetImportCopies(true)),
+            ].spacing(6)
+        );
+    }
+    if !preview.report.skipped.is_empty() {
+        col = col.push(skipped_list(&preview.report));
+    }
+
+    for conv in preview.new_conversations().take(PREVIEW_SAMPLES) {
+        let first: String = conv.messages.first().map(|m| m.content.chars().take(140).collect()).unwrap_or_default();
+        col = col.push(
+            container(
+                column![
+                    text(conv.title.as_str()).size(FONT_SMALL).color(TEXT_HEAD()),
+                    text(first).size(FONT_CAPTION).color(TEXT_MUTED()),
+                ].spacing(2)
+            ).padding([8, 12]).width(Length::Fill).style(|_: &Theme| container::Style {
+                background: Some(iced::Background::Color(MAIN_BG())),
+                border: Border { radius: 8.0.into(), width: 1.0, color: BORDER_SUBTLE() },
+                ..Default::default()
+            })
+        );
+    }
+
+    let count = preview.new_conversations().count();
+    col = col.push(
+        row![
+            iced::widget::Space::new().width(Length::Fill),
+            button(text("Cancel").size(FONT_CAPTION))
+                .on_press(Message::CancelImport)
+                .padding([8, 14])
+                .style(action_style),
+            button(text(format!("Import {count}")).size(FONT_CAPTION))
+                .on_press_maybe((count > 0).then_some(Message::ConfirmImport))
+                .padding([8, 14])
+                .style(action_style),
+        ].spacing(8)
+    );
+
+    modal(scrollable(col), 520)
+}
+
+/// What an import brought in and what it left out.
+pub fn report(report: &ImportReport) -> Element<'_, Message> {
+    let mut col = column![
+        text("Import finished").size(FONT_BODY).color(TEXT_HEAD()),
+        text(format!("{} conversation(s) imported.", report.imported)).size(FONT_SMALL).color(TEXT_SEC()),
+    ]
+    .spacing(8);
+    if report.already_imported > 0 {
+        col = col.push(
+            text(format!("{} were already in the library and were skipped.", report.already_imported))
+                .size(FONT_SMALL).color(TEXT_SEC())
+        );
+    }
+    if !report.skipped.is_empty() {
+        col = col.push(skipped_list(report));
+    }
+    col = col.push(
+        row![
+            iced::widget::Space::new().width(Length::Fill),
+            button(text("OK").size(FONT_CAPTION))
+                .on_press(Message::DismissImportReport)
+                .padding([8, 14])
+                .style(action_style),
+        ].align_y(Alignment::Center)
+    );
+
+    modal(col, 440)
+}
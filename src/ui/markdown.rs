@@ -51,6 +51,32 @@ fn blockquote_style(_: &Theme) -> container::Style {
 }
 
 pub fn render_markdown(content: &str) -> Element<'static, Message> {
+    render_markdown_highlighted(content, &[])
+}
+
+/// Split `text` into spans, giving the parts that match `terms` a highlight.
+pub fn highlighted_spans(
+    text: &str,
+    terms: &[String],
+    base: impl Fn(String) -> iced::widget::text::Span<'static>,
+) -> Vec<iced::widget::text::Span<'static>> {
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    for range in crate::search::match_ranges(text, terms) {
+        if range.start > pos {
+            spans.push(base(text[pos..range.start].to_string()));
+        }
+        spans.push(base(text[range.clone()].to_string()).color(TEXT_HEAD()).background(SELECTION()));
+        pos = range.end;
+    }
+    if pos < text.len() || spans.is_empty() {
+        spans.push(base(text[pos..].to_string()));
+    }
+    spans
+}
+
+/// Render markdown, highlighting words that match `terms` (e.g. from a search).
+pub fn render_markdown_highlighted(content: &str, terms: &[String]) -> Element<'static, Message> {
     use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd, HeadingLevel};
 
     let parser = Parser::new_ext(content, Options::all());
@@ -223,13 +249,10 @@ pub fn render_markdown(content: &str) -> Element<'static, Message> {
                     } else {
                         TEXT_BODY()
                     };
-                    let mut s = span(t.into_string())
-                        .color(color)
-                        .font(build_font(is_bold, is_italic, false));
-                    if is_strikethrough {
-                        s = s.strikethrough(true);
-                    }
-                    spans.push(s);
+                    let font = build_font(is_bold, is_italic, false);
+                    spans.extend(highlighted_spans(&t, terms, |piece| {
+                        span(piece).color(color).font(font).strikethrough(is_strikethrough)
+                    }));
                 }
             }
             Event::SoftBreak => {
@@ -3,6 +3,8 @@ pub mod bottom_bar;
 pub mod chat_view;
 pub mod command_palette;
 pub mod diagnostics;
+pub mod export;
+pub mod import;
 pub mod input_bar;
 pub mod markdown;
 pub mod quick_switcher;
@@ -10,3 +12,5 @@ pub mod right_panel;
 pub mod settings;
 pub mod shortcut_help;
 pub mod sidebar;
+pub mod trash;
+pub mod unlock;
@@ -1,4 +1,4 @@
-use iced::widget::{button, column, container, scrollable, text, text_input, Column};
+use iced::widget::{button, column, container, row, scrollable, text, text_input, Column};
 use iced::{Alignment, Element, Length, Border, Theme};
 
 use crate::app::{ChatApp, Message};
@@ -50,31 +50,44 @@ fn result_style(active: bool) -> impl Fn(&Theme, button::Status) -> button::Styl
 }
 
 pub fn view(app: &ChatApp) -> Element<'_, Message> {
-    let input = text_input("Search conversations...", &app.quick_switcher_query)
+    let input = text_input("Search conversations... (tag:, model:, is:pinned)", &app.quick_switcher_query)
         .on_input(Message::QuickSwitcherQueryChanged)
         .id("quick-switcher-input")
         .size(FONT_BODY)
         .padding([10, 16])
         .style(input_style);
 
+    let mode_label = if app.quick_switcher_semantic { "Semantic" } else { "Keyword" };
+    let mode_toggle = button(text(mode_label).size(FONT_CAPTION))
+        .on_press(Message::ToggleSemanticSearch)
+        .padding([2, 8])
+        .style(result_style(app.quick_switcher_semantic));
+
+    if app.quick_switcher_semantic {
+        return overlay(input, mode_toggle, semantic_results(app));
+    }
+
     let query = app.quick_switcher_query.to_lowercase();
+    // Filter queries (`tag:`, `model:`, ...) only match through SQLite
+    let filtered = crate::search::SearchQuery::parse(&query).has_filters();
     let mut results = Column::new().spacing(2);
     let mut count = 0;
     for (i, conv) in app.conversations.iter().enumerate() {
         if count >= 12 { break; }
-        if !query.is_empty() {
+        if filtered {
+            if !app.quick_switcher_matches.as_ref().is_some_and(|ids| ids.contains(&conv.id)) { continue; }
+        } else if !query.is_empty() {
             let title_match = conv.title.to_lowercase().contains(&query);
             let tag_match = conv.tags.iter().any(|t| t.to_lowercase().contains(&query));
-            // Only search message content for longer queries (3+ chars) to avoid lag
-            let content_match = query.len() >= 3
-                && conv.messages.iter().any(|m| m.content.to_lowercase().contains(&query));
+            let content_match = app.quick_switcher_matches.as_ref().is_some_and(|ids| ids.contains(&conv.id));
             if !title_match && !tag_match && !content_match { continue; }
         }
 
         let pin_icon = if conv.pinned { "\u{25B6} " } else { "" };
-        let msg_count = conv.messages.iter().filter(|m| !m.streaming).count();
+        let stats = conv.stats();
         let tags_str = if conv.tags.is_empty() { String::new() } else { format!("  [{}]", conv.tags.join(", ")) };
-        let label = format!("{pin_icon}{}{tags_str}  ({msg_count} msgs)", conv.title);
+        let cost_str = if stats.cost > 0.0 { format!(", ${:.4}", stats.cost) } else { String::new() };
+        let label = format!("{pin_icon}{}{tags_str}  ({} msgs{cost_str})", conv.title, stats.message_count);
         let is_active = i == app.active_conversation;
 
         results = results.push(
@@ -87,9 +100,46 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
         count += 1;
     }
 
+    overlay(input, mode_toggle, results)
+}
+
+/// Conversations ranked by meaning rather than wording, with their similarity.
+fn semantic_results(app: &ChatApp) -> Column<'_, Message> {
+    let mut results = Column::new().spacing(2);
+    if !app.config.embeddings.enabled {
+        return results.push(text("Enable semantic search in Settings to search by meaning.").size(FONT_SMALL).color(TEXT_MUTED()));
+    }
+    if let Some(e) = &app.embedding_error {
+        results = results.push(text(e.as_str()).size(FONT_SMALL).color(DANGER()));
+    }
+    for (id, score) in &app.semantic_results {
+        let Some(i) = app.conv_index_by_id(id) else { continue };
+        let conv = &app.conversations[i];
+        let tags_str = if conv.tags.is_empty() { String::new() } else { format!("  [{}]", conv.tags.join(", ")) };
+        let label = format!("{}{tags_str}  ({:.0}%)", conv.title, score * 100.0);
+        results = results.push(
+            button(text(label).size(FONT_SMALL))
+                .on_press(Message::QuickSwitcherSelect(i))
+                .width(Length::Fill)
+                .padding([8, 16])
+                .style(result_style(i == app.active_conversation))
+        );
+    }
+    results
+}
+
+fn overlay<'a>(
+    input: iced::widget::TextInput<'a, Message>,
+    mode_toggle: iced::widget::Button<'a, Message>,
+    results: Column<'a, Message>,
+) -> Element<'a, Message> {
     let modal = container(
         column![
-            text("Quick Switcher").size(FONT_CAPTION).color(TEXT_MUTED()),
+            row![
+                text("Quick Switcher").size(FONT_CAPTION).color(TEXT_MUTED()),
+                iced::widget::Space::new().width(Length::Fill),
+                mode_toggle,
+            ].align_y(Alignment::Center),
             input,
             scrollable(results).height(300),
         ].spacing(8)
@@ -22,6 +22,18 @@ fn stream_card_style(_: &Theme) -> container::Style {
     }
 }
 
+fn related_style(_: &Theme, status: button::Status) -> button::Style {
+    button::Style {
+        background: Some(iced::Background::Color(match status {
+            button::Status::Hovered => BG_HOVER(),
+            _ => iced::Color::TRANSPARENT,
+        })),
+        text_color: TEXT_SEC(),
+        border: Border { radius: 4.0.into(), ..Default::default() },
+        ..Default::default()
+    }
+}
+
 pub fn view(app: &ChatApp) -> Element<'_, Message> {
     let icon = provider_icon(&app.selected_model);
     let provider_name = if app.selected_model.contains("claude") || app.selected_model.contains("haiku") || app.selected_model.contains("sonnet") || app.selected_model.contains("opus") {
@@ -102,6 +114,30 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
     ].spacing(6)).padding([12, 20]);
     content_col = content_col.push(resources);
 
+    // Related section — nearest conversations by embedding similarity
+    if !app.related_conversations.is_empty() {
+        let mut related_col = column![
+            text("Related").size(FONT_SMALL).color(TEXT_MUTED()),
+            iced::widget::Space::new().height(4),
+        ].spacing(2);
+        for (id, score) in &app.related_conversations {
+            let Some(idx) = app.conv_index_by_id(id) else { continue };
+            let title: String = app.conversations[idx].title.chars().take(24).collect();
+            related_col = related_col.push(
+                button(row![
+                    text(title).size(FONT_CAPTION).color(TEXT_SEC()),
+                    iced::widget::Space::new().width(Length::Fill),
+                    text(format!("{:.0}%", score * 100.0)).size(FONT_MICRO).color(TEXT_MUTED()).font(iced::Font::MONOSPACE),
+                ].align_y(iced::Alignment::Center))
+                    .on_press(Message::SelectConversation(idx))
+                    .width(Length::Fill)
+                    .padding([4, 6])
+                    .style(related_style)
+            );
+        }
+        content_col = content_col.push(container(related_col).padding([12, 20]));
+    }
+
     content_col = content_col.push(iced::widget::Space::new().height(Length::Fill));
 
     // Shortcuts section
@@ -2,6 +2,7 @@ use iced::widget::{button, column, container, row, scrollable, text, text_input}
 use iced::{Alignment, Element, Length, Border, Theme};
 
 use crate::app::{ChatApp, Message};
+use crate::config::EncryptionKeySource;
 use crate::model::Provider;
 use crate::shortcuts;
 use crate::theme::*;
@@ -128,6 +129,49 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
         ..Default::default()
     });
 
+    // Workspace
+    let current_workspace = crate::workspace::active();
+    let mut workspace_chips = iced::widget::Row::new().spacing(6);
+    for name in &app.workspaces {
+        let is_active = *name == current_workspace;
+        workspace_chips = workspace_chips.push(
+            button(text(name.as_str()).size(FONT_SMALL))
+                .on_press_maybe((!is_active).then(|| Message::SwitchWorkspace(name.clone())))
+                .padding([6, 16])
+                .style(chip_style(is_active)),
+        );
+    }
+    let workspace_section = container(
+        column![
+            text("Workspace").size(FONT_SMALL).color(TEXT_MUTED()),
+            workspace_chips.wrap(),
+            text(format!("Stored in {}", crate::workspace::dir_in(&crate::workspace::root(), &current_workspace).display()))
+                .size(FONT_CAPTION).color(TEXT_MUTED()),
+            row![
+                text_input("New workspace name", &app.new_workspace_name)
+                    .on_input(Message::NewWorkspaceNameChanged)
+                    .on_submit(Message::CreateWorkspace)
+                    .padding([10, 14])
+                    .size(FONT_BODY)
+                    .style(field_style),
+                button(text("Create").size(FONT_SMALL))
+                    .on_press(Message::CreateWorkspace)
+                    .padding([10, 16])
+                    .style(chip_style(false)),
+            ].spacing(8).align_y(Alignment::Center),
+            labeled_field("Credential profile", text_input("Shared keys", &app.credential_profile_input)
+                .on_input(Message::SetCredentialProfile)
+                .on_submit(Message::ApplyCredentialProfile)
+                .padding([10, 14])
+                .size(FONT_BODY)
+                .style(field_style)),
+            text("Workspaces with the same credential profile share API keys. Press Enter or save to switch.").size(FONT_CAPTION).color(TEXT_MUTED()),
+        ].spacing(10)
+    )
+    .padding(16)
+    .width(Length::Fill)
+    .style(card_style);
+
     // Theme picker
     let mut theme_chips = iced::widget::Row::new().spacing(6);
     for name in ThemeName::all() {
@@ -312,6 +356,256 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
     .width(Length::Fill)
     .style(card_style);
 
+    // Semantic search
+    let embeddings = &config.embeddings;
+    let indexed = app.semantic_index.as_ref().map_or(0, |index| index.entries.len());
+    let embedding_status = match (&app.embedding_error, app.embedding_in_progress) {
+        (Some(e), _) => text(e.as_str()).size(FONT_CAPTION).color(DANGER()),
+        (None, true) => text(format!("Indexing... {indexed} chunks so far")).size(FONT_CAPTION).color(TEXT_MUTED()),
+        (None, false) => text(format!("{indexed} chunks indexed")).size(FONT_CAPTION).color(TEXT_MUTED()),
+    };
+    let semantic_section = container(
+        column![
+            row![
+                text("Semantic Search").size(FONT_SMALL).color(TEXT_MUTED()),
+                iced::widget::Space::new().width(Length::Fill),
+                button(text(if embeddings.enabled { "Enabled" } else { "Disabled" }).size(FONT_SMALL))
+                    .on_press(Message::SetEmbeddingsEnabled(!embeddings.enabled))
+                    .padding([6, 12])
+                    .style(debug_toggle_style(embeddings.enabled)),
+            ]
+            .align_y(Alignment::Center),
+            labeled_field("Embeddings URL", text_input("http://localhost:11434/api/embed", &embeddings.api_url)
+                .on_input(Message::SetEmbeddingsUrl)
+                .padding([10, 14])
+                .size(FONT_BODY)
+                .style(field_style)),
+            labeled_field("API Key", text_input("Not needed for Ollama", &embeddings.api_key)
+                .on_input(Message::SetEmbeddingsApiKey)
+                .padding([10, 14])
+                .size(FONT_BODY)
+                .secure(true)
+                .style(field_style)),
+            labeled_field("Model ID", text_input("nomic-embed-text", &embeddings.model)
+                .on_input(Message::SetEmbeddingsModel)
+                .padding([10, 14])
+                .size(FONT_BODY)
+                .style(field_style)),
+            row![
+                embedding_status,
+                iced::widget::Space::new().width(Length::Fill),
+                button(text("Index now").size(FONT_SMALL))
+                    .on_press_maybe((embeddings.enabled && !app.embedding_in_progress).then_some(Message::IndexEmbeddings))
+                    .padding([6, 12])
+                    .style(chip_style(false)),
+            ]
+            .align_y(Alignment::Center),
+            text("Vectors are stored in the local database. Changes apply on Save.").size(FONT_CAPTION).color(TEXT_MUTED()),
+        ].spacing(12)
+    )
+    .padding(16)
+    .width(Length::Fill)
+    .style(card_style);
+
+    // Encryption
+    let encryption = config.encryption;
+    let encryption_status = match (encryption, &app.db_path) {
+        (_, None) => "Temporary database; nothing is written to disk",
+        (EncryptionKeySource::None, _) => "Not encrypted: conversations are stored as plain SQLite",
+        (EncryptionKeySource::Keychain, _) => "Encrypted with a key kept in the system keychain",
+        (EncryptionKeySource::Passphrase, _) => "Encrypted with a passphrase asked for at startup",
+    };
+    let mut encryption_fields = column![
+        text("Encryption").size(FONT_SMALL).color(TEXT_MUTED()),
+        text(encryption_status).size(FONT_SMALL).color(TEXT_SEC()),
+        labeled_field("Passphrase", text_input("At least 8 characters", &app.encryption_passphrase)
+            .on_input(Message::EncryptionPassphraseChanged)
+            .padding([10, 14])
+            .size(FONT_BODY)
+            .secure(true)
+            .style(field_style)),
+    ]
+    .spacing(12);
+    let action = |label: &'static str, source: EncryptionKeySource| {
+        button(text(label).size(FONT_SMALL))
+            .on_press_maybe(app.db_path.is_some().then_some(Message::SetDatabaseEncryption(source)))
+            .padding([6, 12])
+            .style(chip_style(false))
+    };
+    let mut actions = row![].spacing(8);
+    if encryption != EncryptionKeySource::Keychain {
+        actions = actions.push(action("Encrypt with keychain key", EncryptionKeySource::Keychain));
+    }
+    actions = actions.push(action(
+        if encryption == EncryptionKeySource::Passphrase { "Change passphrase" } else { "Encrypt with passphrase" },
+        EncryptionKeySource::Passphrase,
+    ));
+    if encryption != EncryptionKeySource::None {
+        actions = actions.push(action("Decrypt", EncryptionKeySource::None));
+    }
+    encryption_fields = encryption_fields.push(actions.wrap());
+    encryption_fields = encryption_fields.push(
+        row![
+            text("Backups use the same key as the database.").size(FONT_CAPTION).color(TEXT_MUTED()),
+            iced::widget::Space::new().width(Length::Fill),
+            button(text("Back up database...").size(FONT_SMALL))
+                .on_press(Message::BackupDatabase)
+                .padding([6, 12])
+                .style(chip_style(false)),
+        ]
+        .align_y(Alignment::Center)
+    );
+    encryption_fields = encryption_fields.push(
+        text("A forgotten passphrase can't be recovered. Changes apply immediately.").size(FONT_CAPTION).color(TEXT_MUTED())
+    );
+    let encryption_section = container(encryption_fields)
+        .padding(16)
+        .width(Length::Fill)
+        .style(card_style);
+
+    // Backups
+    let backup = &config.backup;
+    let mut backup_fields = column![
+        row![
+            text("Backups").size(FONT_SMALL).color(TEXT_MUTED()),
+            iced::widget::Space::new().width(Length::Fill),
+            button(text(if backup.enabled { "Scheduled" } else { "Manual only" }).size(FONT_SMALL))
+                .on_press(Message::SetBackupEnabled(!backup.enabled))
+                .padding([6, 12])
+                .style(debug_toggle_style(backup.enabled)),
+        ]
+        .align_y(Alignment::Center),
+        labeled_field("Directory", text_input(&crate::backup::default_dir().display().to_string(), &backup.directory)
+            .on_input(Message::SetBackupDirectory)
+            .padding([10, 14])
+            .size(FONT_BODY)
+            .style(field_style)),
+        row![
+            labeled_field("Every (hours)", text_input("24", &backup.interval_hours)
+                .on_input(Message::SetBackupInterval)
+                .padding([10, 14])
+                .size(FONT_BODY)
+                .style(field_style)),
+            labeled_field("Keep", text_input("7", &backup.keep)
+                .on_input(Message::SetBackupKeep)
+                .padding([10, 14])
+                .size(FONT_BODY)
+                .style(field_style)),
+            labeled_field("Keep deleted items (days)", text_input("30", &config.trash_retention_days)
+                .on_input(Message::SetTrashRetention)
+                .padding([10, 14])
+                .size(FONT_BODY)
+                .style(field_style)),
+        ]
+        .spacing(12),
+    ]
+    .spacing(12);
+    for snapshot in &app.snapshots {
+        let previewing = app.snapshot_preview.as_ref().is_some_and(|p| p.snapshot.path == snapshot.path);
+        backup_fields = backup_fields.push(
+            row![
+                text(snapshot.created_at.format("%Y-%m-%d %H:%M").to_string()).size(FONT_SMALL).color(TEXT_HEAD()).font(iced::Font::MONOSPACE),
+                text(format!("{:.1} MB", snapshot.size as f64 / 1_048_576.0)).size(FONT_CAPTION).color(TEXT_MUTED()),
+                iced::widget::Space::new().width(Length::Fill),
+                button(text("Preview").size(FONT_SMALL))
+                    .on_press(Message::PreviewSnapshot(snapshot.path.clone()))
+                    .padding([4, 10])
+                    .style(chip_style(previewing)),
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center)
+        );
+    }
+    if let Some(preview) = &app.snapshot_preview {
+        backup_fields = backup_fields.push(snapshot_preview(preview));
+    }
+    backup_fields = backup_fields.push(
+        row![
+            text(if app.snapshots.is_empty() { "No snapshots yet." } else { "A restore backs up the current library first." })
+                .size(FONT_CAPTION).color(TEXT_MUTED()),
+            iced::widget::Space::new().width(Length::Fill),
+            button(text(if app.snapshot_in_progress { "Backing up…" } else { "Back up now" }).size(FONT_SMALL))
+                .on_press_maybe((app.db_path.is_some() && !app.snapshot_in_progress).then_some(Message::BackupNow))
+                .padding([6, 12])
+                .style(chip_style(false)),
+        ]
+        .align_y(Alignment::Center)
+    );
+    let backup_section = container(backup_fields)
+        .padding(16)
+        .width(Length::Fill)
+        .style(card_style);
+
+    // Sync
+    let mut sync_fields = column![
+        text("Sync").size(FONT_SMALL).color(TEXT_MUTED()),
+        labeled_field("Folder", text_input("Off", &config.sync.folder)
+            .on_input(Message::SetSyncFolder)
+            .padding([10, 14])
+            .size(FONT_BODY)
+            .style(field_style)),
+        text("Pick a folder Syncthing, Dropbox or git keeps in step across machines. Each sync merges the other machines' changes and adds a file with this one's; edits that lose to a newer one are kept as forks.")
+            .size(FONT_CAPTION).color(TEXT_MUTED()),
+    ]
+    .spacing(12);
+    if let Some(status) = &app.sync_status {
+        sync_fields = sync_fields.push(text(status.as_str()).size(FONT_CAPTION).color(TEXT_SEC()));
+    }
+    sync_fields = sync_fields.push(
+        row![
+            text("Syncs every 5 minutes while a folder is set.").size(FONT_CAPTION).color(TEXT_MUTED()),
+            iced::widget::Space::new().width(Length::Fill),
+            button(text("Sync now").size(FONT_SMALL))
+                .on_press_maybe((config.sync.dir().is_some() && app.db_path.is_some()).then_some(Message::SyncNow))
+                .padding([6, 12])
+                .style(chip_style(false)),
+        ]
+        .align_y(Alignment::Center)
+    );
+    let sync_section = container(sync_fields)
+        .padding(16)
+        .width(Length::Fill)
+        .style(card_style);
+
+    // Obsidian vault
+    let mut vault_fields = column![
+        text("Markdown Vault").size(FONT_SMALL).color(TEXT_MUTED()),
+        labeled_field("Folder", text_input("Not set", &config.vault.folder)
+            .on_input(Message::SetVaultFolder)
+            .padding([10, 14])
+            .size(FONT_BODY)
+            .style(field_style)),
+        text("Writes one note per conversation with YAML front matter, folders as subdirectories and images in assets/. Point it at a folder inside your Obsidian vault.")
+            .size(FONT_CAPTION).color(TEXT_MUTED()),
+        row![
+            text("Only rewrite changed notes").size(FONT_SMALL).color(TEXT_MUTED()),
+            iced::widget::Space::new().width(Length::Fill),
+            button(text(if config.vault.incremental { "Enabled" } else { "Disabled" }).size(FONT_SMALL))
+                .on_press(Message::SetVaultIncremental(!config.vault.incremental))
+                .padding([6, 12])
+                .style(debug_toggle_style(config.vault.incremental)),
+        ]
+        .align_y(Alignment::Center),
+    ]
+    .spacing(12);
+    if let Some(status) = &app.vault_status {
+        vault_fields = vault_fields.push(text(status.as_str()).size(FONT_CAPTION).color(TEXT_SEC()));
+    }
+    vault_fields = vault_fields.push(
+        row![
+            iced::widget::Space::new().width(Length::Fill),
+            button(text("Export now").size(FONT_SMALL))
+                .on_press_maybe(config.vault.dir().is_some().then_some(Message::ExportVault))
+                .padding([6, 12])
+                .style(chip_style(false)),
+        ]
+        .align_y(Alignment::Center)
+    );
+    let vault_section = container(vault_fields)
+        .padding(16)
+        .width(Length::Fill)
+        .style(card_style);
+
     // Keybindings
     let mut keybinding_fields = column![text("Keybindings").size(FONT_SMALL).color(TEXT_MUTED())].spacing(10);
     for spec in shortcuts::specs() {
@@ -394,6 +688,7 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
         .style(save_style(app.config_saved));
 
     let content = column![
+        workspace_section,
         theme_section,
         provider_toggle,
         model_section,
@@ -401,6 +696,11 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
         fields_section,
         generation_section,
         system_prompt_section,
+        semantic_section,
+        encryption_section,
+        backup_section,
+        sync_section,
+        vault_section,
         keybindings_section,
         save_btn,
     ]
@@ -429,6 +729,53 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
     ].into()
 }
 
+/// What a snapshot contains, with the restore button.
+fn snapshot_preview(preview: &crate::backup::SnapshotPreview) -> Element<'_, Message> {
+    let convs = &preview.conversations;
+    let mut col = column![
+        text(format!("{} conversations, {} messages", convs.len(), preview.message_count)).size(FONT_SMALL).color(TEXT_HEAD()),
+    ]
+    .spacing(4);
+    if let (Some(first), Some(last)) = (convs.iter().map(|c| c.created_at).min(), convs.iter().map(|c| c.last_activity()).max()) {
+        col = col.push(
+            text(format!("{} to {}", first.format("%Y-%m-%d"), last.format("%Y-%m-%d"))).size(FONT_CAPTION).color(TEXT_MUTED())
+        );
+    }
+    for conv in convs.iter().take(8) {
+        col = col.push(text(format!("\u{2022} {}", conv.title)).size(FONT_CAPTION).color(TEXT_SEC()));
+    }
+    if convs.len() > 8 {
+        col = col.push(text(format!("and {} more", convs.len() - 8)).size(FONT_CAPTION).color(TEXT_MUTED()));
+    }
+    col = col.push(if preview.problems.is_empty() {
+        text("Integrity check passed").size(FONT_CAPTION).color(SUCCESS())
+    } else {
+        text(format!("Integrity check found {} problems", preview.problems.len())).size(FONT_CAPTION).color(DANGER())
+    });
+    col = col.push(
+        row![
+            button(text("Restore this snapshot").size(FONT_SMALL))
+                .on_press(Message::RestoreSnapshot(preview.snapshot.path.clone()))
+                .padding([6, 12])
+                .style(chip_style(true)),
+            button(text("Cancel").size(FONT_SMALL))
+                .on_press(Message::CancelSnapshotPreview)
+                .padding([6, 12])
+                .style(chip_style(false)),
+        ]
+        .spacing(8)
+    );
+    container(col)
+        .padding(12)
+        .width(Length::Fill)
+        .style(|_: &Theme| container::Style {
+            background: Some(iced::Background::Color(BG_ACTIVE())),
+            border: Border { radius: 6.0.into(), width: 1.0, color: BORDER_DEFAULT() },
+            ..Default::default()
+        })
+        .into()
+}
+
 fn labeled_field<'a>(label: &str, field: impl Into<Element<'a, Message>>) -> Element<'a, Message> {
     column![
         text(label.to_string()).size(FONT_SMALL).color(TEXT_MUTED()),
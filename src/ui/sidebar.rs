@@ -1,10 +1,12 @@
-use iced::widget::{button, column, container, row, scrollable, text, text_input, Column};
+use chrono::{DateTime, Local, Utc};
+use iced::widget::{button, column, container, rich_text, row, scrollable, span, text, text_input, Column};
 use iced::{Alignment, Border, Element, Length, Color, Theme};
 
 use crate::app::{ChatApp, Message, View};
 use crate::config::AppConfig;
+use crate::model::{DateGroup, Role};
 use crate::theme::*;
-use crate::ui::input_bar::provider_icon;
+use crate::ui::input_bar::{provider_icon, short_model_name};
 
 fn nav_style(active: bool) -> impl Fn(&Theme, button::Status) -> button::Style {
     move |_: &Theme, status: button::Status| {
@@ -113,13 +115,19 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
     let settings_active = matches!(app.view, View::Settings);
     let analytics_active = matches!(app.view, View::Analytics);
     let diagnostics_active = matches!(app.view, View::Diagnostics);
+    let trash_active = matches!(app.view, View::Trash);
 
-    let header = container(
+    let workspace = crate::workspace::active();
+    let mut header_col = column![
         row![
             text("Stoa").size(FONT_H1).color(TEXT_HEAD()),
             text("  v0.1.0").size(FONT_SMALL).color(TEXT_MUTED()),
-        ].align_y(Alignment::End)
-    ).padding(iced::Padding { top: 24.0, right: 20.0, bottom: 16.0, left: 20.0 });
+        ].align_y(Alignment::End),
+    ].spacing(2);
+    if workspace != crate::workspace::DEFAULT_WORKSPACE {
+        header_col = header_col.push(text(workspace).size(FONT_CAPTION).color(ACCENT()));
+    }
+    let header = container(header_col).padding(iced::Padding { top: 24.0, right: 20.0, bottom: 16.0, left: 20.0 });
 
     let nav = container(column![
         button(text("\u{2302}  Home").size(FONT_BODY))
@@ -138,6 +146,10 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
             .on_press(Message::ShowDiagnostics)
             .width(Length::Fill).padding([8, 12])
             .style(nav_style(diagnostics_active)),
+        button(text("\u{2327}  Trash").size(FONT_BODY))
+            .on_press(Message::ShowTrash)
+            .width(Length::Fill).padding([8, 12])
+            .style(nav_style(trash_active)),
         button(text("\u{2699}  Settings").size(FONT_BODY))
             .on_press(Message::ShowSettings)
             .width(Length::Fill).padding([8, 12])
@@ -156,12 +168,35 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
 
     let can_delete = app.conversations.len() > 1;
     let mut conv_list = Column::new().spacing(1).padding([0, 10]);
-    for (i, conv) in app.conversations.iter().enumerate() {
+
+    // Pinned first, then grouped by the day of last activity, newest first
+    let today = Local::now().date_naive();
+    let mut order: Vec<(Option<DateGroup>, DateTime<Utc>, usize)> = app.conversations.iter().enumerate()
+        .map(|(i, conv)| {
+            let last = conv.last_activity();
+            let group = (!conv.pinned).then(|| DateGroup::of(last.with_timezone(&Local).date_naive(), today));
+            (group, last, i)
+        })
+        .collect();
+    order.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+
+    let mut current_group = None;
+    for (group, _, i) in order {
+        let conv = &app.conversations[i];
         // Filter by search results if active
         if let Some(ref results) = app.sidebar_search_results {
             if !results.contains(&conv.id) { continue; }
         }
 
+        if current_group != Some(group) {
+            current_group = Some(group);
+            let label = group.map_or("Pinned", |g| g.label());
+            conv_list = conv_list.push(
+                container(text(label).size(FONT_MICRO).color(TEXT_MUTED()))
+                    .padding(iced::Padding { top: 8.0, right: 12.0, bottom: 2.0, left: 12.0 })
+            );
+        }
+
         let is_active = i == app.active_conversation && chat_active;
 
         // Inline rename mode
@@ -178,7 +213,7 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
         }
 
         let has_streams = app.conv_has_streams(&conv.id);
-        let msg_count = conv.messages.iter().filter(|m| !m.streaming).count();
+        let msg_count = conv.stats().message_count;
 
         let max_chars = if is_active { 14 } else { 20 };
         let title: String = conv.title.chars().take(max_chars).collect();
@@ -271,6 +306,35 @@ pub fn view(app: &ChatApp) -> Element<'_, Message> {
         }
     }
 
+    // Individual messages matching the search, best match first
+    if !app.sidebar_search_hits.is_empty() {
+        conv_list = conv_list.push(
+            container(text("Messages").size(FONT_MICRO).color(TEXT_MUTED()))
+                .padding(iced::Padding { top: 8.0, right: 12.0, bottom: 2.0, left: 12.0 })
+        );
+    }
+    for (hit_idx, hit) in app.sidebar_search_hits.iter().enumerate() {
+        let Some(conv) = app.conv_index_by_id(&hit.conversation_id).map(|i| &app.conversations[i]) else { continue };
+        let author = match (&hit.role, &hit.model) {
+            (Role::User, _) => "You".to_string(),
+            (Role::Assistant, Some(m)) => short_model_name(m).to_string(),
+            (Role::Assistant, None) => "Assistant".to_string(),
+        };
+        let title: String = conv.title.chars().take(18).collect();
+        let snippet: Vec<iced::widget::text::Span<'_>> = crate::search::split_marked(&hit.snippet).into_iter()
+            .map(|(piece, is_match)| span(piece.replace('\n', " ")).color(if is_match { ACCENT() } else { TEXT_SEC() }))
+            .collect();
+        conv_list = conv_list.push(
+            button(column![
+                text(format!("{title} \u{00B7} {author}")).size(FONT_MICRO).color(TEXT_MUTED()),
+                rich_text(snippet).size(FONT_CAPTION),
+            ].spacing(2))
+                .on_press(Message::OpenSearchHit(hit_idx))
+                .width(Length::Fill).padding([6, 12])
+                .style(conv_style(app.highlighted_message.as_ref() == Some(&hit.message_id))),
+        );
+    }
+
     // Status with latency
     let is_streaming = app.is_streaming();
     let stream_count = app.active_streams.len();
@@ -0,0 +1,162 @@
+use chrono::Local;
+use iced::widget::{button, column, container, row, scrollable, text};
+use iced::{Alignment, Border, Element, Length, Theme};
+
+use crate::app::{ChatApp, Message};
+use crate::db::TrashKind;
+use crate::theme::*;
+
+fn card_style(_: &Theme) -> container::Style {
+    container::Style {
+        background: Some(iced::Background::Color(CARD_BG())),
+        border: Border { radius: 8.0.into(), width: 1.0, color: BORDER_SUBTLE() },
+        ..Default::default()
+    }
+}
+
+fn modal_style(_: &Theme) -> container::Style {
+    container::Style {
+        background: Some(iced::Background::Color(CARD_BG())),
+        border: Border { radius: 12.0.into(), width: 1.0, color: BORDER_DEFAULT() },
+        ..Default::default()
+    }
+}
+
+fn overlay_style(_: &Theme) -> container::Style {
+    container::Style {
+        background: Some(iced::Background::Color(OVERLAY_BG())),
+        ..Default::default()
+    }
+}
+
+fn action_style(danger: bool) -> impl Fn(&Theme, button::Status) -> button::Style {
+    move |_: &Theme, status: button::Status| button::Style {
+        background: Some(iced::Background::Color(match status {
+            button::Status::Hovered => BG_HOVER(),
+            _ => BG_ACTIVE(),
+        })),
+        text_color: if danger { DANGER() } else { TEXT_HEAD() },
+        border: Border { radius: 8.0.into(), width: 1.0, color: BORDER_DEFAULT() },
+        ..Default::default()
+    }
+}
+
+/// Deleted conversations and messages, newest first.
+pub fn view(app: &ChatApp) -> Element<'_, Message> {
+    let header = container(
+        row![
+            text("Trash").size(FONT_H1).color(TEXT_HEAD()),
+            iced::widget::Space::new().width(Length::Fill),
+            button(text("Empty Trash").size(FONT_CAPTION))
+                .on_press_maybe((!app.trash.is_empty()).then_some(Message::EmptyTrash))
+                .padding([8, 14])
+                .style(action_style(true)),
+        ].align_y(Alignment::Center)
+    ).width(Length::Fill).padding([14, 28]).style(|_: &Theme| container::Style {
+        background: Some(iced::Background::Color(HEADER_BG())),
+        ..Default::default()
+    });
+
+    let retention = app.config.trash_retention().num_days();
+    let mut body = column![
+        text(format!("Items are deleted for good after {retention} days."))
+            .size(FONT_CAPTION).color(TEXT_MUTED()),
+    ]
+    .spacing(8)
+    .padding([20, 32])
+    .max_width(760);
+
+    if app.trash.is_empty() {
+        body = body.push(text("Trash is empty.").size(FONT_SMALL).color(TEXT_SEC()));
+    }
+    for item in &app.trash {
+        let kind = match item.kind {
+            TrashKind::Conversation => "Conversation",
+            TrashKind::Message => "Message",
+        };
+        let deleted = item.deleted_at.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string();
+        body = body.push(
+            container(
+                row![
+                    column![
+                        text(item.label.as_str()).size(FONT_SMALL).color(TEXT_HEAD()),
+                        text(format!("{kind} \u{00b7} deleted {deleted}")).size(FONT_MICRO).color(TEXT_MUTED()),
+                    ].spacing(2).width(Length::Fill),
+                    button(text("Restore").size(FONT_CAPTION))
+                        .on_press(Message::RestoreFromTrash(item.id))
+                        .padding([6, 12])
+                        .style(action_style(false)),
+                    button(text("Delete forever").size(FONT_CAPTION))
+                        .on_press(Message::DeleteFromTrash(item.id))
+                        .padding([6, 12])
+                        .style(action_style(true)),
+                ].spacing(8).align_y(Alignment::Center)
+            ).padding([10, 16]).width(Length::Fill).style(card_style)
+        );
+    }
+
+    column![
+        header,
+        container(iced::widget::Space::new()).width(Length::Fill).height(1)
+            .style(|_: &Theme| container::Style { background: Some(iced::Background::Color(BORDER_SUBTLE())), ..Default::default() }),
+        container(scrollable(container(body).width(Length::Fill)))
+            .width(Length::Fill).height(Length::Fill)
+            .style(|_: &Theme| container::Style { background: Some(iced::Background::Color(MAIN_BG())), ..Default::default() }),
+    ].into()
+}
+
+/// Confirmation shown before deleting a conversation that has forks.
+pub fn confirm_delete(app: &ChatApp, idx: usize) -> Element<'_, Message> {
+    let title = app.conversations.get(idx).map(|c| c.title.as_str()).unwrap_or_default();
+    let mut col = column![
+        text(format!("Delete \"{title}\"?")).size(FONT_BODY).color(TEXT_HEAD()),
+        text("These conversations were forked from it and will keep their messages, but lose the link back:")
+            .size(FONT_SMALL).color(TEXT_SEC()),
+    ]
+    .spacing(8);
+    for fork in app.forks_of(idx) {
+        col = col.push(text(format!("\u{2022} {}", app.conversations[fork].title)).size(FONT_SMALL).color(TEXT_HEAD()));
+    }
+    col = col.push(
+        row![
+            iced::widget::Space::new().width(Length::Fill),
+            button(text("Cancel").size(FONT_CAPTION))
+                .on_press(Message::CancelDeleteConversation)
+                .padding([8, 14])
+                .style(action_style(false)),
+            button(text("Move to Trash").size(FONT_CAPTION))
+                .on_press(Message::DeleteConversation(idx))
+                .padding([8, 14])
+                .style(action_style(true)),
+        ].spacing(8)
+    );
+
+    container(container(col).width(440).padding(18).style(modal_style))
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .align_x(Alignment::Center)
+        .align_y(iced::alignment::Vertical::Center)
+        .style(overlay_style)
+        .into()
+}
+
+/// Toast offering to undo the last delete.
+pub fn undo_toast(label: &str) -> Element<'_, Message> {
+    let toast = container(
+        row![
+            text(label).size(FONT_SMALL).color(TEXT_HEAD()),
+            button(text("Undo").size(FONT_CAPTION))
+                .on_press(Message::UndoDelete)
+                .padding([6, 12])
+                .style(action_style(false)),
+        ].spacing(12).align_y(Alignment::Center)
+    ).padding([8, 14]).style(modal_style);
+
+    container(toast)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .align_x(Alignment::Center)
+        .align_y(iced::alignment::Vertical::Bottom)
+        .padding(iced::Padding { bottom: 56.0, ..iced::Padding::ZERO })
+        .into()
+}
@@ -0,0 +1,77 @@
+use iced::widget::{button, column, container, text, text_input};
+use iced::{Alignment, Border, Element, Length, Theme};
+
+use crate::app::{ChatApp, Message};
+use crate::theme::*;
+
+fn card_style(_: &Theme) -> container::Style {
+    container::Style {
+        background: Some(iced::Background::Color(CARD_BG())),
+        border: Border { radius: 12.0.into(), width: 1.0, color: BORDER_DEFAULT() },
+        ..Default::default()
+    }
+}
+
+fn input_style(_: &Theme, status: text_input::Status) -> text_input::Style {
+    text_input::Style {
+        background: iced::Background::Color(INPUT_BG()),
+        border: Border { radius: 8.0.into(), width: 1.0, color: match status {
+            text_input::Status::Focused { .. } => ACCENT(),
+            _ => BORDER_DEFAULT(),
+        }},
+        icon: TEXT_MUTED(),
+        placeholder: TEXT_MUTED(),
+        value: TEXT_HEAD(),
+        selection: SELECTION(),
+    }
+}
+
+fn unlock_style(_: &Theme, status: button::Status) -> button::Style {
+    button::Style {
+        background: Some(iced::Background::Color(match status {
+            button::Status::Hovered => CHIP_ACTIVE_BG(),
+            _ => BG_ACTIVE(),
+        })),
+        text_color: ACCENT(),
+        border: Border { radius: 8.0.into(), width: 1.0, color: ACCENT() },
+        ..Default::default()
+    }
+}
+
+/// Passphrase prompt shown instead of the app while the database is locked.
+pub fn view(app: &ChatApp) -> Element<'_, Message> {
+    let mut card = column![
+        text("Stoa is locked").size(FONT_H1).color(TEXT_HEAD()),
+        text("Your conversations are encrypted. Enter the passphrase to open them.").size(FONT_SMALL).color(TEXT_SEC()),
+        text_input("Passphrase", &app.unlock_passphrase)
+            .on_input(Message::UnlockPassphraseChanged)
+            .on_submit(Message::UnlockDatabase)
+            .id("unlock-input")
+            .secure(true)
+            .padding([10, 14])
+            .size(FONT_BODY)
+            .style(input_style),
+    ]
+    .spacing(12);
+    if let Some(e) = &app.unlock_error {
+        card = card.push(text(e.as_str()).size(FONT_SMALL).color(DANGER()));
+    }
+    card = card.push(
+        button(container(text("Unlock").size(FONT_BODY)).width(Length::Fill).align_x(Alignment::Center))
+            .on_press(Message::UnlockDatabase)
+            .width(Length::Fill)
+            .padding([10, 20])
+            .style(unlock_style)
+    );
+
+    container(container(card).width(380).padding(24).style(card_style))
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .align_x(Alignment::Center)
+        .align_y(iced::alignment::Vertical::Center)
+        .style(|_: &Theme| container::Style {
+            background: Some(iced::Background::Color(MAIN_BG())),
+            ..Default::default()
+        })
+        .into()
+}
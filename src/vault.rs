@@ -0,0 +1,251 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Component, Path};
+
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::model::Conversation;
+
+/// Subdirectory images are written to.
+pub const ASSETS_DIR: &str = "assets";
+/// What the last export wrote, so the next one can clean up after renames,
+/// moves and deletions without touching the user's own notes.
+const MANIFEST_FILE: &str = ".stoa-vault.json";
+/// Longest note name, leaving room for a collision suffix.
+const MAX_NAME_CHARS: usize = 100;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Manifest {
+    /// Conversation ID -> note path relative to the vault
+    notes: BTreeMap<String, String>,
+    assets: BTreeSet<String>,
+}
+
+/// A file a note embeds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Asset {
+    /// File name inside `ASSETS_DIR`
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VaultReport {
+    pub written: usize,
+    /// Notes left alone because their contents hadn't changed
+    pub unchanged: usize,
+    pub assets: usize,
+    /// Files from an earlier export that no longer belong to the vault
+    pub removed: usize,
+}
+
+/// A title or folder name as a file name Obsidian can link to.
+fn sanitize(name: &str) -> String {
+    let cleaned: String = name.chars()
+        .filter(|c| !c.is_control() && !matches!(c, '\\' | '/' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '^' | '[' | ']'))
+        .take(MAX_NAME_CHARS)
+        .collect();
+    cleaned.trim().trim_end_matches('.').trim().to_string()
+}
+
+/// Note name for each conversation, unique across the vault (ignoring case)
+/// so wikilinks resolve wherever the note lives. The older conversation
+/// keeps the plain title; later ones get their ID's first characters.
+pub fn note_names(conversations: &[Conversation]) -> HashMap<String, String> {
+    note_names_keeping(conversations, &HashMap::new())
+}
+
+/// `note_names`, except a conversation keeps the name from `previous` while
+/// its title still gives that name, so an older conversation arriving later
+/// (e.g. from an import or sync) doesn't rename notes other notes link to.
+fn note_names_keeping(conversations: &[Conversation], previous: &HashMap<String, String>) -> HashMap<String, String> {
+    let mut ordered: Vec<&Conversation> = conversations.iter().collect();
+    ordered.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+    let base = |conv: &Conversation| match sanitize(&conv.title) {
+        name if name.is_empty() => "Untitled".to_string(),
+        name => name,
+    };
+    let suffixed = |conv: &Conversation, base: &str| format!("{base} ({})", conv.id.chars().take(8).collect::<String>());
+    let mut taken = HashSet::new();
+    let mut names = HashMap::new();
+    for conv in &ordered {
+        let Some(name) = previous.get(&conv.id) else { continue };
+        let base = base(conv);
+        if (*name == base || *name == suffixed(conv, &base)) && taken.insert(name.to_lowercase()) {
+            names.insert(conv.id.clone(), name.clone());
+        }
+    }
+    for conv in ordered {
+        if names.contains_key(&conv.id) {
+            continue;
+        }
+        let base = base(conv);
+        let mut name = base.clone();
+        if taken.contains(&name.to_lowercase()) {
+            name = suffixed(conv, &base);
+        }
+        taken.insert(name.to_lowercase());
+        names.insert(conv.id.clone(), name);
+    }
+    names
+}
+
+/// Path of a conversation's note relative to the vault; folders become
+/// subdirectories.
+pub fn note_path(conv: &Conversation, names: &HashMap<String, String>) -> String {
+    let mut parts: Vec<String> = conv.folder.iter()
+        .flat_map(|folder| folder.split('/'))
+        .map(sanitize)
+        .filter(|part| !part.is_empty())
+        .collect();
+    let name = names.get(&conv.id).cloned().unwrap_or_else(|| sanitize(&conv.title));
+    parts.push(format!("{name}.md"));
+    parts.join("/")
+}
+
+fn yaml_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+fn yaml_list(key: &str, items: &[String]) -> String {
+    if items.is_empty() {
+        return format!("{key}: []\n");
+    }
+    let mut out = format!("{key}:\n");
+    for item in items {
+        out.push_str(&format!("  - {}\n", yaml_string(item)));
+    }
+    out
+}
+
+/// When the conversation last got a message.
+fn updated_at(conv: &Conversation) -> DateTime<Utc> {
+    conv.messages.iter()
+        .chain(&conv.alternates)
+        .filter_map(|m| m.completed_at.or(m.created_at))
+        .fold(conv.created_at, DateTime::max)
+}
+
+/// A conversation as an Obsidian note: YAML front matter, then the thread
+/// with images embedded from `ASSETS_DIR`.
+pub fn note(conv: &Conversation, names: &HashMap<String, String>) -> (String, Vec<Asset>) {
+    let mut models: Vec<String> = conv.messages.iter().chain(&conv.alternates).filter_map(|m| m.model.clone()).collect();
+    models.sort();
+    models.dedup();
+    // Obsidian tags can't contain spaces
+    let tags: Vec<String> = conv.tags.iter().map(|t| t.split_whitespace().collect::<Vec<_>>().join("-")).collect();
+
+    let mut md = String::from("---\n");
+    md.push_str(&format!("id: {}\n", yaml_string(&conv.id)));
+    md.push_str(&format!("title: {}\n", yaml_string(&conv.title)));
+    md.push_str(&yaml_list("tags", &tags));
+    if let Some(folder) = &conv.folder {
+        md.push_str(&format!("folder: {}\n", yaml_string(folder)));
+    }
+    md.push_str(&yaml_list("models", &models));
+    md.push_str(&format!("cost: {:.4}\n", crate::cost::conversation_cost(&conv.messages)));
+    md.push_str(&format!("created: {}\n", conv.created_at.to_rfc3339()));
+    md.push_str(&format!("updated: {}\n", updated_at(conv).to_rfc3339()));
+    if let Some(name) = conv.forked_from.as_ref().and_then(|id| names.get(id)) {
+        md.push_str(&format!("forked_from: {}\n", yaml_string(&format!("[[{name}]]"))));
+    }
+    md.push_str("---\n\n");
+
+    md.push_str(&format!("# {}\n\n", conv.title));
+    if !conv.system_prompt.trim().is_empty() {
+        md.push_str("> [!note] System prompt\n");
+        for line in conv.system_prompt.lines() {
+            md.push_str(&format!("> {line}\n"));
+        }
+        md.push('\n');
+    }
+
+    let mut assets = Vec::new();
+    for msg in conv.messages.iter().filter(|m| !m.streaming) {
+        md.push_str(&format!("{}\n\n{}\n\n", crate::export::markdown_label(msg), msg.content));
+        for (name, data) in crate::export::message_images(msg) {
+            md.push_str(&format!("![[{name}]]\n\n"));
+            assets.push(Asset { name, data });
+        }
+        md.push_str("---\n\n");
+    }
+    (md, assets)
+}
+
+/// Write `data` unless `unchanged_only` and the file already holds it.
+/// Returns whether the file was written.
+fn write_file(path: &Path, data: &[u8], unchanged_only: bool) -> Result<bool, String> {
+    if unchanged_only && std::fs::read(path).is_ok_and(|old| old == data) {
+        return Ok(false);
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
+    }
+    std::fs::write(path, data).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+    Ok(true)
+}
+
+/// Whether a manifest entry is a file an export could have written: a note
+/// or an image inside the vault. The manifest syncs along with the vault,
+/// so an edited one mustn't send the cleanup anywhere else.
+fn owned_by_vault(rel: &str) -> bool {
+    let path = Path::new(rel);
+    let inside = path.components().all(|c| matches!(c, Component::Normal(_)));
+    let in_assets = path.components().count() > 1 && path.starts_with(ASSETS_DIR);
+    inside && (in_assets || path.extension().is_some_and(|ext| ext == "md"))
+}
+
+/// Write every conversation with messages to `dir` as a Markdown note. In
+/// incremental mode notes and images whose contents haven't changed are
+/// left alone, so file watchers and sync tools only see real edits. Notes
+/// from an earlier export that were renamed, moved or deleted are removed.
+pub fn export(conn: &Connection, dir: &Path, incremental: bool) -> Result<VaultReport, String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+    let previous: Manifest = std::fs::read_to_string(dir.join(MANIFEST_FILE)).ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+
+    let conversations = crate::db::load_all(conn);
+    let previous_names: HashMap<String, String> = previous.notes.iter()
+        .filter_map(|(id, rel)| Some((id.clone(), Path::new(rel).file_stem()?.to_str()?.to_string())))
+        .collect();
+    let names = note_names_keeping(&conversations, &previous_names);
+    let mut manifest = Manifest::default();
+    let mut report = VaultReport::default();
+    for conv in conversations.iter().filter(|c| !c.messages.is_empty()) {
+        let (body, assets) = note(conv, &names);
+        for asset in assets {
+            let rel = format!("{ASSETS_DIR}/{}", asset.name);
+            if write_file(&dir.join(&rel), &asset.data, incremental)? {
+                report.assets += 1;
+            }
+            manifest.assets.insert(rel);
+        }
+        let rel = note_path(conv, &names);
+        if write_file(&dir.join(&rel), body.as_bytes(), incremental)? {
+            report.written += 1;
+        } else {
+            report.unchanged += 1;
+        }
+        manifest.notes.insert(conv.id.clone(), rel);
+    }
+
+    let current: HashSet<&String> = manifest.notes.values().chain(&manifest.assets).collect();
+    let stale = previous.notes.values().chain(&previous.assets).filter(|rel| !current.contains(rel) && owned_by_vault(rel));
+    for rel in stale {
+        let path = dir.join(rel);
+        if std::fs::remove_file(&path).is_ok() {
+            report.removed += 1;
+            // Drop folders the move left empty; fails harmlessly otherwise
+            if let Some(parent) = path.parent().filter(|p| *p != dir) {
+                let _ = std::fs::remove_dir(parent);
+            }
+        }
+    }
+
+    let json = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    write_file(&dir.join(MANIFEST_FILE), json.as_bytes(), false)?;
+    Ok(report)
+}
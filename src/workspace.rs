@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Name of the workspace that lives directly in the data directory.
+pub const DEFAULT_WORKSPACE: &str = "Default";
+
+/// Environment variable that moves all of Stoa's data, e.g. onto a USB drive.
+pub const DATA_DIR_ENV: &str = "STOA_DATA_DIR";
+
+const LAST_WORKSPACE_FILE: &str = "last-workspace";
+
+/// The open workspace; empty means the default one.
+static ACTIVE: RwLock<String> = RwLock::new(String::new());
+
+/// Directory holding every workspace: `$STOA_DATA_DIR`, or `stoa` under the
+/// platform config directory.
+pub fn root() -> PathBuf {
+    std::env::var_os(DATA_DIR_ENV)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| dirs::config_dir().unwrap_or_else(|| PathBuf::from(".")).join("stoa"))
+}
+
+/// Directory of workspace `name` under `root`. The default workspace is the
+/// root itself, so libraries from before workspaces keep working.
+pub fn dir_in(root: &Path, name: &str) -> PathBuf {
+    if name == DEFAULT_WORKSPACE {
+        root.to_path_buf()
+    } else {
+        root.join("workspaces").join(name)
+    }
+}
+
+/// Directory of the open workspace, creating it if needed.
+pub fn dir() -> PathBuf {
+    let dir = dir_in(&root(), &active());
+    std::fs::create_dir_all(&dir).ok();
+    dir
+}
+
+pub fn active() -> String {
+    let name = ACTIVE.read().map(|name| name.clone()).unwrap_or_default();
+    if name.is_empty() { DEFAULT_WORKSPACE.to_string() } else { name }
+}
+
+/// Make `name` the open workspace. Paths from `dir()` follow it, so the
+/// database and config must be reopened afterwards.
+pub fn set_active(name: &str) {
+    if let Ok(mut active) = ACTIVE.write() {
+        *active = name.to_string();
+    }
+}
+
+/// Workspaces under `root`, the default one first.
+pub fn list(root: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(root.join("workspaces"))
+        .map(|entries| {
+            entries.flatten()
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort_by_key(|name| name.to_lowercase());
+    names.insert(0, DEFAULT_WORKSPACE.to_string());
+    names
+}
+
+/// Check a new workspace name, returning it trimmed. Names become directory
+/// names, so path separators and characters some filesystems reject are refused.
+pub fn validate_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Enter a name for the workspace.".to_string());
+    }
+    if name.eq_ignore_ascii_case(DEFAULT_WORKSPACE) {
+        return Err(format!("\"{DEFAULT_WORKSPACE}\" is reserved."));
+    }
+    if name.chars().count() > 64 {
+        return Err("Workspace names can be at most 64 characters.".to_string());
+    }
+    if name.starts_with('.') || name.chars().any(|c| c.is_control() || "/\\:*?\"<>|".contains(c)) {
+        return Err("Workspace names can't start with a dot or contain / \\ : * ? \" < > |".to_string());
+    }
+    Ok(name.to_string())
+}
+
+/// Create an empty workspace under `root`, returning its name.
+pub fn create(root: &Path, name: &str) -> Result<String, String> {
+    let name = validate_name(name)?;
+    if list(root).iter().any(|existing| existing.eq_ignore_ascii_case(&name)) {
+        return Err(format!("A workspace named \"{name}\" already exists."));
+    }
+    let dir = dir_in(root, &name);
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create workspace {}: {e}", dir.display()))?;
+    Ok(name)
+}
+
+/// The workspace open when Stoa last quit, if it still exists.
+pub fn last_used(root: &Path) -> Option<String> {
+    let name = std::fs::read_to_string(root.join(LAST_WORKSPACE_FILE)).ok()?;
+    let name = name.trim();
+    list(root).into_iter().find(|existing| existing == name)
+}
+
+pub fn remember(root: &Path, name: &str) {
+    std::fs::create_dir_all(root).ok();
+    if let Err(e) = std::fs::write(root.join(LAST_WORKSPACE_FILE), name) {
+        log::warn!("failed to remember workspace: {e}");
+    }
+}
+
+/// The value of `--workspace NAME` or `--workspace=NAME`, if given.
+pub fn from_args(args: impl IntoIterator<Item = String>) -> Option<String> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--workspace" {
+            return args.next();
+        }
+        if let Some(name) = arg.strip_prefix("--workspace=") {
+            return Some(name.to_string());
+        }
+    }
+    None
+}
+
+/// Pick the workspace to open at startup: the one named on the command line
+/// (created if missing), else the last one used.
+pub fn init(args: impl IntoIterator<Item = String>) -> Result<(), String> {
+    let root = root();
+    let name = match from_args(args) {
+        Some(name) if name == DEFAULT_WORKSPACE => name,
+        Some(name) => match list(&root).into_iter().find(|existing| *existing == name) {
+            Some(existing) => existing,
+            None => create(&root, &name)?,
+        },
+        None => last_used(&root).unwrap_or_else(|| DEFAULT_WORKSPACE.to_string()),
+    };
+    log::info!("opening workspace {name} in {}", dir_in(&root, &name).display());
+    set_active(&name);
+    Ok(())
+}
@@ -0,0 +1,17 @@
+-- The first schema: titles and plain messages only.
+CREATE TABLE conversations (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    updated_at TEXT DEFAULT (datetime('now'))
+);
+CREATE TABLE messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    conversation_id TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('user', 'assistant')),
+    content TEXT NOT NULL,
+    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+);
+
+INSERT INTO conversations (id, title, updated_at) VALUES ('conv-1', 'Photosynthesis', '2025-01-05 09:30:00');
+INSERT INTO messages (conversation_id, role, content) VALUES ('conv-1', 'user', 'How do plants make food?');
+INSERT INTO messages (conversation_id, role, content) VALUES ('conv-1', 'assistant', 'Through photosynthesis in chloroplasts.');
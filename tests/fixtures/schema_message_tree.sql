@@ -0,0 +1,39 @@
+-- Stable message IDs, response versions and thread modes, before timestamps.
+CREATE TABLE conversations (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    updated_at TEXT DEFAULT (datetime('now')),
+    tags TEXT DEFAULT '',
+    pinned INTEGER DEFAULT 0,
+    system_prompt TEXT DEFAULT '',
+    forked_from TEXT,
+    folder TEXT,
+    thread_mode TEXT DEFAULT 'shared'
+);
+CREATE TABLE messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    conversation_id TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('user', 'assistant')),
+    content TEXT NOT NULL,
+    model TEXT,
+    token_count INTEGER,
+    rating INTEGER DEFAULT 0,
+    latency_ms INTEGER,
+    uuid TEXT,
+    parent_id TEXT,
+    active INTEGER DEFAULT 1,
+    canonical INTEGER DEFAULT 0,
+    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+);
+CREATE UNIQUE INDEX idx_messages_uuid ON messages(uuid);
+CREATE VIRTUAL TABLE search_index USING fts5(
+    conversation_id UNINDEXED,
+    title,
+    content
+);
+
+INSERT INTO conversations (id, title, updated_at, tags, pinned, system_prompt, folder, thread_mode) VALUES ('conv-1', 'Photosynthesis', '2025-01-05 09:30:00', 'biology,plants', 1, 'Be brief.', 'science', 'isolated');
+INSERT INTO messages (conversation_id, role, content, uuid, parent_id) VALUES ('conv-1', 'user', 'How do plants make food?', '0194330a-0000-7000-8000-000000000001', NULL);
+INSERT INTO messages (conversation_id, role, content, model, rating, uuid, parent_id, active) VALUES ('conv-1', 'assistant', 'Sunlight, somehow.', 'gpt-4.1', -1, '0194330a-0000-7000-8000-000000000002', '0194330a-0000-7000-8000-000000000001', 0);
+INSERT INTO messages (conversation_id, role, content, model, token_count, rating, latency_ms, uuid, parent_id) VALUES ('conv-1', 'assistant', 'Through photosynthesis in chloroplasts.', 'gpt-4.1', 12, 1, 340, '0194330a-0000-7000-8000-000000000003', '0194330a-0000-7000-8000-000000000001');
+INSERT INTO search_index (conversation_id, title, content) VALUES ('conv-1', 'Photosynthesis', 'How do plants make food? Through photosynthesis in chloroplasts.');
@@ -0,0 +1,32 @@
+-- Conversation metadata, ratings and latency, plus the FTS5 search index.
+CREATE TABLE conversations (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    updated_at TEXT DEFAULT (datetime('now')),
+    tags TEXT DEFAULT '',
+    pinned INTEGER DEFAULT 0,
+    system_prompt TEXT DEFAULT '',
+    forked_from TEXT,
+    folder TEXT
+);
+CREATE TABLE messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    conversation_id TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('user', 'assistant')),
+    content TEXT NOT NULL,
+    model TEXT,
+    token_count INTEGER,
+    rating INTEGER DEFAULT 0,
+    latency_ms INTEGER,
+    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+);
+CREATE VIRTUAL TABLE search_index USING fts5(
+    conversation_id UNINDEXED,
+    title,
+    content
+);
+
+INSERT INTO conversations (id, title, updated_at, tags, pinned, system_prompt, folder) VALUES ('conv-1', 'Photosynthesis', '2025-01-05 09:30:00', 'biology,plants', 1, 'Be brief.', 'science');
+INSERT INTO messages (conversation_id, role, content) VALUES ('conv-1', 'user', 'How do plants make food?');
+INSERT INTO messages (conversation_id, role, content, model, token_count, rating, latency_ms) VALUES ('conv-1', 'assistant', 'Through photosynthesis in chloroplasts.', 'gpt-4.1', 12, 1, 340);
+INSERT INTO search_index (conversation_id, title, content) VALUES ('conv-1', 'Photosynthesis', 'How do plants make food? Through photosynthesis in chloroplasts.');
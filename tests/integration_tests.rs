@@ -1,8 +1,11 @@
 use stoa::model::*;
+use stoa::search::SearchQuery;
 use stoa::config::AppConfig;
 use stoa::cost;
 use stoa::diff;
 use stoa::export;
+use stoa::import::{ImportFormat, JsonlField, JsonlMapping};
+use stoa::theme::ThemeName;
 
 // ── Model Tests ──────────────────────────────────────────────
 
@@ -99,6 +102,8 @@ fn fork_creates_correct_copy() {
     conv.tags = vec!["research".to_string()];
     conv.add_user_message("question 1", Some("gpt-4.1".to_string()));
     conv.messages.push(ChatMessage {
+        id: new_message_id(),
+        parent_id: None,
         role: Role::Assistant,
         content: "answer 1".to_string(),
         streaming: false,
@@ -107,9 +112,14 @@ fn fork_creates_correct_copy() {
         rating: 1,
         latency_ms: Some(500),
         images: Vec::new(),
+        canonical: false,
+        created_at: None,
+        completed_at: None,
     });
     conv.add_user_message("question 2", Some("gpt-4.1".to_string()));
     conv.messages.push(ChatMessage {
+        id: new_message_id(),
+        parent_id: None,
         role: Role::Assistant,
         content: "answer 2".to_string(),
         streaming: false,
@@ -118,6 +128,9 @@ fn fork_creates_correct_copy() {
         rating: 0,
         latency_ms: None,
         images: Vec::new(),
+        canonical: false,
+        created_at: None,
+        completed_at: None,
     });
 
     // Fork at message index 1 (after first Q&A)
@@ -151,6 +164,143 @@ fn message_rating_defaults_to_zero() {
     assert_eq!(conv.messages[0].rating, 0);
 }
 
+// ── Message Tree Tests ───────────────────────────────────────
+
+fn answered(conv: &mut Conversation, content: &str) {
+    let idx = conv.push_streaming_assistant(Some("gpt-4.1".to_string()));
+    conv.finalize_at(idx, content);
+}
+
+#[test]
+fn messages_are_chained_by_parent_id() {
+    let mut conv = Conversation::new();
+    conv.add_user_message("question", None);
+    answered(&mut conv, "answer");
+    assert!(conv.messages[0].parent_id.is_none());
+    assert_eq!(conv.messages[1].parent_id, Some(conv.messages[0].id.clone()));
+    assert_ne!(conv.messages[0].id, conv.messages[1].id);
+}
+
+#[test]
+fn branch_from_keeps_old_version() {
+    let mut conv = Conversation::new();
+    conv.add_user_message("question", None);
+    answered(&mut conv, "first answer");
+    conv.branch_from(1);
+    answered(&mut conv, "second answer");
+
+    assert_eq!(conv.messages.len(), 2);
+    assert_eq!(conv.messages[1].content, "second answer");
+    assert_eq!(conv.alternates.len(), 1);
+    assert_eq!(conv.alternates[0].content, "first answer");
+    assert_eq!(conv.version_info(1), (2, 2));
+    assert_eq!(conv.version_info(0), (1, 1));
+}
+
+#[test]
+fn switch_version_restores_branch_below() {
+    let mut conv = Conversation::new();
+    conv.add_user_message("question", None);
+    answered(&mut conv, "first answer");
+    conv.add_user_message("follow-up", None);
+    answered(&mut conv, "follow-up answer");
+
+    // New version of the first answer drops the follow-up from the active path
+    conv.branch_from(1);
+    answered(&mut conv, "second answer");
+    assert_eq!(conv.messages.len(), 2);
+
+    assert!(conv.switch_version(1, -1));
+    assert_eq!(conv.version_info(1), (1, 2));
+    let contents: Vec<&str> = conv.messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, vec!["question", "first answer", "follow-up", "follow-up answer"]);
+    assert_eq!(conv.alternates.len(), 1);
+    assert_eq!(conv.alternates[0].content, "second answer");
+
+    // No version before the first
+    assert!(!conv.switch_version(1, -1));
+    assert!(conv.switch_version(1, 1));
+    assert_eq!(conv.messages.len(), 2);
+    assert_eq!(conv.messages[1].content, "second answer");
+}
+
+#[test]
+fn remove_message_reparents_replies() {
+    let mut conv = Conversation::new();
+    conv.add_user_message("one", None);
+    conv.add_user_message("two", None);
+    conv.add_user_message("three", None);
+    let first_id = conv.messages[0].id.clone();
+    conv.remove_message(1);
+    assert_eq!(conv.messages.len(), 2);
+    assert_eq!(conv.messages[1].parent_id, Some(first_id));
+}
+
+fn multi_model_turn(conv: &mut Conversation, prompt: &str, replies: &[(&str, &str)]) {
+    conv.add_user_message(prompt, None);
+    for (model, content) in replies {
+        let idx = conv.push_streaming_assistant(Some(model.to_string()));
+        conv.finalize_at(idx, content);
+    }
+}
+
+#[test]
+fn shared_thread_history_includes_every_reply() {
+    let mut conv = Conversation::new();
+    multi_model_turn(&mut conv, "q1", &[("gpt-4.1", "a1"), ("o3", "b1")]);
+    assert_eq!(conv.thread_mode, ThreadMode::Shared);
+    assert_eq!(conv.history_for("gpt-4.1").len(), 3);
+}
+
+#[test]
+fn isolated_thread_history_keeps_own_replies() {
+    let mut conv = Conversation::new();
+    conv.thread_mode = ThreadMode::Isolated;
+    multi_model_turn(&mut conv, "q1", &[("gpt-4.1", "a1"), ("o3", "b1")]);
+    multi_model_turn(&mut conv, "q2", &[("gpt-4.1", "a2"), ("o3", "b2")]);
+
+    let contents = |model: &str| -> Vec<String> {
+        conv.history_for(model).into_iter().map(|m| m.content).collect()
+    };
+    assert_eq!(contents("gpt-4.1"), vec!["q1", "a1", "q2", "a2"]);
+    assert_eq!(contents("o3"), vec!["q1", "b1", "q2", "b2"]);
+    // A model that never answered sees only the prompts
+    assert_eq!(contents("claude-sonnet-4-20250514"), vec!["q1", "q2"]);
+}
+
+#[test]
+fn isolated_thread_falls_back_to_canonical_reply() {
+    let mut conv = Conversation::new();
+    conv.thread_mode = ThreadMode::Isolated;
+    multi_model_turn(&mut conv, "q1", &[("gpt-4.1", "a1"), ("o3", "b1")]);
+    conv.toggle_canonical(2);
+    assert!(conv.messages[2].canonical);
+
+    let history: Vec<String> = conv.history_for("claude-sonnet-4-20250514").into_iter().map(|m| m.content).collect();
+    assert_eq!(history, vec!["q1", "b1"]);
+    // A model's own reply wins over the canonical one
+    let history: Vec<String> = conv.history_for("gpt-4.1").into_iter().map(|m| m.content).collect();
+    assert_eq!(history, vec!["q1", "a1"]);
+
+    // Only one canonical reply per turn
+    conv.toggle_canonical(1);
+    assert!(conv.messages[1].canonical);
+    assert!(!conv.messages[2].canonical);
+    conv.toggle_canonical(1);
+    assert!(!conv.messages[1].canonical);
+}
+
+#[test]
+fn fork_assigns_fresh_message_ids() {
+    let mut conv = Conversation::new();
+    conv.add_user_message("question", None);
+    answered(&mut conv, "answer");
+    let forked = conv.fork(1);
+    assert_ne!(forked.messages[0].id, conv.messages[0].id);
+    assert_eq!(forked.messages[1].parent_id, Some(forked.messages[0].id.clone()));
+    assert!(forked.alternates.is_empty());
+}
+
 // ── Provider Config Tests ────────────────────────────────────
 
 #[test]
@@ -278,6 +428,8 @@ fn message_cost_input_vs_output_pricing() {
 fn conversation_cost_sums_all_messages() {
     let messages = vec![
         ChatMessage {
+            id: new_message_id(),
+            parent_id: None,
             role: Role::User,
             content: "a".repeat(400), // ~100 tokens
             streaming: false,
@@ -286,8 +438,13 @@ fn conversation_cost_sums_all_messages() {
             rating: 0,
             latency_ms: None,
             images: Vec::new(),
+            canonical: false,
+            created_at: None,
+            completed_at: None,
         },
         ChatMessage {
+            id: new_message_id(),
+            parent_id: None,
             role: Role::Assistant,
             content: "b".repeat(800), // ~200 tokens
             streaming: false,
@@ -296,6 +453,9 @@ fn conversation_cost_sums_all_messages() {
             rating: 0,
             latency_ms: None,
             images: Vec::new(),
+            canonical: false,
+            created_at: None,
+            completed_at: None,
         },
     ];
     let total = cost::conversation_cost(&messages);
@@ -309,6 +469,8 @@ fn conversation_cost_sums_all_messages() {
 fn conversation_cost_skips_streaming() {
     let messages = vec![
         ChatMessage {
+            id: new_message_id(),
+            parent_id: None,
             role: Role::Assistant,
             content: "streaming...".to_string(),
             streaming: true,
@@ -317,6 +479,9 @@ fn conversation_cost_skips_streaming() {
             rating: 0,
             latency_ms: None,
             images: Vec::new(),
+            canonical: false,
+            created_at: None,
+            completed_at: None,
         },
     ];
     assert_eq!(cost::conversation_cost(&messages), 0.0);
@@ -408,6 +573,8 @@ fn export_with_messages() {
     conv.title = "Test Export".to_string();
     conv.add_user_message("Hello", Some("gpt-4.1".to_string()));
     conv.messages.push(ChatMessage {
+        id: new_message_id(),
+        parent_id: None,
         role: Role::Assistant,
         content: "Hi there!".to_string(),
         streaming: false,
@@ -416,6 +583,9 @@ fn export_with_messages() {
         rating: 0,
         latency_ms: None,
         images: Vec::new(),
+        canonical: false,
+        created_at: None,
+        completed_at: None,
     });
     let md = export::conversation_to_markdown(&conv);
     assert!(md.contains("# Test Export"));
@@ -438,6 +608,8 @@ fn export_with_tags() {
 fn export_skips_streaming() {
     let mut conv = Conversation::new();
     conv.messages.push(ChatMessage {
+        id: new_message_id(),
+        parent_id: None,
         role: Role::Assistant,
         content: "streaming...".to_string(),
         streaming: true,
@@ -446,6 +618,9 @@ fn export_skips_streaming() {
         rating: 0,
         latency_ms: None,
         images: Vec::new(),
+        canonical: false,
+        created_at: None,
+        completed_at: None,
     });
     let md = export::conversation_to_markdown(&conv);
     assert!(!md.contains("streaming..."));
@@ -463,6 +638,8 @@ fn db_roundtrip_conversation() {
     conv.system_prompt = "Be concise".to_string();
     conv.add_user_message("Hello", Some("gpt-4.1".to_string()));
     conv.messages.push(ChatMessage {
+        id: new_message_id(),
+        parent_id: None,
         role: Role::Assistant,
         content: "Hi!".to_string(),
         streaming: false,
@@ -471,6 +648,9 @@ fn db_roundtrip_conversation() {
         rating: 1,
         latency_ms: Some(350),
         images: Vec::new(),
+        canonical: false,
+        created_at: None,
+        completed_at: None,
     });
 
     stoa::db::save_conversation(&conn, &conv).unwrap();
@@ -627,6 +807,42 @@ fn db_system_prompt_persists() {
     assert_eq!(loaded[0].system_prompt, "You are a helpful assistant");
 }
 
+#[test]
+fn db_thread_mode_and_canonical_persist() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.thread_mode = ThreadMode::Isolated;
+    multi_model_turn(&mut conv, "q1", &[("gpt-4.1", "a1"), ("o3", "b1")]);
+    conv.toggle_canonical(2);
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+
+    let loaded = stoa::db::load_all(&conn);
+    assert_eq!(loaded[0].thread_mode, ThreadMode::Isolated);
+    assert!(!loaded[0].messages[1].canonical);
+    assert!(loaded[0].messages[2].canonical);
+}
+
+#[test]
+fn db_message_versions_persist() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.add_user_message("question", None);
+    answered(&mut conv, "first answer");
+    conv.branch_from(1);
+    answered(&mut conv, "second answer");
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+
+    let loaded = stoa::db::load_all(&conn);
+    let c = &loaded[0];
+    assert_eq!(c.messages.len(), 2);
+    assert_eq!(c.messages[1].content, "second answer");
+    assert_eq!(c.messages[1].id, conv.messages[1].id);
+    assert_eq!(c.messages[1].parent_id, Some(c.messages[0].id.clone()));
+    assert_eq!(c.alternates.len(), 1);
+    assert_eq!(c.alternates[0].content, "first answer");
+    assert_eq!(c.version_info(1), (2, 2));
+}
+
 // ── OpenRouter Config Tests ──────────────────────────────────
 
 #[test]
@@ -690,6 +906,19 @@ fn add_user_message_no_images_default() {
     assert!(conv.messages[0].images.is_empty());
 }
 
+#[test]
+fn message_images_survive_a_reload() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.add_user_message_with_images("describe this", None, vec!["iVBORw0KGgo=".to_string()]);
+    conv.add_user_message("and this?", None);
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+
+    let loaded = stoa::db::load_all(&conn).into_iter().find(|c| c.id == conv.id).unwrap();
+    assert_eq!(loaded.messages[0].images, vec!["iVBORw0KGgo=".to_string()]);
+    assert!(loaded.messages[1].images.is_empty());
+}
+
 // ── Folder Tests ─────────────────────────────────────────────
 
 #[test]
@@ -736,6 +965,8 @@ fn export_html_contains_structure() {
     conv.title = "HTML Test".to_string();
     conv.add_user_message("hello", None);
     conv.messages.push(ChatMessage {
+        id: new_message_id(),
+        parent_id: None,
         role: Role::Assistant,
         content: "world".to_string(),
         streaming: false,
@@ -744,8 +975,11 @@ fn export_html_contains_structure() {
         rating: 0,
         latency_ms: Some(100),
         images: Vec::new(),
+        canonical: false,
+        created_at: None,
+        completed_at: None,
     });
-    let html = stoa::export::conversation_to_html(&conv);
+    let html = stoa::export::conversation_to_html(&conv, ThemeName::default());
     assert!(html.contains("<!DOCTYPE html>"));
     assert!(html.contains("HTML Test"));
     assert!(html.contains("hello"));
@@ -755,6 +989,181 @@ fn export_html_contains_structure() {
     assert!(html.contains("100 ms"));
 }
 
+/// A conversation whose first reply failed and was retried, with an image.
+fn conversation_with_error_and_image(title: &str) -> Conversation {
+    let mut conv = Conversation::new();
+    conv.title = title.to_string();
+    conv.tags = vec!["sea".to_string()];
+    conv.add_user_message_with_images("What is this?", None, vec!["iVBORw0KGgo=".to_string()]);
+    let idx = conv.push_streaming_assistant(Some("gpt-4o".to_string()));
+    conv.finalize_at(idx, "[Error: rate limited]");
+    let idx = conv.push_streaming_assistant(Some("gpt-4o".to_string()));
+    conv.finalize_at(idx, "A lighthouse.");
+    conv
+}
+
+#[test]
+fn export_render_markdown_follows_options() {
+    use stoa::export::{render, ExportFormat, ExportOptions};
+    let conv = conversation_with_error_and_image("Coast");
+
+    let md = String::from_utf8(render(std::slice::from_ref(&conv), ExportFormat::Markdown, &ExportOptions::default()).unwrap()).unwrap();
+    assert!(md.contains("**Tags:** sea"));
+    assert!(md.contains("**Assistant (gpt-4o)**"));
+    assert!(md.contains("A lighthouse."));
+    assert!(!md.contains("rate limited"));
+    assert!(md.contains("![image](data:image/png;base64,iVBORw0KGgo=)"));
+
+    let bare = ExportOptions { metadata: false, errored: true, images: false, ..ExportOptions::default() };
+    let md = String::from_utf8(render(&[conv], ExportFormat::Markdown, &bare).unwrap()).unwrap();
+    assert!(!md.contains("**Tags:**"));
+    assert!(!md.contains("**Created:**"));
+    assert!(!md.contains("gpt-4o"));
+    assert!(md.contains("[Error: rate limited]"));
+    assert!(!md.contains("data:image"));
+}
+
+#[test]
+fn export_render_json_and_html_cover_several_conversations() {
+    use stoa::export::{render, ExportFormat, ExportOptions};
+    let convs = vec![conversation_with_error_and_image("Coast"), conversation_with_reply("Tides", "Why?", "gpt-4o", "The Moon.")];
+
+    let json: serde_json::Value = serde_json::from_slice(&render(&convs, ExportFormat::Json, &ExportOptions::default()).unwrap()).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 2);
+    assert_eq!(json[0]["messages"].as_array().unwrap().len(), 2);
+    assert_eq!(json[1]["title"], "Tides");
+
+    let bare = ExportOptions { metadata: false, errored: false, images: false, ..ExportOptions::default() };
+    let json: serde_json::Value = serde_json::from_slice(&render(&convs[1..], ExportFormat::Json, &bare).unwrap()).unwrap();
+    assert_eq!(json, serde_json::json!({
+        "title": "Tides",
+        "messages": [{"role": "User", "content": "Why?"}, {"role": "Assistant", "content": "The Moon."}]
+    }));
+
+    let html = String::from_utf8(render(&convs, ExportFormat::Html, &ExportOptions::default()).unwrap()).unwrap();
+    assert_eq!(html.matches("<!DOCTYPE html>").count(), 1);
+    assert!(html.contains("<title>Stoa export</title>"));
+    assert!(html.contains("<h1>Coast</h1>") && html.contains("<h1>Tides</h1>"));
+    assert!(html.contains("<img src=\"data:image/png;base64,iVBORw0KGgo=\""));
+}
+
+#[test]
+fn export_bundle_zips_each_format_and_images() {
+    use std::io::Read;
+    use stoa::export::{render, ExportFormat, ExportOptions};
+    let conv = conversation_with_error_and_image("Coast");
+    let data = render(std::slice::from_ref(&conv), ExportFormat::Bundle, &ExportOptions::default()).unwrap();
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+    let mut names: Vec<String> = zip.file_names().map(String::from).collect();
+    names.sort();
+    let image = format!("images/{}-0.png", conv.messages[0].id);
+    assert_eq!(names, vec!["html/Coast.html".into(), image.clone(), "json/Coast.json".into(), "markdown/Coast.md".into()]);
+
+    let mut md = String::new();
+    zip.by_name("markdown/Coast.md").unwrap().read_to_string(&mut md).unwrap();
+    assert!(md.contains(&format!("![image](../{image})")));
+}
+
+#[test]
+fn export_html_renders_markdown_and_highlights_code() {
+    let mut conv = Conversation::new();
+    conv.add_user_message("Show me <b>code</b>", None);
+    let idx = conv.push_streaming_assistant(Some("gpt-4o".to_string()));
+    conv.finalize_at(idx, "## Plan\n\n- one\n- two\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n```rust\nfn main() { let s = \"hi\"; } // done\n```\n\n<script>alert(1)</script>\n\n[click](javascript:alert(1))");
+    let html = export::conversation_to_html(&conv, ThemeName::default());
+    assert!(html.contains("<h2>Plan</h2>"));
+    assert!(html.contains("<li>one</li>"));
+    assert!(html.contains("<table>"));
+    assert!(html.contains("<code class=\"language-rust\"><span class=\"hl-kw\">fn</span> main()"));
+    assert!(html.contains("<span class=\"hl-str\">&quot;hi&quot;</span>"));
+    assert!(html.contains("<span class=\"hl-com\">// done</span>"));
+    // Neither the reply's raw HTML nor the user's text becomes markup
+    assert!(!html.contains("<script>") && html.contains("&lt;script&gt;"));
+    assert!(html.contains("<a href=\"#\">click</a>") && !html.contains("javascript:"));
+    assert!(html.contains("Show me &lt;b&gt;code&lt;/b&gt;"));
+}
+
+#[test]
+fn export_html_follows_theme_and_embeds_images() {
+    let conv = conversation_with_error_and_image("Coast");
+    let light = export::conversation_to_html(&conv, ThemeName::SolarizedLight);
+    let dark = export::conversation_to_html(&conv, ThemeName::Nord);
+    let bg = |theme: ThemeName| {
+        let c = theme.colors().main_bg;
+        format!("background: #{:02x}{:02x}{:02x};", (c.r * 255.0).round() as u8, (c.g * 255.0).round() as u8, (c.b * 255.0).round() as u8)
+    };
+    assert!(light.contains(&bg(ThemeName::SolarizedLight)));
+    assert!(dark.contains(&bg(ThemeName::Nord)));
+    assert!(light.contains("<img src=\"data:image/png;base64,iVBORw0KGgo=\""));
+}
+
+#[test]
+fn export_html_shows_multi_model_turns_side_by_side() {
+    let mut conv = Conversation::new();
+    conv.add_user_message("Capital of France?", None);
+    let a = conv.push_streaming_assistant(Some("gpt-4o".to_string()));
+    conv.finalize_at(a, "The capital of France is Paris.");
+    let b = conv.push_streaming_assistant(Some("claude-sonnet".to_string()));
+    conv.finalize_at(b, "Paris is the capital.");
+    let html = export::conversation_to_html(&conv, ThemeName::default());
+    let agreement = diff::agreement_percentage("The capital of France is Paris.", "Paris is the capital.");
+    assert!(html.contains("<div class=\"turn\" style=\"grid-template-columns: repeat(2, minmax(0, 1fr))\">"));
+    assert!(html.contains(&format!("gpt-4o vs claude-sonnet: {agreement:.0}% agreement")));
+    assert_eq!(html.matches("class=\"message assistant\"").count(), 2);
+}
+
+#[test]
+fn highlight_tokens_follow_the_language() {
+    use stoa::highlight::{tokens, Token};
+    assert_eq!(tokens("SELECT id FROM t -- all", "sql"), vec![
+        (Token::Keyword, "SELECT"), (Token::Plain, " id "), (Token::Keyword, "FROM"),
+        (Token::Plain, " t "), (Token::Comment, "-- all"),
+    ]);
+    assert_eq!(tokens("x = 'a' # 2", "python"), vec![
+        (Token::Plain, "x = "), (Token::String, "'a'"), (Token::Plain, " "), (Token::Comment, "# 2"),
+    ]);
+    assert_eq!(tokens("let x = 1;", "brainfuck"), vec![(Token::Plain, "let x = 1;")]);
+}
+
+#[test]
+fn app_export_dialog_resolves_scope() {
+    use stoa::export::ExportScope;
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    let mut boats = conversation_with_reply("Boats", "Sail?", "gpt-4o", "Yes.");
+    boats.folder = Some("Hobbies/Boats".into());
+    boats.tags = vec!["sea".into()];
+    let mut knots = conversation_with_reply("Knots", "Bowline?", "gpt-4o", "Yes.");
+    knots.folder = Some("Hobbies".into());
+    let _ = app.update(stoa::app::Message::ImportComplete(vec![boats.clone(), knots.clone()]));
+
+    let _ = app.update(stoa::app::Message::OpenExportDialog);
+    assert_eq!(app.export_ids(), vec![knots.id.clone()]);
+
+    let _ = app.update(stoa::app::Message::SetExportScope(ExportScope::Folder("Hobbies".into())));
+    let mut ids = app.export_ids();
+    ids.sort();
+    let mut expected = vec![boats.id.clone(), knots.id.clone()];
+    expected.sort();
+    assert_eq!(ids, expected);
+
+    let _ = app.update(stoa::app::Message::SetExportScope(ExportScope::Tag("sea".into())));
+    assert_eq!(app.export_ids(), vec![boats.id.clone()]);
+
+    // Ticking a conversation switches to the chosen set
+    let _ = app.update(stoa::app::Message::ToggleExportChoice(boats.id.clone()));
+    assert_eq!(app.export_dialog.as_ref().unwrap().scope, ExportScope::Chosen);
+    assert_eq!(app.export_ids().len(), 2);
+    let _ = app.update(stoa::app::Message::ToggleExportChoice(knots.id.clone()));
+    assert_eq!(app.export_ids(), vec![boats.id.clone()]);
+
+    let _ = app.update(stoa::app::Message::SidebarSearchChanged("Bowline".into()));
+    let _ = app.update(stoa::app::Message::SetExportScope(ExportScope::SearchResults));
+    assert_eq!(app.export_ids(), vec![knots.id.clone()]);
+
+    let _ = app.update(stoa::app::Message::DismissOverlay);
+    assert!(app.export_dialog.is_none());
+}
+
 // ── Import Tests ─────────────────────────────────────────────
 
 #[test]
@@ -906,7 +1315,7 @@ fn append_streaming_token_out_of_bounds_safe() {
 fn export_html_escapes_xss_in_title() {
     let mut conv = Conversation::new();
     conv.title = "<script>alert('xss')</script>".to_string();
-    let html = stoa::export::conversation_to_html(&conv);
+    let html = stoa::export::conversation_to_html(&conv, ThemeName::default());
     assert!(!html.contains("<script>"));
     assert!(html.contains("&lt;script&gt;"));
 }
@@ -915,7 +1324,7 @@ fn export_html_escapes_xss_in_title() {
 fn export_html_escapes_xss_in_content() {
     let mut conv = Conversation::new();
     conv.add_user_message("<img onerror=alert(1) src=x>", None);
-    let html = stoa::export::conversation_to_html(&conv);
+    let html = stoa::export::conversation_to_html(&conv, ThemeName::default());
     // The < is escaped, so the tag is rendered as text, not HTML
     assert!(!html.contains("<img onerror"));
     assert!(html.contains("&lt;img"));
@@ -925,7 +1334,7 @@ fn export_html_escapes_xss_in_content() {
 fn export_html_escapes_tags() {
     let mut conv = Conversation::new();
     conv.tags = vec!["<b>bold</b>".to_string()];
-    let html = stoa::export::conversation_to_html(&conv);
+    let html = stoa::export::conversation_to_html(&conv, ThemeName::default());
     assert!(!html.contains("<b>bold</b>"));
     assert!(html.contains("&lt;b&gt;bold&lt;/b&gt;"));
 }
@@ -982,6 +1391,8 @@ fn fts5_search_updates_after_resave() {
 
     // Add a message and resave
     conv.messages.push(ChatMessage {
+        id: new_message_id(),
+        parent_id: None,
         role: Role::Assistant,
         content: "quantum mechanics is fascinating".to_string(),
         streaming: false,
@@ -990,6 +1401,9 @@ fn fts5_search_updates_after_resave() {
         rating: 0,
         latency_ms: None,
         images: Vec::new(),
+        canonical: false,
+        created_at: None,
+        completed_at: None,
     });
     stoa::db::save_conversation(&conn, &conv).unwrap();
 
@@ -998,6 +1412,376 @@ fn fts5_search_updates_after_resave() {
     assert_eq!(results.len(), 1);
 }
 
+fn conversation_with_reply(title: &str, question: &str, model: &str, answer: &str) -> Conversation {
+    let mut conv = Conversation::new();
+    conv.title = title.to_string();
+    conv.add_user_message(question, None);
+    let idx = conv.push_streaming_assistant(Some(model.to_string()));
+    conv.finalize_at(idx, answer);
+    conv
+}
+
+#[test]
+fn fts5_search_messages_returns_marked_snippets() {
+    let conn = stoa::db::open_in_memory();
+    let conv = conversation_with_reply(
+        "Botany", "How do plants make food?", "gpt-4o",
+        "Plants make food through photosynthesis, which happens in the chloroplasts of leaf cells.",
+    );
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+
+    let hits = stoa::db::search_messages(&conn, "photosynthesis chloroplasts", 10);
+    assert_eq!(hits.len(), 1);
+    let hit = &hits[0];
+    assert_eq!(hit.conversation_id, conv.id);
+    assert_eq!(hit.message_id, conv.messages[1].id);
+    assert_eq!(hit.role, Role::Assistant);
+    assert_eq!(hit.model.as_deref(), Some("gpt-4o"));
+    assert_eq!(hit.terms, vec!["photosynthesis".to_string(), "chloroplasts".to_string()]);
+    let marked: Vec<String> = stoa::search::split_marked(&hit.snippet).into_iter()
+        .filter(|(_, is_match)| *is_match)
+        .map(|(t, _)| t)
+        .collect();
+    assert_eq!(marked, vec!["photosynthesis", "chloroplasts"]);
+
+    // Words are matched by prefix, so results appear while typing
+    assert_eq!(stoa::db::search_messages(&conn, "photosyn", 10).len(), 1);
+    assert!(stoa::db::search_messages(&conn, "   ", 10).is_empty());
+}
+
+#[test]
+fn fts5_search_messages_ranks_best_match_first() {
+    let conn = stoa::db::open_in_memory();
+    let passing = conversation_with_reply("Misc", "Tell me a fact", "gpt-4o", "Rust has a borrow checker, among many other things worth knowing about.");
+    let focused = conversation_with_reply("Rust", "What is the borrow checker?", "claude-sonnet-4-20250514", "The borrow checker enforces borrow rules.");
+    stoa::db::save_conversation(&conn, &passing).unwrap();
+    stoa::db::save_conversation(&conn, &focused).unwrap();
+
+    let hits = stoa::db::search_messages(&conn, "borrow", 10);
+    assert_eq!(hits.len(), 3);
+    assert_eq!(hits[0].message_id, focused.messages[1].id);
+    assert_eq!(hits[2].message_id, passing.messages[1].id);
+    assert_eq!(stoa::db::search_messages(&conn, "borrow", 1).len(), 1);
+}
+
+#[test]
+fn fts5_search_messages_skips_inactive_branches() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = conversation_with_reply("Chat", "Pick a colour", "gpt-4o", "Vermilion");
+    conv.branch_from(1);
+    let idx = conv.push_streaming_assistant(Some("gpt-4o".to_string()));
+    conv.finalize_at(idx, "Turquoise");
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+
+    assert!(stoa::db::search_messages(&conn, "vermilion", 10).is_empty());
+    assert!(stoa::db::search_conversations(&conn, "vermilion").is_empty());
+    assert_eq!(stoa::db::search_messages(&conn, "turquoise", 10).len(), 1);
+}
+
+#[test]
+fn fts5_index_follows_edits_deletes_and_renames() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = conversation_with_reply("Weather", "Will it rain?", "gpt-4o", "Expect drizzle later.");
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+
+    conv.messages[1].content = "Expect sunshine later.".to_string();
+    stoa::db::save_message(&conn, &conv, 1).unwrap();
+    assert!(stoa::db::search_messages(&conn, "drizzle", 10).is_empty());
+    assert_eq!(stoa::db::search_messages(&conn, "sunshine", 10).len(), 1);
+
+    stoa::db::delete_message(&conn, &conv.messages[1].id).unwrap();
+    assert!(stoa::db::search_messages(&conn, "sunshine", 10).is_empty());
+
+    stoa::db::rename_conversation(&conn, &conv.id, "Forecast").unwrap();
+    assert!(stoa::db::search_conversations(&conn, "weather").is_empty());
+    assert_eq!(stoa::db::search_conversations(&conn, "forecast"), vec![conv.id.clone()]);
+
+    stoa::db::delete_conversation(&conn, &conv.id).unwrap();
+    assert!(stoa::db::search_messages(&conn, "rain", 10).is_empty());
+    assert!(stoa::db::search_conversations(&conn, "forecast").is_empty());
+}
+
+// ── Search Query Tests ───────────────────────────────────────
+
+#[test]
+fn search_query_parses_filters_phrases_and_negation() {
+    use stoa::search::{Comparison, Filter, FilterTerm, TextTerm};
+    let q = SearchQuery::parse(r#"model:Claude-Opus -tag:draft folder:"PhD/thesis" "exact phrase" -noise cost>=0.10 is:fork"#);
+    assert_eq!(q.filters, vec![
+        FilterTerm { filter: Filter::Model("claude-opus".into()), negated: false },
+        FilterTerm { filter: Filter::Tag("draft".into()), negated: true },
+        FilterTerm { filter: Filter::Folder("phd/thesis".into()), negated: false },
+        FilterTerm { filter: Filter::Cost(Comparison::GreaterOrEqual, 0.10), negated: false },
+        FilterTerm { filter: Filter::Fork, negated: false },
+    ]);
+    assert_eq!(q.text, vec![
+        TextTerm { text: "exact phrase".into(), phrase: true, negated: false },
+        TextTerm { text: "noise".into(), phrase: false, negated: true },
+    ]);
+
+    let q = SearchQuery::parse("rating:up role:assistant before:2026-01-01 after:2025-06-01 is:pinned cost<1");
+    assert_eq!(q.filters.iter().map(|f| f.filter.clone()).collect::<Vec<_>>(), vec![
+        Filter::Rating(1),
+        Filter::Role(Role::Assistant),
+        Filter::Before(chrono::NaiveDate::from_ymd_opt(2026, 1, 1).unwrap()),
+        Filter::After(chrono::NaiveDate::from_ymd_opt(2025, 6, 1).unwrap()),
+        Filter::Pinned,
+        Filter::Cost(Comparison::Less, 1.0),
+    ]);
+    assert!(q.text.is_empty());
+}
+
+#[test]
+fn search_query_treats_invalid_filters_as_text() {
+    let q = SearchQuery::parse("rating:sideways before:soon http://example.com - note:");
+    assert!(!q.has_filters());
+    let words: Vec<&str> = q.text.iter().map(|t| t.text.as_str()).collect();
+    assert_eq!(words, vec!["rating:sideways", "before:soon", "http://example.com", "note:"]);
+    assert!(SearchQuery::parse("   ").is_empty());
+}
+
+/// Three conversations covering every filter: a pinned, tagged thesis chat
+/// answered by Claude, an expensive GPT fork, and an old plain chat.
+fn filter_fixture(conn: &rusqlite::Connection) -> (Conversation, Conversation, Conversation) {
+    let mut thesis = conversation_with_reply("Thesis outline", "Outline chapter two", "claude-opus-4-20250514", "Chapter two covers transformers.");
+    thesis.tags = vec!["paper".into(), "phd".into()];
+    thesis.folder = Some("PhD/Thesis".into());
+    thesis.pinned = true;
+    thesis.messages[1].rating = 1;
+
+    let mut fork = conversation_with_reply("Pricing", "Estimate the budget", "gpt-5", &"expensive transformers answer ".repeat(2000));
+    fork.forked_from = Some(thesis.id.clone());
+    fork.messages[1].rating = -1;
+
+    let mut old = conversation_with_reply("Old chat", "Say hello", "o4-mini", "Hello there.");
+    let long_ago = chrono::Utc::now() - chrono::Duration::days(400);
+    for msg in &mut old.messages {
+        msg.created_at = Some(long_ago);
+    }
+
+    for conv in [&thesis, &fork, &old] {
+        stoa::db::save_conversation(conn, conv).unwrap();
+    }
+    (thesis, fork, old)
+}
+
+#[test]
+fn search_filters_select_conversations() {
+    let conn = stoa::db::open_in_memory();
+    let (thesis, fork, old) = filter_fixture(&conn);
+    let search = |q: &str| {
+        let mut ids = stoa::db::search_conversations(&conn, q);
+        ids.sort();
+        ids
+    };
+    let ids = |convs: &[&Conversation]| {
+        let mut ids: Vec<String> = convs.iter().map(|c| c.id.clone()).collect();
+        ids.sort();
+        ids
+    };
+
+    assert_eq!(search("tag:paper"), ids(&[&thesis]));
+    assert_eq!(search("-tag:paper"), ids(&[&fork, &old]));
+    assert_eq!(search("folder:phd"), ids(&[&thesis]));
+    assert_eq!(search("folder:thesis"), Vec::<String>::new());
+    assert_eq!(search("is:pinned"), ids(&[&thesis]));
+    assert_eq!(search("is:fork"), ids(&[&fork]));
+    assert_eq!(search("model:claude-opus"), ids(&[&thesis]));
+    assert_eq!(search("-model:gpt"), ids(&[&thesis, &old]));
+    assert_eq!(search("rating:up"), ids(&[&thesis]));
+    assert_eq!(search("rating:down"), ids(&[&fork]));
+    assert_eq!(search("cost>0.10"), ids(&[&fork]));
+    assert_eq!(search("cost<0.10"), ids(&[&thesis, &old]));
+    let cutoff = (chrono::Local::now() - chrono::Duration::days(30)).format("%Y-%m-%d");
+    assert_eq!(search(&format!("before:{cutoff}")), ids(&[&old]));
+    assert_eq!(search(&format!("after:{cutoff}")), ids(&[&thesis, &fork]));
+
+    // Text combines with filters; message filters apply to the matching message
+    assert_eq!(search("transformers"), ids(&[&thesis, &fork]));
+    assert_eq!(search("transformers -expensive"), ids(&[&thesis]));
+    assert_eq!(search("transformers role:user"), Vec::<String>::new());
+    assert_eq!(search("\"chapter two covers\" model:claude"), ids(&[&thesis]));
+    assert_eq!(search("outline is:pinned"), ids(&[&thesis]));
+}
+
+#[test]
+fn search_filters_apply_to_message_hits_and_analytics() {
+    let conn = stoa::db::open_in_memory();
+    let (thesis, fork, _old) = filter_fixture(&conn);
+
+    let hits = stoa::db::search_messages(&conn, "transformers -tag:paper", 10);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].conversation_id, fork.id);
+    let hits = stoa::db::search_messages(&conn, "chapter role:user", 10);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message_id, thesis.messages[0].id);
+    assert!(stoa::db::search_messages(&conn, "is:pinned", 10).is_empty(), "filters alone have no snippet");
+
+    let claude = stoa::db::analytics(&conn, &DateRange::default(), &SearchQuery::parse("model:claude"));
+    assert_eq!(claude.conversations, 1);
+    assert_eq!(claude.messages, 1);
+    assert_eq!(claude.models.len(), 1);
+    assert_eq!(claude.models[0].model, "claude-opus-4-20250514");
+
+    let tagged = stoa::db::analytics(&conn, &DateRange::default(), &SearchQuery::parse("tag:paper"));
+    assert_eq!(tagged.conversations, 1);
+    assert_eq!(tagged.messages, 2);
+
+    let text = stoa::db::analytics(&conn, &DateRange::default(), &SearchQuery::parse("hello"));
+    assert_eq!(text.conversations, 1);
+    assert_eq!(text.models[0].model, "o4-mini");
+}
+
+#[test]
+fn search_match_ranges_are_case_insensitive_and_longest_first() {
+    let terms = vec!["rust".to_string(), "rustacean".to_string()];
+    let text = "A Rustacean writes rust; trust me.";
+    let found: Vec<&str> = stoa::search::match_ranges(text, &terms).into_iter().map(|r| &text[r]).collect();
+    assert_eq!(found, vec!["Rustacean", "rust", "rust"]);
+    assert!(stoa::search::match_ranges("héllo", &["llo".to_string()]).len() == 1);
+    assert!(stoa::search::match_ranges("anything", &[]).is_empty());
+}
+
+// ── Semantic Search Tests ────────────────────────────────────
+
+#[test]
+fn semantic_chunks_split_long_text_at_boundaries() {
+    use stoa::semantic::{chunks, CHUNK_CHARS};
+    assert_eq!(chunks("  short answer \n"), vec!["short answer".to_string()]);
+    assert!(chunks("   ").is_empty());
+
+    let paragraph = "word ".repeat(250);
+    let text = format!("{paragraph}\n\n{paragraph}\n\n{paragraph}");
+    let parts = chunks(&text);
+    assert!(parts.len() > 1);
+    assert!(parts.iter().all(|p| p.chars().count() <= CHUNK_CHARS && !p.is_empty()));
+    assert!(parts.iter().all(|p| p.starts_with("word") && p.ends_with("word")));
+    assert_eq!(parts.concat().matches("word").count(), 750);
+}
+
+#[test]
+fn semantic_vectors_normalize_and_roundtrip_blobs() {
+    use stoa::semantic::{dot, from_blob, normalize, to_blob};
+    let mut v = vec![3.0, 4.0];
+    normalize(&mut v);
+    assert_eq!(v, vec![0.6, 0.8]);
+    assert!((dot(&v, &v) - 1.0).abs() < 1e-6);
+    assert_eq!(from_blob(&to_blob(&v)), v);
+
+    let mut zero = vec![0.0, 0.0];
+    normalize(&mut zero);
+    assert_eq!(zero, vec![0.0, 0.0]);
+}
+
+#[test]
+fn semantic_index_ranks_search_and_related() {
+    use stoa::semantic::VectorIndex;
+    let mut index = VectorIndex::new("test-model", Vec::new());
+    index.insert("cooking", "m1", vec![vec![1.0, 0.0, 0.0]]);
+    index.insert("baking", "m2", vec![vec![0.8, 0.6, 0.0], vec![0.0, 0.0, 1.0]]);
+    index.insert("rockets", "m3", vec![vec![0.0, 1.0, 0.0]]);
+
+    let hits = index.search(&[1.0, 0.0, 0.0], 10);
+    let order: Vec<&str> = hits.iter().map(|(id, _)| id.as_str()).collect();
+    assert_eq!(order, vec!["cooking", "baking", "rockets"]);
+    assert!((hits[1].1 - 0.8).abs() < 1e-6);
+    // Each conversation is scored by its best chunk
+    assert_eq!(index.search(&[0.0, 0.0, 1.0], 1)[0].0, "baking");
+
+    let related = index.related("cooking", 5);
+    assert_eq!(related[0].0, "baking");
+    assert!(related.iter().all(|(id, _)| id != "cooking"));
+    assert!(index.related("unknown", 5).is_empty());
+
+    // Re-embedding a message replaces its chunks
+    index.insert("baking", "m2", vec![vec![0.0, 1.0, 0.0]]);
+    assert_eq!(index.entries.len(), 3);
+    // Vectors from a different dimension are ignored
+    assert!(index.search(&[1.0, 0.0], 5).is_empty());
+}
+
+#[test]
+fn parse_embeddings_reads_ollama_and_openai_shapes() {
+    use stoa::api::embeddings::parse_embeddings;
+    let ollama = serde_json::json!({ "model": "nomic-embed-text", "embeddings": [[0.1, 0.2], [0.3, 0.4]] });
+    assert_eq!(parse_embeddings(&ollama).unwrap(), vec![vec![0.1, 0.2], vec![0.3, 0.4]]);
+
+    let openai = serde_json::json!({ "data": [
+        { "index": 1, "embedding": [0.3, 0.4] },
+        { "index": 0, "embedding": [0.1, 0.2] },
+    ]});
+    assert_eq!(parse_embeddings(&openai).unwrap(), vec![vec![0.1, 0.2], vec![0.3, 0.4]]);
+
+    assert!(parse_embeddings(&serde_json::json!({ "embeddings": [["x"]] })).is_err());
+    assert!(parse_embeddings(&serde_json::json!({ "error": "model not found" })).is_err());
+}
+
+#[test]
+fn db_embeddings_track_pending_edits_and_deletes() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = conversation_with_reply("Bread", "How do I proof dough?", "gpt-4o", "Let it rise somewhere warm.");
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+
+    let pending = stoa::db::pending_embeddings(&conn, "m", 10);
+    assert_eq!(pending.len(), 2);
+    assert_eq!(pending[0], (conv.id.clone(), conv.messages[0].id.clone(), "How do I proof dough?".to_string()));
+    assert_eq!(stoa::db::pending_embeddings(&conn, "m", 1).len(), 1);
+
+    for msg in &conv.messages {
+        stoa::db::save_embeddings(&conn, "m", &msg.id, &[vec![1.0, 0.0], vec![0.0, 1.0]]).unwrap();
+    }
+    assert!(stoa::db::pending_embeddings(&conn, "m", 10).is_empty());
+    // Other models keep their own vectors
+    assert_eq!(stoa::db::pending_embeddings(&conn, "other", 10).len(), 2);
+    let entries = stoa::db::load_embeddings(&conn, "m");
+    assert_eq!(entries.len(), 4);
+    assert_eq!(entries[1].vector, vec![0.0, 1.0]);
+    assert_eq!(entries[0].conversation_id, conv.id);
+
+    // Editing a message invalidates its vectors
+    conv.messages[1].content = "Leave it in the fridge overnight.".to_string();
+    stoa::db::save_message(&conn, &conv, 1).unwrap();
+    let pending = stoa::db::pending_embeddings(&conn, "m", 10);
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].1, conv.messages[1].id);
+
+    stoa::db::save_embeddings(&conn, "other", &conv.messages[0].id, &[vec![1.0]]).unwrap();
+    stoa::db::prune_embeddings(&conn, "m").unwrap();
+    assert!(stoa::db::load_embeddings(&conn, "other").is_empty());
+    assert_eq!(stoa::db::load_embeddings(&conn, "m").len(), 2);
+
+    stoa::db::delete_conversation(&conn, &conv.id).unwrap();
+    assert!(stoa::db::load_embeddings(&conn, "m").is_empty());
+    assert!(stoa::db::save_embeddings(&conn, "m", &conv.messages[0].id, &[vec![1.0]]).is_err());
+}
+
+#[test]
+fn config_embeddings_default_disabled() {
+    let config = AppConfig::default();
+    assert!(!config.embeddings.enabled);
+    assert_eq!(config.embeddings.model, "nomic-embed-text");
+    assert!(config.embeddings.api_url.ends_with("/api/embed"));
+}
+
+#[test]
+fn app_semantic_query_waits_for_typing_to_pause() {
+    use stoa::app::Message;
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    app.config.embeddings.enabled = true;
+    app.quick_switcher_semantic = true;
+
+    // Each keystroke only starts a timer
+    for query in ["t", "ti", "tid", "tide"] {
+        let _ = app.update(Message::QuickSwitcherQueryChanged(query.into()));
+    }
+    // Timers for queries typed past embed nothing; the last one does
+    assert_eq!(app.update(Message::SemanticQuerySettled("tid".into())).units(), 0);
+    assert_eq!(app.update(Message::SemanticQuerySettled("tide".into())).units(), 1);
+
+    app.quick_switcher_semantic = false;
+    assert_eq!(app.update(Message::SemanticQuerySettled("tide".into())).units(), 0);
+}
+
 // ── Diff Guard Tests ─────────────────────────────────────────
 
 #[test]
@@ -1079,82 +1863,2166 @@ fn import_chatgpt_skips_empty_content() {
     assert!(convs.is_empty()); // no non-empty messages = no conversation
 }
 
-// ── DB Update Rating Tests ───────────────────────────────────
+const CHATGPT_BRANCHED: &str = r#"[{
+    "title": "Branches",
+    "conversation_id": "6f1c2a44-5b7e-4d8f-9a10-2b3c4d5e6f70",
+    "create_time": 1700000000.0,
+    "current_node": "u2",
+    "mapping": {
+        "root": {"id": "root", "message": null, "parent": null, "children": ["ctx"]},
+        "ctx": {"id": "ctx", "parent": "root", "children": ["sys"], "message": {
+            "author": {"role": "user"},
+            "content": {"content_type": "user_editable_context", "user_profile": "I sail.", "user_instructions": "Be brief."},
+            "metadata": {"is_visually_hidden_from_conversation": true}
+        }},
+        "sys": {"id": "sys", "parent": "ctx", "children": ["u1"], "message": {
+            "author": {"role": "system"}, "content": {"content_type": "text", "parts": [""]},
+            "metadata": {"is_visually_hidden_from_conversation": true}
+        }},
+        "u1": {"id": "u1", "parent": "sys", "children": ["a1", "a1b"], "message": {
+            "author": {"role": "user"}, "create_time": 1700000001.0,
+            "content": {"content_type": "multimodal_text", "parts": [
+                {"content_type": "image_asset_pointer", "asset_pointer": "file-service://file-abc"}, "When is high tide?"
+            ]}
+        }},
+        "a1": {"id": "a1", "parent": "u1", "children": [], "message": {
+            "author": {"role": "assistant"}, "create_time": 1700000002.0,
+            "content": {"content_type": "text", "parts": ["First answer"]}, "metadata": {"model_slug": "gpt-4"}
+        }},
+        "a1b": {"id": "a1b", "parent": "u1", "children": ["tool"], "message": {
+            "author": {"role": "assistant"}, "create_time": 1700000003.0, "update_time": 1700000005.0,
+            "content": {"content_type": "text", "parts": ["Regenerated answer"]}, "metadata": {"model_slug": "gpt-4o"}
+        }},
+        "tool": {"id": "tool", "parent": "a1b", "children": ["u2"], "message": {
+            "author": {"role": "tool"}, "content": {"content_type": "execution_output", "text": "42"}
+        }},
+        "u2": {"id": "u2", "parent": "tool", "children": [], "message": {
+            "author": {"role": "user"}, "create_time": 1700000010.0,
+            "content": {"content_type": "text", "parts": ["Thanks"]}
+        }}
+    }
+}]"#;
 
 #[test]
-fn db_update_rating() {
-    let conn = stoa::db::open_in_memory();
-    let mut conv = Conversation::new();
-    conv.add_user_message("hello", None);
-    conv.messages.push(ChatMessage {
-        role: Role::Assistant,
-        content: "hi".to_string(),
-        streaming: false,
-        model: None,
-        token_count: None,
-        rating: 0,
-        latency_ms: None,
-        images: Vec::new(),
-    });
-    stoa::db::save_conversation(&conn, &conv).unwrap();
-
-    stoa::db::update_rating(&conn, &conv.id, 1, 1).unwrap();
-    let loaded = stoa::db::load_all(&conn);
-    assert_eq!(loaded[0].messages[1].rating, 1);
-
-    stoa::db::update_rating(&conn, &conv.id, 1, -1).unwrap();
-    let loaded = stoa::db::load_all(&conn);
-    assert_eq!(loaded[0].messages[1].rating, -1);
+fn import_chatgpt_follows_current_node_and_keeps_branches() {
+    let (convs, report) = stoa::import::parse(ImportFormat::ChatGpt, CHATGPT_BRANCHED, &JsonlMapping::default());
+    assert_eq!(convs.len(), 1);
+    let conv = &convs[0];
+    assert_eq!(conv.id, "6f1c2a44-5b7e-4d8f-9a10-2b3c4d5e6f70");
+    assert_eq!(conv.created_at.timestamp(), 1700000000);
+    assert_eq!(conv.system_prompt, "I sail.\n\nBe brief.");
+
+    let thread: Vec<&str> = conv.messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(thread, vec!["[Image: file-abc]\nWhen is high tide?", "Regenerated answer", "Thanks"]);
+    assert_eq!(conv.messages[0].parent_id, None);
+    assert_eq!(conv.messages[2].parent_id.as_deref(), Some(conv.messages[1].id.as_str()));
+    assert_eq!(conv.messages[1].model.as_deref(), Some("gpt-4o"));
+    assert_eq!(conv.messages[0].created_at.unwrap().timestamp(), 1700000001);
+    assert_eq!(conv.messages[1].completed_at.unwrap().timestamp(), 1700000005);
+
+    // The first answer survives as an earlier version of the second
+    assert_eq!(conv.alternates.len(), 1);
+    assert_eq!(conv.alternates[0].content, "First answer");
+    assert_eq!(conv.alternates[0].parent_id.as_deref(), Some(conv.messages[0].id.as_str()));
+
+    assert_eq!(report.skipped.get("image"), Some(&1));
+    assert_eq!(report.skipped.get("tool output"), Some(&1));
 }
 
-// ── App State Tests ──────────────────────────────────────────
+/// What `ImportFile` delivers once the picked file has been read.
+fn load_import(app: &mut stoa::app::ChatApp, format: Option<ImportFormat>, source: &str, data: &str) {
+    let preview = stoa::import::preview(format, source.into(), data.into(), &app.config.jsonl_mapping);
+    let _ = app.update(stoa::app::Message::ImportLoaded(preview));
+}
 
 #[test]
-fn new_conversation_message_creates_and_selects() {
+fn app_reimporting_chatgpt_export_adds_nothing_twice() {
     let mut app = stoa::app::ChatApp::new_for_tests();
-    let initial_count = app.conversations.len();
-    let _ = app.update(stoa::app::Message::NewConversation);
-    assert_eq!(app.conversations.len(), initial_count + 1);
-    assert_eq!(app.active_conversation, app.conversations.len() - 1);
+    let before = app.conversations.len();
+    for _ in 0..2 {
+        load_import(&mut app, Some(ImportFormat::ChatGpt), "conversations.json", CHATGPT_BRANCHED);
+        let _ = app.update(stoa::app::Message::ConfirmImport);
+    }
+    assert_eq!(app.conversations.len(), before + 1);
+    let imported = &app.conversations[app.active_conversation];
+    assert_eq!(imported.messages.len(), 3);
+    assert_eq!(imported.alternates.len(), 1);
+    let report = app.import_report.clone().unwrap();
+    assert_eq!(report.imported, 0);
+    assert_eq!(report.already_imported, 1);
+
+    let _ = app.update(stoa::app::Message::DismissImportReport);
+    assert!(app.import_report.is_none());
 }
 
 #[test]
-fn select_conversation_bounds_check() {
-    let mut app = stoa::app::ChatApp::new_for_tests();
-    let _ = app.update(stoa::app::Message::SelectConversation(999));
-    // Should not crash, active_conversation unchanged
-    assert!(app.active_conversation < app.conversations.len());
+fn import_claude_keeps_timestamps_and_attachments() {
+    let data = r#"[{
+        "uuid": "c1",
+        "name": "Tide tables",
+        "created_at": "2024-03-04T10:20:00.000000Z",
+        "chat_messages": [
+            {
+                "sender": "human",
+                "text": "Summarize this file",
+                "created_at": "2024-03-04T10:20:30.000000Z",
+                "attachments": [{"file_name": "tides.csv", "extracted_content": "high,06:12"}],
+                "files": [{"file_name": "chart.png"}]
+            },
+            {
+                "sender": "assistant",
+                "text": "",
+                "content": [{"type": "text", "text": "High tide is at 06:12."}],
+                "created_at": "2024-03-04T10:20:31.000000Z",
+                "updated_at": "2024-03-04T10:20:35.000000Z"
+            },
+            {"sender": "human", "text": "", "created_at": "2024-03-04T10:21:00.000000Z"}
+        ]
+    }, {"uuid": "c2", "name": "", "chat_messages": []}]"#;
+
+    let convs = stoa::import::import_claude(data);
+    assert_eq!(convs.len(), 1);
+    let conv = &convs[0];
+    assert_eq!(conv.title, "Tide tables");
+    assert_eq!(conv.created_at.to_rfc3339(), "2024-03-04T10:20:00+00:00");
+    assert_eq!(conv.tags, vec!["imported".to_string(), "claude".to_string()]);
+    assert_eq!(conv.messages.len(), 2);
+
+    let question = &conv.messages[0];
+    assert_eq!(question.role, Role::User);
+    assert!(question.content.starts_with("Summarize this file"));
+    assert!(question.content.contains("**Attachment: tides.csv**\n\n```\nhigh,06:12\n```"));
+    assert!(question.content.contains("**File: chart.png**"));
+    assert_eq!(question.created_at.unwrap().to_rfc3339(), "2024-03-04T10:20:30+00:00");
+
+    let answer = &conv.messages[1];
+    assert_eq!(answer.role, Role::Assistant);
+    assert_eq!(answer.content, "High tide is at 06:12.");
+    assert_eq!(answer.parent_id.as_deref(), Some(question.id.as_str()));
+    assert_eq!(answer.completed_at.unwrap().to_rfc3339(), "2024-03-04T10:20:35+00:00");
 }
 
 #[test]
-fn delete_conversation_adjusts_active_index() {
-    let mut app = stoa::app::ChatApp::new_for_tests();
-    let _ = app.update(stoa::app::Message::NewConversation);
-    let _ = app.update(stoa::app::Message::NewConversation);
-    assert_eq!(app.conversations.len(), 3);
-    app.active_conversation = 2;
-    let _ = app.update(stoa::app::Message::DeleteConversation(0));
-    assert_eq!(app.conversations.len(), 2);
-    assert_eq!(app.active_conversation, 1); // adjusted down
+fn import_claude_invalid_json() {
+    assert!(stoa::import::import_claude("not json").is_empty());
+    assert!(stoa::import::import_claude("[]").is_empty());
 }
 
 #[test]
-fn delete_last_conversation_is_noop() {
-    let mut app = stoa::app::ChatApp::new_for_tests();
-    assert_eq!(app.conversations.len(), 1);
-    let _ = app.update(stoa::app::Message::DeleteConversation(0));
-    assert_eq!(app.conversations.len(), 1); // can't delete last
+fn import_detect_recognizes_each_format() {
+    use stoa::import::detect;
+    assert_eq!(detect(CHATGPT_BRANCHED), Some(ImportFormat::ChatGpt));
+    assert_eq!(detect(r#"[{"uuid": "c1", "chat_messages": []}]"#), Some(ImportFormat::Claude));
+    assert_eq!(detect(r#"[{"id": "w1", "chat": {"history": {"messages": {}}}}]"#), Some(ImportFormat::OpenWebUi));
+    assert_eq!(detect(r#"{"conversationId": "l1", "messages": []}"#), Some(ImportFormat::LibreChat));
+    assert_eq!(detect("{\"role\": \"user\", \"content\": \"hi\"}\n{\"role\": \"assistant\", \"content\": \"hey\"}\n"), Some(ImportFormat::Jsonl));
+    assert_eq!(detect("not an export"), None);
+    assert_eq!(detect("[1, 2]"), None);
+    assert_eq!(detect(""), None);
 }
 
-// ── Additional DB Tests ──────────────────────────────────────
+#[test]
+fn import_open_webui_follows_current_id_and_keeps_images() {
+    let data = r#"[{
+        "id": "3b1f0c9e-7a2d-4e5f-8a6b-1c2d3e4f5a6b",
+        "title": "Knots",
+        "created_at": 1700000000,
+        "chat": {
+            "system": "Answer like a sailor.",
+            "history": {
+                "currentId": "a2",
+                "messages": {
+                    "u1": {"id": "u1", "parentId": null, "role": "user", "content": "Which knot?", "timestamp": 1700000001,
+                           "files": [{"type": "image", "url": "data:image/png;base64,iVBORw0KGgo="}, {"type": "file", "name": "notes.pdf"}]},
+                    "a1": {"id": "a1", "parentId": "u1", "role": "assistant", "content": "A reef knot.", "model": "llama3", "timestamp": 1700000002},
+                    "a2": {"id": "a2", "parentId": "u1", "role": "assistant", "content": "A bowline.", "model": "mistral", "timestamp": 1700000003}
+                }
+            }
+        }
+    }]"#;
+    let (convs, report) = stoa::import::parse(ImportFormat::OpenWebUi, data, &JsonlMapping::default());
+    assert_eq!(convs.len(), 1);
+    let conv = &convs[0];
+    assert_eq!(conv.id, "3b1f0c9e-7a2d-4e5f-8a6b-1c2d3e4f5a6b");
+    assert_eq!(conv.title, "Knots");
+    assert_eq!(conv.system_prompt, "Answer like a sailor.");
+    assert_eq!(conv.tags, vec!["imported".to_string(), "open-webui".to_string()]);
+
+    let thread: Vec<&str> = conv.messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(thread, vec!["Which knot?", "A bowline."]);
+    assert_eq!(conv.messages[0].images, vec!["iVBORw0KGgo=".to_string()]);
+    assert_eq!(conv.messages[1].model.as_deref(), Some("mistral"));
+    assert_eq!(conv.alternates.len(), 1);
+    assert_eq!(conv.alternates[0].content, "A reef knot.");
+    assert_eq!(report.skipped.get("file"), Some(&1));
+}
 
 #[test]
-fn db_save_overwrite_existing() {
-    let conn = stoa::db::open_in_memory();
-    let mut conv = Conversation::new();
-    conv.title = "Version 1".to_string();
-    conv.add_user_message("first message", None);
-    stoa::db::save_conversation(&conn, &conv).unwrap();
+fn import_librechat_reads_nested_children() {
+    let data = r#"{
+        "conversationId": "lc-1",
+        "title": "Tides",
+        "messages": [{
+            "messageId": "m1", "sender": "User", "isCreatedByUser": true, "text": "High tide?",
+            "createdAt": "2024-05-01T08:00:00.000Z",
+            "children": [{
+                "messageId": "m2", "sender": "GPT-4", "isCreatedByUser": false, "text": "At noon.",
+                "model": "gpt-4", "createdAt": "2024-05-01T08:00:05.000Z",
+                "children": [{"messageId": "m3", "isCreatedByUser": true, "text": "Thanks", "createdAt": "2024-05-01T08:01:00.000Z", "children": []}]
+            }]
+        }]
+    }"#;
+    let (convs, _) = stoa::import::parse(ImportFormat::LibreChat, data, &JsonlMapping::default());
+    assert_eq!(convs.len(), 1);
+    let conv = &convs[0];
+    assert_eq!(conv.title, "Tides");
+    let roles: Vec<Role> = conv.messages.iter().map(|m| m.role.clone()).collect();
+    assert_eq!(roles, vec![Role::User, Role::Assistant, Role::User]);
+    assert_eq!(conv.messages[1].content, "At noon.");
+    assert_eq!(conv.messages[1].model.as_deref(), Some("gpt-4"));
+    assert_eq!(conv.messages[2].parent_id.as_deref(), Some(conv.messages[1].id.as_str()));
+    assert_eq!(conv.created_at.to_rfc3339(), "2024-05-01T08:00:00+00:00");
+}
+
+const SILLYTAVERN_CHAT: &str = r#"{"user_name": "Ann", "character_name": "Captain"}
+{"name": "Ann", "is_user": true, "send_date": "2024-06-01T09:00:00Z", "mes": "Where to?"}
+{"name": "Captain", "is_user": false, "send_date": "2024-06-01T09:00:04Z", "mes": "North."}
+"#;
+
+#[test]
+fn import_jsonl_uses_field_mapping() {
+    let mapping = JsonlMapping {
+        conversation: String::new(),
+        role: "is_user".into(),
+        content: "mes".into(),
+        timestamp: "send_date".into(),
+        model: String::new(),
+        title: "character_name".into(),
+        ..JsonlMapping::default()
+    };
+    let (convs, report) = stoa::import::parse(ImportFormat::Jsonl, SILLYTAVERN_CHAT, &mapping);
+    assert_eq!(convs.len(), 1);
+    let conv = &convs[0];
+    assert_eq!(conv.title, "Captain");
+    assert_eq!(conv.messages.len(), 2);
+    assert_eq!(conv.messages[0].role, Role::User);
+    assert_eq!(conv.messages[0].content, "Where to?");
+    assert_eq!(conv.messages[1].role, Role::Assistant);
+    assert_eq!(conv.messages[1].created_at.unwrap().to_rfc3339(), "2024-06-01T09:00:04+00:00");
+    // The header line has no content
+    assert_eq!(report.skipped.get("line without content"), Some(&1));
+
+    // The default mapping finds nothing to read
+    let (convs, _) = stoa::import::parse(ImportFormat::Jsonl, SILLYTAVERN_CHAT, &JsonlMapping::default());
+    assert!(convs.is_empty());
+}
+
+#[test]
+fn import_jsonl_groups_lines_by_conversation_field() {
+    let data = "{\"conversation_id\": \"a\", \"role\": \"user\", \"content\": \"one\"}\n\
+                {\"conversation_id\": \"b\", \"role\": \"user\", \"content\": \"two\"}\n\
+                not json\n\
+                {\"conversation_id\": \"a\", \"role\": \"assistant\", \"content\": \"three\", \"model\": \"m\"}\n";
+    let (convs, report) = stoa::import::parse(ImportFormat::Jsonl, data, &JsonlMapping::default());
+    assert_eq!(convs.len(), 2);
+    let a = convs.iter().find(|c| c.messages[0].content == "one").unwrap();
+    assert_eq!(a.messages.len(), 2);
+    assert_eq!(a.messages[1].model.as_deref(), Some("m"));
+    assert_eq!(report.skipped.get("unreadable line"), Some(&1));
+}
+
+#[test]
+fn import_find_duplicates_matches_id_or_title_and_start() {
+    let mut kept = Conversation::new();
+    kept.title = "Harbor".into();
+    let same_id = kept.clone();
+    let mut same_start = Conversation::new();
+    same_start.title = "Harbor".into();
+    same_start.created_at = kept.created_at;
+    let mut other = Conversation::new();
+    other.title = "Harbor".into();
+    other.created_at = kept.created_at - chrono::Duration::hours(1);
+
+    let dups = stoa::import::find_duplicates(&[same_id.clone(), same_start.clone(), other.clone()], &[kept]);
+    assert!(dups.contains(&same_id.id));
+    assert!(dups.contains(&same_start.id));
+    assert!(!dups.contains(&other.id));
+}
+
+#[test]
+fn app_import_preview_saves_nothing_until_confirmed() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    let before = app.conversations.len();
+    load_import(&mut app, None, "chat.jsonl", SILLYTAVERN_CHAT);
+    let preview = app.import_preview.as_ref().unwrap();
+    assert_eq!(preview.format, ImportFormat::Jsonl);
+    assert!(preview.conversations.is_empty());
+
+    // Pointing the mapping at the right fields reads the file again once typing pauses
+    let mut stale = None;
+    for (field, value) in [(JsonlField::Role, "is_user"), (JsonlField::Content, "mes"), (JsonlField::Title, "character_name")] {
+        assert_eq!(app.update(stoa::app::Message::SetJsonlField(field, value.into())).units(), 1);
+        stale.get_or_insert_with(|| app.config.jsonl_mapping.clone());
+    }
+    let _ = app.update(stoa::app::Message::JsonlMappingSettled(stale.unwrap()));
+    assert!(app.import_preview.as_ref().unwrap().conversations.is_empty());
+    let _ = app.update(stoa::app::Message::JsonlMappingSettled(app.config.jsonl_mapping.clone()));
+    let preview = app.import_preview.as_ref().unwrap();
+    assert_eq!(preview.conversations.len(), 1);
+    assert_eq!(preview.message_count(), 2);
+    assert_eq!(app.conversations.len(), before);
+
+    let _ = app.update(stoa::app::Message::CancelImport);
+    assert!(app.import_preview.is_none());
+    assert_eq!(app.conversations.len(), before);
+}
+
+#[test]
+fn app_import_confirm_uses_the_latest_jsonl_mapping() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    let before = app.conversations.len();
+    load_import(&mut app, None, "chat.jsonl", SILLYTAVERN_CHAT);
+    for (field, value) in [(JsonlField::Role, "is_user"), (JsonlField::Content, "mes")] {
+        let _ = app.update(stoa::app::Message::SetJsonlField(field, value.into()));
+    }
+    // Confirmed before the preview caught up
+    let _ = app.update(stoa::app::Message::ConfirmImport);
+    assert_eq!(app.conversations.len(), before + 1);
+    assert_eq!(app.import_report.as_ref().unwrap().imported, 1);
+}
+
+#[test]
+fn app_import_rejects_unrecognized_file() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    load_import(&mut app, None, "notes.txt", "hello");
+    assert!(app.import_preview.is_none());
+    assert_eq!(app.error_message.as_deref(), Some("notes.txt isn't an export Stoa recognizes."));
+}
+
+#[test]
+fn import_markdown_export_round_trips() {
+    let mut conv = Conversation::new();
+    conv.title = "Tide tables".into();
+    conv.tags = vec!["sailing".into(), "planning".into()];
+    conv.add_user_message("When is high tide?", None);
+    let idx = conv.push_streaming_assistant(Some("gpt-4o".into()));
+    conv.finalize_at(idx, "Around noon.\n\n---\n\nCheck the harbor office too.");
+    let idx = conv.push_streaming_assistant(Some("claude-3".into()));
+    conv.finalize_at(idx, "```text\n**You**\n```");
+    let md = export::conversation_to_markdown(&conv);
+
+    assert_eq!(stoa::import::detect(&md), Some(ImportFormat::Markdown));
+    let (convs, _) = stoa::import::parse(ImportFormat::Markdown, &md, &JsonlMapping::default());
+    let imported = &convs[0];
+    assert_eq!(imported.title, "Tide tables");
+    assert_eq!(imported.tags, vec!["imported", "sailing", "planning"]);
+    assert_eq!(imported.created_at.format("%Y-%m-%d %H:%M").to_string(), conv.created_at.format("%Y-%m-%d %H:%M").to_string());
+    let contents: Vec<&str> = imported.messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, ["When is high tide?", "Around noon.\n\n---\n\nCheck the harbor office too.", "```text\n**You**\n```"]);
+    assert_eq!(imported.messages[0].role, Role::User);
+    assert_eq!(imported.messages[1].model.as_deref(), Some("gpt-4o"));
+    assert_eq!(imported.messages[2].model.as_deref(), Some("claude-3"));
+    assert!(imported.messages[0].created_at.is_some());
+    assert_eq!(imported.messages[2].parent_id.as_ref(), Some(&imported.messages[1].id));
+}
+
+#[test]
+fn import_markdown_vault_note_keeps_front_matter() {
+    let mut conv = Conversation::new();
+    conv.title = "Knots: a primer".into();
+    conv.tags = vec!["sailing".into()];
+    conv.folder = Some("Hobbies/Boats".into());
+    conv.system_prompt = "Answer like a sailor.\nKeep it short.".into();
+    conv.add_user_message("What is a bowline?", None);
+    let idx = conv.push_streaming_assistant(Some("gpt-4o".into()));
+    conv.finalize_at(idx, "A loop that doesn't slip.");
+    let names = stoa::vault::note_names(std::slice::from_ref(&conv));
+    let (note, _) = stoa::vault::note(&conv, &names);
+
+    let (convs, _) = stoa::import::parse(ImportFormat::Markdown, &note, &JsonlMapping::default());
+    let imported = &convs[0];
+    // The note's ID lets a re-import be recognized as a duplicate
+    assert_eq!(imported.id, conv.id);
+    assert_eq!(imported.title, "Knots: a primer");
+    assert_eq!(imported.tags, vec!["imported", "sailing"]);
+    assert_eq!(imported.folder.as_deref(), Some("Hobbies/Boats"));
+    assert_eq!(imported.system_prompt, "Answer like a sailor.\nKeep it short.");
+    assert_eq!(imported.created_at.timestamp(), conv.created_at.timestamp());
+    assert_eq!(imported.messages.len(), 2);
+    assert_eq!(imported.messages[1].content, "A loop that doesn't slip.");
+}
+
+#[test]
+fn import_markdown_reads_prefix_and_heading_transcripts() {
+    let prefixed = "---\ntags: [notes, \"old chats\"]\n---\nUser: Hi there\nAssistant: Hello!\nHow can I help?\nUser: Say \"Assistant: hi\"\nAssistant: Assistant: hi\n";
+    assert_eq!(stoa::import::detect(prefixed), Some(ImportFormat::Markdown));
+    let (convs, _) = stoa::import::parse(ImportFormat::Markdown, prefixed, &JsonlMapping::default());
+    let conv = &convs[0];
+    assert_eq!(conv.title, "Imported Chat");
+    assert_eq!(conv.tags, vec!["imported", "notes", "old chats"]);
+    let contents: Vec<&str> = conv.messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, ["Hi there", "Hello!\nHow can I help?", "Say \"Assistant: hi\"", "Assistant: hi"]);
+
+    let headed = "# Packing list\n\n## User\n\nWhat should I bring?\n\nUser: a note to self\n\n## Assistant (gpt-4o)\n\nA raincoat.\n";
+    let (convs, _) = stoa::import::parse(stoa::import::detect(headed).unwrap(), headed, &JsonlMapping::default());
+    let conv = &convs[0];
+    assert_eq!(conv.title, "Packing list");
+    assert_eq!(conv.messages.len(), 2);
+    // Once headings mark speakers, a `User:` line is part of the message
+    assert_eq!(conv.messages[0].content, "What should I bring?\n\nUser: a note to self");
+    assert_eq!(conv.messages[1].model.as_deref(), Some("gpt-4o"));
+}
+
+#[test]
+fn import_markdown_needs_both_speakers() {
+    assert_eq!(stoa::import::detect("# Notes\n\nUser: remember milk\n"), None);
+    assert_eq!(stoa::import::detect("Plain prose that mentions the user: nothing else.\n"), None);
+}
+
+// ── Stoa Archive Tests ───────────────────────────────────────
+
+/// A rated, tagged, filed conversation with a retried reply and an image,
+/// and a fork of it.
+fn archived_pair() -> (Conversation, Conversation) {
+    let mut conv = conversation_with_error_and_image("Coast");
+    conv.folder = Some("Research/Sea".into());
+    conv.system_prompt = "Be brief.".into();
+    conv.messages[2].rating = 1;
+    conv.messages[2].latency_ms = Some(420);
+    // The failed first reply becomes an older version of the answer
+    let retried = conv.messages.remove(1);
+    conv.messages[1].parent_id = Some(conv.messages[0].id.clone());
+    conv.alternates.push(retried);
+    let mut fork = conv.fork(1);
+    fork.title = "Coast (fork)".into();
+    (conv, fork)
+}
+
+#[test]
+fn archive_round_trip_keeps_everything() {
+    let (conv, fork) = archived_pair();
+    let data = stoa::archive::write(&[conv.clone(), fork.clone()]).unwrap();
+    assert_eq!(stoa::import::detect(&data), Some(ImportFormat::Stoa));
+    let value: serde_json::Value = serde_json::from_str(&data).unwrap();
+    assert_eq!(value["format"], "stoa-archive");
+    assert_eq!(value["version"], 1);
+
+    let (back, report) = stoa::import::parse(ImportFormat::Stoa, &data, &JsonlMapping::default());
+    assert!(report.skipped.is_empty());
+    assert_eq!(back.len(), 2);
+    let restored = &back[0];
+    assert_eq!(restored.id, conv.id);
+    assert_eq!(restored.tags, conv.tags);
+    assert_eq!(restored.folder.as_deref(), Some("Research/Sea"));
+    assert_eq!(restored.system_prompt, "Be brief.");
+    assert_eq!(restored.messages[0].images, vec!["iVBORw0KGgo=".to_string()]);
+    assert_eq!(restored.messages[1].rating, 1);
+    assert_eq!(restored.messages[1].latency_ms, Some(420));
+    assert_eq!(restored.alternates.len(), 1);
+    assert_eq!(back[1].forked_from.as_deref(), Some(conv.id.as_str()));
+}
+
+#[test]
+fn archive_reads_json_exports_and_refuses_newer_versions() {
+    let conv = conversation_with_reply("Tides", "Why?", "gpt-4o", "The Moon.");
+    let json = export::conversation_to_json(&conv);
+    assert_eq!(stoa::import::detect(&json), Some(ImportFormat::Stoa));
+    let (back, _) = stoa::import::parse(ImportFormat::Stoa, &json, &JsonlMapping::default());
+    assert_eq!(back[0].id, conv.id);
+
+    let newer = r#"{"format":"stoa-archive","version":99,"conversations":[]}"#;
+    let err = stoa::archive::read(newer).unwrap_err();
+    assert!(err.contains("newer Stoa"));
+}
+
+#[test]
+fn import_stoa_json_from_before_message_ids_links_the_thread() {
+    let data = r#"{"id": "7d0f3c2e-3b7a-4c1e-9a51-2f9c1d8e6b40", "title": "Old chat", "messages": [
+        {"role": "User", "content": "Hi"},
+        {"role": "Assistant", "content": "Hello!", "model": "gpt-4o"},
+        {"role": "User", "content": "Tell me a joke"},
+        {"role": "Assistant", "content": "Why did the chicken...", "model": "gpt-4o"}
+    ]}"#;
+    assert_eq!(stoa::import::detect(data), Some(ImportFormat::Stoa));
+    let (mut convs, _) = stoa::import::parse(ImportFormat::Stoa, data, &JsonlMapping::default());
+    let conv = &mut convs[0];
+    assert_eq!(conv.messages[3].parent_id.as_ref(), Some(&conv.messages[2].id));
+
+    // Retrying the last reply makes a version of it and nothing else
+    conv.branch_from(3);
+    let idx = conv.push_streaming_assistant(Some("gpt-4o".into()));
+    conv.finalize_at(idx, "A knock-knock joke instead.");
+    assert_eq!(conv.version_info(3), (2, 2));
+    for i in 0..3 {
+        assert_eq!(conv.version_info(i), (1, 1), "message {i}");
+    }
+}
+
+#[test]
+fn import_remap_ids_gives_fresh_ids_and_moves_forks_along() {
+    let (conv, fork) = archived_pair();
+    let mut convs = vec![conv.clone(), fork];
+    stoa::import::remap_ids(&mut convs, &[conv.id.clone()].into_iter().collect());
+    let copy = &convs[0];
+    assert_ne!(copy.id, conv.id);
+    assert_ne!(copy.messages[0].id, conv.messages[0].id);
+    assert_eq!(copy.messages[1].parent_id.as_deref(), Some(copy.messages[0].id.as_str()));
+    assert_eq!(copy.alternates[0].parent_id.as_deref(), Some(copy.messages[0].id.as_str()));
+    assert_eq!(copy.messages[1].content, "A lighthouse.");
+    assert_eq!(convs[1].forked_from.as_deref(), Some(copy.id.as_str()));
+    // The retried-away reply is still the older version
+    assert_eq!(copy.version_info(1), conv.version_info(1));
+    assert_eq!(copy.version_info(1), (2, 2));
+}
+
+#[test]
+fn app_import_archive_skips_or_copies_duplicates() {
+    let (conv, fork) = archived_pair();
+    let data = stoa::archive::write(&[conv.clone(), fork.clone()]).unwrap();
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    let _ = app.update(stoa::app::Message::ImportComplete(vec![conv.clone()]));
+    let before = app.conversations.len();
+
+    load_import(&mut app, None, "coast.stoa", &data);
+    let preview = app.import_preview.as_ref().unwrap();
+    assert_eq!(preview.format, ImportFormat::Stoa);
+    assert!(preview.duplicates.contains(&conv.id));
+    assert_eq!(preview.new_conversations().count(), 1);
+    let _ = app.update(stoa::app::Message::ConfirmImport);
+    assert_eq!(app.conversations.len(), before + 1);
+    assert_eq!(app.import_report.as_ref().unwrap().already_imported, 1);
+
+    // Again, keeping both: the original and the fork arrive as copies
+    load_import(&mut app, None, "coast.stoa", &data);
+    let _ = app.update(stoa::app::Message::SetImportCopies(true));
+    assert_eq!(app.import_preview.as_ref().unwrap().new_conversations().count(), 2);
+    let _ = app.update(stoa::app::Message::ConfirmImport);
+    assert_eq!(app.conversations.len(), before + 3);
+    let copies: Vec<&Conversation> = app.conversations[before + 1..].iter().collect();
+    assert!(copies.iter().all(|c| c.id != conv.id && c.id != fork.id));
+    assert_eq!(copies[1].forked_from.as_deref(), Some(copies[0].id.as_str()));
+
+    // The copy's messages don't take over the original's rows
+    let _ = app.update(stoa::app::Message::SelectConversation(before + 1));
+    let original = app.conversations.iter().position(|c| c.id == conv.id).unwrap();
+    let _ = app.update(stoa::app::Message::SelectConversation(original));
+    let (copy, original) = (&app.conversations[before + 1], &app.conversations[original]);
+    assert_eq!(copy.messages.len(), 2);
+    assert_eq!(original.messages.len(), 2);
+    assert!(copy.messages.iter().all(|m| original.messages.iter().all(|o| o.id != m.id)));
+}
+
+#[test]
+fn app_import_refuses_archive_from_newer_version() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    let newer = r#"{"format":"stoa-archive","version":2,"conversations":[]}"#;
+    load_import(&mut app, None, "future.stoa", newer);
+    assert!(app.import_preview.is_none());
+    assert!(app.error_message.as_deref().unwrap().starts_with("Can't import future.stoa: It was written by a newer Stoa"));
+}
+
+// ── Dataset Export Tests ─────────────────────────────────────
+
+/// A prompt answered by several models, each reply given a rating.
+fn rated_turn(conv: &mut Conversation, prompt: &str, replies: &[(&str, &str, i8)]) {
+    conv.add_user_message(prompt, None);
+    for (model, answer, rating) in replies {
+        let idx = conv.push_streaming_assistant(Some(model.to_string()));
+        conv.finalize_at(idx, answer);
+        conv.messages[idx].rating = *rating;
+    }
+}
+
+fn jsonl_lines(data: &str) -> Vec<serde_json::Value> {
+    data.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+#[test]
+fn dataset_fine_tuning_weights_out_filtered_replies() {
+    use stoa::dataset::{fine_tuning, DatasetFilter, RatingFilter};
+    let mut conv = Conversation::new();
+    conv.system_prompt = "Be brief.".into();
+    rated_turn(&mut conv, "Hi", &[("gpt-4o", "Hello!", -1)]);
+    rated_turn(&mut conv, "Capital of France?", &[("gpt-4o", "Lyon.", -1), ("claude-sonnet", "Paris.", 1)]);
+    rated_turn(&mut conv, "Thanks", &[("gpt-4o", "[Error: timeout]", 0)]);
+
+    let filter = DatasetFilter { rating: RatingFilter::NotDown, ..DatasetFilter::default() };
+    let lines = jsonl_lines(&fine_tuning(std::slice::from_ref(&conv), &filter));
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["messages"], serde_json::json!([
+        {"role": "system", "content": "Be brief."},
+        {"role": "user", "content": "Hi"},
+        {"role": "assistant", "content": "Hello!", "weight": 0},
+        {"role": "user", "content": "Capital of France?"},
+        {"role": "assistant", "content": "Paris."},
+    ]));
+
+    let nothing = DatasetFilter { model: "llama".into(), ..DatasetFilter::default() };
+    assert_eq!(fine_tuning(&[conv], &nothing), "");
+}
+
+#[test]
+fn dataset_preference_pairs_come_from_rated_multi_model_turns() {
+    use stoa::dataset::{preference_pairs, DatasetFilter};
+    let mut conv = Conversation::new();
+    rated_turn(&mut conv, "Capital of France?", &[("gpt-4o", "Lyon.", -1), ("claude-sonnet", "Paris.", 1), ("llama", "France.", 0)]);
+    rated_turn(&mut conv, "And Spain?", &[("gpt-4o", "Madrid.", 1)]);
+
+    let lines = jsonl_lines(&preference_pairs(std::slice::from_ref(&conv), &DatasetFilter::default()));
+    assert_eq!(lines, vec![serde_json::json!({
+        "prompt": [{"role": "user", "content": "Capital of France?"}],
+        "chosen": [{"role": "assistant", "content": "Paris."}],
+        "rejected": [{"role": "assistant", "content": "Lyon."}],
+    })]);
+
+    let claude = DatasetFilter { model: "Claude".into(), ..DatasetFilter::default() };
+    assert_eq!(jsonl_lines(&preference_pairs(std::slice::from_ref(&conv), &claude)).len(), 1);
+    let llama = DatasetFilter { model: "llama".into(), ..DatasetFilter::default() };
+    assert_eq!(preference_pairs(&[conv], &llama), "");
+}
+
+#[test]
+fn dataset_scrub_pii_replaces_personal_details() {
+    let text = "Mail jane.doe@example.co.uk or call +1 (555) 123-4567. Card 4111 1111 1111 1111, \
+                order 1234 5678 9012 3456, server 10.0.0.12, key sk-abcdefghijklmnop1234. \
+                Meeting on 2024-01-15 10:30 about v2.1.";
+    assert_eq!(
+        stoa::dataset::scrub_pii(text),
+        "Mail [EMAIL] or call [PHONE]. Card [CARD], \
+         order 1234 5678 9012 3456, server [IP], key [SECRET]. \
+         Meeting on 2024-01-15 10:30 about v2.1."
+    );
+}
+
+#[test]
+fn export_render_writes_scrubbed_datasets() {
+    use stoa::export::{render, ExportFormat, ExportOptions};
+    let mut conv = Conversation::new();
+    rated_turn(&mut conv, "Email me at bob@example.com", &[("gpt-4o", "Sure.", -1), ("claude-sonnet", "Will do.", 1)]);
+    let mut options = ExportOptions::default();
+    options.dataset.scrub_pii = true;
+    let data = String::from_utf8(render(std::slice::from_ref(&conv), ExportFormat::Preferences, &options).unwrap()).unwrap();
+    assert!(data.contains("Email me at [EMAIL]") && !data.contains("bob@"));
+    let data = String::from_utf8(render(&[conv], ExportFormat::FineTune, &options).unwrap()).unwrap();
+    assert_eq!(jsonl_lines(&data)[0]["messages"][1]["content"], "Will do.");
+}
+
+// ── Comparison Report Tests ──────────────────────────────────
+
+fn three_model_conversation() -> Conversation {
+    let mut conv = Conversation::new();
+    conv.title = "Capitals".into();
+    rated_turn(&mut conv, "Hi", &[("gpt-4.1", "Hello!", 0)]);
+    rated_turn(&mut conv, "Capital of France?", &[
+        ("gpt-4.1", "The capital of France is Paris.", 1),
+        ("o3", "Paris is the capital, \"obviously\".", 0),
+        ("claude-sonnet", "It's **Paris**.", -1),
+    ]);
+    conv.messages[3].token_count = Some(10);
+    conv.messages[3].latency_ms = Some(100);
+    conv
+}
+
+#[test]
+fn report_turns_cover_multi_model_turns_or_one_turn() {
+    use stoa::report::turns;
+    let conv = three_model_conversation();
+    let all = turns(&conv, None);
+    assert_eq!(all.len(), 1);
+    assert_eq!(all[0].prompt.unwrap().content, "Capital of France?");
+    assert_eq!(all[0].replies.len(), 3);
+
+    let single = turns(&conv, Some(1));
+    assert_eq!(single.len(), 1);
+    assert_eq!(single[0].replies[0].content, "Hello!");
+    assert!(turns(&conv, Some(0)).is_empty());
+
+    // A failed reply isn't compared, and a turn it leaves with one answer isn't listed
+    let mut conv = three_model_conversation();
+    rated_turn(&mut conv, "Capital of Peru?", &[("gpt-4.1", "Lima.", 0), ("o3", "[Error: timeout]", 0)]);
+    conv.messages[4].content = "[Error: rate limited]".into();
+    let all = turns(&conv, None);
+    assert_eq!(all.len(), 1);
+    assert_eq!(all[0].replies.len(), 2);
+    assert!(all[0].replies.iter().all(|m| !m.is_error()));
+    assert_eq!(turns(&conv, Some(7))[0].replies.len(), 1);
+}
+
+#[test]
+fn report_markdown_lists_metrics_and_agreement_matrix() {
+    use stoa::report::{render, turns, ReportFormat};
+    let conv = three_model_conversation();
+    let md = render(&conv.title, &turns(&conv, None), ReportFormat::Markdown, ThemeName::default());
+    assert!(md.starts_with("# Comparison: Capitals\n\n## Turn 1\n\n> Capital of France?\n"));
+    assert!(md.contains("| gpt-4.1 | 10 | 100 ms | $0.0001 | \u{1F44D} |"));
+    let agreement = diff::agreement_percentage("The capital of France is Paris.", "It's **Paris**.");
+    assert!(md.contains("| | gpt-4.1 | o3 | claude-sonnet |\n|---|---:|---:|---:|\n"));
+    assert!(md.contains(&format!("| claude-sonnet | {agreement:.0}% |")));
+    assert!(md.contains("### o3\n\nParis is the capital"));
+}
+
+#[test]
+fn report_html_and_csv_are_ready_to_share() {
+    use stoa::report::{render, turns, ReportFormat};
+    let conv = three_model_conversation();
+    let turns = turns(&conv, None);
+    let html = render(&conv.title, &turns, ReportFormat::Html, ThemeName::Nord);
+    assert!(html.contains("<title>Comparison: Capitals</title>"));
+    assert!(html.contains("<h3>Agreement</h3>"));
+    assert!(html.contains("<strong>Paris</strong>"));
+
+    let csv = render(&conv.title, &turns, ReportFormat::Csv, ThemeName::default());
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "turn,prompt,model,tokens,latency_ms,cost_usd,rating,mean_agreement,agreement,answer");
+    assert_eq!(lines.len(), 4);
+    assert!(lines[1].starts_with("1,Capital of France?,gpt-4.1,10,100,0.000080,1,"));
+    assert!(lines[2].ends_with(",\"Paris is the capital, \"\"obviously\"\".\""));
+}
+
+#[test]
+fn app_report_needs_a_multi_model_turn() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    let _ = app.update(stoa::app::Message::ImportComplete(vec![conversation_with_reply("Tides", "Why?", "gpt-4o", "The Moon.")]));
+    let _ = app.update(stoa::app::Message::OpenReport(None));
+    let _ = app.update(stoa::app::Message::SaveReport);
+    let dialog = app.report_dialog.as_ref().unwrap();
+    assert!(dialog.whole);
+    assert_eq!(dialog.status.as_deref(), Some("No turn here was answered by more than one model."));
+    let _ = app.update(stoa::app::Message::CloseReport);
+    assert!(app.report_dialog.is_none());
+}
+
+// ── DB Update Rating Tests ───────────────────────────────────
+
+#[test]
+fn db_update_rating() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.add_user_message("hello", None);
+    conv.messages.push(ChatMessage {
+        id: new_message_id(),
+        parent_id: None,
+        role: Role::Assistant,
+        content: "hi".to_string(),
+        streaming: false,
+        model: None,
+        token_count: None,
+        rating: 0,
+        latency_ms: None,
+        images: Vec::new(),
+        canonical: false,
+        created_at: None,
+        completed_at: None,
+    });
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+
+    stoa::db::update_rating(&conn, &conv.messages[1].id, 1).unwrap();
+    let loaded = stoa::db::load_all(&conn);
+    assert_eq!(loaded[0].messages[1].rating, 1);
+
+    stoa::db::update_rating(&conn, &conv.messages[1].id, -1).unwrap();
+    let loaded = stoa::db::load_all(&conn);
+    assert_eq!(loaded[0].messages[1].rating, -1);
+}
+
+#[test]
+fn db_update_rating_ignores_inactive_versions() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.add_user_message("question", None);
+    answered(&mut conv, "first answer");
+    conv.branch_from(1);
+    answered(&mut conv, "second answer");
+    conv.add_user_message("follow-up", None);
+    answered(&mut conv, "follow-up answer");
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+
+    stoa::db::update_rating(&conn, &conv.messages[3].id, 1).unwrap();
+    let loaded = stoa::db::load_all(&conn);
+    assert_eq!(loaded[0].messages[3].rating, 1);
+    assert!(loaded[0].messages[..3].iter().all(|m| m.rating == 0));
+    assert_eq!(loaded[0].alternates[0].rating, 0);
+}
+
+// ── Incremental Persistence Tests ────────────────────────────
+
+fn message_rowids(conn: &rusqlite::Connection) -> Vec<(i64, String)> {
+    let mut stmt = conn.prepare("SELECT id, uuid FROM messages ORDER BY id").unwrap();
+    stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().map(|r| r.unwrap()).collect()
+}
+
+#[test]
+fn db_save_message_appends_without_rewriting() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.add_user_message("question", None);
+    answered(&mut conv, "answer");
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+    let before = message_rowids(&conn);
+
+    conv.add_user_message("follow-up", None);
+    stoa::db::save_message(&conn, &conv, 2).unwrap();
+    let after = message_rowids(&conn);
+    assert_eq!(after.len(), 3);
+    assert_eq!(&after[..2], &before[..]);
+
+    // Re-saving the whole conversation keeps existing rows in place
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+    assert_eq!(message_rowids(&conn), after);
+
+    let loaded = stoa::db::load_all(&conn);
+    let contents: Vec<&str> = loaded[0].messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, vec!["question", "answer", "follow-up"]);
+}
+
+#[test]
+fn db_save_message_skips_streaming() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.add_user_message("question", None);
+    let idx = conv.push_streaming_assistant(Some("gpt-4.1".to_string()));
+    stoa::db::save_message(&conn, &conv, 0).unwrap();
+    stoa::db::save_message(&conn, &conv, idx).unwrap();
+    assert_eq!(stoa::db::load_all(&conn)[0].messages.len(), 1);
+
+    conv.finalize_at(idx, "done");
+    stoa::db::save_message(&conn, &conv, idx).unwrap();
+    let loaded = stoa::db::load_all(&conn);
+    assert_eq!(loaded[0].messages.len(), 2);
+    assert_eq!(loaded[0].messages[1].content, "done");
+    assert_eq!(loaded[0].messages[1].id, conv.messages[1].id);
+}
+
+#[test]
+fn db_multi_model_replies_keep_order_when_finished_out_of_order() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.add_user_message("question", None);
+    stoa::db::save_message(&conn, &conv, 0).unwrap();
+    let a = conv.push_streaming_assistant(Some("gpt-4.1".to_string()));
+    let b = conv.push_streaming_assistant(Some("o3".to_string()));
+    conv.finalize_at(b, "from o3");
+    stoa::db::save_message(&conn, &conv, b).unwrap();
+    conv.finalize_at(a, "from gpt");
+    stoa::db::save_message(&conn, &conv, a).unwrap();
+
+    let loaded = stoa::db::load_all(&conn);
+    let contents: Vec<&str> = loaded[0].messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, vec!["question", "from gpt", "from o3"]);
+}
+
+#[test]
+fn db_delete_message_reattaches_replies() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.add_user_message("one", None);
+    conv.add_user_message("two", None);
+    conv.add_user_message("three", None);
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+
+    stoa::db::delete_message(&conn, &conv.messages[1].id).unwrap();
+    conv.remove_message(1);
+
+    let loaded = stoa::db::load_all(&conn);
+    assert_eq!(loaded[0].messages.len(), 2);
+    assert_eq!(loaded[0].messages[1].content, "three");
+    assert_eq!(loaded[0].messages[1].parent_id, Some(conv.messages[0].id.clone()));
+    assert!(stoa::db::search_conversations(&conn, "two").is_empty());
+}
+
+// ── Timestamp Tests ──────────────────────────────────────────
+
+#[test]
+fn messages_record_created_and_completed_times() {
+    let mut conv = Conversation::new();
+    conv.add_user_message("question", None);
+    let idx = conv.push_streaming_assistant(Some("gpt-4.1".to_string()));
+    assert!(conv.messages[0].created_at.is_some());
+    assert!(conv.messages[0].completed_at.is_none());
+    assert!(conv.messages[idx].completed_at.is_none());
+
+    conv.finalize_at(idx, "answer");
+    let reply = &conv.messages[idx];
+    assert!(reply.completed_at.unwrap() >= reply.created_at.unwrap());
+    assert_eq!(conv.last_activity(), reply.completed_at.unwrap());
+}
+
+#[test]
+fn last_activity_falls_back_to_conversation_created_at() {
+    let mut conv = Conversation::new();
+    assert_eq!(conv.last_activity(), conv.created_at);
+    conv.add_user_message("hi", None);
+    conv.messages[0].created_at = None;
+    assert_eq!(conv.last_activity(), conv.created_at);
+}
+
+#[test]
+fn db_round_trips_timestamps() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.add_user_message("question", None);
+    answered(&mut conv, "answer");
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+
+    let loaded = &stoa::db::load_all(&conn)[0];
+    assert_eq!(loaded.created_at, conv.created_at);
+    for (a, b) in loaded.messages.iter().zip(&conv.messages) {
+        assert_eq!(a.created_at, b.created_at);
+        assert_eq!(a.completed_at, b.completed_at);
+    }
+    assert!(loaded.messages[1].completed_at.is_some());
+}
+
+#[test]
+fn db_keeps_conversation_created_at_on_update() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.created_at -= chrono::Duration::days(3);
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+    let mut changed = conv.clone();
+    changed.created_at = chrono::Utc::now();
+    changed.title = "Renamed".to_string();
+    stoa::db::upsert_conversation(&conn, &changed).unwrap();
+
+    let loaded = &stoa::db::load_all(&conn)[0];
+    assert_eq!(loaded.title, "Renamed");
+    assert_eq!(loaded.created_at, conv.created_at);
+}
+
+#[test]
+fn date_group_buckets_by_day() {
+    let today = chrono::NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+    let day = |d| chrono::NaiveDate::from_ymd_opt(2025, 3, d).unwrap();
+    assert_eq!(DateGroup::of(day(10), today), DateGroup::Today);
+    assert_eq!(DateGroup::of(day(11), today), DateGroup::Today);
+    assert_eq!(DateGroup::of(day(9), today), DateGroup::Yesterday);
+    assert_eq!(DateGroup::of(day(3), today), DateGroup::Last7Days);
+    assert_eq!(DateGroup::of(day(2), today), DateGroup::Older);
+    assert!(DateGroup::Today < DateGroup::Older);
+}
+
+#[test]
+fn date_range_parse_and_contains() {
+    let unbounded = DateRange::parse("", "not a date");
+    assert!(unbounded.is_unbounded());
+    assert!(unbounded.contains(None));
+
+    let now = chrono::Utc::now();
+    let today = now.with_timezone(&chrono::Local).date_naive();
+    let range = DateRange::parse(&today.format("%Y-%m-%d").to_string(), "");
+    assert!(range.contains(Some(now)));
+    assert!(!range.contains(Some(now - chrono::Duration::days(2))));
+    assert!(!range.contains(None));
+
+    let until_yesterday = DateRange { from: None, to: today.pred_opt() };
+    assert!(!until_yesterday.contains(Some(now)));
+}
+
+#[test]
+fn exports_include_timestamps() {
+    let mut conv = Conversation::new();
+    conv.add_user_message("hello", None);
+    let stamp = conv.messages[0].created_at.unwrap().format("%Y-%m-%d %H:%M UTC").to_string();
+
+    let md = export::conversation_to_markdown(&conv);
+    assert!(md.contains("**Created:**"));
+    assert!(md.contains(&format!("**You** · _{stamp}_")));
+
+    let html = export::conversation_to_html(&conv, ThemeName::default());
+    assert!(html.contains(&stamp));
+
+    let parsed: serde_json::Value = serde_json::from_str(&export::conversation_to_json(&conv)).unwrap();
+    assert!(parsed["created_at"].is_string());
+    assert!(parsed["messages"][0]["created_at"].is_string());
+}
+
+#[test]
+fn json_without_timestamps_still_deserializes() {
+    let json = r#"{"id":"c1","title":"Old","messages":[{"role":"User","content":"hi"}]}"#;
+    let conv: Conversation = serde_json::from_str(json).unwrap();
+    assert!(conv.messages[0].created_at.is_none());
+    assert!(conv.messages[0].completed_at.is_none());
+}
+
+// ── Schema Migration Tests ───────────────────────────────────
+
+/// Build a database file from a fixture in `tests/fixtures`, as an older build
+/// of Stoa would have left it.
+fn fixture_db(name: &str) -> (tempfile::TempDir, std::path::PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chat.db");
+    let sql = std::fs::read_to_string(format!("{}/tests/fixtures/{name}.sql", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    rusqlite::Connection::open(&path).unwrap().execute_batch(&sql).unwrap();
+    (dir, path)
+}
+
+fn backups_in(dir: &std::path::Path) -> usize {
+    std::fs::read_dir(dir).unwrap()
+        .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().ends_with(".bak"))
+        .count()
+}
+
+fn assert_upgraded(name: &str) -> Conversation {
+    let (dir, path) = fixture_db(name);
+    let conn = stoa::db::open_at(&path).unwrap();
+    assert_eq!(stoa::db::schema_version(&conn), stoa::db::SCHEMA_VERSION);
+    assert_eq!(backups_in(dir.path()), 1, "{name}: expected a pre-migration backup");
+
+    let loaded = stoa::db::load_all(&conn);
+    assert_eq!(loaded.len(), 1, "{name}");
+    let conv = loaded[0].clone();
+    assert_eq!(conv.title, "Photosynthesis");
+    let contents: Vec<&str> = conv.messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, vec!["How do plants make food?", "Through photosynthesis in chloroplasts."], "{name}");
+    assert!(conv.messages.iter().all(|m| !m.id.is_empty()));
+    assert_eq!(conv.messages[1].parent_id.as_deref(), Some(conv.messages[0].id.as_str()));
+    assert_eq!(stoa::db::search_conversations(&conn, "chloroplasts"), vec!["conv-1".to_string()], "{name}");
+
+    // Upgraded databases keep working with the current write path
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+    assert_eq!(stoa::db::load_all(&conn)[0].messages.len(), 2);
+    conv
+}
+
+#[test]
+fn migrate_fresh_database_to_latest_without_backup() {
+    let dir = tempfile::tempdir().unwrap();
+    let conn = stoa::db::open_at(&dir.path().join("chat.db")).unwrap();
+    assert_eq!(stoa::db::schema_version(&conn), stoa::db::SCHEMA_VERSION);
+    assert_eq!(backups_in(dir.path()), 0);
+    assert_eq!(stoa::db::schema_version(&stoa::db::open_in_memory()), stoa::db::SCHEMA_VERSION);
+}
+
+#[test]
+fn migrate_from_initial_schema() {
+    let conv = assert_upgraded("schema_initial");
+    assert!(conv.tags.is_empty());
+    assert!(conv.messages[1].model.is_none());
+}
+
+#[test]
+fn migrate_from_tags_schema() {
+    let conv = assert_upgraded("schema_tags");
+    assert_eq!(conv.tags, vec!["biology", "plants"]);
+    assert!(conv.pinned);
+    assert_eq!(conv.messages[1].model.as_deref(), Some("gpt-4.1"));
+}
+
+#[test]
+fn migrate_from_search_schema() {
+    let conv = assert_upgraded("schema_search");
+    assert_eq!(conv.folder.as_deref(), Some("science"));
+    assert_eq!(conv.system_prompt, "Be brief.");
+    assert_eq!(conv.messages[1].rating, 1);
+    assert_eq!(conv.messages[1].latency_ms, Some(340));
+    let created = conv.created_at.format("%Y-%m-%d %H:%M:%S").to_string();
+    assert_eq!(created, "2025-01-05 09:30:00", "created_at is backfilled from updated_at");
+}
+
+#[test]
+fn migrate_from_message_tree_schema() {
+    let conv = assert_upgraded("schema_message_tree");
+    assert_eq!(conv.thread_mode, ThreadMode::Isolated);
+    assert_eq!(conv.messages[1].id, "0194330a-0000-7000-8000-000000000003");
+    assert_eq!(conv.alternates.len(), 1);
+    assert_eq!(conv.version_info(1), (2, 2));
+    assert!(conv.messages[1].created_at.is_none());
+}
+
+#[test]
+fn migrate_from_timestamps_schema() {
+    let conv = assert_upgraded("schema_timestamps");
+    assert_eq!(conv.created_at.format("%Y-%m-%d %H:%M").to_string(), "2025-01-04 18:00");
+    assert_eq!(conv.messages[1].completed_at.unwrap().format("%H:%M:%S").to_string(), "18:00:09");
+}
+
+#[test]
+fn migrate_is_a_no_op_when_current() {
+    let (dir, path) = fixture_db("schema_search");
+    drop(stoa::db::open_at(&path).unwrap());
+    let conn = stoa::db::open_at(&path).unwrap();
+    assert_eq!(backups_in(dir.path()), 1);
+    assert_eq!(stoa::db::load_all(&conn)[0].messages.len(), 2);
+}
+
+#[test]
+fn migrate_failure_rolls_back_and_reports() {
+    let (dir, path) = fixture_db("schema_message_tree");
+    // Duplicate IDs make the unique index impossible to (re)build
+    {
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "DROP INDEX idx_messages_uuid;
+             UPDATE messages SET uuid = '0194330a-0000-7000-8000-000000000001';"
+        ).unwrap();
+    }
+
+    let err = stoa::db::open_at(&path).expect_err("migration should fail");
+    assert!(err.contains("stable message IDs"), "{err}");
+    assert!(err.contains("schema version 3"), "{err}");
+    assert!(err.contains(".bak"), "{err}");
+    assert_eq!(backups_in(dir.path()), 1);
+
+    // Earlier migrations stay applied; the failed one left nothing behind
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    assert_eq!(stoa::db::schema_version(&conn), 3);
+    let uuid_columns: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info('messages') WHERE name = 'uuid'", [], |r| r.get(0),
+    ).unwrap();
+    assert_eq!(uuid_columns, 1);
+    let index_count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE name = 'idx_messages_uuid'", [], |r| r.get(0),
+    ).unwrap();
+    assert_eq!(index_count, 0);
+}
+
+#[test]
+fn migrate_refuses_newer_schema() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chat.db");
+    rusqlite::Connection::open(&path).unwrap().pragma_update(None, "user_version", 999).unwrap();
+    let err = stoa::db::open_at(&path).expect_err("newer schema should be refused");
+    assert!(err.contains("999"), "{err}");
+    assert_eq!(backups_in(dir.path()), 0);
+}
+
+// ── Encryption Tests ─────────────────────────────────────────
+
+fn saved_conversation_at(path: &std::path::Path, key: Option<&str>) -> Conversation {
+    let conn = stoa::db::open_encrypted_at(path, key).unwrap();
+    let conv = conversation_with_reply("Client notes", "Summarise the contract", "gpt-4o", "The renewal clause is confidential.");
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+    conv
+}
+
+#[test]
+fn encrypted_database_needs_its_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chat.db");
+    let conv = saved_conversation_at(&path, Some("correct horse"));
+    assert!(stoa::db::is_encrypted(&path));
+    let raw = std::fs::read(&path).unwrap();
+    assert!(!raw.windows(12).any(|w| w == b"confidential"));
+
+    let err = stoa::db::open_at(&path).expect_err("no key");
+    assert!(err.contains("encrypted"), "{err}");
+    let err = stoa::db::open_encrypted_at(&path, Some("wrong")).expect_err("wrong key");
+    assert!(err.contains("Wrong key"), "{err}");
+
+    let conn = stoa::db::open_encrypted_at(&path, Some("correct horse")).unwrap();
+    assert_eq!(stoa::db::load_all(&conn)[0].messages[1].content, conv.messages[1].content);
+    assert_eq!(stoa::db::search_conversations(&conn, "renewal"), vec![conv.id]);
+}
+
+#[test]
+fn rekey_file_encrypts_and_decrypts_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chat.db");
+    let conv = saved_conversation_at(&path, None);
+    assert!(!stoa::db::is_encrypted(&path));
+    assert!(!stoa::db::is_encrypted(&dir.path().join("missing.db")));
+
+    stoa::db::rekey_file(&path, None, Some("first")).unwrap();
+    assert!(stoa::db::is_encrypted(&path));
+    {
+        let conn = stoa::db::open_encrypted_at(&path, Some("first")).unwrap();
+        assert_eq!(stoa::db::schema_version(&conn), stoa::db::SCHEMA_VERSION);
+        // Search indexes and triggers survive the rewrite
+        assert_eq!(stoa::db::search_messages(&conn, "renewal", 10).len(), 1);
+        let mut other = conversation_with_reply("Later", "Add a note", "gpt-4o", "Noted the indemnity cap.");
+        stoa::db::save_conversation(&conn, &other).unwrap();
+        other.messages[1].content = "Noted the liability cap.".to_string();
+        stoa::db::save_message(&conn, &other, 1).unwrap();
+        assert_eq!(stoa::db::search_messages(&conn, "liability", 10).len(), 1);
+    }
+
+    stoa::db::rekey_file(&path, Some("first"), Some("second")).unwrap();
+    stoa::db::open_encrypted_at(&path, Some("first")).expect_err("old key");
+    stoa::db::rekey_file(&path, Some("second"), None).unwrap();
+    assert!(!stoa::db::is_encrypted(&path));
+    let conn = stoa::db::open_at(&path).unwrap();
+    let loaded = stoa::db::load_all(&conn);
+    assert_eq!(loaded.len(), 2);
+    assert!(loaded.iter().any(|c| c.id == conv.id));
+
+    // A wrong old key leaves the file untouched
+    drop(conn);
+    stoa::db::rekey_file(&path, Some("nope"), Some("x")).expect_err("wrong key");
+    assert!(!stoa::db::is_encrypted(&path));
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().ends_with(".rekey")).count(), 0);
+}
+
+#[test]
+fn export_to_keeps_or_drops_encryption() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chat.db");
+    let conv = saved_conversation_at(&path, Some("secret"));
+    let conn = stoa::db::open_encrypted_at(&path, Some("secret")).unwrap();
+
+    let backup = dir.path().join("backup.db");
+    stoa::db::export_to(&conn, &backup, Some("secret")).unwrap();
+    assert!(stoa::db::is_encrypted(&backup));
+    // Exporting again replaces the earlier copy
+    stoa::db::export_to(&conn, &backup, Some("secret")).unwrap();
+    let copy = stoa::db::open_encrypted_at(&backup, Some("secret")).unwrap();
+    assert_eq!(stoa::db::schema_version(&copy), stoa::db::SCHEMA_VERSION);
+    assert_eq!(stoa::db::load_all(&copy)[0].id, conv.id);
+
+    let plain = dir.path().join("plain.db");
+    stoa::db::export_to(&conn, &plain, None).unwrap();
+    assert!(!stoa::db::is_encrypted(&plain));
+    assert_eq!(stoa::db::load_all(&stoa::db::open_at(&plain).unwrap()).len(), 1);
+}
+
+#[test]
+fn migration_backup_of_encrypted_database_stays_encrypted() {
+    let (dir, path) = fixture_db("schema_initial");
+    let encrypted = dir.path().join("encrypted.db");
+    {
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute("ATTACH DATABASE ?1 AS enc KEY 'pw'", [encrypted.to_string_lossy()]).unwrap();
+        conn.query_row("SELECT sqlcipher_export('enc')", [], |_| Ok(())).unwrap();
+        conn.execute_batch("DETACH DATABASE enc;").unwrap();
+    }
+    std::fs::remove_file(&path).unwrap();
+    std::fs::rename(&encrypted, &path).unwrap();
+
+    let conn = stoa::db::open_encrypted_at(&path, Some("pw")).unwrap();
+    assert_eq!(stoa::db::schema_version(&conn), stoa::db::SCHEMA_VERSION);
+    let backup = std::fs::read_dir(dir.path()).unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.to_string_lossy().ends_with(".bak"))
+        .expect("pre-migration backup");
+    assert!(stoa::db::is_encrypted(&backup));
+    assert_eq!(stoa::db::load_all(&stoa::db::open_encrypted_at(&backup, Some("pw")).unwrap())[0].title, "Photosynthesis");
+}
+
+#[test]
+fn app_backup_and_encryption_need_a_database_file() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    let _ = app.update(stoa::app::Message::SetDatabaseEncryption(stoa::config::EncryptionKeySource::Passphrase));
+    assert!(app.error_message.as_deref().unwrap().contains("temporary"));
+    assert_eq!(app.config.encryption, stoa::config::EncryptionKeySource::None);
+
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("backup.db");
+    app.error_message = None;
+    let _ = app.update(stoa::app::Message::BackupDatabaseTo(dest.clone()));
+    assert!(app.error_message.is_none());
+    assert_eq!(stoa::db::load_all(&stoa::db::open_at(&dest).unwrap()).len(), app.conversations.len());
+}
+
+// ── Backup Tests ─────────────────────────────────────────────
+
+fn local_time(s: &str) -> chrono::DateTime<chrono::Local> {
+    use chrono::TimeZone;
+    chrono::Local.from_local_datetime(&chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()).unwrap()
+}
+
+#[test]
+fn backup_snapshots_are_listed_newest_first_and_rotated() {
+    use stoa::backup::{is_due, list_snapshots, rotate, snapshot_file_name, take_snapshot};
+    let dir = tempfile::tempdir().unwrap();
+    assert_eq!(snapshot_file_name(local_time("2026-03-04 05:06:07")), "stoa-20260304-050607.db");
+    assert!(list_snapshots(&dir.path().join("missing")).is_empty());
+
+    let conn = stoa::db::open_in_memory();
+    for day in ["01", "03", "02"] {
+        take_snapshot(&conn, dir.path(), None, 10, local_time(&format!("2026-01-{day} 12:00:00"))).unwrap();
+    }
+    std::fs::write(dir.path().join("notes.txt"), "not a snapshot").unwrap();
+    let snapshots = list_snapshots(dir.path());
+    let days: Vec<String> = snapshots.iter().map(|s| s.created_at.format("%d").to_string()).collect();
+    assert_eq!(days, vec!["03", "02", "01"]);
+    assert!(snapshots.iter().all(|s| s.size > 0));
+
+    assert!(!is_due(snapshots.first(), chrono::Duration::hours(24), local_time("2026-01-04 11:00:00")));
+    assert!(is_due(snapshots.first(), chrono::Duration::hours(24), local_time("2026-01-04 12:00:00")));
+    assert!(is_due(None, chrono::Duration::hours(24), local_time("2026-01-04 12:00:00")));
+
+    let removed = rotate(dir.path(), 2).unwrap();
+    assert_eq!(removed, vec![snapshots[2].path.clone()]);
+    // A snapshot beyond the limit is rotated out as soon as it's taken
+    take_snapshot(&conn, dir.path(), None, 2, local_time("2026-01-05 12:00:00")).unwrap();
+    let days: Vec<String> = list_snapshots(dir.path()).iter().map(|s| s.created_at.format("%d").to_string()).collect();
+    assert_eq!(days, vec!["05", "03"]);
+    assert!(dir.path().join("notes.txt").exists());
+}
+
+#[test]
+fn backup_preview_reads_conversations_and_integrity() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chat.db");
+    let conv = saved_conversation_at(&path, Some("pw"));
+    let conn = stoa::db::open_encrypted_at(&path, Some("pw")).unwrap();
+    assert_eq!(stoa::db::integrity_check(&conn).unwrap(), Vec::<String>::new());
+
+    let backups = dir.path().join("backups");
+    let snapshot_path = stoa::backup::take_snapshot(&conn, &backups, Some("pw"), 7, chrono::Local::now()).unwrap();
+    assert!(stoa::db::is_encrypted(&snapshot_path));
+    let snapshot = stoa::backup::list_snapshots(&backups).remove(0);
+    assert_eq!(snapshot.path, snapshot_path);
+
+    stoa::backup::preview(&snapshot, None).expect_err("encrypted snapshot needs the key");
+    let preview = stoa::backup::preview(&snapshot, Some("pw")).unwrap();
+    assert_eq!(preview.conversations.len(), 1);
+    assert_eq!(preview.conversations[0].id, conv.id);
+    assert_eq!(preview.message_count, 2);
+    assert!(preview.problems.is_empty());
+
+    // Plain snapshots from before encryption was turned on still preview
+    let plain = stoa::db::open_in_memory();
+    stoa::db::save_conversation(&plain, &conv).unwrap();
+    let plain_path = stoa::backup::take_snapshot(&plain, &backups, None, 7, local_time("2020-01-01 00:00:00")).unwrap();
+    let plain_snapshot = stoa::backup::list_snapshots(&backups).into_iter().find(|s| s.path == plain_path).unwrap();
+    assert_eq!(stoa::backup::preview(&plain_snapshot, Some("pw")).unwrap().conversations.len(), 1);
+}
+
+#[test]
+fn app_restores_snapshot_after_backing_up_current_library() {
+    use stoa::app::Message;
+    let dir = tempfile::tempdir().unwrap();
+    let backups = dir.path().join("backups");
+    let old = stoa::db::open_in_memory();
+    let restored = conversation_with_reply("From the snapshot", "Old question", "gpt-4o", "Old answer");
+    stoa::db::save_conversation(&old, &restored).unwrap();
+    let snapshot = stoa::backup::take_snapshot(&old, &backups, None, 7, local_time("2025-06-01 09:00:00")).unwrap();
+
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    app.config.backup.directory = backups.display().to_string();
+    let _ = app.update(Message::BackupNow);
+    assert_eq!(stoa::backup::list_snapshots(&backups).len(), 1, "temporary databases are not snapshotted");
+
+    app.db_path = Some(dir.path().join("chat.db"));
+    let _ = app.update(Message::ShowSettings);
+    assert_eq!(app.snapshots.len(), 1);
+    let _ = app.update(Message::PreviewSnapshot(snapshot.clone()));
+    let preview = app.snapshot_preview.as_ref().unwrap();
+    assert_eq!(preview.conversations[0].title, "From the snapshot");
+
+    let _ = app.update(Message::RestoreSnapshot(snapshot));
+    assert!(app.error_message.is_none(), "{:?}", app.error_message);
+    assert!(app.snapshot_preview.is_none());
+    let titles: Vec<&str> = app.conversations.iter().map(|c| c.title.as_str()).collect();
+    assert_eq!(titles, vec!["From the snapshot"]);
+    assert_eq!(app.conversations[0].messages.len(), 2);
+    // The library as it was before the restore was kept
+    assert_eq!(app.snapshots.len(), 2);
+
+    let _ = app.update(Message::RunDiagnostics);
+    assert_eq!(app.integrity_report, Some(Ok(Vec::new())));
+}
+
+#[test]
+fn app_scheduled_snapshot_is_taken_off_the_ui_thread() {
+    use stoa::app::Message;
+    let dir = tempfile::tempdir().unwrap();
+    let backups = dir.path().join("backups");
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    app.db_path = Some(dir.path().join("chat.db"));
+    app.config.backup.enabled = true;
+    app.config.backup.directory = backups.display().to_string();
+
+    // The tick only starts the copy, and a second one doesn't start another
+    assert_eq!(app.update(Message::BackupTick).units(), 1);
+    assert!(app.snapshot_in_progress);
+    assert!(stoa::backup::list_snapshots(&backups).is_empty());
+    assert_eq!(app.update(Message::BackupTick).units(), 0);
+    assert_eq!(app.update(Message::BackupNow).units(), 0);
+
+    let _ = app.update(Message::SnapshotTaken(Err("Disk full".into())));
+    assert!(!app.snapshot_in_progress);
+    assert_eq!(app.error_message.as_deref(), Some("Disk full"));
+}
+
+#[test]
+fn config_backup_settings_fall_back_on_bad_input() {
+    let mut config = AppConfig::default();
+    assert!(config.backup.enabled);
+    assert_eq!(config.backup.dir(), stoa::backup::default_dir());
+    assert_eq!(config.backup.interval(), chrono::Duration::hours(24));
+    config.backup.interval_hours = "6".into();
+    config.backup.keep = "0".into();
+    config.backup.directory = " /tmp/stoa-backups ".into();
+    assert_eq!(config.backup.interval(), chrono::Duration::hours(6));
+    assert_eq!(config.backup.keep_count(), 7);
+    assert_eq!(config.backup.dir(), std::path::PathBuf::from("/tmp/stoa-backups"));
+}
+
+// ── Trash Tests ──────────────────────────────────────────────
+
+#[test]
+fn trashed_conversation_restores_with_messages() {
+    let conn = stoa::db::open_in_memory();
+    let conv = conversation_with_reply("Tides", "Why are there two tides a day?", "gpt-4o", "The Moon's pull on both sides.");
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+
+    let trash_id = stoa::db::trash_conversation(&conn, &conv).unwrap();
+    assert!(stoa::db::load_summaries(&conn).is_empty());
+    assert!(stoa::db::search_messages(&conn, "tides", 10).is_empty());
+    let trash = stoa::db::list_trash(&conn);
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].kind, stoa::db::TrashKind::Conversation);
+    assert_eq!(trash[0].label, "Tides");
+
+    assert_eq!(stoa::db::restore_from_trash(&conn, trash_id).unwrap(), conv.id);
+    let mut restored = stoa::db::load_summaries(&conn).remove(0);
+    stoa::db::load_messages(&conn, &mut restored).unwrap();
+    assert_eq!(restored.messages.len(), 2);
+    assert_eq!(restored.messages[1].content, "The Moon's pull on both sides.");
+    assert!(stoa::db::list_trash(&conn).is_empty());
+}
+
+#[test]
+fn trashed_message_restores_and_reattaches_replies() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = conversation_with_reply("Chain", "first", "gpt-4o", "second");
+    conv.add_user_message("third", None);
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+
+    let middle = conv.messages[1].clone();
+    let trash_id = stoa::db::trash_message(&conn, &conv.id, &middle, true).unwrap();
+    let mut loaded = conv.clone();
+    loaded.unload();
+    stoa::db::load_messages(&conn, &mut loaded).unwrap();
+    let contents: Vec<_> = loaded.messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, ["first", "third"]);
+
+    stoa::db::restore_from_trash(&conn, trash_id).unwrap();
+    loaded.unload();
+    stoa::db::load_messages(&conn, &mut loaded).unwrap();
+    let contents: Vec<_> = loaded.messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, ["first", "second", "third"]);
+}
+
+#[test]
+fn trashed_message_needs_its_conversation_to_restore() {
+    let conn = stoa::db::open_in_memory();
+    let conv = conversation_with_reply("Gone", "q", "gpt-4o", "a");
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+    let trash_id = stoa::db::trash_message(&conn, &conv.id, &conv.messages[1], true).unwrap();
+    stoa::db::delete_conversation(&conn, &conv.id).unwrap();
+
+    let err = stoa::db::restore_from_trash(&conn, trash_id).unwrap_err();
+    assert!(err.contains("restore the conversation first"), "{err}");
+    assert_eq!(stoa::db::list_trash(&conn).len(), 1);
+}
+
+#[test]
+fn purge_trash_removes_only_entries_before_cutoff() {
+    let conn = stoa::db::open_in_memory();
+    let old = conversation_with_reply("Old", "q", "gpt-4o", "a");
+    let new = conversation_with_reply("New", "q", "gpt-4o", "a");
+    stoa::db::save_conversation(&conn, &old).unwrap();
+    stoa::db::save_conversation(&conn, &new).unwrap();
+    stoa::db::trash_conversation(&conn, &old).unwrap();
+    let cutoff = chrono::Utc::now();
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    stoa::db::trash_conversation(&conn, &new).unwrap();
+
+    assert_eq!(stoa::db::purge_trash(&conn, Some(cutoff)).unwrap(), 1);
+    let trash = stoa::db::list_trash(&conn);
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].label, "New");
+    assert_eq!(stoa::db::purge_trash(&conn, None).unwrap(), 1);
+    assert!(stoa::db::list_trash(&conn).is_empty());
+}
+
+#[test]
+fn app_undo_restores_deleted_conversation() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    let _ = app.update(stoa::app::Message::NewConversation);
+    app.conversations[1].title = "Keep me".to_string();
+    let _ = app.update(stoa::app::Message::DeleteConversation(1));
+    assert_eq!(app.conversations.len(), 1);
+    assert!(app.undo.as_ref().is_some_and(|(_, label)| label.contains("Keep me")));
+
+    let _ = app.update(stoa::app::Message::UndoDelete);
+    assert!(app.undo.is_none());
+    assert_eq!(app.conversations.len(), 2);
+    assert_eq!(app.conversations[app.active_conversation].title, "Keep me");
+}
+
+#[test]
+fn app_undo_expires() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    app.conversations[0].add_user_message("doomed", None);
+    let _ = app.update(stoa::app::Message::DeleteMessage(0));
+    let (id, _) = app.undo.clone().unwrap();
+    let _ = app.update(stoa::app::Message::ExpireUndo(id + 1));
+    assert!(app.undo.is_some(), "a stale timer leaves a newer toast alone");
+    let _ = app.update(stoa::app::Message::ExpireUndo(id));
+    assert!(app.undo.is_none());
+}
+
+#[test]
+fn app_delete_conversation_with_forks_asks_first() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    app.conversations[0].add_user_message("root", None);
+    let _ = app.update(stoa::app::Message::NewConversation);
+    let fork = app.conversations[0].fork(0);
+    app.conversations.push(fork);
+    assert_eq!(app.forks_of(0), vec![2]);
+
+    let _ = app.update(stoa::app::Message::DeleteConversation(0));
+    assert_eq!(app.pending_delete, Some(0));
+    assert_eq!(app.conversations.len(), 3);
+    let _ = app.update(stoa::app::Message::CancelDeleteConversation);
+    assert_eq!(app.pending_delete, None);
+
+    let _ = app.update(stoa::app::Message::DeleteConversation(0));
+    let _ = app.update(stoa::app::Message::DeleteConversation(0));
+    assert_eq!(app.pending_delete, None);
+    assert_eq!(app.conversations.len(), 2);
+}
+
+#[test]
+fn trash_retention_falls_back_on_bad_input() {
+    let mut config = AppConfig::default();
+    assert_eq!(config.trash_retention(), chrono::Duration::days(30));
+    config.trash_retention_days = "7".to_string();
+    assert_eq!(config.trash_retention(), chrono::Duration::days(7));
+    config.trash_retention_days = "soon".to_string();
+    assert_eq!(config.trash_retention(), chrono::Duration::days(30));
+}
+
+// ── Workspace Tests ──────────────────────────────────────────
+
+#[test]
+fn default_workspace_lives_in_the_data_root() {
+    let root = std::path::Path::new("/data/stoa");
+    assert_eq!(stoa::workspace::dir_in(root, "Default"), root);
+    assert_eq!(stoa::workspace::dir_in(root, "Client A"), root.join("workspaces").join("Client A"));
+}
+
+#[test]
+fn workspaces_are_listed_default_first() {
+    let root = tempfile::tempdir().unwrap();
+    assert_eq!(stoa::workspace::list(root.path()), ["Default"]);
+    stoa::workspace::create(root.path(), "personal").unwrap();
+    stoa::workspace::create(root.path(), "  Client A ").unwrap();
+    assert_eq!(stoa::workspace::list(root.path()), ["Default", "Client A", "personal"]);
+    assert!(root.path().join("workspaces").join("Client A").is_dir());
+}
+
+#[test]
+fn workspace_names_are_validated() {
+    let root = tempfile::tempdir().unwrap();
+    stoa::workspace::create(root.path(), "Lab").unwrap();
+    assert!(stoa::workspace::create(root.path(), "lab").unwrap_err().contains("already exists"));
+    assert!(stoa::workspace::create(root.path(), "default").unwrap_err().contains("reserved"));
+    assert!(stoa::workspace::create(root.path(), "   ").is_err());
+    assert!(stoa::workspace::create(root.path(), "../escape").is_err());
+    assert!(stoa::workspace::create(root.path(), ".hidden").is_err());
+    assert_eq!(stoa::workspace::list(root.path()), ["Default", "Lab"]);
+}
+
+#[test]
+fn last_used_workspace_must_still_exist() {
+    let root = tempfile::tempdir().unwrap();
+    assert_eq!(stoa::workspace::last_used(root.path()), None);
+    stoa::workspace::create(root.path(), "Lab").unwrap();
+    stoa::workspace::remember(root.path(), "Lab");
+    assert_eq!(stoa::workspace::last_used(root.path()).as_deref(), Some("Lab"));
+    stoa::workspace::remember(root.path(), "Removed");
+    assert_eq!(stoa::workspace::last_used(root.path()), None);
+}
+
+#[test]
+fn workspace_flag_is_parsed_from_args() {
+    let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(stoa::workspace::from_args(args(&["--workspace", "Client A"])).as_deref(), Some("Client A"));
+    assert_eq!(stoa::workspace::from_args(args(&["-v", "--workspace=Lab"])).as_deref(), Some("Lab"));
+    assert_eq!(stoa::workspace::from_args(args(&["--workspace"])), None);
+    assert_eq!(stoa::workspace::from_args(args(&[])), None);
+}
+
+#[test]
+fn new_workspace_config_starts_clean() {
+    let mut config = AppConfig::default();
+    config.system_prompt = "You are a tax advisor.".to_string();
+    config.encryption = stoa::config::EncryptionKeySource::Keychain;
+    config.backup.directory = "/mnt/backups".to_string();
+    config.selected_model = Some("gpt-4o".to_string());
+    config.credential_profile = "client".to_string();
+
+    let fresh = config.for_new_workspace();
+    assert!(fresh.system_prompt.is_empty());
+    assert_eq!(fresh.encryption, stoa::config::EncryptionKeySource::None);
+    assert!(fresh.backup.directory.is_empty());
+    assert_eq!(fresh.selected_model.as_deref(), Some("gpt-4o"));
+    assert_eq!(fresh.credential_profile, "client");
+}
+
+#[test]
+fn app_credential_profile_applies_on_submit_not_per_keystroke() {
+    use stoa::app::Message;
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    app.config.openai.api_key = "sk-typed-in-the-form".to_string();
+    for typed in ["c", "cl", "client"] {
+        let _ = app.update(Message::SetCredentialProfile(typed.into()));
+    }
+    // Half-typed names don't switch profiles or wipe the keys
+    assert_eq!(app.config.credential_profile, "");
+    assert_eq!(app.config.openai.api_key, "sk-typed-in-the-form");
+
+    let _ = app.update(Message::ApplyCredentialProfile);
+    assert_eq!(app.config.credential_profile, "client");
+    assert_eq!(app.credential_profile_input, "client");
+}
+
+#[test]
+fn command_palette_lists_other_workspaces() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    app.workspaces = vec!["Default".to_string(), "Client A".to_string()];
+    assert_eq!(app.other_workspaces(), ["Client A"]);
+
+    let commands = stoa::commands::filtered_commands("client", &app.config.keybindings, &app.other_workspaces());
+    assert_eq!(commands.len(), 1);
+    assert_eq!(commands[0].label, "Workspace: Client A");
+    assert!(matches!(&commands[0].message, stoa::app::Message::SwitchWorkspace(name) if name == "Client A"));
+}
+
+// ── Sync Tests ───────────────────────────────────────────────
+
+/// Two libraries that both hold the same conversation after a sync through `dir`.
+fn synced_pair(dir: &std::path::Path) -> (rusqlite::Connection, rusqlite::Connection, Conversation) {
+    let a = stoa::db::open_in_memory();
+    let b = stoa::db::open_in_memory();
+    let conv = conversation_with_reply("Tides", "Why are there two tides a day?", "gpt-4o", "The Moon's pull on both sides.");
+    stoa::db::save_conversation(&a, &conv).unwrap();
+    stoa::sync::run(&a, dir).unwrap();
+    stoa::sync::run(&b, dir).unwrap();
+    (a, b, conv)
+}
+
+fn load_only(conn: &rusqlite::Connection, title: &str) -> Conversation {
+    let mut conv = stoa::db::load_summaries(conn).into_iter().find(|c| c.title == title).unwrap();
+    stoa::db::load_messages(conn, &mut conv).unwrap();
+    conv
+}
+
+/// Sync clocks have millisecond resolution.
+fn tick() {
+    std::thread::sleep(std::time::Duration::from_millis(5));
+}
+
+#[test]
+fn sync_clocks_are_stamped_and_deletions_recorded() {
+    let conn = stoa::db::open_in_memory();
+    let conv = conversation_with_reply("Tides", "q", "gpt-4o", "a");
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+    let created = stoa::db::sync_clocks(&conn, stoa::db::SyncEntity::Conversation, &conv.id);
+    assert!(created.contains_key("title") && created.contains_key("tags"));
+
+    tick();
+    stoa::db::rename_conversation(&conn, &conv.id, "Tides explained").unwrap();
+    let renamed = stoa::db::sync_clocks(&conn, stoa::db::SyncEntity::Conversation, &conv.id);
+    assert!(renamed["title"] > created["title"]);
+    assert_eq!(renamed["tags"], created["tags"]);
+
+    stoa::db::delete_conversation(&conn, &conv.id).unwrap();
+    let deletions = stoa::db::deletions_since(&conn, None);
+    assert_eq!(deletions.len(), 1);
+    assert_eq!(deletions[0].0, stoa::db::SyncEntity::Conversation);
+    assert_eq!(deletions[0].1, conv.id);
+}
+
+#[test]
+fn sync_copies_conversations_to_another_library() {
+    let dir = tempfile::tempdir().unwrap();
+    let (_a, b, conv) = synced_pair(dir.path());
+
+    let copy = load_only(&b, "Tides");
+    assert_eq!(copy.id, conv.id);
+    assert_eq!(copy.messages.len(), 2);
+    assert_eq!(copy.messages[1].content, "The Moon's pull on both sides.");
+    assert_eq!(copy.messages[1].model.as_deref(), Some("gpt-4o"));
+
+    // Nothing new on either side: no files merged, nothing written
+    let again = stoa::sync::run(&b, dir.path()).unwrap();
+    assert_eq!(again.merged_files, 0);
+    assert!(again.written.is_none());
+    assert!(!again.changed());
+}
+
+#[test]
+fn sync_merges_fields_last_writer_wins() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b, conv) = synced_pair(dir.path());
+
+    stoa::db::rename_conversation(&a, &conv.id, "Tides explained").unwrap();
+    tick();
+    stoa::db::set_tags(&b, &conv.id, &["science".to_string()]).unwrap();
+    tick();
+    stoa::db::rename_conversation(&b, &conv.id, "Why two tides").unwrap();
+
+    stoa::sync::run(&a, dir.path()).unwrap();
+    stoa::sync::run(&b, dir.path()).unwrap();
+    stoa::sync::run(&a, dir.path()).unwrap();
+
+    for conn in [&a, &b] {
+        let merged = stoa::db::load_summaries(conn).remove(0);
+        assert_eq!(merged.title, "Why two tides");
+        assert_eq!(merged.tags, vec!["science".to_string()]);
+    }
+}
+
+#[test]
+fn sync_concurrent_replies_become_versions() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b, _) = synced_pair(dir.path());
+
+    let mut on_a = load_only(&a, "Tides");
+    on_a.add_user_message("Asked on the laptop", None);
+    stoa::db::save_conversation(&a, &on_a).unwrap();
+    let mut on_b = load_only(&b, "Tides");
+    on_b.add_user_message("Asked on the desktop", None);
+    stoa::db::save_conversation(&b, &on_b).unwrap();
+
+    stoa::sync::run(&a, dir.path()).unwrap();
+    stoa::sync::run(&b, dir.path()).unwrap();
+    stoa::sync::run(&a, dir.path()).unwrap();
+
+    let merged_a = load_only(&a, "Tides");
+    assert_eq!(merged_a.messages.len(), 3);
+    assert_eq!(merged_a.messages[2].content, "Asked on the laptop");
+    assert_eq!(merged_a.alternates.len(), 1);
+    assert_eq!(merged_a.alternates[0].content, "Asked on the desktop");
+
+    let merged_b = load_only(&b, "Tides");
+    assert_eq!(merged_b.messages[2].content, "Asked on the desktop");
+    assert_eq!(merged_b.alternates[0].content, "Asked on the laptop");
+}
+
+#[test]
+fn sync_reply_from_a_slow_clock_stays_after_its_parent() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b, _) = synced_pair(dir.path());
+
+    let mut on_b = load_only(&b, "Tides");
+    on_b.add_user_message("And spring tides?", None);
+    let idx = on_b.push_streaming_assistant(Some("gpt-4o".into()));
+    on_b.finalize_at(idx, "When Sun and Moon line up.");
+    // Written on a machine whose clock is behind: the reply's ID sorts first
+    on_b.messages[idx].id = "00000000-0000-7000-8000-000000000000".to_string();
+    stoa::db::save_conversation(&b, &on_b).unwrap();
+    assert!(on_b.messages[idx].id < on_b.messages[idx - 1].id);
+
+    stoa::sync::run(&b, dir.path()).unwrap();
+    stoa::sync::run(&a, dir.path()).unwrap();
+
+    for conn in [&a, &b] {
+        let merged = load_only(conn, "Tides");
+        let contents: Vec<&str> = merged.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["Why are there two tides a day?", "The Moon's pull on both sides.", "And spring tides?", "When Sun and Moon line up."]);
+        assert!(merged.alternates.is_empty());
+    }
+}
+
+#[test]
+fn sync_conflicting_edit_is_kept_as_fork_on_losing_side() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b, conv) = synced_pair(dir.path());
+
+    let mut reply = conv.messages[1].clone();
+    reply.content = "Edited on the laptop".to_string();
+    stoa::db::upsert_message(&a, &conv.id, &reply, true).unwrap();
+    tick();
+    reply.content = "Edited on the desktop".to_string();
+    stoa::db::upsert_message(&b, &conv.id, &reply, true).unwrap();
+
+    let first = stoa::sync::run(&a, dir.path()).unwrap();
+    assert_eq!(first.conflicts, 0);
+    let second = stoa::sync::run(&b, dir.path()).unwrap();
+    assert_eq!(second.conflicts, 0, "the newer edit wins without a fork");
+    let third = stoa::sync::run(&a, dir.path()).unwrap();
+    assert_eq!(third.conflicts, 1);
+
+    assert_eq!(load_only(&a, "Tides").messages[1].content, "Edited on the desktop");
+    let fork = load_only(&a, "Tides (sync conflict)");
+    assert_eq!(fork.forked_from.as_deref(), Some(conv.id.as_str()));
+    assert_eq!(fork.messages.len(), 2);
+    assert_eq!(fork.messages[0].content, "Why are there two tides a day?");
+    assert_eq!(fork.messages[1].content, "Edited on the laptop");
+
+    // The fork reaches the other library like any new conversation
+    let last = stoa::sync::run(&b, dir.path()).unwrap();
+    assert_eq!(last.conflicts, 0);
+    assert_eq!(stoa::db::load_summaries(&b).len(), 2);
+}
+
+#[test]
+fn sync_deletion_moves_to_trash_on_other_library() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b, conv) = synced_pair(dir.path());
+
+    let mut loaded = load_only(&a, "Tides");
+    stoa::db::trash_conversation(&a, &loaded).unwrap();
+    stoa::sync::run(&a, dir.path()).unwrap();
+    let report = stoa::sync::run(&b, dir.path()).unwrap();
+
+    assert_eq!(report.deleted, 1);
+    assert!(stoa::db::load_summaries(&b).is_empty());
+    let trash = stoa::db::list_trash(&b);
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].label, "Tides");
+
+    // Restoring on one side brings it back on the other
+    stoa::db::restore_from_trash(&b, trash[0].id).unwrap();
+    stoa::sync::run(&b, dir.path()).unwrap();
+    stoa::sync::run(&a, dir.path()).unwrap();
+    loaded = load_only(&a, "Tides");
+    assert_eq!(loaded.id, conv.id);
+    assert_eq!(loaded.messages.len(), 2);
+}
+
+#[test]
+fn app_sync_runs_off_the_ui_thread() {
+    use stoa::app::Message;
+    let dir = tempfile::tempdir().unwrap();
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    app.db_path = Some(dir.path().join("chat.db"));
+    app.config.sync.folder = dir.path().join("sync").display().to_string();
+
+    // The tick only starts the exchange, and a second one doesn't start another
+    assert_eq!(app.update(Message::SyncNow).units(), 1);
+    assert!(app.sync_in_progress);
+    assert_eq!(app.update(Message::SyncNow).units(), 0);
+
+    let _ = app.update(Message::SyncFinished(Err("Sync folder missing".into())));
+    assert!(!app.sync_in_progress);
+    assert_eq!(app.sync_status.as_deref(), Some("Sync folder missing"));
+
+    let report = stoa::sync::SyncReport { merged_files: 1, ..Default::default() };
+    let _ = app.update(Message::SyncFinished(Ok(report)));
+    assert!(app.sync_status.as_deref().unwrap().ends_with("1 changeset(s) merged."));
+}
+
+// ── Vault Export Tests ───────────────────────────────────────
+
+#[test]
+fn vault_export_writes_front_matter_folders_and_assets() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.title = "Knots: a primer".into();
+    conv.tags = vec!["sailing".into(), "how to".into()];
+    conv.folder = Some("Hobbies/Boats".into());
+    conv.system_prompt = "Answer like a sailor.".into();
+    // PNG signature
+    conv.add_user_message_with_images("What is this knot?", None, vec!["iVBORw0KGgo=".into()]);
+    let idx = conv.push_streaming_assistant(Some("gpt-4o".into()));
+    conv.finalize_at(idx, "A bowline.");
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+    let mut fork = conv.fork(1);
+    fork.title = "Knots follow-up".into();
+    stoa::db::save_conversation(&conn, &fork).unwrap();
+    let empty = Conversation::new();
+    stoa::db::save_conversation(&conn, &empty).unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let report = stoa::vault::export(&conn, dir.path(), true).unwrap();
+    assert_eq!(report.written, 2);
+    // The fork's copy of the message has its own ID, so its image is saved again
+    assert_eq!(report.assets, 2);
+
+    let note = std::fs::read_to_string(dir.path().join("Hobbies/Boats/Knots a primer.md")).unwrap();
+    assert!(note.starts_with(&format!("---\nid: \"{}\"\ntitle: \"Knots: a primer\"\n", conv.id)));
+    assert!(note.contains("tags:\n  - \"sailing\"\n  - \"how-to\"\n"));
+    assert!(note.contains("folder: \"Hobbies/Boats\"\n"));
+    assert!(note.contains("models:\n  - \"gpt-4o\"\n"));
+    assert!(note.contains(&format!("created: {}\n", conv.created_at.to_rfc3339())));
+    assert!(note.contains("> [!note] System prompt\n> Answer like a sailor.\n"));
+    assert!(note.contains("**Assistant (gpt-4o)**"));
+    let asset = format!("{}-0.png", conv.messages[0].id);
+    assert!(note.contains(&format!("![[{asset}]]")));
+    assert_eq!(std::fs::read(dir.path().join("assets").join(&asset)).unwrap()[..4], [0x89, b'P', b'N', b'G']);
+
+    let fork_note = std::fs::read_to_string(dir.path().join("Hobbies/Boats/Knots follow-up.md")).unwrap();
+    assert!(fork_note.contains("forked_from: \"[[Knots a primer]]\"\n"));
+}
+
+#[test]
+fn vault_export_incremental_rewrites_only_changed_notes() {
+    let conn = stoa::db::open_in_memory();
+    let tides = conversation_with_reply("Tides", "Why two tides?", "gpt-4o", "The Moon.");
+    let knots = conversation_with_reply("Knots", "Best knot?", "gpt-4o", "Bowline.");
+    stoa::db::save_conversation(&conn, &tides).unwrap();
+    stoa::db::save_conversation(&conn, &knots).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("My own note.md"), "mine").unwrap();
+
+    assert_eq!(stoa::vault::export(&conn, dir.path(), true).unwrap().written, 2);
+    let again = stoa::vault::export(&conn, dir.path(), true).unwrap();
+    assert_eq!((again.written, again.unchanged), (0, 2));
+
+    stoa::db::rename_conversation(&conn, &tides.id, "Tidal forces").unwrap();
+    stoa::db::delete_conversation(&conn, &knots.id).unwrap();
+    let report = stoa::vault::export(&conn, dir.path(), true).unwrap();
+    assert_eq!((report.written, report.unchanged, report.removed), (1, 0, 2));
+    assert!(dir.path().join("Tidal forces.md").exists());
+    assert!(!dir.path().join("Tides.md").exists());
+    assert!(!dir.path().join("Knots.md").exists());
+    assert!(dir.path().join("My own note.md").exists());
+
+    // A full export rewrites every note
+    assert_eq!(stoa::vault::export(&conn, dir.path(), false).unwrap().written, 1);
+}
+
+#[test]
+fn vault_export_keeps_note_names_when_an_older_namesake_arrives() {
+    let conn = stoa::db::open_in_memory();
+    let tides = conversation_with_reply("Tides", "Why two tides?", "gpt-4o", "The Moon.");
+    stoa::db::save_conversation(&conn, &tides).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    stoa::vault::export(&conn, dir.path(), true).unwrap();
+
+    // Imported later but started earlier, it would take the plain title
+    let mut older = conversation_with_reply("Tides", "Spring tides?", "gpt-4o", "Sun and Moon align.");
+    older.created_at = tides.created_at - chrono::Duration::days(1);
+    stoa::db::save_conversation(&conn, &older).unwrap();
+    let report = stoa::vault::export(&conn, dir.path(), true).unwrap();
+    assert_eq!((report.written, report.unchanged, report.removed), (1, 1, 0));
+    assert!(dir.path().join("Tides.md").exists());
+    assert!(dir.path().join(format!("Tides ({}).md", &older.id[..8])).exists());
+    let note = std::fs::read_to_string(dir.path().join("Tides.md")).unwrap();
+    assert!(note.contains(&format!("id: \"{}\"", tides.id)));
+}
+
+#[test]
+fn vault_export_cleanup_stays_inside_the_vault() {
+    let conn = stoa::db::open_in_memory();
+    let tides = conversation_with_reply("Tides", "Why two tides?", "gpt-4o", "The Moon.");
+    stoa::db::save_conversation(&conn, &tides).unwrap();
+    let root = tempfile::tempdir().unwrap();
+    let vault = root.path().join("vault");
+    std::fs::create_dir_all(vault.join("assets")).unwrap();
+    let outside = root.path().join("outside.md");
+    for file in [&outside, &vault.join("Old.md"), &vault.join("settings.json"), &vault.join("assets/old.png")] {
+        std::fs::write(file, "x").unwrap();
+    }
+    // A manifest edited on another machine, or by hand
+    let manifest = serde_json::json!({
+        "notes": {
+            "a": outside.to_string_lossy(),
+            "b": "../outside.md",
+            "c": "assets/../../outside.md",
+            "d": "settings.json",
+            "e": "Old.md",
+        },
+        "assets": ["assets/old.png"],
+    });
+    std::fs::write(vault.join(".stoa-vault.json"), manifest.to_string()).unwrap();
+
+    let report = stoa::vault::export(&conn, &vault, true).unwrap();
+    assert_eq!(report.removed, 2);
+    assert!(outside.exists());
+    assert!(vault.join("settings.json").exists());
+    assert!(!vault.join("Old.md").exists());
+    assert!(!vault.join("assets/old.png").exists());
+}
+
+#[test]
+fn vault_note_names_are_unique_and_safe() {
+    let mut first = Conversation::new();
+    first.title = "Plan / draft?".into();
+    let mut second = Conversation::new();
+    second.title = "plan  draft".into();
+    second.created_at = first.created_at + chrono::Duration::seconds(1);
+    let mut untitled = Conversation::new();
+    untitled.title = "  ".into();
+    let names = stoa::vault::note_names(&[second.clone(), first.clone(), untitled.clone()]);
+    assert_eq!(names[&first.id], "Plan  draft");
+    assert_eq!(names[&second.id], format!("plan  draft ({})", &second.id[..8]));
+    assert_eq!(names[&untitled.id], "Untitled");
+}
+
+// ── Lazy Loading Tests ───────────────────────────────────────
+
+#[test]
+fn db_load_summaries_skips_messages_but_keeps_totals() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.tags = vec!["physics".to_string()];
+    conv.folder = Some("research".to_string());
+    multi_model_turn(&mut conv, "Explain entropy in detail", &[("gpt-4.1", "Entropy measures disorder."), ("o3", "It counts microstates.")]);
+    conv.branch_from(2);
+    answered(&mut conv, "A newer version");
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+
+    let summaries = stoa::db::load_summaries(&conn);
+    let summary = &summaries[0];
+    assert!(!summary.is_loaded());
+    assert!(summary.messages.is_empty() && summary.alternates.is_empty());
+    assert_eq!(summary.tags, conv.tags);
+    assert_eq!(summary.folder, conv.folder);
+
+    let expected = conv.stats();
+    let stats = summary.stats();
+    assert_eq!(stats.message_count, 3, "only the active path is counted");
+    assert_eq!(stats.message_count, expected.message_count);
+    assert!((stats.cost - expected.cost).abs() < 1e-12);
+    assert_eq!(stats.last_message_at, expected.last_message_at);
+    assert_eq!(summary.last_activity(), conv.last_activity());
+}
+
+#[test]
+fn db_load_messages_fills_an_unloaded_conversation() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.add_user_message("question", None);
+    answered(&mut conv, "first");
+    conv.branch_from(1);
+    answered(&mut conv, "second");
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+
+    let mut loaded = stoa::db::load_summaries(&conn).remove(0);
+    stoa::db::load_messages(&conn, &mut loaded).unwrap();
+    assert!(loaded.is_loaded());
+    assert_eq!(loaded.messages.len(), 2);
+    assert_eq!(loaded.alternates.len(), 1);
+    assert_eq!(loaded.version_info(1), (2, 2));
+
+    // Loading twice does not duplicate messages
+    stoa::db::load_messages(&conn, &mut loaded).unwrap();
+    assert_eq!(loaded.messages.len(), 2);
+}
+
+#[test]
+fn unload_keeps_stats_and_save_does_not_drop_messages() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.add_user_message("question", None);
+    answered(&mut conv, "answer");
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+    let before = conv.stats();
+
+    conv.unload();
+    assert!(conv.messages.is_empty());
+    assert_eq!(conv.stats(), before);
+
+    conv.title = "Renamed".to_string();
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+    let reloaded = stoa::db::load_all(&conn);
+    assert_eq!(reloaded[0].title, "Renamed");
+    assert_eq!(reloaded[0].messages.len(), 2);
+}
+
+#[test]
+fn db_analytics_aggregates_per_model() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    multi_model_turn(&mut conv, "q1", &[("gpt-4.1", "a1"), ("o3", "b1")]);
+    multi_model_turn(&mut conv, "q2", &[("gpt-4.1", "a2")]);
+    conv.messages[1].rating = 1;
+    conv.messages[1].latency_ms = Some(200);
+    conv.messages[4].latency_ms = Some(400);
+    conv.messages[4].token_count = Some(1000);
+    conv.messages[2].rating = -1;
+    stoa::db::save_conversation(&conn, &conv).unwrap();
+    stoa::db::save_conversation(&conn, &Conversation::new()).unwrap();
+
+    let summary = stoa::db::analytics(&conn, &DateRange::default(), &SearchQuery::default());
+    assert_eq!(summary.conversations, 2);
+    assert_eq!(summary.messages, 5);
+    assert_eq!(summary.models.len(), 2);
+
+    let gpt = &summary.models[0];
+    assert_eq!(gpt.model, "gpt-4.1");
+    assert_eq!(gpt.messages, 2);
+    assert_eq!(gpt.total_tokens, cost::estimate_tokens("a1") + 1000);
+    assert_eq!((gpt.thumbs_up, gpt.thumbs_down), (1, 0));
+    assert_eq!((gpt.total_latency_ms, gpt.latency_count), (600, 2));
+    assert!((gpt.total_cost - cost::message_cost("gpt-4.1", &Role::Assistant, gpt.total_tokens)).abs() < 1e-12);
+
+    let o3 = &summary.models[1];
+    assert_eq!((o3.messages, o3.thumbs_down, o3.latency_count), (1, 1, 0));
+    assert!((summary.total_cost - gpt.total_cost - o3.total_cost).abs() < 1e-12);
+}
+
+#[test]
+fn db_analytics_filters_by_date_range() {
+    let conn = stoa::db::open_in_memory();
+    let month_ago = chrono::Utc::now() - chrono::Duration::days(30);
+
+    let mut old = Conversation::new();
+    old.created_at = month_ago;
+    old.add_user_message("old question", None);
+    answered(&mut old, "old answer");
+    for msg in &mut old.messages {
+        msg.created_at = Some(month_ago);
+    }
+    stoa::db::save_conversation(&conn, &old).unwrap();
+
+    let mut recent = Conversation::new();
+    recent.add_user_message("new question", None);
+    answered(&mut recent, "new answer");
+    recent.add_user_message("untimed", None);
+    recent.messages[2].created_at = None;
+    stoa::db::save_conversation(&conn, &recent).unwrap();
+
+    let all = stoa::db::analytics(&conn, &DateRange::default(), &SearchQuery::default());
+    assert_eq!((all.conversations, all.messages), (2, 5));
+
+    let today = chrono::Local::now().date_naive();
+    let last_week = DateRange { from: today.checked_sub_days(chrono::Days::new(6)), to: None };
+    let week = stoa::db::analytics(&conn, &last_week, &SearchQuery::default());
+    assert_eq!((week.conversations, week.messages), (1, 2));
+    assert_eq!(week.models[0].messages, 1);
+
+    let before = DateRange { from: None, to: today.checked_sub_days(chrono::Days::new(7)) };
+    let older = stoa::db::analytics(&conn, &before, &SearchQuery::default());
+    assert_eq!((older.conversations, older.messages), (1, 2));
+}
+
+// ── App State Tests ──────────────────────────────────────────
+
+#[test]
+fn new_conversation_message_creates_and_selects() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    let initial_count = app.conversations.len();
+    let _ = app.update(stoa::app::Message::NewConversation);
+    assert_eq!(app.conversations.len(), initial_count + 1);
+    assert_eq!(app.active_conversation, app.conversations.len() - 1);
+}
+
+#[test]
+fn select_conversation_bounds_check() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    let _ = app.update(stoa::app::Message::SelectConversation(999));
+    // Should not crash, active_conversation unchanged
+    assert!(app.active_conversation < app.conversations.len());
+}
+
+#[test]
+fn delete_conversation_adjusts_active_index() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    let _ = app.update(stoa::app::Message::NewConversation);
+    let _ = app.update(stoa::app::Message::NewConversation);
+    assert_eq!(app.conversations.len(), 3);
+    app.active_conversation = 2;
+    let _ = app.update(stoa::app::Message::DeleteConversation(0));
+    assert_eq!(app.conversations.len(), 2);
+    assert_eq!(app.active_conversation, 1); // adjusted down
+}
+
+#[test]
+fn delete_last_conversation_is_noop() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    assert_eq!(app.conversations.len(), 1);
+    let _ = app.update(stoa::app::Message::DeleteConversation(0));
+    assert_eq!(app.conversations.len(), 1); // can't delete last
+}
+
+// ── Additional DB Tests ──────────────────────────────────────
+
+#[test]
+fn db_save_overwrite_existing() {
+    let conn = stoa::db::open_in_memory();
+    let mut conv = Conversation::new();
+    conv.title = "Version 1".to_string();
+    conv.add_user_message("first message", None);
+    stoa::db::save_conversation(&conn, &conv).unwrap();
 
     // Modify and save again with same ID
     conv.title = "Version 2".to_string();
@@ -1319,6 +4187,35 @@ fn app_sidebar_search_filters() {
     assert!(app.sidebar_search_results.as_ref().unwrap().is_empty());
 }
 
+#[test]
+fn app_open_search_hit_selects_and_highlights_message() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    let mut conv = Conversation::new();
+    conv.add_user_message("Where do penguins live?", None);
+    let idx = conv.push_streaming_assistant(Some("gpt-4o".to_string()));
+    conv.finalize_at(idx, "Mostly in the Southern Hemisphere, especially Antarctica.");
+    let _ = app.update(stoa::app::Message::ImportComplete(vec![conv.clone()]));
+    let _ = app.update(stoa::app::Message::SelectConversation(0));
+
+    let _ = app.update(stoa::app::Message::SidebarSearchChanged("antarctica".to_string()));
+    assert_eq!(app.sidebar_search_hits.len(), 1);
+    let _ = app.update(stoa::app::Message::OpenSearchHit(0));
+    assert_eq!(app.conversations[app.active_conversation].id, conv.id);
+    assert_eq!(app.highlighted_message.as_deref(), Some(conv.messages[1].id.as_str()));
+    assert_eq!(app.highlight_terms, vec!["Antarctica".to_string()]);
+
+    // Filter queries reach SQLite even when short
+    let _ = app.update(stoa::app::Message::QuickSwitcherQueryChanged("is:fork".to_string()));
+    assert_eq!(app.quick_switcher_matches, Some(Vec::new()));
+    let _ = app.update(stoa::app::Message::QuickSwitcherQueryChanged("model:gpt-4o".to_string()));
+    assert_eq!(app.quick_switcher_matches, Some(vec![conv.id.clone()]));
+
+    // Clearing the search drops the highlight
+    let _ = app.update(stoa::app::Message::SidebarSearchChanged(String::new()));
+    assert!(app.sidebar_search_hits.is_empty());
+    assert!(app.highlighted_message.is_none());
+}
+
 #[test]
 fn app_toggle_pin_reorders() {
     let mut app = stoa::app::ChatApp::new_for_tests();
@@ -1345,6 +4242,8 @@ fn app_fork_conversation_creates_copy() {
     // Add messages to active conversation (in memory)
     app.conversations[0].add_user_message("What is Rust?", Some("gpt-4.1".to_string()));
     app.conversations[0].messages.push(ChatMessage {
+        id: new_message_id(),
+        parent_id: None,
         role: Role::Assistant,
         content: "Rust is a systems programming language.".to_string(),
         streaming: false,
@@ -1353,6 +4252,9 @@ fn app_fork_conversation_creates_copy() {
         rating: 0,
         latency_ms: None,
         images: Vec::new(),
+        canonical: false,
+        created_at: None,
+        completed_at: None,
     });
 
     // Fork at message index 1 (include both messages)
@@ -1377,6 +4279,8 @@ fn app_rate_message_toggles() {
     // Add messages to rate
     app.conversations[0].add_user_message("test", None);
     app.conversations[0].messages.push(ChatMessage {
+        id: new_message_id(),
+        parent_id: None,
         role: Role::Assistant,
         content: "response".to_string(),
         streaming: false,
@@ -1385,6 +4289,9 @@ fn app_rate_message_toggles() {
         rating: 0,
         latency_ms: None,
         images: Vec::new(),
+        canonical: false,
+        created_at: None,
+        completed_at: None,
     });
 
     // Rate thumbs up
@@ -1419,6 +4326,81 @@ fn app_delete_message_removes_from_conversation() {
     assert_eq!(app.conversations[0].messages[0].content, "second");
 }
 
+#[test]
+fn app_retry_keeps_previous_answer() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    app.conversations[0].add_user_message("question", None);
+    answered(&mut app.conversations[0], "first answer");
+
+    let _ = app.update(stoa::app::Message::RetryMessage);
+
+    let conv = &app.conversations[0];
+    assert_eq!(conv.messages.len(), 2);
+    assert!(conv.messages[1].streaming);
+    assert_eq!(conv.alternates.len(), 1);
+    assert_eq!(conv.alternates[0].content, "first answer");
+    assert_eq!(conv.version_info(1), (2, 2));
+}
+
+#[test]
+fn app_switch_version_is_blocked_while_streaming() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    app.conversations[0].add_user_message("question", None);
+    answered(&mut app.conversations[0], "first answer");
+    let _ = app.update(stoa::app::Message::RetryMessage);
+
+    let _ = app.update(stoa::app::Message::SwitchVersion(1, -1));
+    assert_eq!(app.conversations[0].version_info(1), (2, 2));
+
+    let _ = app.update(stoa::app::Message::StopStreaming);
+    let _ = app.update(stoa::app::Message::SwitchVersion(1, -1));
+    assert_eq!(app.conversations[0].version_info(1), (1, 2));
+    assert_eq!(app.conversations[0].messages[1].content, "first answer");
+}
+
+#[test]
+fn app_edit_message_resends_to_same_models() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    let conv = &mut app.conversations[0];
+    conv.add_user_message("helo", None);
+    for (model, content) in [("gpt-4.1", "a"), ("o3", "b")] {
+        let idx = conv.push_streaming_assistant(Some(model.to_string()));
+        conv.finalize_at(idx, content);
+    }
+    conv.add_user_message("thanks", None);
+    assert_eq!(conv.turn_models(0), vec!["gpt-4.1", "o3"]);
+
+    let _ = app.update(stoa::app::Message::StartEditMessage(0));
+    assert_eq!(app.editing_message, Some(0));
+    assert_eq!(app.edit_message_value, "helo");
+    let _ = app.update(stoa::app::Message::EditMessageChanged("hello".to_string()));
+    let _ = app.update(stoa::app::Message::SubmitMessageEdit);
+
+    assert!(app.editing_message.is_none());
+    assert_eq!(app.active_streams.len(), 2);
+    let conv = &app.conversations[0];
+    assert_eq!(conv.messages.len(), 3);
+    assert_eq!(conv.messages[0].content, "hello");
+    assert!(conv.messages[1].streaming && conv.messages[2].streaming);
+    assert_eq!(conv.messages[1].model.as_deref(), Some("gpt-4.1"));
+    assert_eq!(conv.messages[2].model.as_deref(), Some("o3"));
+    // The original turn, including the later reply, is kept as a branch
+    assert_eq!(conv.version_info(0), (2, 2));
+    assert_eq!(conv.alternates.len(), 4);
+}
+
+#[test]
+fn app_edit_message_ignores_blank_text() {
+    let mut app = stoa::app::ChatApp::new_for_tests();
+    app.conversations[0].add_user_message("question", Some("gpt-4.1".to_string()));
+    let _ = app.update(stoa::app::Message::StartEditMessage(0));
+    let _ = app.update(stoa::app::Message::EditMessageChanged("   ".to_string()));
+    let _ = app.update(stoa::app::Message::SubmitMessageEdit);
+    assert_eq!(app.conversations[0].messages[0].content, "question");
+    assert!(app.conversations[0].alternates.is_empty());
+    assert!(app.active_streams.is_empty());
+}
+
 #[test]
 fn app_tag_submit_adds_tag() {
     let mut app = stoa::app::ChatApp::new_for_tests();
@@ -1487,6 +4469,8 @@ fn export_markdown_with_system_prompt() {
     conv.system_prompt = "You are a physics tutor.".to_string();
     conv.add_user_message("What is gravity?", None);
     conv.messages.push(ChatMessage {
+        id: new_message_id(),
+        parent_id: None,
         role: Role::Assistant,
         content: "Gravity is a fundamental force of attraction.".to_string(),
         streaming: false,
@@ -1495,6 +4479,9 @@ fn export_markdown_with_system_prompt() {
         rating: 0,
         latency_ms: None,
         images: Vec::new(),
+        canonical: false,
+        created_at: None,
+        completed_at: None,
     });
 
     let md = export::conversation_to_markdown(&conv);
@@ -1516,6 +4503,8 @@ fn export_json_all_fields_present() {
     conv.folder = Some("research".to_string());
     conv.add_user_message("hello", Some("gpt-4.1".to_string()));
     conv.messages.push(ChatMessage {
+        id: new_message_id(),
+        parent_id: None,
         role: Role::Assistant,
         content: "world".to_string(),
         streaming: false,
@@ -1524,6 +4513,9 @@ fn export_json_all_fields_present() {
         rating: 1,
         latency_ms: Some(250),
         images: Vec::new(),
+        canonical: false,
+        created_at: None,
+        completed_at: None,
     });
 
     let json = export::conversation_to_json(&conv);